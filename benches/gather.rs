@@ -0,0 +1,45 @@
+//! Compares gathering a shuffled batch from an in-memory data set
+//! against `select` on the same arrays.
+//!
+//! Run with `cargo bench --bench gather`
+use std::time::{Duration, Instant};
+
+use linear_networks::data::{Dataset, InMemoryDataset};
+use ndarray::{Array2, Axis};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+const ITERATIONS: u32 = 10_000;
+
+/// Average time per call of `f`, after a short warm up
+fn time(mut f: impl FnMut()) -> Duration {
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let inputs = Array2::<f32>::from_shape_simple_fn((10_000, 784), || rng.gen());
+    let targets = Array2::<f32>::from_shape_simple_fn((10_000, 10), || rng.gen());
+    let data = InMemoryDataset::new(inputs, targets);
+
+    for batch_size in [1, 32, 256] {
+        let mut indices: Vec<usize> = (0..data.len()).collect();
+        indices.shuffle(&mut rng);
+        let indices = &indices[..batch_size];
+
+        let gather = time(|| drop(data.batch(indices)));
+        let select = time(|| {
+            drop((
+                data.inputs.select(Axis(0), indices),
+                data.targets.select(Axis(0), indices),
+            ));
+        });
+        println!("batch of {batch_size:>3}: gather {gather:>10.2?}, select {select:>10.2?}");
+    }
+}
@@ -0,0 +1,55 @@
+//! Compares the latency of running one sample through `SingleSample`
+//! against the batched `exec`, with a batch of one and as a throughput baseline.
+//!
+//! Run with `cargo bench --bench single_sample`
+use std::time::{Duration, Instant};
+
+use linear_networks::{
+    activation::relu::Relu, dense::Dense, initialisers::Xavier, net, single::SingleSample, Graph,
+    GraphExec,
+};
+use ndarray::{Array1, Array2, Axis};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const ITERATIONS: u32 = 100_000;
+
+/// Average time per call of `f`, after a short warm up
+fn time(mut f: impl FnMut()) -> Duration {
+    for _ in 0..ITERATIONS / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let network = net![
+        Dense::output_size(32)
+            .with_initialiser(Xavier)
+            .with_activation(Relu),
+        Dense::output_size(32)
+            .with_initialiser(Xavier)
+            .with_activation(Relu),
+        Dense::output_size(4).with_initialiser(Xavier)
+    ];
+    let state = Graph::<f32, _>::init_with_random(network, &mut rng, 16);
+    let model = SingleSample::<f32, 32>::new(&state).unwrap();
+
+    let input = Array1::<f32>::from_shape_simple_fn(16, || rng.gen_range(-1.0..1.0));
+    let batch = input.view().insert_axis(Axis(0));
+    let inputs = Array2::<f32>::from_shape_simple_fn((256, 16), || rng.gen_range(-1.0..1.0));
+    let samples = input.as_slice().unwrap();
+    let mut output = [0.0; 4];
+
+    let single = time(|| model.predict_into(samples, &mut output));
+    let one = time(|| drop(state.exec(batch)));
+    let batched = time(|| drop(state.exec(inputs.view()))) / 256;
+
+    println!("single sample:        {single:>10.2?} per sample");
+    println!("exec, batch of 1:     {one:>10.2?} per sample");
+    println!("exec, batch of 256:   {batched:>10.2?} per sample");
+}
@@ -7,6 +7,7 @@ use std::sync::{
 use std::thread;
 use std::time::Duration;
 
+use linear_networks::callback::TrainEvent;
 use termion::event::Key;
 use termion::input::TermRead;
 
@@ -15,7 +16,7 @@ use crate::train::train;
 pub enum Event {
     Input(Key),
     Tick,
-    EpochComplete(f64),
+    Train(TrainEvent<f64>),
 }
 
 /// A small event handler that wrap termion input and tick events. Each event
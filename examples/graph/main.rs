@@ -1,8 +1,8 @@
 mod event;
-mod parse;
 mod train;
 
 use event::{Event, Events};
+use linear_networks::callback::TrainEvent;
 use std::{error::Error, io};
 use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
 use tui::{
@@ -37,7 +37,15 @@ fn main() -> Result<(), Box<dyn Error>> {
             Event::Tick => {
                 terminal.draw(|f| app.draw(f))?;
             }
-            Event::EpochComplete(cost) => {
+            Event::Train(TrainEvent::BatchEnd {
+                epoch,
+                batch,
+                batches,
+                ..
+            }) => {
+                app.progress = (epoch, batch + 1, batches);
+            }
+            Event::Train(TrainEvent::EpochEnd { cost, .. }) => {
                 app.add_cost(cost);
             }
         }
@@ -48,11 +56,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
 struct App {
     costs: Vec<(f64, f64)>,
+    /// (epoch, batch, total batches) of the most recently trained batch
+    progress: (usize, usize, usize),
 }
 
 impl App {
     fn new() -> Self {
-        App { costs: vec![] }
+        App {
+            costs: vec![],
+            progress: (0, 0, 0),
+        }
     }
     fn add_cost(&mut self, cost: f64) {
         self.costs
@@ -79,11 +92,14 @@ impl App {
             (0.0, width)
         };
 
+        let (epoch, batch, batches) = self.progress;
+        let title = format!("Epoch {}: batch {}/{}", epoch + 1, batch, batches);
+
         let chart = Chart::new(datasets)
             .block(
                 Block::default()
                     .title(Span::styled(
-                        "Chart 1",
+                        title,
                         Style::default()
                             .fg(Color::Cyan)
                             .add_modifier(Modifier::BOLD),
@@ -1,6 +1,9 @@
 use linear_networks::{
     activation::{relu::Relu, sigmoid::Sigmoid},
+    callback::TrainEvent,
     cost::mse::MSE,
+    data::InMemoryDataset,
+    datasets::mnist::Mnist,
     dense::Dense,
     initialisers::Xavier,
     net,
@@ -8,15 +11,17 @@ use linear_networks::{
     train::Train,
     Graph, Shaped,
 };
-use ndarray::Array2;
 use std::sync::mpsc;
 
-use crate::{event::Event, parse};
+use crate::event::Event;
 
 pub fn train(tx: mpsc::Sender<Event>) {
     // Load MNIST data set
-    let data = parse::load_data();
-    let training_data = process_data(&data.training);
+    let data = Mnist::load(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/mnist/data")).unwrap();
+    let training_data = InMemoryDataset::new(
+        data.training.inputs.mapv(f64::from),
+        data.training.targets.mapv(f64::from),
+    );
 
     // Create a new compute graph which uses three Dense components
     // With the input having size 28*28 and the output having size 10
@@ -36,40 +41,17 @@ pub fn train(tx: mpsc::Sender<Event>) {
 
     // New trainer with mean squared error cost function
     let optimiser = Adam::new(0.001, 0.9, 0.99, 1e-8, network.shape());
-    let mut trainer = Train {
-        graph: network,
-        optimiser,
-        cost: MSE,
-        regularisation: None,
-        dropout: 0.0,
-    };
+    let mut trainer = Train::builder(network)
+        .cost(MSE)
+        .optimiser(optimiser)
+        .callback(move |event: &TrainEvent<f64>| {
+            let _ = tx.send(Event::Train(*event));
+        })
+        .build();
 
     const BATCH_SIZE: usize = 120;
 
-    for _ in 0.. {
-        let cost =
-            trainer.perform_epoch(&training_data.0.view(), &training_data.1.view(), BATCH_SIZE);
-        tx.send(Event::EpochComplete(cost)).unwrap();
-    }
-}
-
-fn process_data(data: &parse::DataSet) -> (Array2<f64>, Array2<f64>) {
-    unsafe {
-        let data_len = data.images.len();
-        assert_eq!(data_len, data.labels.len());
-
-        let mut input = Array2::uninitialized((data_len, 28 * 28));
-        let mut expected = Array2::zeros((data_len, 10));
-
-        for (i, image) in data.images.iter().enumerate() {
-            for (j, &b) in image.iter().enumerate() {
-                input[(i, j)] = (b as f64) / 255.0;
-            }
-        }
-        for (i, &label) in data.labels.iter().enumerate() {
-            expected[(i, label as usize)] = 1.0;
-        }
-
-        (input, expected)
+    loop {
+        trainer.perform_epoch(&training_data, BATCH_SIZE);
     }
 }
@@ -1,21 +1,25 @@
-mod parse;
-
 use linear_networks::{
     activation::{relu::Relu, sigmoid::Sigmoid},
+    callback::Progress,
+    classify::{classes, predict_classes, predict_proba},
     cost::mse::MSE,
+    data::loader::DataLoader,
+    datasets::mnist::Mnist,
     dense::Dense,
     initialisers::Xavier,
+    metrics::Accuracy,
     net,
     optimise::adam::Adam,
     train::{Regularisation, Train},
-    GraphExec, Shaped, HDF5, Graph,
+    Graph, Shaped, HDF5,
 };
-use ndarray::{Array2, AssignElem, Axis};
+use ndarray::s;
+use std::sync::Arc;
 
 fn main() {
     // Load MNIST data set
-    let data = parse::load_data();
-    let training_data = process_data(&data.training);
+    let data = Mnist::load(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/mnist/data")).unwrap();
+    let training_data = Arc::new(data.training);
 
     // Create a new compute graph which uses three Dense components
     // With the input having size 28*28 and the output having size 10
@@ -32,66 +36,71 @@ fn main() {
             .with_activation(Sigmoid)
     ];
 
-    let file = hdf5::File::open("mnist.h5").unwrap();
-    let graph = network.load(&file).unwrap();
-
-    // let graph = network.input_shape(28 * 28);
+    // Continue from the last saved weights and optimiser state, if there are any
+    let (graph, optimiser) = match hdf5::File::open("mnist.h5") {
+        Ok(file) => {
+            let graph = network.load(&file).unwrap();
+            let optimiser = Adam::load(&network, &file.group("optimiser").unwrap()).unwrap();
+            (graph, optimiser)
+        }
+        Err(_) => {
+            let graph = network.input_shape(28 * 28);
+            let optimiser = Adam::new(0.001, 0.9, 0.99, 1e-8, graph.shape());
+            (graph, optimiser)
+        }
+    };
 
     // New trainer with mean squared error cost function and
     // stochastic gradient descent optimisation (alpha=0.1)
     // let mut trainer = Train::new(network, MSE, SGD::new(0.01));
 
-    let optimiser = Adam::new(0.001, 0.9, 0.99, 1e-8, graph.shape());
-    let mut trainer = Train {
-        graph,
-        optimiser,
-        cost: MSE,
-        regularisation: Some(Regularisation::L2(0.01)),
-        dropout: 0.2,
-    };
+    let mut trainer = Train::builder(graph)
+        .cost(MSE)
+        .optimiser(optimiser)
+        .regularisation(Regularisation::L2(0.01))
+        .dropout(0.2)
+        .callback(Progress::new())
+        .build();
 
     let mut costs = vec![];
 
     const BATCH_SIZE: usize = 120;
 
     for _ in 0..20 {
-        let cost =
-            trainer.perform_epoch(&training_data.0.view(), &training_data.1.view(), BATCH_SIZE);
+        // gather the next few batches in the background while training
+        let loader = DataLoader::shuffled(Arc::clone(&training_data), BATCH_SIZE, 4);
+        let cost = trainer.perform_epoch_loaded(loader);
 
-        costs.push(dbg!(cost));
+        costs.push(cost);
     }
 
+    let testing_data = data.testing;
+    let evaluation = trainer
+        .evaluate(
+            &testing_data.inputs.view(),
+            &testing_data.targets.view(),
+            BATCH_SIZE,
+            &[&Accuracy],
+        )
+        .unwrap();
+    println!(
+        "test cost: {:?}, test accuracy: {:?}",
+        evaluation.cost, evaluation.metrics[0]
+    );
+
     let graph = trainer.graph;
 
-    // let file = hdf5::File::create("mnist.h5").unwrap();
-    // network.save(&graph, &file).unwrap();
-    // file.close().unwrap();
+    let file = hdf5::File::create("mnist.h5").unwrap();
+    network.save(&graph, &file).unwrap();
+    let optimiser = file.create_group("optimiser").unwrap();
+    trainer.optimiser.save(&network, &optimiser).unwrap();
+    file.close().unwrap();
 
     // println!("network: {:?}", network);
 
-    let input = training_data.0.index_axis(Axis(0), 0);
-    let expected = training_data.1.index_axis(Axis(0), 0);
-
-    let output = graph.exec(input);
-    println!("output: {:?}", output);
-    println!("expected: {:?}", expected);
-}
-
-fn process_data(data: &parse::DataSet) -> (Array2<f32>, Array2<f32>) {
-    let data_len = data.images.len();
-    assert_eq!(data_len, data.labels.len());
-
-    let mut input = Array2::uninit((data_len, 28 * 28));
-    let mut expected = Array2::zeros((data_len, 10));
-
-    for (i, image) in data.images.iter().enumerate() {
-        for (j, &b) in image.iter().enumerate() {
-            input[(i, j)].assign_elem((b as f32) / 255.0);
-        }
-    }
-    for (i, &label) in data.labels.iter().enumerate() {
-        expected[(i, label as usize)] = 1.0;
-    }
-
-    unsafe { (input.assume_init(), expected) }
+    let inputs = testing_data.inputs.slice(s![..5, ..]);
+    let expected = classes(&testing_data.targets.slice(s![..5, ..]).to_owned());
+    println!("predicted: {}", predict_classes(&graph, inputs));
+    println!("expected:  {}", expected);
+    println!("confidence: {:.3}", predict_proba(&graph, inputs));
 }
@@ -1,22 +1,48 @@
-use crate::{train::GraphExecTrain, Graph, GraphExec, Mappable, Shaped, HDF5};
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    binary::Element,
+    error::Result,
+    named::Named,
+    train::{GraphExecTrain, Modal, Mode},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+#[cfg(feature = "hdf5")]
 use hdf5::H5Type;
+use num_traits::Float;
 use rand::Rng;
 
 pub mod relu;
 pub mod sigmoid;
 
-pub trait Activation {}
+pub trait Activation {
+    /// A lowercase name for the activation, used when exporting models
+    const NAME: &'static str;
+
+    /// Applies the activation to a single value
+    fn apply<F: Float>(&self, x: F) -> F;
+}
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Linear<G, L> {
-    graph: G,
-    linear: L,
+    pub(crate) graph: G,
+    pub(crate) linear: L,
 }
 
 impl<G, L> Linear<G, L> {
     pub const fn new(graph: G, linear: L) -> Self {
         Self { graph, linear }
     }
+
+    pub fn named(self, name: impl Into<Arc<str>>) -> Named<Self> {
+        Named::new(name, self)
+    }
 }
 
 impl<I, G, F, L> Graph<F, I> for Linear<G, L>
@@ -31,30 +57,36 @@ where
     }
 
     fn init_with_random(self, rng: &mut impl Rng, input_shape: I) -> Self::State {
-        let Linear { graph, linear } = self;
+        let Self { graph, linear } = self;
         Linear {
             graph: graph.init_with_random(rng, input_shape),
             linear,
         }
     }
+
+    fn num_params(&self, input_shape: I) -> usize {
+        self.graph.num_params(input_shape)
+    }
 }
 
 impl<G, L, Input> GraphExec<Input> for Linear<G, L>
 where
     G: GraphExec<Input>,
-    L: GraphExec<G::Output, Output = G::Output>,
+    L: Activation + GraphExec<G::Output, Output = G::Output>,
 {
     type Output = G::Output;
     fn exec(&self, input: Input) -> Self::Output {
-        let output = self.graph.exec(input);
-        self.linear.exec(output)
+        self.graph.exec_activated(input, &self.linear)
+    }
+    fn try_exec(&self, input: Input) -> Result<Self::Output> {
+        Ok(self.linear.exec(self.graph.try_exec(input)?))
     }
 }
 
 impl<G, L, Input> GraphExecTrain<Input> for Linear<G, L>
 where
     G: GraphExecTrain<Input>,
-    L: GraphExecTrain<G::Output, Output = G::Output>,
+    L: Activation + GraphExecTrain<G::Output, Output = G::Output>,
 {
     type State = Linear<G::State, L::State>;
     fn forward(&self, input: Input) -> (Self::State, Self::Output) {
@@ -69,6 +101,19 @@ where
         let (d_input, graph) = self.graph.back(graph, d_output);
         (d_input, Self { graph, linear })
     }
+
+    fn back_into(&self, state: Self::State, d_output: Self::Output, grads: &mut Self) -> Input {
+        let Linear { linear, graph } = state;
+        let d_output = self.linear.back_into(linear, d_output, &mut grads.linear);
+        self.graph.back_into(graph, d_output, &mut grads.graph)
+    }
+}
+
+impl<G: Modal, L: Modal> Modal for Linear<G, L> {
+    fn set_mode(&mut self, mode: Mode) {
+        self.graph.set_mode(mode);
+        self.linear.set_mode(mode);
+    }
 }
 
 impl<T, G, L> Mappable<T> for Linear<G, L>
@@ -88,6 +133,15 @@ where
     fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
         self.graph.map_mut_with(&rhs.graph, f);
     }
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.graph.map_weights_mut_with(&rhs.graph, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, f: F)
+    where
+        T: Clone,
+    {
+        self.graph.for_each(f);
+    }
 }
 
 impl<F, G, L> Shaped<F> for Linear<G, L>
@@ -122,6 +176,20 @@ where
     }
 }
 
+impl<F: Element, I, G: Persist<F, I>, L: Clone> Persist<F, I> for Linear<G, L> {
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        self.graph.write_state(&state.graph, w)
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        Ok(Linear {
+            graph: self.graph.read_state(r)?,
+            linear: self.linear.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "hdf5")]
 impl<F: H5Type, I, G: HDF5<F, I>, L: Clone> HDF5<F, I> for Linear<G, L> {
     fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()> {
         self.graph.save(&state.graph, group)
@@ -133,4 +201,71 @@ impl<F: H5Type, I, G: HDF5<F, I>, L: Clone> HDF5<F, I> for Linear<G, L> {
             linear: self.linear.clone(),
         })
     }
+
+    fn save_layers(
+        &self,
+        state: &Self::State,
+        group: &hdf5::Group,
+        index: &mut usize,
+    ) -> hdf5::Result<()> {
+        self.graph.save_layers(&state.graph, group, index)
+    }
+
+    fn load_layers(&self, group: &hdf5::Group, index: &mut usize) -> hdf5::Result<Self::State> {
+        Ok(Linear {
+            graph: self.graph.load_layers(group, index)?,
+            linear: self.linear.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{relu::Relu, sigmoid::Sigmoid};
+    use crate::{dense::Dense, initialisers::Xavier, train::GraphExecTrain, Graph, GraphExec};
+
+    #[test]
+    fn test_with_activation_on_any_graph() {
+        use crate::{named::Named, repeat::Repeat, GraphExt};
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let input = Array2::<f64>::from_shape_simple_fn((5, 8), || rng.gen_range(-1.0..1.0));
+
+        let network = Repeat::new(Dense::output_size(4).with_initialiser(Xavier), 2)
+            .with_activation(Relu)
+            .init_with_random(&mut rng, 8);
+        let output = network.exec(input.view());
+        assert_eq!(output, Relu.exec(network.graph.exec(input.view())));
+
+        let network = Named::new("output", Dense::output_size(4).with_initialiser(Xavier))
+            .with_activation(Sigmoid)
+            .init_with_random(&mut rng, 8);
+        let output = network.exec(input.view());
+        assert_eq!(output, Sigmoid.exec(network.graph.exec(input.view())));
+        assert_eq!(network.graph.name.as_ref(), "output");
+    }
+
+    #[test]
+    fn test_fused_exec() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let input = Array2::<f64>::from_shape_simple_fn((5, 8), || rng.gen_range(-1.0..1.0));
+
+        let relu = Dense::output_size(4)
+            .with_initialiser(Xavier)
+            .with_activation(Relu)
+            .init_with_random(&mut rng, 8);
+        let unfused = Relu.exec(relu.graph.exec(input.view()));
+        assert_eq!(relu.exec(input.view()), unfused);
+        assert_eq!(relu.forward(input.clone()).1, unfused);
+
+        let sigmoid = Dense::output_size(4)
+            .with_initialiser(Xavier)
+            .with_activation(Sigmoid)
+            .init_with_random(&mut rng, 8);
+        let unfused = Sigmoid.exec(sigmoid.graph.exec(input.view()));
+        assert_eq!(sigmoid.exec(input.view()), unfused);
+    }
 }
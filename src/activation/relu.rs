@@ -1,12 +1,23 @@
-use crate::{train::GraphExecTrain, GraphExec};
-use ndarray::{Array, Dimension, LinalgScalar, ScalarOperand};
+use crate::{
+    train::{GraphExecTrain, Modal},
+    GraphExec,
+};
+use ndarray::{Array, Dimension, LinalgScalar, ScalarOperand, Zip};
 use num_traits::Float;
 
 use super::Activation;
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Relu;
-impl Activation for Relu {}
+impl Activation for Relu {
+    const NAME: &'static str = "relu";
+
+    fn apply<F: Float>(&self, x: F) -> F {
+        x.max(F::zero())
+    }
+}
+impl Modal for Relu {}
 
 impl<F, D> GraphExec<Array<F, D>> for Relu
 where
@@ -15,8 +26,7 @@ where
 {
     type Output = Array<F, D>;
     fn exec(&self, input: Array<F, D>) -> Self::Output {
-        let zero = F::zero();
-        input.mapv(|x| x.max(zero))
+        input.mapv_into(|x| self.apply(x))
     }
 }
 
@@ -25,14 +35,29 @@ where
     F: LinalgScalar + ScalarOperand + Float,
     D: Dimension,
 {
-    type State = (Array<F, D>, Self::Output);
-    fn forward(&self, input: Array<F, D>) -> (Self::State, Self::Output) {
-        let output = self.exec(input.clone());
-        ((input, output.clone()), output)
+    /// Which inputs were positive. The gradient only flows through those
+    type State = Array<bool, D>;
+    fn forward(&self, mut input: Array<F, D>) -> (Self::State, Self::Output) {
+        let zero = F::zero();
+        let mut mask = Array::from_elem(input.raw_dim(), false);
+        Zip::from(&mut input)
+            .and(&mut mask)
+            .for_each(|x, positive| {
+                *positive = *x > zero;
+                *x = self.apply(*x);
+            });
+        (mask, input)
     }
 
-    fn back(&self, (input, output): Self::State, d_output: Self::Output) -> (Array<F, D>, Self) {
-        let d_input: Array<F, D> = output / input * d_output;
-        (d_input, Self)
+    fn back(&self, mask: Self::State, mut d_output: Self::Output) -> (Array<F, D>, Self) {
+        let zero = F::zero();
+        Zip::from(&mut d_output)
+            .and(&mask)
+            .for_each(|d, &positive| {
+                if !positive {
+                    *d = zero;
+                }
+            });
+        (d_output, Self)
     }
 }
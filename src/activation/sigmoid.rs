@@ -1,12 +1,23 @@
-use crate::{train::GraphExecTrain, GraphExec};
+use crate::{
+    train::{GraphExecTrain, Modal},
+    GraphExec,
+};
 use ndarray::{Array, Dimension, LinalgScalar, ScalarOperand};
 use num_traits::Float;
 
 use super::Activation;
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Sigmoid;
-impl Activation for Sigmoid {}
+impl Activation for Sigmoid {
+    const NAME: &'static str = "sigmoid";
+
+    fn apply<F: Float>(&self, x: F) -> F {
+        F::one() / (F::one() + (-x).exp())
+    }
+}
+impl Modal for Sigmoid {}
 
 impl<F, D> GraphExec<Array<F, D>> for Sigmoid
 where
@@ -15,8 +26,7 @@ where
 {
     type Output = Array<F, D>;
     fn exec(&self, input: Array<F, D>) -> Self::Output {
-        let one = F::one();
-        input.mapv(|x| (one / (one + (-x).exp())))
+        input.mapv_into(|x| self.apply(x))
     }
 }
 
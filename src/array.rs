@@ -1,25 +1,55 @@
-use ndarray::{Array, Array2, ArrayBase, Data, DataShared, Dimension, Ix2, LinalgScalar, RawData};
+use ndarray::{
+    Array, Array2, ArrayBase, ArrayView, CowArray, Data, DataShared, Dimension, Ix2, LinalgScalar,
+    RawData,
+};
+use num_traits::Float;
+
+use crate::{error::Error, Mappable};
 
 pub fn compact_shape(shape: &[usize]) -> (usize, usize) {
     let (last, rest) = shape.split_last().unwrap();
     (rest.iter().product(), *last)
 }
 
-pub fn compact_front<S, F, D>(a: ArrayBase<S, D>) -> ArrayBase<S, Ix2>
+/// Views the array as a matrix with one row per element of the leading axes.
+/// Arrays that aren't in standard layout, such as transposed or strided views, are copied
+pub fn compact_front<S, F, D>(a: &ArrayBase<S, D>) -> CowArray<'_, F, Ix2>
 where
-    S: RawData<Elem = F> + Data,
-    F: LinalgScalar,
+    S: Data<Elem = F>,
+    F: Clone,
     D: Dimension,
 {
     let shape = compact_shape(a.shape());
-    a.into_shape(shape).unwrap()
+    a.as_standard_layout()
+        .into_shape(shape)
+        .expect("arrays in standard layout can always be reshaped")
+}
+
+/// Checks that the last axis of the input has the size the layer was initialised with
+pub fn input_shape(layer: &'static str, shape: &[usize], input_size: usize) -> Result<(), Error> {
+    if shape.last() == Some(&input_size) {
+        Ok(())
+    } else {
+        Err(Error::InputShape {
+            layer,
+            input_size,
+            shape: shape.to_vec(),
+        })
+    }
+}
+
+/// Like [`input_shape`], but panics with the error message naming the layer
+pub fn check_input_shape(layer: &'static str, shape: &[usize], input_size: usize) {
+    if let Err(err) = input_shape(layer, shape, input_size) {
+        panic!("{}", err);
+    }
 }
 
 /// `AxBxCxI` dot `IxO` -> `AxBxCxO`
 /// Converts `AxBxCxI` Array into (AxBxC)xI Array2
 /// Performs dot product
 /// Then converts back into `AxBxCxO` Array
-pub fn dot_inner<S1, S2, F, D>(lhs: ArrayBase<S1, D>, rhs: &ArrayBase<S2, Ix2>) -> Array<F, D>
+pub fn dot_inner<S1, S2, F, D>(lhs: &ArrayBase<S1, D>, rhs: &ArrayBase<S2, Ix2>) -> Array<F, D>
 where
     S1: RawData<Elem = F> + Data,
     S2: RawData<Elem = F> + DataShared,
@@ -31,15 +61,19 @@ where
 
     let i = compact_front(lhs);
 
-    i.dot(rhs).into_shape(dim).unwrap()
+    i.dot(rhs)
+        .into_shape(dim)
+        .expect("the product is in standard layout")
 }
 
 /// `AxBxCxI` dot `AxBxCxO` -> `IxO`
 /// Converts `AxBxCxI` Array into (AxBxC)xI Array2 = l
 /// Converts `AxBxCxO` Array into (AxBxC)xO Array2 = r
 /// Performs dot product for l.t and r
-pub fn dot_front<F, D>(lhs: Array<F, D>, rhs: Array<F, D>) -> Array2<F>
+pub fn dot_front<S1, S2, F, D>(lhs: &ArrayBase<S1, D>, rhs: &ArrayBase<S2, D>) -> Array2<F>
 where
+    S1: Data<Elem = F>,
+    S2: Data<Elem = F>,
     F: LinalgScalar,
     D: Dimension,
 {
@@ -48,3 +82,67 @@ where
 
     l.t().dot(&r)
 }
+
+/// Index of the largest value in the (flattened) array
+pub fn argmax<F: Float, D: Dimension>(a: &ArrayView<F, D>) -> usize {
+    a.iter()
+        .enumerate()
+        .fold((0, F::neg_infinity()), |(i, max), (j, &x)| {
+            if x > max {
+                (j, x)
+            } else {
+                (i, max)
+            }
+        })
+        .0
+}
+
+impl<T, D> Mappable<T> for Array<T, D>
+where
+    D: Dimension,
+{
+    // not redundant. just forces a capture without needing to clone
+    #![allow(clippy::redundant_closure)]
+
+    fn map<F: FnMut(&T) -> T>(&self, mut f: F) -> Self {
+        self.map(|a| f(a))
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, f: F) {
+        self.map_inplace(f);
+    }
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.zip_mut_with(rhs, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, f: F)
+    where
+        T: Clone,
+    {
+        self.for_each(f);
+    }
+}
+
+/// Borrowed inputs are only copied if they are modified, eg by adversarial training
+impl<T, D> Mappable<T> for CowArray<'_, T, D>
+where
+    T: Clone,
+    D: Dimension,
+{
+    // not redundant. just forces a capture without needing to clone
+    #![allow(clippy::redundant_closure)]
+
+    fn map<F: FnMut(&T) -> T>(&self, mut f: F) -> Self {
+        self.map(|a| f(a)).into()
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, f: F) {
+        self.map_inplace(f);
+    }
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.zip_mut_with(rhs, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, f: F)
+    where
+        T: Clone,
+    {
+        self.for_each(f);
+    }
+}
@@ -0,0 +1,633 @@
+//! Autoencoders, trained to reconstruct their own inputs.
+//!
+//! An autoencoder squeezes its input through a narrow hidden layer and expands it back
+//! out again. Since the target is the input itself, it needs no labels, and the encoder
+//! it learns can be used to pretrain the first layer of a classifier.
+//!
+//! ```no_run
+//! use linear_networks::{
+//!     activation::relu::Relu,
+//!     autoencoder::{Reconstruct, TiedDense},
+//!     data::InMemoryDataset,
+//!     initialisers::Xavier,
+//!     optimise::adam::Adam,
+//!     train::Train,
+//!     Graph, Shaped,
+//! };
+//! use ndarray::Array2;
+//!
+//! // the MNIST training set, as loaded by `Mnist::load`
+//! let images = InMemoryDataset::new(
+//!     Array2::<f32>::zeros((60_000, 784)),
+//!     Array2::zeros((60_000, 10)),
+//! );
+//! let autoencoder = TiedDense::hidden_size(64, Relu)
+//!     .with_initialiser(Xavier)
+//!     .input_shape(28 * 28);
+//! let optimiser = Adam::new(0.001, 0.9, 0.99, 1e-8, autoencoder.shape());
+//! let mut trainer = Train::builder(autoencoder).optimiser(optimiser).build();
+//! trainer.fit(&Reconstruct(images), 120, 5);
+//!
+//! // the first layer of a classifier, without the decoder
+//! let encoder = trainer.graph.encoder();
+//! ```
+use std::{
+    any::Any,
+    io::{self, Read, Write},
+    marker::PhantomData,
+    sync::Arc,
+};
+
+#[cfg(feature = "hdf5")]
+use hdf5::H5Type;
+
+use ndarray::{
+    Array, Array1, Array2, ArrayBase, ArrayViewD, ArrayViewMutD, Axis, Data, Dim, DimMax,
+    Dimension, Ix1, LinalgScalar, RemoveAxis, ScalarOperand,
+};
+use num_traits::{Float, FromPrimitive, One, Zero};
+use rand::{distributions::Distribution, Rng};
+
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    activation::{Activation, Linear},
+    array::{check_input_shape, compact_front, dot_front, dot_inner, input_shape},
+    binary::{invalid, read_array, write_array, Element},
+    data::Dataset,
+    dense::DenseState,
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    error::Result,
+    initialisers::Initialiser,
+    named::{GetLayer, Named},
+    stats::{LayerStats, Stats, UnitActivity},
+    tensors::{ParamPath, Tensors},
+    train::{GraphExecTrain, Modal},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+
+/// A dense encoder and a decoder that shares its weights, transposed.
+///
+/// Tying the weights halves the number of parameters, and stops the encoder and
+/// decoder from learning to undo each other's scaling. The output is the same
+/// size as the input
+#[derive(Debug, Copy, Clone)]
+pub struct TiedDense<I, A> {
+    hidden_size: usize,
+    activation: A,
+    initialiser: I,
+}
+
+pub struct TiedDenseSize<I, A> {
+    hidden_size: usize,
+    activation: A,
+    initialiser: PhantomData<I>,
+}
+
+/// The output shape of an autoencoder, which is the same as its input shape.
+/// That isn't known until the graph is initialised, so an autoencoder
+/// has to be the last layer of a network
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Reconstruction;
+
+impl<I, A> TiedDense<I, A> {
+    /// Encodes its inputs into `hidden_size` values, applying `activation`
+    /// to them before decoding
+    pub const fn hidden_size(hidden_size: usize, activation: A) -> TiedDenseSize<I, A> {
+        TiedDenseSize {
+            hidden_size,
+            activation,
+            initialiser: PhantomData,
+        }
+    }
+
+    /// Applies an activation to the reconstructed output
+    pub const fn with_activation<L: Activation>(self, a: L) -> Linear<Self, L> {
+        Linear::new(self, a)
+    }
+
+    pub fn named(self, name: impl Into<Arc<str>>) -> Named<Self> {
+        Named::new(name, self)
+    }
+}
+
+impl<I, A> TiedDenseSize<I, A> {
+    pub fn with_initialiser(self, initialiser: I) -> TiedDense<I, A> {
+        TiedDense {
+            hidden_size: self.hidden_size,
+            activation: self.activation,
+            initialiser,
+        }
+    }
+}
+
+impl<I, A, F> Graph<F, usize> for TiedDense<I, A>
+where
+    I: Initialiser<F, (usize, usize)>,
+    F: Zero + Clone,
+{
+    type State = TiedDenseState<F, A>;
+    type OutputShape = Reconstruction;
+
+    fn get_output_shape(&self) -> Reconstruction {
+        Reconstruction
+    }
+
+    fn init_with_random(self, rng: &mut impl Rng, input_size: usize) -> Self::State {
+        let d = self
+            .initialiser
+            .into_distribution((input_size, self.hidden_size));
+
+        let w = Array2::from_shape_simple_fn((input_size, self.hidden_size), || d.sample(rng));
+        // both biases start at zero, like a dense layer's
+        let b = Array1::zeros(self.hidden_size);
+        let c = Array1::zeros(input_size);
+
+        TiedDenseState {
+            w,
+            b,
+            c,
+            activation: self.activation,
+        }
+    }
+
+    fn num_params(&self, input_size: usize) -> usize {
+        (input_size + 1) * self.hidden_size + input_size
+    }
+}
+
+impl<I, A> TiedDense<I, A> {
+    /// Checks that loaded weights and biases fit together and have this layer's hidden size
+    fn check_state<F>(&self, w: &Array2<F>, b: &Array1<F>, c: &Array1<F>) -> Result<(), String> {
+        if w.ncols() != self.hidden_size {
+            return Err(format!(
+                "tied dense layer has {} hidden units, but the saved weights have shape {:?}",
+                self.hidden_size,
+                w.shape()
+            ));
+        }
+        if b.len() != w.ncols() || c.len() != w.nrows() {
+            return Err(format!(
+                "tied dense layer weights have shape {:?}, but the saved biases have shapes {:?} and {:?}",
+                w.shape(),
+                b.shape(),
+                c.shape()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<F: Element + Zero, I, A: Clone> Persist<F, usize> for TiedDense<I, A>
+where
+    I: Initialiser<F, (usize, usize)>,
+{
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        write_array(w, &state.w)?;
+        write_array(w, &state.b)?;
+        write_array(w, &state.c)
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        let w = read_array(r)?;
+        let b = read_array(r)?;
+        let c = read_array(r)?;
+        self.check_state(&w, &b, &c).map_err(|msg| invalid(&msg))?;
+        Ok(TiedDenseState {
+            w,
+            b,
+            c,
+            activation: self.activation.clone(),
+        })
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<F: H5Type + Zero + Clone, I, A: Clone> HDF5<F, usize> for TiedDense<I, A>
+where
+    I: Initialiser<F, (usize, usize)>,
+{
+    fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()> {
+        group
+            .new_dataset_builder()
+            .with_data(state.w.view())
+            .create("weights")?;
+        group
+            .new_dataset_builder()
+            .with_data(state.b.view())
+            .create("bias")?;
+        group
+            .new_dataset_builder()
+            .with_data(state.c.view())
+            .create("decoder_bias")?;
+        Ok(())
+    }
+
+    fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State> {
+        let w = group.dataset("weights")?.read()?;
+        let b = group.dataset("bias")?.read()?;
+        let c = group.dataset("decoder_bias")?.read()?;
+        self.check_state(&w, &b, &c)?;
+
+        Ok(TiedDenseState {
+            w,
+            b,
+            c,
+            activation: self.activation.clone(),
+        })
+    }
+}
+
+/// The state of a [`TiedDense`] autoencoder
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TiedDenseState<F, A> {
+    /// The encoder weights, of shape `(inputs, hidden)`. The decoder uses their transpose
+    pub w: Array2<F>,
+    /// The encoder bias
+    pub b: Array1<F>,
+    /// The decoder bias
+    pub c: Array1<F>,
+    pub activation: A,
+}
+
+impl<F: Clone, A> TiedDenseState<F, A> {
+    /// A copy of the encoder as a dense layer, without the hidden activation
+    #[must_use]
+    pub fn encoder(&self) -> DenseState<F> {
+        DenseState {
+            w: self.w.clone(),
+            b: self.b.clone(),
+        }
+    }
+
+    /// A copy of the decoder as a dense layer, from the hidden values to the outputs
+    #[must_use]
+    pub fn decoder(&self) -> DenseState<F> {
+        DenseState {
+            w: self.w.t().to_owned(),
+            b: self.c.clone(),
+        }
+    }
+}
+
+impl<F, A, S, D> GraphExec<ArrayBase<S, D>> for TiedDenseState<F, A>
+where
+    F: LinalgScalar + Float,
+    A: GraphExec<Array<F, D>, Output = Array<F, D>>,
+    D: Dimension + DimMax<Ix1, Output = D>,
+    S: Data<Elem = F>,
+{
+    type Output = Array<F, D>;
+
+    fn exec(&self, input: ArrayBase<S, D>) -> Self::Output {
+        check_input_shape("TiedDense layer", input.shape(), self.w.nrows());
+        let hidden = self
+            .activation
+            .exec(dot_inner(&input, &self.w.view()) + &self.b);
+        dot_inner(&hidden, &self.w.t()) + &self.c
+    }
+
+    fn try_exec(&self, input: ArrayBase<S, D>) -> Result<Self::Output> {
+        input_shape("TiedDense layer", input.shape(), self.w.nrows())?;
+        Ok(self.exec(input))
+    }
+}
+
+impl<F, A, D> GraphExecTrain<Array<F, D>> for TiedDenseState<F, A>
+where
+    F: LinalgScalar + Float + FromPrimitive + ScalarOperand,
+    A: GraphExecTrain<Array<F, D>, Output = Array<F, D>> + Clone,
+    D: Dimension + DimMax<Ix1, Output = D> + RemoveAxis,
+{
+    /// The input, the activation's state and the hidden values
+    type State = (Array<F, D>, A::State, Array<F, D>);
+    fn forward(&self, input: Array<F, D>) -> (Self::State, Self::Output) {
+        let (activation, hidden) = self
+            .activation
+            .forward(dot_inner(&input, &self.w.view()) + &self.b);
+        let output = dot_inner(&hidden, &self.w.t()) + &self.c;
+        ((input, activation, hidden), output)
+    }
+
+    fn back(&self, state: Self::State, d_output: Self::Output) -> (Array<F, D>, Self) {
+        let mut grads = Self {
+            w: Array2::zeros(self.w.raw_dim()),
+            b: Array1::zeros(self.b.len()),
+            c: Array1::zeros(self.c.len()),
+            activation: self.activation.clone(),
+        };
+        let di = self.back_into(state, d_output, &mut grads);
+        (di, grads)
+    }
+
+    fn back_into(
+        &self,
+        (input, activation, hidden): Self::State,
+        d_output: Self::Output,
+        grads: &mut Self,
+    ) -> Array<F, D> {
+        // the decoder's share of the weight gradients, transposed back to the encoder's shape
+        let d_hidden = dot_inner(&d_output, &self.w.view());
+        let decoder = dot_front(&d_output, &hidden);
+        grads.c = compact_front(&d_output).sum_axis(Axis(0));
+
+        let (d_hidden, _) = self.activation.back(activation, d_hidden);
+        let di = dot_inner(&d_hidden, &self.w.t());
+        grads.b = compact_front(&d_hidden).sum_axis(Axis(0));
+        grads.w = dot_front(&input, &d_hidden) + decoder;
+        di
+    }
+}
+
+impl<F, A: Modal> Modal for TiedDenseState<F, A> {}
+
+impl<T, A: Clone> Mappable<T> for TiedDenseState<T, A> {
+    // not redundant. just forces a capture without needing to clone
+    #![allow(clippy::redundant_closure)]
+
+    fn map<F: FnMut(&T) -> T>(&self, mut f: F) -> Self {
+        Self {
+            w: self.w.map(|a| f(a)),
+            b: self.b.map(|a| f(a)),
+            c: self.c.map(f),
+            activation: self.activation.clone(),
+        }
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, mut f: F) {
+        self.w.map_mut(|a| f(a));
+        self.b.map_mut(|a| f(a));
+        self.c.map_mut(f);
+    }
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, mut f: F) {
+        self.w.zip_mut_with(&rhs.w, |a, b| f(a, b));
+        self.b.zip_mut_with(&rhs.b, |a, b| f(a, b));
+        self.c.zip_mut_with(&rhs.c, f);
+    }
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.w.zip_mut_with(&rhs.w, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, mut f: F)
+    where
+        T: Clone,
+    {
+        self.w.for_each(|a| f(a));
+        self.b.for_each(|a| f(a));
+        self.c.for_each(f);
+    }
+}
+
+impl<T, A> Shaped<T> for TiedDenseState<T, A>
+where
+    T: Clone + Zero + One,
+    A: Clone,
+{
+    type Shape = (Dim<[usize; 2]>, A);
+    fn shape(&self) -> Self::Shape {
+        (self.w.raw_dim(), self.activation.clone())
+    }
+    fn zero((shape, activation): Self::Shape) -> Self {
+        Self {
+            w: Array2::zeros(shape),
+            b: Array1::zeros(shape[1]),
+            c: Array1::zeros(shape[0]),
+            activation,
+        }
+    }
+    fn one((shape, activation): Self::Shape) -> Self {
+        Self {
+            w: Array2::ones(shape),
+            b: Array1::ones(shape[1]),
+            c: Array1::ones(shape[0]),
+            activation,
+        }
+    }
+    fn iter((shape, activation): Self::Shape, mut i: impl Iterator<Item = T>) -> Self {
+        Self {
+            w: Array2::from_shape_fn(shape, |_| i.next().unwrap()),
+            b: Array1::from_shape_fn(shape[1], |_| i.next().unwrap()),
+            c: Array1::from_shape_fn(shape[0], |_| i.next().unwrap()),
+            activation,
+        }
+    }
+}
+
+impl<F: Copy, A> DerivativeTesting<F> for TiedDenseState<F, A> {
+    fn len(&self) -> usize {
+        self.w.len() + self.b.len() + self.c.len()
+    }
+    fn get(&self, i: usize) -> F {
+        let (w, b) = (self.w.len(), self.b.len());
+        let cols = self.w.ncols();
+        if i < w {
+            self.w[(i / cols, i % cols)]
+        } else if i < w + b {
+            self.b[i - w]
+        } else {
+            self.c[i - w - b]
+        }
+    }
+    fn set(&mut self, i: usize, f: F) {
+        let (w, b) = (self.w.len(), self.b.len());
+        let cols = self.w.ncols();
+        if i < w {
+            self.w[(i / cols, i % cols)] = f;
+        } else if i < w + b {
+            self.b[i - w] = f;
+        } else {
+            self.c[i - w - b] = f;
+        }
+    }
+}
+
+/// Matches the names `PyTorch` would give an encoder `nn.Linear` followed by a decoder bias
+impl<F, A> Tensors<F> for TiedDenseState<F, A> {
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        path.push("weight");
+        f(path, self.w.t().into_dyn());
+        path.pop();
+        path.push("bias");
+        f(path, self.b.view().into_dyn());
+        path.pop();
+        path.push("decoder_bias");
+        f(path, self.c.view().into_dyn());
+        path.pop();
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        path.push("weight");
+        f(path, self.w.view_mut().reversed_axes().into_dyn());
+        path.pop();
+        path.push("bias");
+        f(path, self.b.view_mut().into_dyn());
+        path.pop();
+        path.push("decoder_bias");
+        f(path, self.c.view_mut().into_dyn());
+        path.pop();
+    }
+}
+
+impl<I, A> Dot for TiedDense<I, A> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        vec![dot.layer(&format!("TiedDense({})", self.hidden_size), inputs)]
+    }
+}
+
+impl<F, A> Dot for TiedDenseState<F, A> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let (i, h) = self.w.dim();
+        vec![dot.layer(&format!("TiedDense({i} → {h} → {i})"), inputs)]
+    }
+}
+
+impl<F, A> GetLayer for TiedDenseState<F, A> {
+    fn get_layer(&self, _name: &str) -> Option<&dyn Any> {
+        None
+    }
+    fn get_layer_mut(&mut self, _name: &str) -> Option<&mut dyn Any> {
+        None
+    }
+}
+
+impl<F: Float + FromPrimitive, A: Clone> LayerStats<F> for TiedDenseState<F, A> {
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        stats.push(Stats::of(self));
+    }
+    /// The units are the hidden units. The decoder bias is only counted towards the zeros
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        let mut layer = UnitActivity::new(self.b.len());
+        for ((_, unit), grad) in self.w.indexed_iter() {
+            layer.record(unit, grad);
+        }
+        for (unit, grad) in self.b.iter().enumerate() {
+            layer.record(unit, grad);
+        }
+        layer.count += self.c.len();
+        layer.zeros += self.c.iter().filter(|c| c.is_zero()).count();
+        activity.push(layer);
+    }
+}
+
+/// Uses the inputs of a data set as the targets too, to train an autoencoder
+/// to reconstruct them. The original targets are ignored
+#[derive(Debug, Clone)]
+pub struct Reconstruct<DS>(pub DS);
+
+impl<DS> Dataset for Reconstruct<DS>
+where
+    DS: Dataset,
+    DS::Input: Clone,
+{
+    type Input = DS::Input;
+    type Target = DS::Input;
+
+    fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target) {
+        let (input, _) = self.0.batch(indices);
+        (input.clone(), input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array2};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{Reconstruct, TiedDense, TiedDenseState};
+    use crate::{
+        activation::{relu::Relu, sigmoid::Sigmoid, Linear},
+        cost::mse::MSE,
+        data::{Dataset, InMemoryDataset},
+        derivative::check_grads,
+        initialisers::Xavier,
+        optimise::SGD,
+        precision::Cast,
+        train::Train,
+        Graph, GraphExec, Persist,
+    };
+
+    #[test]
+    fn test_tied_dense() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut layer = TiedDense::hidden_size(3, Sigmoid)
+            .with_initialiser(Xavier)
+            .with_activation(Sigmoid)
+            .init_with_random(&mut rng, 6);
+        assert_eq!(layer.graph.b, Array1::<f64>::zeros(3));
+        assert_eq!(layer.graph.c, Array1::<f64>::zeros(6));
+        // give the biases values so the comparisons below cover them
+        layer.graph.b = Array1::from_shape_simple_fn(3, || rng.gen());
+        layer.graph.c = Array1::from_shape_simple_fn(6, || rng.gen());
+
+        // the same as running the encoder and decoder as separate layers
+        let input = Array2::<f64>::from_shape_simple_fn((4, 6), || rng.gen());
+        let separate = (
+            Linear::new(layer.graph.encoder(), Sigmoid),
+            layer.graph.decoder(),
+        );
+        let error = (layer.graph.exec(input.view()) - separate.exec(input.view())).mapv(f64::abs);
+        assert!(error.iter().all(|&e| e < 1e-12));
+
+        let input = input.row(0).to_owned();
+        let expected = Array1::<f64>::from_shape_simple_fn(6, || rng.gen());
+        let error = check_grads(&mut layer, &MSE, 1e-6, &input, &expected);
+        assert!(error < 1e-5, "max relative error {}", error);
+    }
+
+    #[test]
+    fn test_reconstruct() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // every sample lies on a line, so two hidden units are enough to reconstruct them
+        let t = Array1::linspace(0.0, 1.0, 50);
+        let inputs =
+            Array2::from_shape_fn((50, 4), |(i, j)| [t[i], 1.0 - t[i], 0.5 * t[i], 0.25][j]);
+        let data = Reconstruct(InMemoryDataset::new(
+            inputs.clone(),
+            Array2::<f64>::zeros((50, 1)),
+        ));
+        let (input, target) = data.batch(&[3, 7]);
+        assert_eq!(input, target);
+
+        let graph = TiedDense::hidden_size(2, Relu)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 4);
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.01)).build();
+        trainer.fit(&data, 10, 200);
+        let error = (trainer.graph.exec(inputs.view()) - &inputs).mapv(f64::abs);
+        assert!(error.iter().all(|&e| e < 0.05), "{}", error);
+    }
+
+    #[test]
+    fn test_persist_and_cast() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = TiedDense::hidden_size(3, Relu).with_initialiser(Xavier);
+        let mut state: TiedDenseState<f64, Relu> = graph.init_with_random(&mut rng, 5);
+        state.c = Array1::from_shape_simple_fn(5, || rng.gen());
+
+        let mut bytes = vec![];
+        graph.write_state(&state, &mut bytes).unwrap();
+        let loaded = Persist::<f64, _>::read_state(&graph, &mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.w, state.w);
+        assert_eq!(loaded.c, state.c);
+
+        let other = TiedDense::hidden_size(4, Relu).with_initialiser(Xavier);
+        let err = Persist::<f64, _>::read_state(&other, &mut bytes.as_slice()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "tied dense layer has 4 hidden units, but the saved weights have shape [5, 3]"
+        );
+
+        let single: TiedDenseState<f32, Relu> = state.cast();
+        let mut restored = state.clone();
+        single.cast_into(&mut restored);
+        assert!((restored.c - &state.c).iter().all(|d| d.abs() < 1e-6));
+    }
+}
@@ -0,0 +1,216 @@
+//! Beam search decoding for step-wise sequence models.
+//!
+//! Instead of committing to one token at each step like [`sampling`](crate::sampling),
+//! beam search keeps the `width` most likely partial sequences, and extends each of them
+//! by every likely next token. This finds sequences that start with a less likely token
+//! but are more likely overall
+use std::cmp::Ordering;
+
+use ndarray::Array1;
+use num_traits::Float;
+
+/// A decoded sequence and its score
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hypothesis<F> {
+    /// The decoded tokens, including the end token if one was reached
+    pub tokens: Vec<usize>,
+    /// The log probability of the tokens, divided by the length penalty
+    pub score: F,
+}
+
+/// Beam search settings
+///
+/// ```
+/// use linear_networks::beam::BeamSearch;
+/// use ndarray::array;
+///
+/// // the sequences are ended by token 2
+/// let step = |_: &(), token: usize| {
+///     let probs = match token {
+///         0 => array![0.0, 0.6, 0.4],
+///         _ => array![0.0, 0.5, 0.5],
+///     };
+///     ((), probs)
+/// };
+/// let hypotheses = BeamSearch::new(2, 5).with_end(2).search((), 0, step);
+/// assert_eq!(hypotheses[0].tokens, [2]);
+/// assert_eq!(hypotheses[1].tokens, [1, 2]);
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct BeamSearch<F> {
+    /// How many sequences are kept at each step
+    pub width: usize,
+    /// The most tokens to decode
+    pub max_len: usize,
+    /// The token that ends a sequence
+    pub end: Option<usize>,
+    /// Scores are divided by `length ^ length_penalty`. Without a penalty, shorter
+    /// sequences are favoured since every extra token lowers the probability
+    pub length_penalty: F,
+}
+
+impl<F: Float> BeamSearch<F> {
+    /// A search without an end token or a length penalty
+    ///
+    /// # Panics
+    /// If `width` is zero
+    #[must_use]
+    pub fn new(width: usize, max_len: usize) -> Self {
+        assert!(width > 0, "beam search needs a width of at least one");
+        Self {
+            width,
+            max_len,
+            end: None,
+            length_penalty: F::zero(),
+        }
+    }
+
+    #[must_use]
+    pub const fn with_end(self, end: usize) -> Self {
+        Self {
+            end: Some(end),
+            ..self
+        }
+    }
+
+    #[must_use]
+    pub const fn with_length_penalty(self, length_penalty: F) -> Self {
+        Self {
+            length_penalty,
+            ..self
+        }
+    }
+
+    /// Decodes from `state`, starting with the `start` token. `step` is given a state
+    /// and the last token, and returns the next state along with the probabilities of
+    /// the next token. Returns up to `width` hypotheses, best first
+    pub fn search<S: Clone>(
+        &self,
+        state: S,
+        start: usize,
+        mut step: impl FnMut(&S, usize) -> (S, Array1<F>),
+    ) -> Vec<Hypothesis<F>> {
+        let mut beams = vec![Beam {
+            state,
+            tokens: vec![],
+            last: start,
+            log_prob: F::zero(),
+        }];
+        let mut finished = vec![];
+
+        for _ in 0..self.max_len {
+            let mut states = Vec::with_capacity(beams.len());
+            let mut candidates = vec![];
+            for (i, beam) in beams.iter().enumerate() {
+                let (state, probs) = step(&beam.state, beam.last);
+                states.push(state);
+                // only the best `width` tokens of any beam can make it into the next step
+                let mut tokens: Vec<_> = probs
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &p)| p > F::zero())
+                    .map(|(token, &p)| (i, token, beam.log_prob + p.ln()))
+                    .collect();
+                sort_by_score(&mut tokens);
+                tokens.truncate(self.width);
+                candidates.extend(tokens);
+            }
+            sort_by_score(&mut candidates);
+            candidates.truncate(self.width);
+
+            beams = candidates
+                .into_iter()
+                .filter_map(|(i, token, log_prob)| {
+                    let mut tokens = beams[i].tokens.clone();
+                    tokens.push(token);
+                    if self.end == Some(token) {
+                        finished.push(self.hypothesis(tokens, log_prob));
+                        None
+                    } else {
+                        Some(Beam {
+                            state: states[i].clone(),
+                            tokens,
+                            last: token,
+                            log_prob,
+                        })
+                    }
+                })
+                .collect();
+            if beams.is_empty() {
+                break;
+            }
+        }
+
+        finished.extend(
+            beams
+                .into_iter()
+                .map(|beam| self.hypothesis(beam.tokens, beam.log_prob)),
+        );
+        finished.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        finished.truncate(self.width);
+        finished
+    }
+
+    fn hypothesis(&self, tokens: Vec<usize>, log_prob: F) -> Hypothesis<F> {
+        let len = F::from(tokens.len().max(1)).unwrap();
+        Hypothesis {
+            score: log_prob / len.powf(self.length_penalty),
+            tokens,
+        }
+    }
+}
+
+struct Beam<S, F> {
+    state: S,
+    tokens: Vec<usize>,
+    last: usize,
+    log_prob: F,
+}
+
+/// Sorts `(beam, token, log_prob)` candidates, best first
+fn sort_by_score<F: Float>(candidates: &mut [(usize, usize, F)]) {
+    candidates.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal));
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::BeamSearch;
+
+    #[test]
+    fn test_beam_search() {
+        // 0 starts and 3 ends a sequence. [2, 3] is the most likely sequence,
+        // but a greedy search would pick 1 first
+        let step = |history: &Vec<usize>, token: usize| {
+            // finished sequences are never extended
+            assert!(!history.contains(&3));
+            let probs = match token {
+                0 => array![0.0, 0.6, 0.4, 0.0],
+                1 => array![0.0, 0.3, 0.3, 0.4],
+                _ => array![0.0, 0.05, 0.05, 0.9],
+            };
+            let mut history = history.clone();
+            history.push(token);
+            (history, probs)
+        };
+
+        let greedy = BeamSearch::new(1, 10).with_end(3).search(vec![], 0, step);
+        assert_eq!(greedy.len(), 1);
+        assert_eq!(greedy[0].tokens, [1, 3]);
+
+        let search = BeamSearch::new(2, 10).with_end(3);
+        let hypotheses = search.search(vec![], 0, step);
+        assert_eq!(hypotheses.len(), 2);
+        assert_eq!(hypotheses[0].tokens, [2, 3]);
+        assert!((hypotheses[0].score - 0.36_f64.ln()).abs() < 1e-12);
+        assert_eq!(hypotheses[1].tokens, [1, 3]);
+
+        // without an end token, decoding stops at the max length
+        let hypotheses = BeamSearch::new(3, 3).search(vec![], 0, step);
+        assert!(hypotheses.iter().all(|h| h.tokens.len() == 3));
+
+        let penalised = search.with_length_penalty(1.0).search(vec![], 0, step);
+        assert!((penalised[0].score - 0.36_f64.ln() / 2.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,189 @@
+//! A simple little-endian binary encoding for arrays.
+//!
+//! Each array is written as its element tag, the number of dimensions,
+//! each dimension as a `u64`, then every element in standard (row-major) order
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    ops::RangeInclusive,
+};
+
+use ndarray::{Array, ArrayBase, Data, Dimension, IxDyn};
+
+pub(crate) fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Float types that can be stored in the binary encoding
+pub trait Element: Copy {
+    /// Identifies the element type in the encoding
+    const TAG: u8;
+    /// The size of each element in bytes
+    const SIZE: usize;
+
+    fn to_le(self, bytes: &mut Vec<u8>);
+    fn from_le(bytes: &[u8]) -> Self;
+}
+
+impl Element for f32 {
+    const TAG: u8 = 4;
+    const SIZE: usize = 4;
+
+    fn to_le(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+    fn from_le(bytes: &[u8]) -> Self {
+        Self::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+}
+
+impl Element for f64 {
+    const TAG: u8 = 8;
+    const SIZE: usize = 8;
+
+    fn to_le(self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.to_le_bytes());
+    }
+    fn from_le(bytes: &[u8]) -> Self {
+        let mut le = [0; 8];
+        le.copy_from_slice(&bytes[..8]);
+        Self::from_le_bytes(le)
+    }
+}
+
+/// A 64 bit FNV-1a hash, used to detect corrupted files.
+/// Unlike `DefaultHasher`, it's guaranteed to be stable between releases
+#[must_use]
+pub fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Writes a file header: a 4 byte magic identifying the kind of file, then a version byte
+pub fn write_header(w: &mut impl Write, magic: &[u8; 4], version: u8) -> io::Result<()> {
+    w.write_all(magic)?;
+    w.write_all(&[version])
+}
+
+/// Reads and checks a header written by [`write_header`]
+pub fn read_header(r: &mut impl Read, magic: &[u8; 4], version: u8) -> io::Result<()> {
+    read_header_versions(r, magic, version..=version).map(drop)
+}
+
+/// Like [`read_header`], but accepts any of the given versions and returns the one found
+pub fn read_header_versions(
+    r: &mut impl Read,
+    magic: &[u8; 4],
+    versions: RangeInclusive<u8>,
+) -> io::Result<u8> {
+    let mut header = [0; 5];
+    r.read_exact(&mut header)?;
+    if &header[..4] != magic {
+        return Err(invalid("file has the wrong magic bytes"));
+    }
+    if !versions.contains(&header[4]) {
+        return Err(invalid("file has an unsupported version"));
+    }
+    Ok(header[4])
+}
+
+/// Reads exactly `len` bytes. The length usually comes from the file itself,
+/// so the bytes are read in chunks as they arrive rather than allocated up front
+pub(crate) fn read_bytes(r: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![];
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() == len {
+        Ok(bytes)
+    } else {
+        Err(io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+pub fn write_array<F, S, D>(w: &mut impl Write, a: &ArrayBase<S, D>) -> io::Result<()>
+where
+    F: Element,
+    S: Data<Elem = F>,
+    D: Dimension,
+{
+    let ndim = u8::try_from(a.ndim()).map_err(|_| invalid("too many dimensions"))?;
+    let mut bytes = Vec::with_capacity(2 + 8 * a.ndim() + F::SIZE * a.len());
+    bytes.push(F::TAG);
+    bytes.push(ndim);
+    for &dim in a.shape() {
+        bytes.extend_from_slice(&(dim as u64).to_le_bytes());
+    }
+    // iteration is always in logical (row-major) order
+    a.iter().for_each(|&x| x.to_le(&mut bytes));
+    w.write_all(&bytes)
+}
+
+pub fn read_array<F, D>(r: &mut impl Read) -> io::Result<Array<F, D>>
+where
+    F: Element,
+    D: Dimension,
+{
+    let mut header = [0; 2];
+    r.read_exact(&mut header)?;
+    let [tag, ndim] = header;
+    if tag != F::TAG {
+        return Err(invalid("array has a different element type"));
+    }
+
+    let mut shape = Vec::with_capacity(ndim as usize);
+    for _ in 0..ndim {
+        let mut dim = [0; 8];
+        r.read_exact(&mut dim)?;
+        let dim = usize::try_from(u64::from_le_bytes(dim))
+            .map_err(|_| invalid("array dimension is too large"))?;
+        shape.push(dim);
+    }
+
+    let len = shape
+        .iter()
+        .try_fold(F::SIZE, |len, &dim| len.checked_mul(dim))
+        .ok_or_else(|| invalid("array is too large"))?;
+    let bytes = read_bytes(r, len)?;
+    let elements = bytes.chunks_exact(F::SIZE).map(F::from_le).collect();
+
+    Array::from_shape_vec(IxDyn(&shape), elements)
+        .map_err(|_| invalid("array shape mismatch"))?
+        .into_dimensionality()
+        .map_err(|_| invalid("array has a different number of dimensions"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, Array2};
+
+    #[test]
+    fn test_round_trip() {
+        let a = array![[1.0_f32, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let mut bytes = vec![];
+        write_array(&mut bytes, &a.t()).unwrap();
+
+        let b: Array2<f32> = read_array(&mut bytes.as_slice()).unwrap();
+        assert_eq!(a.t(), b);
+
+        assert!(read_array::<f64, ndarray::Ix2>(&mut bytes.as_slice()).is_err());
+        assert!(read_array::<f32, ndarray::Ix1>(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_corrupt_shape() {
+        // a shape whose length overflows
+        let mut bytes = vec![f32::TAG, 2];
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        let err = read_array::<f32, ndarray::Ix2>(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // a huge shape, with only a few elements after it
+        let mut bytes = vec![f32::TAG, 1];
+        bytes.extend_from_slice(&(1_u64 << 40).to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        let err = read_array::<f32, ndarray::Ix1>(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
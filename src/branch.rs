@@ -0,0 +1,337 @@
+use std::{
+    any::Any,
+    io::{self, Read, Write},
+    ops::Add,
+};
+
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    binary::Element,
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    error::Result,
+    named::GetLayer,
+    stats::{LayerStats, Stats, UnitActivity},
+    tensors::{ParamPath, Tensors},
+    train::{GraphExecTrain, Modal, Mode},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+#[cfg(feature = "hdf5")]
+use hdf5::H5Type;
+use ndarray::{ArrayViewD, ArrayViewMutD};
+use rand::Rng;
+
+/// Feeds the same input into two graphs, producing both of their outputs as a tuple.
+/// Used to build networks with multiple output heads
+///
+/// ```
+/// use linear_networks::{branch::Branch, dense::Dense, initialisers::Xavier, net, Graph};
+///
+/// let network = net![
+///     Dense::output_size(16).with_initialiser(Xavier),
+///     Branch(
+///         Dense::output_size(10).with_initialiser(Xavier),
+///         Dense::output_size(1).with_initialiser(Xavier),
+///     )
+/// ];
+/// assert_eq!(Graph::<f32, usize>::get_output_shape(&network), (10, 1));
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Branch<G0, G1>(pub G0, pub G1);
+
+impl<I, G0, G1, F> Graph<F, I> for Branch<G0, G1>
+where
+    I: Clone,
+    G0: Graph<F, I>,
+    G1: Graph<F, I>,
+{
+    type State = Branch<G0::State, G1::State>;
+    type OutputShape = (G0::OutputShape, G1::OutputShape);
+
+    fn get_output_shape(&self) -> Self::OutputShape {
+        (self.0.get_output_shape(), self.1.get_output_shape())
+    }
+
+    fn init_with_random(self, rng: &mut impl Rng, input_shape: I) -> Self::State {
+        Branch(
+            self.0.init_with_random(rng, input_shape.clone()),
+            self.1.init_with_random(rng, input_shape),
+        )
+    }
+
+    fn num_params(&self, input_shape: I) -> usize {
+        self.0.num_params(input_shape.clone()) + self.1.num_params(input_shape)
+    }
+}
+
+impl<G0, G1, Input> GraphExec<Input> for Branch<G0, G1>
+where
+    Input: Clone,
+    G0: GraphExec<Input>,
+    G1: GraphExec<Input>,
+{
+    type Output = (G0::Output, G1::Output);
+    fn exec(&self, input: Input) -> Self::Output {
+        (self.0.exec(input.clone()), self.1.exec(input))
+    }
+    fn try_exec(&self, input: Input) -> Result<Self::Output> {
+        Ok((self.0.try_exec(input.clone())?, self.1.try_exec(input)?))
+    }
+}
+
+impl<G0, G1, Input> GraphExecTrain<Input> for Branch<G0, G1>
+where
+    Input: Clone + Add<Output = Input>,
+    G0: GraphExecTrain<Input>,
+    G1: GraphExecTrain<Input>,
+{
+    type State = (G0::State, G1::State);
+    fn forward(&self, input: Input) -> (Self::State, Self::Output) {
+        let (s0, o0) = self.0.forward(input.clone());
+        let (s1, o1) = self.1.forward(input);
+        ((s0, s1), (o0, o1))
+    }
+
+    fn back(&self, state: Self::State, d_output: Self::Output) -> (Input, Self) {
+        let (s0, s1) = state;
+        let (d0, d1) = d_output;
+        let (d_input0, g0) = self.0.back(s0, d0);
+        let (d_input1, g1) = self.1.back(s1, d1);
+        (d_input0 + d_input1, Self(g0, g1))
+    }
+
+    fn back_into(&self, state: Self::State, d_output: Self::Output, grads: &mut Self) -> Input {
+        let (s0, s1) = state;
+        let (d0, d1) = d_output;
+        let d_input0 = self.0.back_into(s0, d0, &mut grads.0);
+        let d_input1 = self.1.back_into(s1, d1, &mut grads.1);
+        d_input0 + d_input1
+    }
+}
+
+impl<G0: Modal, G1: Modal> Modal for Branch<G0, G1> {
+    fn set_mode(&mut self, mode: Mode) {
+        self.0.set_mode(mode);
+        self.1.set_mode(mode);
+    }
+}
+
+impl<S, T, U> Mappable<S> for Branch<T, U>
+where
+    T: Mappable<S>,
+    U: Mappable<S>,
+{
+    fn map<F: FnMut(&S) -> S>(&self, mut f: F) -> Self {
+        let t = self.0.map(|a| f(a));
+        let u = self.1.map(f);
+        Self(t, u)
+    }
+    fn map_mut<F: FnMut(&mut S)>(&mut self, mut f: F) {
+        self.0.map_mut(|a| f(a));
+        self.1.map_mut(f);
+    }
+    fn map_mut_with<F: FnMut(&mut S, &S)>(&mut self, rhs: &Self, mut f: F) {
+        self.0.map_mut_with(&rhs.0, |a, b| f(a, b));
+        self.1.map_mut_with(&rhs.1, f);
+    }
+    fn map_weights_mut_with<F: FnMut(&mut S, &S)>(&mut self, rhs: &Self, mut f: F) {
+        self.0.map_weights_mut_with(&rhs.0, |a, b| f(a, b));
+        self.1.map_weights_mut_with(&rhs.1, f);
+    }
+    fn for_each<F: FnMut(&S)>(&self, mut f: F)
+    where
+        S: Clone,
+    {
+        self.0.for_each(|a| f(a));
+        self.1.for_each(f);
+    }
+}
+
+impl<F, T, U> Shaped<F> for Branch<T, U>
+where
+    T: Shaped<F>,
+    U: Shaped<F>,
+{
+    type Shape = Branch<T::Shape, U::Shape>;
+    fn shape(&self) -> Self::Shape {
+        Branch(self.0.shape(), self.1.shape())
+    }
+    fn zero(shape: Self::Shape) -> Self {
+        Self(T::zero(shape.0), U::zero(shape.1))
+    }
+    fn one(shape: Self::Shape) -> Self {
+        Self(T::one(shape.0), U::one(shape.1))
+    }
+    fn iter(shape: Self::Shape, mut i: impl Iterator<Item = F>) -> Self {
+        Self(T::iter(shape.0, &mut i), U::iter(shape.1, &mut i))
+    }
+}
+
+impl<T: Dot, U: Dot> Dot for Branch<T, U> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let mut outputs = self.0.write_dot(dot, inputs);
+        outputs.extend(self.1.write_dot(dot, inputs));
+        outputs
+    }
+}
+
+impl<T: GetLayer, U: GetLayer> GetLayer for Branch<T, U> {
+    fn get_layer(&self, name: &str) -> Option<&dyn Any> {
+        self.0.get_layer(name).or_else(|| self.1.get_layer(name))
+    }
+    fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any> {
+        match self.0.get_layer_mut(name) {
+            Some(layer) => Some(layer),
+            None => self.1.get_layer_mut(name),
+        }
+    }
+}
+
+impl<F, T, U> LayerStats<F> for Branch<T, U>
+where
+    T: LayerStats<F>,
+    U: LayerStats<F>,
+{
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        self.0.push_stats(stats);
+        self.1.push_stats(stats);
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        self.0.push_activity(activity);
+        self.1.push_activity(activity);
+    }
+}
+
+impl<F, T, U> DerivativeTesting<F> for Branch<T, U>
+where
+    T: DerivativeTesting<F>,
+    U: DerivativeTesting<F>,
+{
+    fn len(&self) -> usize {
+        self.0.len() + self.1.len()
+    }
+    fn get(&self, i: usize) -> F {
+        let n = self.0.len();
+        if i < n {
+            self.0.get(i)
+        } else {
+            self.1.get(i - n)
+        }
+    }
+    fn set(&mut self, i: usize, f: F) {
+        let n = self.0.len();
+        if i < n {
+            self.0.set(i, f);
+        } else {
+            self.1.set(i - n, f);
+        }
+    }
+}
+
+impl<F, T, U> Tensors<F> for Branch<T, U>
+where
+    T: Tensors<F>,
+    U: Tensors<F>,
+{
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        path.push(0);
+        self.0.visit(path, f);
+        path.pop();
+        path.push(1);
+        self.1.visit(path, f);
+        path.pop();
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        path.push(0);
+        self.0.visit_mut(path, f);
+        path.pop();
+        path.push(1);
+        self.1.visit_mut(path, f);
+        path.pop();
+    }
+}
+
+impl<F: Element, I: Clone, T, U> Persist<F, I> for Branch<T, U>
+where
+    T: Persist<F, I>,
+    U: Persist<F, I>,
+{
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        self.0.write_state(&state.0, w)?;
+        self.1.write_state(&state.1, w)
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        Ok(Branch(self.0.read_state(r)?, self.1.read_state(r)?))
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<F: H5Type, I: Clone, T, U> HDF5<F, I> for Branch<T, U>
+where
+    T: HDF5<F, I>,
+    U: HDF5<F, I>,
+{
+    fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()> {
+        self.0.save(&state.0, &group.create_group("0")?)?;
+        self.1.save(&state.1, &group.create_group("1")?)?;
+        Ok(())
+    }
+
+    fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State> {
+        Ok(Branch(
+            self.0.load(&group.group("0")?)?,
+            self.1.load(&group.group("1")?)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array1;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Branch;
+    use crate::{
+        activation::sigmoid::Sigmoid,
+        cost::{mse::MSE, weighted::Weighted},
+        dense::Dense,
+        derivative::check_grads,
+        initialisers::Xavier,
+        net, Graph,
+    };
+
+    #[test]
+    fn test_branch_grads() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut network = net![
+            Dense::output_size(5)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid),
+            Branch(
+                Dense::output_size(3).with_initialiser(Xavier),
+                Dense::output_size(2)
+                    .with_initialiser(Xavier)
+                    .with_activation(Sigmoid),
+            )
+        ]
+        .init_with_random(&mut rng, 4);
+
+        let input = Array1::<f64>::from_shape_simple_fn(4, || rng.gen());
+        let expected = (
+            Array1::<f64>::from_shape_simple_fn(3, || rng.gen()),
+            Array1::<f64>::from_shape_simple_fn(2, || rng.gen()),
+        );
+        let cost = (MSE, Weighted::new(MSE, 0.5));
+
+        let error = check_grads(&mut network, &cost, 1e-6, &input, &expected);
+        assert!(error < 1e-5, "max relative error {}", error);
+    }
+}
@@ -0,0 +1,475 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    convert::TryFrom,
+    fmt::Display,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    rc::Rc,
+    sync::mpsc,
+    time::Duration,
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// Progress events emitted while training
+#[derive(Debug, Copy, Clone)]
+pub enum TrainEvent<F> {
+    /// A batch has finished training
+    BatchEnd {
+        epoch: usize,
+        /// The index of the batch within the epoch
+        batch: usize,
+        /// The total number of batches in the epoch
+        batches: usize,
+        cost: F,
+        /// The number of samples in the batch
+        samples: usize,
+        /// How long the training step took
+        duration: Duration,
+        /// How long training waited for the batch to be gathered. If this is a large
+        /// part of each batch, loading the data is the bottleneck
+        waited: Duration,
+    },
+    /// An epoch has finished training
+    EpochEnd {
+        epoch: usize,
+        /// The average cost of every batch in the epoch
+        cost: F,
+        /// The number of samples trained on
+        samples: usize,
+        /// The optimiser's learning rate at the end of the epoch, if the trainer was built with
+        /// [`log_learning_rate`](crate::train::TrainBuilder::log_learning_rate)
+        learning_rate: Option<F>,
+        /// How long the whole epoch took, including gathering batches and callbacks
+        duration: Duration,
+    },
+}
+
+impl<F> TrainEvent<F> {
+    /// The samples trained on per second of the event's duration.
+    /// Always zero on wasm32, where no time is measured
+    #[must_use]
+    pub fn samples_per_second(&self) -> f64 {
+        let (samples, duration) = match *self {
+            Self::BatchEnd {
+                samples, duration, ..
+            }
+            | Self::EpochEnd {
+                samples, duration, ..
+            } => (samples, duration),
+        };
+        let seconds = duration.as_secs_f64();
+        if seconds > 0.0 {
+            f64::from(u32::try_from(samples).unwrap_or(u32::MAX)) / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Measures how long training takes. [`Instant`] panics on wasm32, so no time
+/// is measured there
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Timer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: Instant,
+}
+
+impl Timer {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        Duration::ZERO
+    }
+}
+
+/// Receives progress events from a [`Train`](crate::train::Train)
+pub trait Callback<F> {
+    fn on_event(&mut self, event: &TrainEvent<F>);
+}
+
+/// Forwards every event down the channel. Events are dropped if the receiver has hung up
+impl<F: Clone> Callback<F> for mpsc::Sender<TrainEvent<F>> {
+    fn on_event(&mut self, event: &TrainEvent<F>) {
+        let _ = self.send(event.clone());
+    }
+}
+
+impl<F, T> Callback<F> for T
+where
+    T: FnMut(&TrainEvent<F>),
+{
+    fn on_event(&mut self, event: &TrainEvent<F>) {
+        self(event);
+    }
+}
+
+/// A value computed from the epoch number, written as an extra column by [`CsvLogger`]
+type Column<F> = (String, Box<dyn FnMut(usize) -> F>);
+
+/// Appends a row to a CSV file at the end of every epoch, so runs can be compared
+/// and plotted afterwards.
+///
+/// Each row has the epoch, its average cost, the learning rate if the trainer was built with
+/// [`log_learning_rate`](crate::train::TrainBuilder::log_learning_rate), any extra columns,
+/// the seconds the epoch took and the samples trained on per second.
+///
+/// Rows are flushed as they're written. So that logging never interrupts training, nothing
+/// more is written once a write fails, and the error is kept for
+/// [`take_error`](Self::take_error). Clones share the same file, so keep a clone to check
+/// it once training is done
+///
+/// ```no_run
+/// use linear_networks::{
+///     callback::CsvLogger, dense::Dense, initialisers::Xavier, optimise::sgd::SGD,
+///     train::Train, Graph,
+/// };
+///
+/// let graph = Graph::<f64, _>::input_shape(Dense::output_size(2).with_initialiser(Xavier), 4);
+/// let logger = CsvLogger::create("training.csv").unwrap();
+/// let trainer = Train::builder(graph)
+///     .optimiser(SGD::new(0.01))
+///     .log_learning_rate()
+///     .callback(logger.clone())
+///     .build();
+/// // after training
+/// if let Some(err) = logger.take_error() {
+///     eprintln!("the training log is incomplete: {err}");
+/// }
+/// ```
+pub struct CsvLogger<F> {
+    inner: Rc<RefCell<CsvFile<F>>>,
+}
+
+struct CsvFile<F> {
+    file: BufWriter<File>,
+    columns: Vec<Column<F>>,
+    /// The header is written before the first row, once all the columns are known
+    header: bool,
+    /// Whether the rows have a learning rate, decided by the first row
+    learning_rate: Option<bool>,
+    /// The first write that failed, until it's taken
+    error: Option<io::Error>,
+    /// Whether a write has failed. Unlike `error`, this stays set once the error is taken,
+    /// so the log never restarts part way through
+    failed: bool,
+}
+
+impl<F> Clone for CsvLogger<F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<F> CsvLogger<F> {
+    /// Opens the file for appending. A header row is written first if the file is empty
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let header = file.metadata()?.len() == 0;
+        let file = CsvFile {
+            file: BufWriter::new(file),
+            columns: vec![],
+            header,
+            learning_rate: None,
+            error: None,
+            failed: false,
+        };
+        Ok(Self {
+            inner: Rc::new(RefCell::new(file)),
+        })
+    }
+
+    /// Adds a column, computed from the epoch number at the end of each epoch.
+    /// For example a metric recorded elsewhere
+    #[must_use]
+    pub fn column(self, name: impl Into<String>, value: impl FnMut(usize) -> F + 'static) -> Self {
+        let column: Column<F> = (name.into(), Box::new(value));
+        self.inner.borrow_mut().columns.push(column);
+        self
+    }
+
+    /// Takes the error that stopped the log from being written, if there was one.
+    /// Nothing more is written afterwards either way
+    #[must_use]
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.inner.borrow_mut().error.take()
+    }
+}
+
+impl<F: Display> CsvFile<F> {
+    fn write_row(&mut self, event: &TrainEvent<F>) -> io::Result<()> {
+        let (epoch, cost, learning_rate, duration) = match event {
+            TrainEvent::EpochEnd {
+                epoch,
+                cost,
+                learning_rate,
+                duration,
+                ..
+            } => (*epoch, cost, learning_rate, duration),
+            TrainEvent::BatchEnd { .. } => return Ok(()),
+        };
+        let has_learning_rate = *self
+            .learning_rate
+            .get_or_insert_with(|| learning_rate.is_some());
+        if self.header {
+            write!(self.file, "epoch,cost")?;
+            if has_learning_rate {
+                write!(self.file, ",learning_rate")?;
+            }
+            for (name, _) in &self.columns {
+                write!(self.file, ",{}", escape(name))?;
+            }
+            writeln!(self.file, ",seconds,samples_per_second")?;
+            self.header = false;
+        }
+
+        write!(self.file, "{epoch},{cost}")?;
+        if has_learning_rate {
+            match learning_rate {
+                Some(learning_rate) => write!(self.file, ",{learning_rate}")?,
+                None => write!(self.file, ",")?,
+            }
+        }
+        for (_, value) in &mut self.columns {
+            write!(self.file, ",{}", value(epoch))?;
+        }
+        let seconds = duration.as_secs_f64();
+        writeln!(self.file, ",{seconds},{}", event.samples_per_second())?;
+        self.file.flush()
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or newline
+fn escape(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+impl<F: Display> Callback<F> for CsvLogger<F> {
+    fn on_event(&mut self, event: &TrainEvent<F>) {
+        let mut file = self.inner.borrow_mut();
+        if !file.failed {
+            if let Err(err) = file.write_row(event) {
+                file.error = Some(err);
+                file.failed = true;
+            }
+        }
+    }
+}
+
+/// Draws a progress bar in the terminal for each epoch, with the batches done,
+/// the running average cost and an estimate of the time left.
+///
+/// The bar for each finished epoch is left in place, showing the epoch's cost
+///
+/// ```no_run
+/// use linear_networks::{callback::Progress, dense::Dense, initialisers::Xavier, train::Train, Graph};
+///
+/// let graph = Graph::<f64, _>::input_shape(Dense::output_size(2).with_initialiser(Xavier), 4);
+/// let trainer: Train<f64, _, _, _> = Train::builder(graph).callback(Progress::new()).build();
+/// ```
+#[cfg(feature = "progress")]
+pub struct Progress {
+    bar: Option<indicatif::ProgressBar>,
+    draw_target: fn() -> indicatif::ProgressDrawTarget,
+    epoch: usize,
+    /// The sum of the batch costs so far this epoch
+    cost: f64,
+}
+
+#[cfg(feature = "progress")]
+impl Progress {
+    /// Draws the progress bars to stderr
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            bar: None,
+            draw_target: indicatif::ProgressDrawTarget::stderr,
+            epoch: 0,
+            cost: 0.0,
+        }
+    }
+
+    /// The bar for the epoch, starting a new one if the epoch has changed
+    #[allow(clippy::literal_string_with_formatting_args)] // indicatif's template syntax
+    fn bar(&mut self, epoch: usize, batches: usize) -> &indicatif::ProgressBar {
+        if self.bar.is_none() || self.epoch != epoch {
+            let len = u64::try_from(batches).unwrap_or(u64::MAX);
+            let bar = indicatif::ProgressBar::with_draw_target(len, (self.draw_target)());
+            bar.set_style(
+                indicatif::ProgressStyle::default_bar()
+                    .template("epoch {prefix:>3} [{bar:40}] {pos}/{len} cost {msg} eta {eta}")
+                    .progress_chars("=> "),
+            );
+            bar.set_prefix(epoch.to_string());
+            self.bar = Some(bar);
+            self.epoch = epoch;
+            self.cost = 0.0;
+        }
+        self.bar.as_ref().unwrap()
+    }
+}
+
+#[cfg(feature = "progress")]
+impl Default for Progress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "progress")]
+impl<F: num_traits::Float> Callback<F> for Progress {
+    fn on_event(&mut self, event: &TrainEvent<F>) {
+        match *event {
+            TrainEvent::BatchEnd {
+                epoch,
+                batch,
+                batches,
+                cost,
+                ..
+            } => {
+                self.bar(epoch, batches);
+                self.cost += cost.to_f64().unwrap_or(f64::NAN);
+                let running = self.cost / f64::from(u32::try_from(batch + 1).unwrap_or(u32::MAX));
+                let bar = self.bar.as_ref().unwrap();
+                bar.set_message(format!("{running:.4}"));
+                bar.inc(1);
+            }
+            TrainEvent::EpochEnd { epoch, cost, .. } => {
+                let cost = cost.to_f64().unwrap_or(f64::NAN);
+                self.bar(epoch, 0).finish_with_message(format!("{cost:.4}"));
+                self.bar = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Callback, CsvLogger, TrainEvent};
+
+    #[test]
+    fn test_csv_logger() {
+        let path = crate::temp_path("log.csv");
+        let _ = std::fs::remove_file(&path);
+
+        for epoch in 0..2 {
+            let mut logger = CsvLogger::create(&path)
+                .unwrap()
+                .column("accuracy, %", |epoch| [50.0, 75.0][epoch]);
+            logger.on_event(&TrainEvent::BatchEnd {
+                epoch,
+                batch: 0,
+                batches: 1,
+                cost: 1.0,
+                samples: 10,
+                duration: Duration::from_millis(400),
+                waited: Duration::ZERO,
+            });
+            logger.on_event(&TrainEvent::EpochEnd {
+                epoch,
+                cost: 0.25,
+                samples: 10,
+                learning_rate: Some([0.5, 0.25][epoch]),
+                duration: Duration::from_millis(500),
+            });
+            assert!(logger.take_error().is_none());
+        }
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let rows: Vec<&str> = log.lines().collect();
+        assert_eq!(
+            rows,
+            [
+                "epoch,cost,learning_rate,\"accuracy, %\",seconds,samples_per_second",
+                "0,0.25,0.5,50,0.5,20",
+                "1,0.25,0.25,75,0.5,20",
+            ]
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_csv_logger_error() {
+        // every write to /dev/full fails
+        let mut logger = CsvLogger::create("/dev/full").unwrap();
+        let handle = logger.clone();
+        for epoch in 0..2 {
+            logger.on_event(&TrainEvent::EpochEnd {
+                epoch,
+                cost: 0.25,
+                samples: 10,
+                learning_rate: None,
+                duration: Duration::from_millis(500),
+            });
+        }
+        let err = handle.take_error().unwrap();
+        assert_eq!(err.raw_os_error(), Some(28));
+        // only the first error is kept
+        assert!(handle.take_error().is_none());
+
+        // and nothing more is written once the error is taken, which would fail again
+        logger.on_event(&TrainEvent::EpochEnd {
+            epoch: 2,
+            cost: 0.25,
+            samples: 10,
+            learning_rate: None,
+            duration: Duration::from_millis(500),
+        });
+        assert!(handle.take_error().is_none());
+    }
+
+    #[cfg(feature = "progress")]
+    #[test]
+    fn test_progress() {
+        use super::Progress;
+
+        let mut progress = Progress {
+            draw_target: indicatif::ProgressDrawTarget::hidden,
+            ..Progress::new()
+        };
+        for batch in 0..2 {
+            progress.on_event(&TrainEvent::BatchEnd {
+                epoch: 3,
+                batch,
+                batches: 4,
+                cost: [1.0, 2.0][batch],
+                samples: 10,
+                duration: Duration::ZERO,
+                waited: Duration::ZERO,
+            });
+        }
+        let bar = progress.bar.clone().unwrap();
+        assert_eq!((bar.position(), bar.length()), (2, 4));
+        assert!((progress.cost - 3.0).abs() < 1e-12);
+
+        progress.on_event(&TrainEvent::EpochEnd {
+            epoch: 3,
+            cost: 1.5,
+            samples: 20,
+            learning_rate: None,
+            duration: Duration::ZERO,
+        });
+        assert!(bar.is_finished());
+        assert!(progress.bar.is_none());
+    }
+}
@@ -0,0 +1,270 @@
+//! Gradient checkpointing, trading compute for memory while training.
+//!
+//! Training keeps the state of every layer's forward pass until [`back`](GraphExecTrain::back)
+//! runs, which for deep networks and large batches can take more memory than the parameters.
+//! A [`Checkpointed`] graph only keeps its input, and runs its forward pass again during
+//! `back` to recover the rest. Only one checkpointed segment's intermediate states are
+//! held at a time, at the cost of running each segment's forward pass twice
+use std::{
+    any::Any,
+    io::{self, Read, Write},
+};
+
+use ndarray::{ArrayViewD, ArrayViewMutD};
+use rand::Rng;
+
+use crate::{
+    binary::Element,
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    error::Result,
+    named::GetLayer,
+    stats::{LayerStats, Stats, UnitActivity},
+    tensors::{ParamPath, Tensors},
+    train::{GraphExecTrain, Modal, Mode},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+
+/// A graph that recomputes its forward pass when training, instead of storing it.
+/// Used as both the builder and the state
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, checkpoint::Checkpointed, dense::Dense, initialisers::Xavier,
+///     net, Graph,
+/// };
+///
+/// let block = || {
+///     net![
+///         Dense::output_size(64)
+///             .with_initialiser(Xavier)
+///             .with_activation(Relu),
+///         Dense::output_size(64)
+///             .with_initialiser(Xavier)
+///             .with_activation(Relu)
+///     ]
+/// };
+/// // only the inputs of each block are kept between the forward and backward passes
+/// let network = net![
+///     Checkpointed::new(block()),
+///     Checkpointed::new(block()),
+///     Dense::output_size(10).with_initialiser(Xavier)
+/// ];
+/// let state = Graph::<f32, _>::input_shape(network, 784);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Checkpointed<G> {
+    pub graph: G,
+}
+
+impl<G> Checkpointed<G> {
+    pub const fn new(graph: G) -> Self {
+        Self { graph }
+    }
+}
+
+impl<I, G, F> Graph<F, I> for Checkpointed<G>
+where
+    G: Graph<F, I>,
+{
+    type State = Checkpointed<G::State>;
+    type OutputShape = G::OutputShape;
+
+    fn get_output_shape(&self) -> Self::OutputShape {
+        self.graph.get_output_shape()
+    }
+
+    fn init_with_random(self, rng: &mut impl Rng, input_shape: I) -> Self::State {
+        Checkpointed::new(self.graph.init_with_random(rng, input_shape))
+    }
+
+    fn num_params(&self, input_shape: I) -> usize {
+        self.graph.num_params(input_shape)
+    }
+}
+
+impl<G, Input> GraphExec<Input> for Checkpointed<G>
+where
+    G: GraphExec<Input>,
+{
+    type Output = G::Output;
+    fn exec(&self, input: Input) -> Self::Output {
+        self.graph.exec(input)
+    }
+    fn try_exec(&self, input: Input) -> Result<Self::Output> {
+        self.graph.try_exec(input)
+    }
+}
+
+/// The training state is just the input. The inner graph's state is dropped as soon as
+/// the forward pass is done
+impl<G, Input> GraphExecTrain<Input> for Checkpointed<G>
+where
+    G: GraphExecTrain<Input>,
+    Input: Clone,
+{
+    type State = Input;
+    fn forward(&self, input: Input) -> (Self::State, Self::Output) {
+        let (_, output) = self.graph.forward(input.clone());
+        (input, output)
+    }
+
+    fn back(&self, state: Self::State, d_output: Self::Output) -> (Input, Self) {
+        let (state, _) = self.graph.forward(state);
+        let (d_input, grads) = self.graph.back(state, d_output);
+        (d_input, Self::new(grads))
+    }
+
+    fn back_into(&self, state: Self::State, d_output: Self::Output, grads: &mut Self) -> Input {
+        let (state, _) = self.graph.forward(state);
+        self.graph.back_into(state, d_output, &mut grads.graph)
+    }
+}
+
+impl<G: Modal> Modal for Checkpointed<G> {
+    fn set_mode(&mut self, mode: Mode) {
+        self.graph.set_mode(mode);
+    }
+}
+
+impl<T, G: Mappable<T>> Mappable<T> for Checkpointed<G> {
+    fn map<F: FnMut(&T) -> T>(&self, f: F) -> Self {
+        Self::new(self.graph.map(f))
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, f: F) {
+        self.graph.map_mut(f);
+    }
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.graph.map_mut_with(&rhs.graph, f);
+    }
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.graph.map_weights_mut_with(&rhs.graph, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, f: F)
+    where
+        T: Clone,
+    {
+        self.graph.for_each(f);
+    }
+}
+
+impl<F, G: Shaped<F>> Shaped<F> for Checkpointed<G> {
+    type Shape = G::Shape;
+    fn shape(&self) -> Self::Shape {
+        self.graph.shape()
+    }
+    fn zero(shape: Self::Shape) -> Self {
+        Self::new(G::zero(shape))
+    }
+    fn one(shape: Self::Shape) -> Self {
+        Self::new(G::one(shape))
+    }
+    fn iter(shape: Self::Shape, i: impl Iterator<Item = F>) -> Self {
+        Self::new(G::iter(shape, i))
+    }
+}
+
+impl<F, G: LayerStats<F>> LayerStats<F> for Checkpointed<G> {
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        self.graph.push_stats(stats);
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        self.graph.push_activity(activity);
+    }
+}
+
+impl<F, G: DerivativeTesting<F>> DerivativeTesting<F> for Checkpointed<G> {
+    fn len(&self) -> usize {
+        self.graph.len()
+    }
+    fn get(&self, i: usize) -> F {
+        self.graph.get(i)
+    }
+    fn set(&mut self, i: usize, f: F) {
+        self.graph.set(i, f);
+    }
+}
+
+impl<F, G: Tensors<F>> Tensors<F> for Checkpointed<G> {
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        self.graph.visit(path, f);
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        self.graph.visit_mut(path, f);
+    }
+}
+
+impl<G: GetLayer> GetLayer for Checkpointed<G> {
+    fn get_layer(&self, name: &str) -> Option<&dyn Any> {
+        self.graph.get_layer(name)
+    }
+    fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any> {
+        self.graph.get_layer_mut(name)
+    }
+}
+
+impl<G: Dot> Dot for Checkpointed<G> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        self.graph.write_dot(dot, inputs)
+    }
+}
+
+impl<F: Element, I, G: Persist<F, I>> Persist<F, I> for Checkpointed<G> {
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        self.graph.write_state(&state.graph, w)
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        Ok(Checkpointed::new(self.graph.read_state(r)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Checkpointed;
+    use crate::{
+        activation::relu::Relu, cost::mse::MSE, dense::Dense, initialisers::Xavier, net,
+        train::GraphExecTrain, Graph, GraphExec, Mappable,
+    };
+
+    #[test]
+    fn test_checkpointed() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = net![
+            Dense::output_size(5)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ]
+        .init_with_random(&mut rng, 3);
+        let checkpointed = Checkpointed::new(network.clone());
+
+        let input = Array2::<f64>::from_shape_simple_fn((4, 3), || rng.gen_range(-1.0..1.0));
+        let expected = Array2::from_shape_simple_fn((4, 2), || rng.gen_range(-1.0..1.0));
+        assert_eq!(
+            checkpointed.exec(input.clone()),
+            network.exec(input.clone())
+        );
+
+        // the only thing kept from the forward pass is the input
+        let (state, _) = checkpointed.forward(input.clone());
+        assert_eq!(state, input);
+
+        let (grads, cost) = network.get_grads(input.clone(), expected.clone(), &MSE);
+        let (checkpointed_grads, checkpointed_cost) = checkpointed.get_grads(input, expected, &MSE);
+        assert!((cost - checkpointed_cost).abs() < 1e-12);
+        let (mut x, mut y) = (vec![], vec![]);
+        grads.for_each(|&g| x.push(g));
+        checkpointed_grads.for_each(|&g| y.push(g));
+        assert_eq!(x, y);
+    }
+}
@@ -0,0 +1,176 @@
+//! Helpers for reading the outputs of classifiers.
+//!
+//! A classifier's outputs have one row per sample and one column per class
+use ndarray::{Array1, Array2};
+use num_traits::Float;
+
+use crate::{array::argmax, data::layout::Layout, GraphExec};
+
+/// Runs the graph and returns the index of the most likely class for each sample
+///
+/// ```
+/// use linear_networks::{classify::predict_classes, dense::DenseState};
+/// use ndarray::array;
+///
+/// let graph = DenseState {
+///     w: array![[1.0, -1.0]],
+///     b: array![0.0, 0.0],
+/// };
+/// let classes = predict_classes(&graph, array![[2.0], [-3.0]]);
+/// assert_eq!(classes, array![0, 1]);
+/// ```
+pub fn predict_classes<G, I, F>(graph: &G, input: I) -> Array1<usize>
+where
+    G: GraphExec<I, Output = Array2<F>>,
+    F: Float,
+{
+    classes(&graph.exec(input))
+}
+
+/// Runs the graph and returns the probability of each class for each sample.
+/// Softmax is applied to the outputs, unless every row already sums to one
+pub fn predict_proba<G, I, F>(graph: &G, input: I) -> Array2<F>
+where
+    G: GraphExec<I, Output = Array2<F>>,
+    F: Float,
+{
+    let output = graph.exec(input);
+    if is_probabilities(&output) {
+        output
+    } else {
+        softmax(output)
+    }
+}
+
+/// The index of the largest value in each row
+#[must_use]
+pub fn classes<F: Float>(output: &Array2<F>) -> Array1<usize> {
+    output.outer_iter().map(|row| argmax(&row)).collect()
+}
+
+/// The indices and values of the `k` largest outputs of each sample, largest first.
+/// Both arrays have shape `[samples, k]`
+///
+/// ```
+/// use linear_networks::classify::top_k;
+/// use ndarray::array;
+///
+/// let output = array![[0.1, 0.5, 0.2, 0.7], [0.9, 0.3, 0.4, 0.0]];
+/// let (classes, scores) = top_k(&output, 2);
+/// assert_eq!(classes, array![[3, 1], [0, 2]]);
+/// assert_eq!(scores, array![[0.7, 0.5], [0.9, 0.4]]);
+/// ```
+///
+/// # Panics
+/// If `k` is larger than the number of classes
+#[must_use]
+pub fn top_k<F: Float>(output: &Array2<F>, k: usize) -> (Array2<usize>, Array2<F>) {
+    let (samples, n) = output.dim();
+    assert!(k <= n, "cannot take the top {} of {} classes", k, n);
+
+    let mut classes = Array2::zeros((samples, k));
+    let mut scores = Array2::zeros((samples, k));
+    let mut order: Vec<usize> = (0..n).collect();
+    for (i, row) in output.outer_iter().enumerate() {
+        // NaNs are sorted last, so they're only picked if there's nothing else
+        let by_score = |&a: &usize, &b: &usize| {
+            let (a, b) = (row[a], row[b]);
+            b.partial_cmp(&a)
+                .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+        };
+        if k < n {
+            order.select_nth_unstable_by(k, by_score);
+        }
+        order[..k].sort_by(by_score);
+        for (j, &class) in order[..k].iter().enumerate() {
+            classes[(i, j)] = class;
+            scores[(i, j)] = row[class];
+        }
+    }
+    (classes, scores)
+}
+
+/// Normalises each row with the softmax function, so that it sums to one
+#[must_use]
+pub fn softmax<F: Float>(output: Array2<F>) -> Array2<F> {
+    softmax_with_layout(output, Layout::SamplesFirst)
+}
+
+/// Normalises each sample with the softmax function, where the samples are laid out
+/// according to `layout`. With [`Layout::SamplesLast`], each column is normalised
+#[must_use]
+pub fn softmax_with_layout<F: Float>(mut output: Array2<F>, layout: Layout) -> Array2<F> {
+    for mut row in output.axis_iter_mut(layout.sample_axis(2)) {
+        let max = row.fold(F::neg_infinity(), |a, &b| a.max(b));
+        row.mapv_inplace(|x| (x - max).exp());
+        let sum = row.sum();
+        row.mapv_inplace(|x| x / sum);
+    }
+    output
+}
+
+fn is_probabilities<F: Float>(output: &Array2<F>) -> bool {
+    let tolerance = F::epsilon().sqrt();
+    output.iter().all(|&x| x >= F::zero() && x <= F::one())
+        && output
+            .outer_iter()
+            .all(|row| (row.sum() - F::one()).abs() < tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::{predict_classes, predict_proba, softmax, softmax_with_layout, top_k};
+    use crate::{data::layout::Layout, dense::DenseState};
+
+    #[test]
+    fn test_predict() {
+        let graph = DenseState {
+            w: array![[1.0, 0.0, -1.0]],
+            b: array![0.0, 0.5, 0.0],
+        };
+        let input = array![[1.0], [0.0], [-1.0]];
+        assert_eq!(predict_classes(&graph, input.clone()), array![0, 1, 2]);
+
+        let proba = predict_proba(&graph, input);
+        let e = std::f64::consts::E;
+        let expected = array![1.0, (0.5 - 1.0_f64).exp(), 1.0 / (e * e)];
+        let expected = &expected / expected.sum();
+        assert!(proba
+            .row(0)
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| (a - b).abs() < 1e-12));
+        assert!(proba
+            .rows()
+            .into_iter()
+            .all(|r| (r.sum() - 1.0).abs() < 1e-12));
+
+        // outputs that are already probabilities are left alone
+        let graph = DenseState {
+            w: array![[0.0, 0.0]],
+            b: array![0.25, 0.75],
+        };
+        assert_eq!(predict_proba(&graph, array![[1.0]]), array![[0.25, 0.75]]);
+    }
+
+    #[test]
+    fn test_softmax_columns() {
+        let rows = array![[1.0_f64, 2.0, 3.0], [0.0, 0.0, 0.0]];
+        let columns = softmax_with_layout(rows.t().to_owned(), Layout::SamplesLast);
+        assert_eq!(columns.t(), softmax(rows));
+    }
+
+    #[test]
+    fn test_top_k() {
+        let output = array![[0.3, f64::NAN, 0.1, 0.6], [0.5, 0.2, 0.8, 0.1]];
+        let (classes, scores) = top_k(&output, 3);
+        assert_eq!(classes, array![[3, 0, 2], [2, 0, 1]]);
+        assert_eq!(scores, array![[0.6, 0.3, 0.1], [0.8, 0.5, 0.2]]);
+
+        let (classes, _) = top_k(&output, 4);
+        assert_eq!(classes.row(0), array![3, 0, 2, 1]);
+        assert_eq!(top_k(&output, 0).0.dim(), (2, 0));
+    }
+}
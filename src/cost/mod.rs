@@ -1,7 +1,37 @@
+use std::ops::Add;
+
 pub mod mse;
+pub mod reduction;
+pub mod weighted;
+
+pub use self::{
+    mse::MSE,
+    reduction::{Reduced, Reduction},
+    weighted::{ClassWeighted, Weighted},
+};
 
 pub trait Cost<T> {
     type Inner;
     fn cost(&self, output: &T, expected: &T) -> Self::Inner;
     fn diff(&self, output: &T, expected: &T) -> T;
 }
+
+/// Costs for graphs with multiple outputs. Each output has its own cost function
+/// and the total cost is the sum of them
+impl<T0, T1, C0, C1> Cost<(T0, T1)> for (C0, C1)
+where
+    C0: Cost<T0>,
+    C1: Cost<T1, Inner = C0::Inner>,
+    C0::Inner: Add<Output = C0::Inner>,
+{
+    type Inner = C0::Inner;
+    fn cost(&self, output: &(T0, T1), expected: &(T0, T1)) -> Self::Inner {
+        self.0.cost(&output.0, &expected.0) + self.1.cost(&output.1, &expected.1)
+    }
+    fn diff(&self, output: &(T0, T1), expected: &(T0, T1)) -> (T0, T1) {
+        (
+            self.0.diff(&output.0, &expected.0),
+            self.1.diff(&output.1, &expected.1),
+        )
+    }
+}
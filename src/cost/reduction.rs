@@ -0,0 +1,99 @@
+use super::Cost;
+use ndarray::{Array, Axis, Dimension, RemoveAxis};
+use num_traits::{Float, FromPrimitive};
+
+/// How the gradients of each sample in a batch are combined.
+///
+/// Every layer sums its parameter gradients over the batch, so by default the size of each
+/// step grows with the batch size. Wrapping the cost function using [`Reduced`] with
+/// [`Reduction::Mean`] averages them instead, which keeps the effective learning rate
+/// the same for any batch size
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Reduction {
+    #[default]
+    Sum,
+    Mean,
+}
+
+/// Applies a [`Reduction`] to the gradient of the wrapped cost function.
+/// The first axis is treated as the batch axis, and the cost itself is left as it was
+///
+/// ```
+/// use linear_networks::cost::{mse::MSE, reduction::{Reduced, Reduction}, Cost};
+/// use ndarray::array;
+///
+/// let cost = Reduced::new(MSE, Reduction::Mean);
+/// let output = array![[1.0], [2.0]];
+/// let expected = array![[0.0], [0.0]];
+/// assert_eq!(cost.diff(&output, &expected), array![[1.0], [2.0]]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Reduced<C> {
+    cost: C,
+    reduction: Reduction,
+}
+
+impl<C> Reduced<C> {
+    pub const fn new(cost: C, reduction: Reduction) -> Self {
+        Self { cost, reduction }
+    }
+}
+
+impl<C, F, D> Cost<Array<F, D>> for Reduced<C>
+where
+    C: Cost<Array<F, D>>,
+    F: Float + FromPrimitive,
+    D: Dimension + RemoveAxis,
+{
+    type Inner = C::Inner;
+    fn cost(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Self::Inner {
+        self.cost.cost(output, expected)
+    }
+    fn diff(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Array<F, D> {
+        let diff = self.cost.diff(output, expected);
+        match self.reduction {
+            Reduction::Sum => diff,
+            Reduction::Mean => {
+                let n = F::from_usize(output.len_of(Axis(0)).max(1)).unwrap();
+                diff.mapv_into(|x| x / n)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{concatenate, Array2, Axis};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{Reduced, Reduction};
+    use crate::{cost::mse::MSE, dense::Dense, initialisers::Xavier, train::GraphExecTrain, Graph};
+
+    #[test]
+    fn test_mean_reduction() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(2)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 3);
+        let input = Array2::<f64>::from_shape_simple_fn((4, 3), || rng.gen_range(-1.0..1.0));
+        let expected = Array2::from_shape_simple_fn((4, 2), || rng.gen_range(-1.0..1.0));
+
+        // the same batch twice over has twice the summed gradients, but the same mean
+        let double = |a: &Array2<f64>| concatenate![Axis(0), *a, *a];
+        let grads = |input, expected, reduction| {
+            let cost = Reduced::new(MSE, reduction);
+            graph.get_grads(input, expected, &cost).0
+        };
+
+        let once = grads(input.clone(), expected.clone(), Reduction::Sum);
+        let twice = grads(double(&input), double(&expected), Reduction::Sum);
+        assert!((&twice.w - &once.w * 2.0).iter().all(|d| d.abs() < 1e-12));
+        assert!((&twice.b - &once.b * 2.0).iter().all(|d| d.abs() < 1e-12));
+
+        let once = grads(input.clone(), expected.clone(), Reduction::Mean);
+        let twice = grads(double(&input), double(&expected), Reduction::Mean);
+        assert!((&twice.w - &once.w).iter().all(|d| d.abs() < 1e-12));
+        assert!((&twice.b - &once.b).iter().all(|d| d.abs() < 1e-12));
+    }
+}
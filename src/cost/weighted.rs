@@ -0,0 +1,108 @@
+use super::Cost;
+use crate::{
+    array::argmax,
+    error::{same_shape, Result},
+};
+use ndarray::{Array, Array1, Axis, Dimension, RemoveAxis, Slice};
+use num_traits::{Float, FromPrimitive};
+
+#[derive(Debug, Clone)]
+/// Scales each sample's contribution to the wrapped cost function by the weight of its target class.
+/// Useful for training on imbalanced data sets without manual resampling.
+///
+/// The target class of a sample is the index of the largest value in its expected output,
+/// and the first axis is treated as the batch axis. Like the wrapped cost, the weighted
+/// costs are summed over the batch.
+pub struct ClassWeighted<C, F> {
+    cost: C,
+    weights: Array1<F>,
+}
+
+impl<C, F> ClassWeighted<C, F> {
+    /// Weights the cost of each of the `classes` target classes.
+    /// Returns an error if there isn't exactly one weight per class
+    pub fn new(cost: C, weights: Array1<F>, classes: usize) -> Result<Self> {
+        same_shape("class weights", &[classes], weights.shape())?;
+        Ok(Self { cost, weights })
+    }
+}
+
+impl<C, F, D> Cost<Array<F, D>> for ClassWeighted<C, F>
+where
+    C: Cost<Array<F, D>, Inner = F>,
+    F: Float + FromPrimitive,
+    D: Dimension + RemoveAxis,
+{
+    type Inner = F;
+    fn cost(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Self::Inner {
+        (0..output.len_of(Axis(0))).fold(F::zero(), |cost, i| {
+            let sample = Slice::from(i..=i);
+            let output = output.slice_axis(Axis(0), sample).to_owned();
+            let expected = expected.slice_axis(Axis(0), sample).to_owned();
+            let weight = self.weights[argmax(&expected.view())];
+            cost + self.cost.cost(&output, &expected) * weight
+        })
+    }
+    fn diff(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Array<F, D> {
+        let mut diff = self.cost.diff(output, expected);
+        for (mut d, e) in diff.outer_iter_mut().zip(expected.outer_iter()) {
+            let weight = self.weights[argmax(&e)];
+            d.mapv_inplace(|x| x * weight);
+        }
+        diff
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Scales the wrapped cost function by a constant weight.
+/// Mostly useful for balancing the costs of a network with multiple outputs
+pub struct Weighted<C, F> {
+    cost: C,
+    weight: F,
+}
+
+impl<C, F> Weighted<C, F> {
+    pub const fn new(cost: C, weight: F) -> Self {
+        Self { cost, weight }
+    }
+}
+
+impl<C, F, D> Cost<Array<F, D>> for Weighted<C, F>
+where
+    C: Cost<Array<F, D>, Inner = F>,
+    F: Float,
+    D: Dimension,
+{
+    type Inner = F;
+    fn cost(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Self::Inner {
+        self.cost.cost(output, expected) * self.weight
+    }
+    fn diff(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Array<F, D> {
+        let weight = self.weight;
+        self.cost.diff(output, expected).mapv_into(|x| x * weight)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::ClassWeighted;
+    use crate::cost::{mse::MSE, Cost};
+
+    #[test]
+    fn test_diff_scaled_by_class() {
+        let cost = ClassWeighted::new(MSE, array![1.0, 3.0], 2).unwrap();
+        let output = array![[0.5, 0.5], [0.5, 0.5]];
+        let expected = array![[1.0, 0.0], [0.0, 1.0]];
+
+        let diff = cost.diff(&output, &expected);
+        assert_eq!(diff, array![[-1.0, 1.0], [3.0, -3.0]]);
+        // summed over the batch, like the cost it wraps. Each sample costs 0.0625
+        let output = array![[0.5, 0.0], [0.0, 0.5]];
+        let cost: f64 = cost.cost(&output, &expected);
+        assert!((cost - 0.25).abs() < 1e-12, "{}", cost);
+
+        assert!(ClassWeighted::new(MSE, array![1.0, 3.0], 3).is_err());
+    }
+}
@@ -0,0 +1,233 @@
+// pixel coordinates are always small enough to be cast freely
+#![allow(
+    clippy::cast_possible_wrap,
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss
+)]
+
+use std::convert::TryFrom;
+
+use ndarray::{s, Array, ArrayViewMut2, ArrayViewMut3, Dimension};
+use num_traits::Float;
+use rand::{thread_rng, Rng, RngCore};
+use rand_distr::{Distribution, Normal, StandardNormal};
+
+use super::Dataset;
+
+/// A random transformation applied to a batch of images.
+///
+/// Augmentations can be composed by putting them in a tuple, which applies them in order
+pub trait Augment<F> {
+    /// Augments a batch of images in place. The axes are `(sample, row, column)`
+    fn augment(&self, images: ArrayViewMut3<F>, rng: &mut dyn RngCore);
+}
+
+impl<F, A0, A1> Augment<F> for (A0, A1)
+where
+    A0: Augment<F>,
+    A1: Augment<F>,
+{
+    fn augment(&self, mut images: ArrayViewMut3<F>, rng: &mut dyn RngCore) {
+        self.0.augment(images.view_mut(), rng);
+        self.1.augment(images, rng);
+    }
+}
+
+/// Translates each image by up to `max` pixels in each direction, filling the gaps with zero
+#[derive(Debug, Copy, Clone)]
+pub struct Shift {
+    pub max: usize,
+}
+
+impl<F: Float> Augment<F> for Shift {
+    fn augment(&self, mut images: ArrayViewMut3<F>, rng: &mut dyn RngCore) {
+        let max = self.max as isize;
+        for image in images.outer_iter_mut() {
+            let dy = rng.gen_range(-max..=max);
+            let dx = rng.gen_range(-max..=max);
+            resample(image, |i, j| Some((i - dy, j - dx)));
+        }
+    }
+}
+
+/// Rotates each image about its centre by up to `max_degrees` in either direction,
+/// using nearest neighbour sampling
+#[derive(Debug, Copy, Clone)]
+pub struct Rotate {
+    pub max_degrees: f64,
+}
+
+impl<F: Float> Augment<F> for Rotate {
+    fn augment(&self, mut images: ArrayViewMut3<F>, rng: &mut dyn RngCore) {
+        let (_, rows, cols) = images.dim();
+        let cy = (rows as f64 - 1.0) / 2.0;
+        let cx = (cols as f64 - 1.0) / 2.0;
+
+        for image in images.outer_iter_mut() {
+            let (sine, cosine) = rng
+                .gen_range(-self.max_degrees..=self.max_degrees)
+                .to_radians()
+                .sin_cos();
+            resample(image, |i, j| {
+                let (y, x) = (i as f64 - cy, j as f64 - cx);
+                let sy = cosine.mul_add(y, sine.mul_add(-x, cy)).round();
+                let sx = sine.mul_add(y, cosine.mul_add(x, cx)).round();
+                Some((sy as isize, sx as isize))
+            });
+        }
+    }
+}
+
+/// Mirrors each image left to right with the given probability
+#[derive(Debug, Copy, Clone)]
+pub struct HorizontalFlip {
+    pub probability: f64,
+}
+
+impl<F: Float> Augment<F> for HorizontalFlip {
+    fn augment(&self, mut images: ArrayViewMut3<F>, rng: &mut dyn RngCore) {
+        for mut image in images.outer_iter_mut() {
+            if rng.gen_bool(self.probability) {
+                let flipped = image.slice(s![.., ..;-1]).to_owned();
+                image.assign(&flipped);
+            }
+        }
+    }
+}
+
+/// Adds normally distributed noise to every pixel
+#[derive(Debug, Copy, Clone)]
+pub struct GaussianNoise<F> {
+    pub std_dev: F,
+}
+
+impl<F> Augment<F> for GaussianNoise<F>
+where
+    F: Float,
+    StandardNormal: Distribution<F>,
+{
+    fn augment(&self, mut images: ArrayViewMut3<F>, mut rng: &mut dyn RngCore) {
+        let normal = Normal::new(F::zero(), self.std_dev).unwrap();
+        images.map_inplace(|x| *x = *x + normal.sample(&mut rng));
+    }
+}
+
+/// Zeroes a `size` by `size` square centred on a random pixel in each image.
+/// The square may be partially outside of the image
+#[derive(Debug, Copy, Clone)]
+pub struct Cutout {
+    pub size: usize,
+}
+
+impl<F: Float> Augment<F> for Cutout {
+    fn augment(&self, mut images: ArrayViewMut3<F>, rng: &mut dyn RngCore) {
+        let (_, rows, cols) = images.dim();
+        for mut image in images.outer_iter_mut() {
+            let i = rng.gen_range(0..rows);
+            let j = rng.gen_range(0..cols);
+            let half = self.size / 2;
+            let rows = i.saturating_sub(half)..rows.min(i + self.size - half);
+            let cols = j.saturating_sub(half)..cols.min(j + self.size - half);
+            image.slice_mut(s![rows, cols]).fill(F::zero());
+        }
+    }
+}
+
+/// Replaces every pixel `(i, j)` with the pixel at `source(i, j)`, or zero if that is out of bounds
+fn resample<F: Float>(
+    mut image: ArrayViewMut2<F>,
+    source: impl Fn(isize, isize) -> Option<(isize, isize)>,
+) {
+    let original = image.to_owned();
+    let (rows, cols) = original.dim();
+    let get = |(i, j): (isize, isize)| -> Option<F> {
+        let i = usize::try_from(i).ok().filter(|&i| i < rows)?;
+        let j = usize::try_from(j).ok().filter(|&j| j < cols)?;
+        Some(original[(i, j)])
+    };
+    image.indexed_iter_mut().for_each(|((i, j), x)| {
+        *x = source(i as isize, j as isize)
+            .and_then(get)
+            .unwrap_or_else(F::zero);
+    });
+}
+
+/// A data set whose inputs are augmented every time a batch is taken from it.
+///
+/// Each input sample must contain `height * width` values, which are treated as a single image
+#[derive(Debug, Clone)]
+pub struct Augmented<DS, A> {
+    pub data: DS,
+    pub augment: A,
+    pub height: usize,
+    pub width: usize,
+}
+
+impl<DS, A> Augmented<DS, A> {
+    pub const fn new(data: DS, augment: A, height: usize, width: usize) -> Self {
+        Self {
+            data,
+            augment,
+            height,
+            width,
+        }
+    }
+}
+
+impl<F, D, DS, A> Dataset for Augmented<DS, A>
+where
+    DS: Dataset<Input = Array<F, D>>,
+    D: Dimension,
+    A: Augment<F>,
+{
+    type Input = Array<F, D>;
+    type Target = DS::Target;
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target) {
+        let (input, target) = self.data.batch(indices);
+        let dim = input.raw_dim();
+
+        let mut images = input
+            .into_shape((indices.len(), self.height, self.width))
+            .expect("input samples should be height * width images");
+        self.augment.augment(images.view_mut(), &mut thread_rng());
+
+        (images.into_shape(dim).unwrap(), target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::InMemoryDataset;
+    use ndarray::{array, Array2};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_flip_and_shift() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut images = array![[[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]];
+
+        HorizontalFlip { probability: 1.0 }.augment(images.view_mut(), &mut rng);
+        assert_eq!(images, array![[[3.0, 2.0, 1.0], [6.0, 5.0, 4.0]]]);
+
+        // shifting and rotating by zero is the identity
+        (Shift { max: 0 }, Rotate { max_degrees: 0.0 }).augment(images.view_mut(), &mut rng);
+        assert_eq!(images, array![[[3.0, 2.0, 1.0], [6.0, 5.0, 4.0]]]);
+
+        Cutout { size: 3 }.augment(images.view_mut(), &mut rng);
+        assert!(images.iter().any(|&x: &f64| x.abs() < f64::EPSILON));
+    }
+
+    #[test]
+    fn test_augmented_dataset() {
+        let data = InMemoryDataset::new(Array2::<f64>::ones((4, 6)), Array2::<f64>::zeros((4, 1)));
+        let augmented = Augmented::new(data, HorizontalFlip { probability: 0.5 }, 2, 3);
+        let (input, _) = augmented.batch(&[0, 2]);
+        assert_eq!(input, Array2::<f64>::ones((2, 6)));
+    }
+}
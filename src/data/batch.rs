@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+
+use rand::{prelude::SliceRandom, rngs::StdRng, Rng, SeedableRng};
+
+/// What to do with the final batch of an epoch when the number of samples
+/// isn't a multiple of the batch size
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Remainder {
+    /// Yield a smaller final batch
+    Keep,
+    /// Skip the final batch entirely
+    Drop,
+    /// Fill up the final batch by wrapping around to the start of the epoch
+    Pad,
+}
+
+/// An iterator over the sample indices of each mini-batch in an epoch
+///
+/// ```
+/// use linear_networks::data::batch::Batches;
+///
+/// let batches: Vec<_> = Batches::new(5, 2).collect();
+/// assert_eq!(batches, vec![vec![0, 1], vec![2, 3], vec![4]]);
+///
+/// let batches: Vec<_> = Batches::new(5, 2).drop_last().collect();
+/// assert_eq!(batches, vec![vec![0, 1], vec![2, 3]]);
+///
+/// let batches: Vec<_> = Batches::new(5, 2).pad_last().collect();
+/// assert_eq!(batches, vec![vec![0, 1], vec![2, 3], vec![4, 0]]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Batches {
+    indices: Vec<usize>,
+    batch_size: usize,
+    position: usize,
+    remainder: Remainder,
+}
+
+impl Batches {
+    /// Batches over `0..total` in order
+    #[must_use]
+    pub fn new(total: usize, batch_size: usize) -> Self {
+        Self::from_indices((0..total).collect(), batch_size)
+    }
+
+    /// Batches over `0..total` in a random order
+    pub fn shuffled(total: usize, batch_size: usize, rng: &mut impl Rng) -> Self {
+        let mut indices: Vec<_> = (0..total).collect();
+        indices.shuffle(rng);
+        Self::from_indices(indices, batch_size)
+    }
+
+    /// Batches over `0..total` in a random order that is determined by the seed
+    #[must_use]
+    pub fn seeded(total: usize, batch_size: usize, seed: u64) -> Self {
+        Self::shuffled(total, batch_size, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Batches over `0..classes.len()`, resampling so that every class appears equally often.
+    /// `classes` is the class of each sample, eg from [`classes`](super::classes).
+    ///
+    /// The epoch stays the same length, so minority classes are oversampled (with repeats)
+    /// and majority classes are undersampled. The classes are interleaved so every batch
+    /// is approximately balanced
+    pub fn balanced(classes: &[usize], batch_size: usize, rng: &mut impl Rng) -> Self {
+        let mut by_class = BTreeMap::<_, Vec<_>>::new();
+        for (i, &class) in classes.iter().enumerate() {
+            by_class.entry(class).or_default().push(i);
+        }
+
+        let per_class = classes.len().div_ceil(by_class.len().max(1));
+        let resampled: Vec<Vec<_>> = by_class
+            .into_values()
+            .map(|mut samples| {
+                let mut resampled = Vec::with_capacity(per_class);
+                while resampled.len() < per_class {
+                    samples.shuffle(rng);
+                    let take = samples.len().min(per_class - resampled.len());
+                    resampled.extend_from_slice(&samples[..take]);
+                }
+                resampled
+            })
+            .collect();
+
+        let indices = (0..per_class)
+            .flat_map(|i| resampled.iter().map(move |samples| samples[i]))
+            .take(classes.len())
+            .collect();
+        Self::from_indices(indices, batch_size)
+    }
+
+    /// Batches over `0..classes.len()` in a random order, where every batch has about the
+    /// same class proportions as the whole data set. `classes` is the class of each sample.
+    ///
+    /// Unlike [`balanced`](Self::balanced), every sample appears exactly once. Each class
+    /// is shuffled, then spread evenly over the epoch, so even a rare class turns up at
+    /// regular intervals rather than in bursts. Use `balanced` for uniform proportions instead
+    pub fn stratified(classes: &[usize], batch_size: usize, rng: &mut impl Rng) -> Self {
+        let mut by_class = BTreeMap::<_, Vec<_>>::new();
+        for (i, &class) in classes.iter().enumerate() {
+            by_class.entry(class).or_default().push(i);
+        }
+
+        // the k-th of a class's n samples sits at (2k + 1) / 2n of the way through the epoch
+        let mut positioned: Vec<_> = by_class
+            .into_values()
+            .flat_map(|mut samples| {
+                samples.shuffle(rng);
+                let n = samples.len();
+                samples
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(k, i)| ((2 * k + 1, 2 * n), i))
+            })
+            .collect();
+        positioned.sort_by(|((a, n), _), ((b, m), _)| (a * m).cmp(&(b * n)));
+
+        let indices = positioned.into_iter().map(|(_, i)| i).collect();
+        Self::from_indices(indices, batch_size)
+    }
+
+    /// Batches over the given indices, in the order provided
+    #[must_use]
+    pub fn from_indices(indices: Vec<usize>, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "batch size must be non-zero");
+        Self {
+            indices,
+            batch_size,
+            position: 0,
+            remainder: Remainder::Keep,
+        }
+    }
+
+    /// Skip the final batch if it would be smaller than the batch size
+    #[must_use]
+    pub const fn drop_last(mut self) -> Self {
+        self.remainder = Remainder::Drop;
+        self
+    }
+
+    /// Fill the final batch up to the batch size using samples from the start of the epoch
+    #[must_use]
+    pub const fn pad_last(mut self) -> Self {
+        self.remainder = Remainder::Pad;
+        self
+    }
+
+    /// The size of each full batch
+    #[must_use]
+    pub const fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+}
+
+impl Iterator for Batches {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let remaining = self.indices.len() - self.position;
+        if remaining == 0 || (remaining < self.batch_size && self.remainder == Remainder::Drop) {
+            return None;
+        }
+
+        let end = self.indices.len().min(self.position + self.batch_size);
+        let mut batch = self.indices[self.position..end].to_vec();
+        self.position = end;
+
+        if self.remainder == Remainder::Pad {
+            let padding = self.indices.iter().cycle();
+            batch.extend(padding.take(self.batch_size - batch.len()));
+        }
+
+        Some(batch)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.indices.len() - self.position;
+        let len = match self.remainder {
+            Remainder::Drop => remaining / self.batch_size,
+            Remainder::Keep | Remainder::Pad => remaining.div_ceil(self.batch_size),
+        };
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for Batches {}
+
+#[cfg(test)]
+mod tests {
+    use super::Batches;
+
+    #[test]
+    fn test_seeded_is_deterministic() {
+        let a: Vec<_> = Batches::seeded(100, 7, 42).collect();
+        let b: Vec<_> = Batches::seeded(100, 7, 42).collect();
+        assert_eq!(a, b);
+
+        let mut all: Vec<_> = a.into_iter().flatten().collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_len() {
+        assert_eq!(Batches::new(10, 3).len(), 4);
+        assert_eq!(Batches::new(10, 3).drop_last().len(), 3);
+        assert_eq!(Batches::new(10, 3).pad_last().len(), 4);
+        assert_eq!(Batches::new(9, 3).drop_last().len(), 3);
+        assert_eq!(Batches::new(2, 3).pad_last().last(), Some(vec![0, 1, 0]));
+    }
+
+    #[test]
+    fn test_balanced() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        // 9 samples of class 0, 3 samples of class 1
+        let classes = [0, 0, 0, 1, 0, 0, 1, 0, 0, 1, 0, 0];
+        let mut rng = StdRng::seed_from_u64(0);
+        let batches: Vec<_> = Batches::balanced(&classes, 4, &mut rng).collect();
+        assert_eq!(batches.len(), 3);
+        for batch in batches {
+            let ones = batch.iter().filter(|&&i| classes[i] == 1).count();
+            assert_eq!(ones, 2);
+        }
+    }
+
+    #[test]
+    fn test_stratified() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        // 90 samples of class 0, 10 of class 1 and 20 of class 2
+        let classes: Vec<_> = [(0, 90), (1, 10), (2, 20)]
+            .iter()
+            .flat_map(|&(class, n)| vec![class; n])
+            .collect();
+        let mut rng = StdRng::seed_from_u64(0);
+        let batches: Vec<_> = Batches::stratified(&classes, 12, &mut rng).collect();
+        assert_eq!(batches.len(), 10);
+        for batch in &batches {
+            let count = |class| batch.iter().filter(|&&i| classes[i] == class).count();
+            assert_eq!((count(0), count(1), count(2)), (9, 1, 2));
+        }
+
+        let mut seen: Vec<_> = batches.concat();
+        seen.sort_unstable();
+        assert_eq!(seen, (0..120).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+use ndarray::Dimension;
+
+use super::InMemoryDataset;
+use crate::binary::{read_array, read_header, write_array, write_header, Element};
+
+const MAGIC: &[u8; 4] = b"NRDS";
+const VERSION: u8 = 1;
+
+impl<F, D1, D2> InMemoryDataset<F, D1, D2>
+where
+    F: Element,
+    D1: Dimension,
+    D2: Dimension,
+{
+    /// Writes the inputs and targets to a binary file
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(fs::File::create(path)?);
+        write_header(&mut w, MAGIC, VERSION)?;
+        write_array(&mut w, &self.inputs)?;
+        write_array(&mut w, &self.targets)?;
+        w.flush()
+    }
+
+    /// Reads a data set written by [`save`](Self::save)
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut r = BufReader::new(fs::File::open(path)?);
+        read_header(&mut r, MAGIC, VERSION)?;
+
+        let inputs = read_array(&mut r)?;
+        let targets = read_array(&mut r)?;
+        Ok(Self::new(inputs, targets))
+    }
+
+    /// Loads the data set from the cache file if it exists. Otherwise, builds it
+    /// and writes it to the cache file so later runs can skip any preprocessing.
+    ///
+    /// The cache is not invalidated automatically. Delete the file if `build` changes
+    pub fn cached(
+        path: impl AsRef<Path>,
+        build: impl FnOnce() -> io::Result<Self>,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Self::load(path);
+        }
+
+        let data = build()?;
+        data.save(path)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{array, Ix1, Ix2};
+
+    #[test]
+    fn test_cached() {
+        let path = crate::temp_path("cached.bin");
+        let _ = fs::remove_file(&path);
+
+        let make = || Ok(InMemoryDataset::new(array![1.0, 2.0], array![[3.0], [4.0]]));
+        let first = InMemoryDataset::<f64, Ix1, Ix2>::cached(&path, make).unwrap();
+        let second = InMemoryDataset::<f64, Ix1, Ix2>::cached(&path, || unreachable!()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first.inputs, second.inputs);
+        assert_eq!(first.targets, second.targets);
+    }
+}
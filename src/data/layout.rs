@@ -0,0 +1,111 @@
+//! Arrays with the samples along a different axis.
+//!
+//! Every graph, cost function and trainer in this crate expects batches with the samples
+//! along the first axis, `(samples, features)`. Some pipelines, especially ones ported from
+//! column-major libraries, lay batches out as `(features, samples)` instead. Passing those
+//! in directly often doesn't fail, as a square batch or a bias of the right length can
+//! broadcast silently, so the layout should be converted once at the edge of the pipeline
+use ndarray::{Array, Axis, Dimension};
+
+/// Which axis of an array holds the samples
+///
+/// ```
+/// use linear_networks::data::layout::Layout;
+/// use ndarray::array;
+///
+/// // three samples with two features each, one sample per column
+/// let columns = array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+/// let rows = Layout::SamplesLast.to_samples_first(columns.clone());
+/// assert_eq!(rows, array![[1.0, 4.0], [2.0, 5.0], [3.0, 6.0]]);
+/// assert_eq!(Layout::SamplesLast.to_layout(rows), columns);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Layout {
+    /// `(samples, features..)`, which is what this crate uses
+    #[default]
+    SamplesFirst,
+    /// `(features.., samples)`
+    SamplesLast,
+}
+
+impl Layout {
+    /// The axis holding the samples, in an array with `ndim` dimensions
+    #[must_use]
+    pub const fn sample_axis(self, ndim: usize) -> Axis {
+        match self {
+            Self::SamplesFirst => Axis(0),
+            Self::SamplesLast => Axis(ndim.saturating_sub(1)),
+        }
+    }
+
+    /// Converts an array in this layout to have the samples first.
+    /// The other axes keep their order
+    #[must_use]
+    pub fn to_samples_first<F: Clone, D: Dimension>(self, a: Array<F, D>) -> Array<F, D> {
+        match self {
+            Self::SamplesFirst => a,
+            Self::SamplesLast => {
+                let mut a = a;
+                for i in (1..a.ndim()).rev() {
+                    a.swap_axes(i - 1, i);
+                }
+                a.as_standard_layout().into_owned()
+            }
+        }
+    }
+
+    /// Converts an array with the samples first into this layout,
+    /// eg to hand the outputs of a graph back to the rest of a pipeline
+    #[must_use]
+    pub fn to_layout<F: Clone, D: Dimension>(self, a: Array<F, D>) -> Array<F, D> {
+        match self {
+            Self::SamplesFirst => a,
+            Self::SamplesLast => {
+                let mut a = a;
+                for i in 1..a.ndim() {
+                    a.swap_axes(i - 1, i);
+                }
+                a.as_standard_layout().into_owned()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array2, Array3, Axis};
+
+    use super::Layout;
+    use crate::data::{Dataset, InMemoryDataset};
+
+    #[test]
+    fn test_layout() {
+        // 2 channels of 3 values, for 4 samples
+        let a = Array3::from_shape_fn((2, 3, 4), |(c, v, s)| c * 100 + v * 10 + s);
+        let first = Layout::SamplesLast.to_samples_first(a.clone());
+        assert_eq!(first.dim(), (4, 2, 3));
+        assert_eq!(first[(3, 1, 2)], 123);
+        assert!(first.is_standard_layout());
+        assert_eq!(Layout::SamplesLast.to_layout(first), a);
+
+        assert_eq!(Layout::SamplesLast.sample_axis(3), Axis(2));
+        assert_eq!(Layout::default().sample_axis(3), Axis(0));
+        assert_eq!(Layout::SamplesFirst.to_samples_first(a.clone()), a);
+
+        // 4 samples of 2 features, with 1 target each
+        let data = InMemoryDataset::with_layout(
+            Array2::<f64>::zeros((2, 4)),
+            Array2::zeros((1, 4)),
+            Layout::SamplesLast,
+        )
+        .unwrap();
+        assert_eq!(data.len(), 4);
+        assert_eq!(data.get(0).0.dim(), (1, 2));
+        assert!(InMemoryDataset::with_layout(
+            Array2::<f64>::zeros((2, 4)),
+            Array2::zeros((4, 1)),
+            Layout::SamplesLast,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{sync::mpsc, thread};
+
+use rand::thread_rng;
+
+use super::{batch::Batches, Dataset};
+
+/// The indices of a batch, along with the gathered inputs and targets
+pub type Loaded<DS> = (Vec<usize>, <DS as Dataset>::Input, <DS as Dataset>::Target);
+
+/// Gathers batches from a data set on a background thread, so the next batches
+/// are ready by the time the current one has finished training.
+///
+/// Pass it to [`Train::perform_epoch_loaded`](crate::train::Train::perform_epoch_loaded)
+/// to train for one epoch.
+///
+/// If gathering a batch panics, the panic is resumed on the thread iterating the loader.
+///
+/// On wasm32 there are no threads, so each batch is gathered when it's needed instead
+pub struct DataLoader<DS: Dataset> {
+    data: Arc<DS>,
+    remaining: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    receiver: mpsc::Receiver<Loaded<DS>>,
+    /// Joined once the channel closes, to find out whether it closed early
+    #[cfg(not(target_arch = "wasm32"))]
+    worker: Option<thread::JoinHandle<()>>,
+    #[cfg(target_arch = "wasm32")]
+    batches: Batches,
+}
+
+impl<DS> DataLoader<DS>
+where
+    DS: Dataset + Send + Sync + 'static,
+    DS::Input: Send,
+    DS::Target: Send,
+{
+    /// Starts gathering each of the batches in order, keeping up to `prefetch` batches ready
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(data: Arc<DS>, batches: Batches, prefetch: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(prefetch);
+        let remaining = batches.len();
+
+        let shared = Arc::clone(&data);
+        let worker = thread::spawn(move || {
+            for indices in batches {
+                let (input, target) = shared.batch(&indices);
+                // the loader was dropped, so nothing needs the remaining batches
+                if sender.send((indices, input, target)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            data,
+            remaining,
+            receiver,
+            worker: Some(worker),
+        }
+    }
+
+    /// Gathers each of the batches in order
+    #[cfg(target_arch = "wasm32")]
+    pub fn new(data: Arc<DS>, batches: Batches, _prefetch: usize) -> Self {
+        Self {
+            data,
+            remaining: batches.len(),
+            batches,
+        }
+    }
+
+    /// Loads the whole data set once, in a random order
+    pub fn shuffled(data: Arc<DS>, batch_size: usize, prefetch: usize) -> Self {
+        let batches = Batches::shuffled(data.len(), batch_size, &mut thread_rng());
+        Self::new(data, batches, prefetch)
+    }
+}
+
+impl<DS: Dataset> DataLoader<DS> {
+    /// The data set the batches are gathered from
+    #[must_use]
+    pub const fn data(&self) -> &Arc<DS> {
+        &self.data
+    }
+}
+
+impl<DS: Dataset> Iterator for DataLoader<DS> {
+    type Item = Loaded<DS>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let Ok(loaded) = self.receiver.recv() else {
+            // the worker has finished, either after the last batch or by panicking
+            if let Some(Err(panic)) = self.worker.take().map(thread::JoinHandle::join) {
+                std::panic::resume_unwind(panic);
+            }
+            return None;
+        };
+        #[cfg(target_arch = "wasm32")]
+        let loaded = {
+            let indices = self.batches.next()?;
+            let (input, target) = self.data.batch(&indices);
+            (indices, input, target)
+        };
+        self.remaining -= 1;
+        Some(loaded)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<DS: Dataset> ExactSizeIterator for DataLoader<DS> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::InMemoryDataset;
+    use ndarray::{Array1, Array2};
+
+    #[test]
+    fn test_loader_yields_batches_in_order() {
+        let data = InMemoryDataset::new(
+            Array1::from_iter((0..5).map(f64::from)),
+            Array2::<f64>::zeros((5, 1)),
+        );
+        let loader = DataLoader::new(Arc::new(data), Batches::new(5, 2), 1);
+        assert_eq!(loader.len(), 3);
+
+        let inputs: Vec<_> = loader.map(|(_, input, _)| input.to_vec()).collect();
+        assert_eq!(inputs, vec![vec![0.0, 1.0], vec![2.0, 3.0], vec![4.0]]);
+    }
+
+    #[test]
+    #[should_panic = "Index 7 must be less than axis length 5"]
+    fn test_loader_resumes_panics() {
+        let data = InMemoryDataset::new(
+            Array1::from_iter((0..5).map(f64::from)),
+            Array2::<f64>::zeros((5, 1)),
+        );
+        // the second batch is out of range, so gathering it panics
+        let batches = Batches::from_indices(vec![0, 1, 7], 2);
+        DataLoader::new(Arc::new(data), batches, 1).for_each(drop);
+    }
+}
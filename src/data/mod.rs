@@ -0,0 +1,228 @@
+use ndarray::{Array, ArrayView, Axis, CowArray, Dimension, RemoveAxis};
+use num_traits::Float;
+
+use crate::{
+    array::argmax,
+    error::{same_samples, Result},
+};
+
+use self::layout::Layout;
+
+pub mod augment;
+pub mod batch;
+pub mod cache;
+pub mod layout;
+pub mod loader;
+pub mod scale;
+pub mod sequence;
+pub mod split;
+
+/// A collection of training samples, each with an input and a target output
+pub trait Dataset {
+    /// A batch of inputs
+    type Input;
+    /// A batch of target outputs
+    type Target;
+
+    /// The number of samples in the data set
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gathers the given samples into a single batch, in order
+    fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target);
+
+    /// Gets a single sample, as a batch of one
+    fn get(&self, index: usize) -> (Self::Input, Self::Target) {
+        self.batch(&[index])
+    }
+}
+
+/// A data set where every sample is stored in memory.
+/// The first axis of the inputs and targets is the sample axis
+#[derive(Debug, Clone)]
+pub struct InMemoryDataset<F, D1: Dimension, D2: Dimension> {
+    pub inputs: Array<F, D1>,
+    pub targets: Array<F, D2>,
+}
+
+impl<F, D1, D2> InMemoryDataset<F, D1, D2>
+where
+    D1: Dimension,
+    D2: Dimension,
+{
+    pub fn new(inputs: Array<F, D1>, targets: Array<F, D2>) -> Self {
+        assert_eq!(
+            inputs.raw_dim()[0],
+            targets.raw_dim()[0],
+            "inputs and targets must have the same number of samples"
+        );
+        Self { inputs, targets }
+    }
+
+    /// Like [`new`](Self::new), but returns an error if the inputs and targets
+    /// have a different number of samples
+    pub fn try_new(inputs: Array<F, D1>, targets: Array<F, D2>) -> Result<Self> {
+        same_samples("targets", inputs.raw_dim()[0], targets.raw_dim()[0])?;
+        Ok(Self { inputs, targets })
+    }
+
+    /// Like [`try_new`](Self::try_new), for inputs and targets laid out with the samples
+    /// along another axis. They're converted to have the samples first
+    pub fn with_layout(inputs: Array<F, D1>, targets: Array<F, D2>, layout: Layout) -> Result<Self>
+    where
+        F: Clone,
+    {
+        Self::try_new(
+            layout.to_samples_first(inputs),
+            layout.to_samples_first(targets),
+        )
+    }
+}
+
+impl<F, D1, D2> Dataset for InMemoryDataset<F, D1, D2>
+where
+    F: Clone,
+    D1: Dimension + RemoveAxis,
+    D2: Dimension + RemoveAxis,
+{
+    type Input = Array<F, D1>;
+    type Target = Array<F, D2>;
+
+    fn len(&self) -> usize {
+        self.inputs.len_of(Axis(0))
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target) {
+        (
+            gather(&self.inputs.view(), indices),
+            gather(&self.targets.view(), indices),
+        )
+    }
+}
+
+/// Borrowed inputs and targets can be used as a data set directly
+impl<'a, F, D1, D2> Dataset for (ArrayView<'a, F, D1>, ArrayView<'a, F, D2>)
+where
+    F: Clone,
+    D1: Dimension + RemoveAxis,
+    D2: Dimension + RemoveAxis,
+{
+    type Input = Array<F, D1>;
+    type Target = Array<F, D2>;
+
+    fn len(&self) -> usize {
+        self.0.len_of(Axis(0))
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target) {
+        (gather(&self.0, indices), gather(&self.1, indices))
+    }
+}
+
+/// A batch that holds its samples along the first axis.
+/// Used to pair up the samples of a batch for [mixup](crate::train::Train::mixup)
+pub trait Samples {
+    /// The number of samples in the batch
+    fn samples(&self) -> usize;
+    /// Copies the given samples out of the batch, in order
+    #[must_use]
+    fn select(&self, indices: &[usize]) -> Self;
+}
+
+impl<F: Clone, D: Dimension + RemoveAxis> Samples for Array<F, D> {
+    fn samples(&self) -> usize {
+        self.len_of(Axis(0))
+    }
+    fn select(&self, indices: &[usize]) -> Self {
+        gather(&self.view(), indices)
+    }
+}
+
+impl<F: Clone, D: Dimension + RemoveAxis> Samples for CowArray<'_, F, D> {
+    fn samples(&self) -> usize {
+        self.len_of(Axis(0))
+    }
+    fn select(&self, indices: &[usize]) -> Self {
+        gather(&self.view(), indices).into()
+    }
+}
+
+/// Graphs with several inputs or outputs take one batch of each, with the same samples
+impl<A: Samples, B: Samples> Samples for (A, B) {
+    fn samples(&self) -> usize {
+        self.0.samples()
+    }
+    fn select(&self, indices: &[usize]) -> Self {
+        (self.0.select(indices), self.1.select(indices))
+    }
+}
+
+/// The class of every sample, taken as the index of the largest value in its target
+/// (as with one-hot labels)
+pub fn classes<F, D>(targets: &ArrayView<F, D>) -> Vec<usize>
+where
+    F: Float,
+    D: Dimension + RemoveAxis,
+{
+    targets.outer_iter().map(|target| argmax(&target)).collect()
+}
+
+/// Copies the given samples out of the array, in order.
+///
+/// Each sample is copied straight into the new array's buffer, which benchmarks
+/// faster than [`select`](ndarray::ArrayBase::select) for batches of rows.
+/// Compare them with `cargo bench --bench gather`
+fn gather<F, D>(a: &ArrayView<F, D>, indicies: &[usize]) -> Array<F, D>
+where
+    F: Clone,
+    D: Dimension + RemoveAxis,
+{
+    let mut dim = a.raw_dim();
+    dim.as_array_view_mut()[0] = indicies.len();
+
+    let mut gathered = Vec::with_capacity(dim.size());
+    for &i in indicies {
+        let sample = a.index_axis(Axis(0), i);
+        match sample.as_slice() {
+            Some(sample) => gathered.extend_from_slice(sample),
+            None => gathered.extend(sample.iter().cloned()),
+        }
+    }
+    Array::from_shape_vec(dim, gathered).unwrap()
+}
+
+/// Samples of `y = x0 - 2 * x1 + 0.5`, with inputs drawn from `[-1, 1)`.
+/// A single dense layer can fit them exactly, which the training tests rely on
+#[cfg(test)]
+pub(crate) fn linear_samples<F: Float>(
+    rng: &mut impl rand::Rng,
+    samples: usize,
+) -> (ndarray::Array2<F>, ndarray::Array2<F>) {
+    let inputs = ndarray::Array2::from_shape_simple_fn((samples, 2), || {
+        F::from(rng.gen_range(-1.0..1.0)).unwrap()
+    });
+    let targets = inputs.map_axis(Axis(1), |x| x[0] - (x[1] + x[1]) + F::from(0.5).unwrap());
+    (inputs, targets.insert_axis(Axis(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Axis};
+
+    use super::gather;
+
+    #[test]
+    fn test_gather() {
+        let a = array![[1, 2, 3], [4, 5, 6], [7, 8, 9]];
+        assert_eq!(
+            gather(&a.view(), &[2, 0, 2]),
+            array![[7, 8, 9], [1, 2, 3], [7, 8, 9]]
+        );
+
+        // samples that aren't contiguous in memory
+        let t = a.t();
+        assert_eq!(gather(&t, &[1]), t.select(Axis(0), &[1]));
+    }
+}
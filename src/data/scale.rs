@@ -0,0 +1,177 @@
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "hdf5")]
+use hdf5::H5Type;
+use ndarray::{Array1, Array2, ArrayView2, Axis};
+use num_traits::{Float, FromPrimitive};
+
+use crate::binary::{read_array, write_array, Element};
+
+/// Scales every feature (column) to have zero mean and unit variance.
+///
+/// Fit the scaler on the training inputs only, and save it alongside the model so that
+/// inference can apply the same preprocessing
+#[derive(Debug, Clone)]
+pub struct StandardScaler<F> {
+    pub mean: Array1<F>,
+    pub std: Array1<F>,
+}
+
+impl<F: Float + FromPrimitive> StandardScaler<F> {
+    /// Computes the mean and standard deviation of every feature in the inputs
+    #[must_use]
+    pub fn fit(inputs: &ArrayView2<F>) -> Self {
+        let mean = inputs
+            .mean_axis(Axis(0))
+            .expect("inputs should not be empty");
+        // constant features are left unscaled rather than dividing by zero
+        let std =
+            inputs
+                .std_axis(Axis(0), F::zero())
+                .mapv(|s| if s > F::zero() { s } else { F::one() });
+        Self { mean, std }
+    }
+
+    #[must_use]
+    pub fn transform(&self, inputs: &ArrayView2<F>) -> Array2<F> {
+        (inputs - &self.mean) / &self.std
+    }
+
+    #[must_use]
+    pub fn inverse_transform(&self, inputs: &ArrayView2<F>) -> Array2<F> {
+        inputs * &self.std + &self.mean
+    }
+}
+
+impl<F: Element> StandardScaler<F> {
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        write_array(w, &self.mean)?;
+        write_array(w, &self.std)
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let mean = read_array(r)?;
+        let std = read_array(r)?;
+        Ok(Self { mean, std })
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<F: H5Type> StandardScaler<F> {
+    pub fn save(&self, group: &hdf5::Group) -> hdf5::Result<()> {
+        group
+            .new_dataset_builder()
+            .with_data(self.mean.view())
+            .create("mean")?;
+        group
+            .new_dataset_builder()
+            .with_data(self.std.view())
+            .create("std")?;
+        Ok(())
+    }
+
+    pub fn load(group: &hdf5::Group) -> hdf5::Result<Self> {
+        let mean = group.dataset("mean")?.read()?;
+        let std = group.dataset("std")?.read()?;
+        Ok(Self { mean, std })
+    }
+}
+
+/// Scales every feature (column) linearly into the range `0..=1`
+#[derive(Debug, Clone)]
+pub struct MinMaxScaler<F> {
+    pub min: Array1<F>,
+    pub max: Array1<F>,
+}
+
+impl<F: Float> MinMaxScaler<F> {
+    /// Computes the minimum and maximum of every feature in the inputs
+    #[must_use]
+    pub fn fit(inputs: &ArrayView2<F>) -> Self {
+        let min = inputs.fold_axis(Axis(0), F::infinity(), |&a, &b| a.min(b));
+        let max = inputs.fold_axis(Axis(0), F::neg_infinity(), |&a, &b| a.max(b));
+        Self { min, max }
+    }
+
+    /// The width of each feature's range. Constant features are given a width of one
+    fn range(&self) -> Array1<F> {
+        let mut range = &self.max - &self.min;
+        range.mapv_inplace(|r| if r > F::zero() { r } else { F::one() });
+        range
+    }
+
+    #[must_use]
+    pub fn transform(&self, inputs: &ArrayView2<F>) -> Array2<F> {
+        (inputs - &self.min) / &self.range()
+    }
+
+    #[must_use]
+    pub fn inverse_transform(&self, inputs: &ArrayView2<F>) -> Array2<F> {
+        inputs * &self.range() + &self.min
+    }
+}
+
+impl<F: Element> MinMaxScaler<F> {
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        write_array(w, &self.min)?;
+        write_array(w, &self.max)
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let min = read_array(r)?;
+        let max = read_array(r)?;
+        Ok(Self { min, max })
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<F: H5Type> MinMaxScaler<F> {
+    pub fn save(&self, group: &hdf5::Group) -> hdf5::Result<()> {
+        group
+            .new_dataset_builder()
+            .with_data(self.min.view())
+            .create("min")?;
+        group
+            .new_dataset_builder()
+            .with_data(self.max.view())
+            .create("max")?;
+        Ok(())
+    }
+
+    pub fn load(group: &hdf5::Group) -> hdf5::Result<Self> {
+        let min = group.dataset("min")?.read()?;
+        let max = group.dataset("max")?.read()?;
+        Ok(Self { min, max })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::array;
+
+    #[test]
+    fn test_scalers() {
+        let inputs = array![[1.0, 10.0, 5.0], [3.0, 30.0, 5.0], [5.0, 20.0, 5.0]];
+
+        let standard = StandardScaler::fit(&inputs.view());
+        let scaled = standard.transform(&inputs.view());
+        assert!(scaled
+            .mean_axis(Axis(0))
+            .unwrap()
+            .iter()
+            .all(|m: &f64| m.abs() < 1e-12));
+        assert_eq!(scaled.column(2), array![0.0, 0.0, 0.0]);
+        let restored = standard.inverse_transform(&scaled.view());
+        assert!((restored - &inputs).iter().all(|d: &f64| d.abs() < 1e-12));
+
+        let min_max = MinMaxScaler::fit(&inputs.view());
+        let scaled = min_max.transform(&inputs.view());
+        assert_eq!(
+            scaled,
+            array![[0.0, 0.0, 0.0], [0.5, 1.0, 0.0], [1.0, 0.5, 0.0]]
+        );
+        let restored = min_max.inverse_transform(&scaled.view());
+        assert!((restored - &inputs).iter().all(|d: &f64| d.abs() < 1e-12));
+    }
+}
@@ -0,0 +1,85 @@
+use ndarray::{s, Array2, Array3, ArrayView2};
+use num_traits::{One, Zero};
+use rand::{prelude::SliceRandom, Rng};
+
+use super::batch::Batches;
+
+/// Pads a batch of variable-length sequences, each of shape `(time, features)`,
+/// with zeros at the end so they all share the same length.
+/// Sequences longer than `max_len` are truncated.
+///
+/// Returns the padded batch of shape `(batch, time, features)`, along with a mask of
+/// shape `(batch, time)` which is one for real timesteps and zero for padding
+///
+/// ```
+/// use linear_networks::data::sequence::pad_sequences;
+/// use ndarray::array;
+///
+/// let a = array![[1.0], [2.0], [3.0]];
+/// let b = array![[4.0]];
+/// let (padded, mask) = pad_sequences(&[a.view(), b.view()], None);
+/// assert_eq!(padded, array![[[1.0], [2.0], [3.0]], [[4.0], [0.0], [0.0]]]);
+/// assert_eq!(mask, array![[1.0, 1.0, 1.0], [1.0, 0.0, 0.0]]);
+/// ```
+pub fn pad_sequences<F>(
+    sequences: &[ArrayView2<F>],
+    max_len: Option<usize>,
+) -> (Array3<F>, Array2<F>)
+where
+    F: Clone + Zero + One,
+{
+    let longest = sequences.iter().map(ArrayView2::nrows).max().unwrap_or(0);
+    let len = max_len.map_or(longest, |max| longest.min(max));
+    let features = sequences.first().map_or(0, ArrayView2::ncols);
+
+    let mut padded = Array3::zeros((sequences.len(), len, features));
+    let mut mask = Array2::zeros((sequences.len(), len));
+    for (i, sequence) in sequences.iter().enumerate() {
+        let n = sequence.nrows().min(len);
+        padded
+            .slice_mut(s![i, ..n, ..])
+            .assign(&sequence.slice(s![..n, ..]));
+        mask.slice_mut(s![i, ..n]).fill(F::one());
+    }
+    (padded, mask)
+}
+
+/// Batches sequences of similar lengths together to minimise the padding needed.
+///
+/// Samples are sorted by length (with ties broken randomly) and split into batches,
+/// then the order of the full batches is shuffled. Any smaller final batch stays last
+pub fn bucket_by_length(lengths: &[usize], batch_size: usize, rng: &mut impl Rng) -> Batches {
+    assert!(batch_size > 0, "batch size must be non-zero");
+
+    let mut indices: Vec<_> = (0..lengths.len()).collect();
+    indices.shuffle(rng);
+    indices.sort_by_key(|&i| lengths[i]);
+
+    let full = indices.len() / batch_size * batch_size;
+    let mut buckets: Vec<_> = indices[..full].chunks(batch_size).collect();
+    buckets.shuffle(rng);
+
+    let mut ordered: Vec<_> = buckets.concat();
+    ordered.extend_from_slice(&indices[full..]);
+    Batches::from_indices(ordered, batch_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_bucket_by_length() {
+        let lengths = [5, 1, 4, 2, 5, 1, 3];
+        let mut rng = StdRng::seed_from_u64(0);
+        let batches: Vec<_> = bucket_by_length(&lengths, 2, &mut rng).collect();
+
+        assert_eq!(batches.len(), 4);
+        assert_eq!(batches[3].len(), 1);
+        for batch in &batches[..3] {
+            let diff = lengths[batch[0]].abs_diff(lengths[batch[1]]);
+            assert!(diff <= 1, "{:?}", batch);
+        }
+    }
+}
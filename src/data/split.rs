@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use ndarray::{ArrayView, Axis, Dimension, RemoveAxis};
+use num_traits::Float;
+use rand::{prelude::SliceRandom, rngs::StdRng, SeedableRng};
+
+use super::{gather, InMemoryDataset};
+
+/// The training and testing halves of a split data set
+pub type Split<F, D1, D2> = (InMemoryDataset<F, D1, D2>, InMemoryDataset<F, D1, D2>);
+
+/// Randomly splits the samples into a training and testing set, where `ratio` is
+/// the fraction of samples that end up in the testing set.
+/// Samples keep their original relative order within each set
+pub fn train_test_split<F, D1, D2>(
+    inputs: &ArrayView<F, D1>,
+    targets: &ArrayView<F, D2>,
+    ratio: f64,
+    seed: u64,
+) -> Split<F, D1, D2>
+where
+    F: Clone,
+    D1: Dimension + RemoveAxis,
+    D2: Dimension + RemoveAxis,
+{
+    let mut indices: Vec<_> = (0..inputs.len_of(Axis(0))).collect();
+    indices.shuffle(&mut StdRng::seed_from_u64(seed));
+
+    let test = test_len(indices.len(), ratio);
+    let mut testing = indices.split_off(indices.len() - test);
+    split_at(inputs, targets, indices, &mut testing)
+}
+
+/// Like [`train_test_split`], but preserves the proportion of each class in both sets.
+/// The class of a sample is the index of the largest value in its target, as with one-hot labels
+pub fn stratified_split<F, D1, D2>(
+    inputs: &ArrayView<F, D1>,
+    targets: &ArrayView<F, D2>,
+    ratio: f64,
+    seed: u64,
+) -> Split<F, D1, D2>
+where
+    F: Float,
+    D1: Dimension + RemoveAxis,
+    D2: Dimension + RemoveAxis,
+{
+    let mut classes = BTreeMap::<_, Vec<_>>::new();
+    for (i, class) in super::classes(targets).into_iter().enumerate() {
+        classes.entry(class).or_default().push(i);
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut training = vec![];
+    let mut testing = vec![];
+    for indices in classes.values_mut() {
+        indices.shuffle(&mut rng);
+        let test = test_len(indices.len(), ratio);
+        testing.extend(indices.drain(indices.len() - test..));
+        training.append(indices);
+    }
+
+    split_at(inputs, targets, training, &mut testing)
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss,
+    clippy::cast_possible_truncation
+)]
+fn test_len(total: usize, ratio: f64) -> usize {
+    assert!(
+        (0.0..=1.0).contains(&ratio),
+        "split ratio must be between 0 and 1"
+    );
+    (total as f64 * ratio).round() as usize
+}
+
+fn split_at<F, D1, D2>(
+    inputs: &ArrayView<F, D1>,
+    targets: &ArrayView<F, D2>,
+    mut training: Vec<usize>,
+    testing: &mut [usize],
+) -> Split<F, D1, D2>
+where
+    F: Clone,
+    D1: Dimension + RemoveAxis,
+    D2: Dimension + RemoveAxis,
+{
+    training.sort_unstable();
+    testing.sort_unstable();
+    (
+        InMemoryDataset::new(gather(inputs, &training), gather(targets, &training)),
+        InMemoryDataset::new(gather(inputs, testing), gather(targets, testing)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Dataset;
+    use ndarray::{Array1, Array2};
+
+    #[test]
+    fn test_stratified_split() {
+        // 8 samples of class 0, 4 samples of class 1
+        let inputs = Array1::from_iter((0..12).map(f64::from));
+        let mut targets = Array2::zeros((12, 2));
+        for i in 0..12 {
+            targets[(i, usize::from(i >= 8))] = 1.0;
+        }
+
+        let (training, testing) = stratified_split(&inputs.view(), &targets.view(), 0.25, 0);
+        assert_eq!(training.len(), 9);
+        assert_eq!(testing.len(), 3);
+        assert!((testing.targets.column(1).sum() - 1.0).abs() < f64::EPSILON);
+
+        let (training, testing) = train_test_split(&inputs.view(), &targets.view(), 0.5, 0);
+        assert_eq!(training.len(), 6);
+        assert_eq!(testing.len(), 6);
+        let mut all: Vec<_> = training.inputs.iter().chain(&testing.inputs).collect();
+        all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(all, inputs.iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,97 @@
+use std::{fs, io, path::Path};
+
+use ndarray::{Array4, Ix2, Ix4};
+
+use super::{download::download_tar_gz, idx::one_hot};
+use crate::data::InMemoryDataset;
+
+const TRAINING: [&str; 5] = [
+    "data_batch_1.bin",
+    "data_batch_2.bin",
+    "data_batch_3.bin",
+    "data_batch_4.bin",
+    "data_batch_5.bin",
+];
+const TESTING: &str = "test_batch.bin";
+const ARCHIVE: &str = "https://www.cs.toronto.edu/~kriz/cifar-10-binary.tar.gz";
+
+const CHANNELS: usize = 3;
+const SIZE: usize = 32;
+/// Each record is a label byte followed by the red, green then blue channels of the image
+const RECORD: usize = 1 + CHANNELS * SIZE * SIZE;
+
+/// The CIFAR-10 data set of small colour images in 10 classes.
+///
+/// Inputs have shape `(sample, channel, row, column)` with every pixel scaled into `0..=1`.
+/// Targets are the one-hot encoded classes
+#[derive(Debug, Clone)]
+pub struct Cifar10 {
+    pub training: InMemoryDataset<f32, Ix4, Ix2>,
+    pub testing: InMemoryDataset<f32, Ix4, Ix2>,
+}
+
+impl Cifar10 {
+    /// Opens the data set from the binary batch files in the given directory.
+    ///
+    /// With the `download` feature enabled, missing files are downloaded into the directory first
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let missing = TRAINING
+            .iter()
+            .chain(&[TESTING])
+            .any(|name| !dir.join(name).exists());
+        if missing {
+            download_tar_gz(ARCHIVE, dir)?;
+        }
+
+        let training = TRAINING
+            .iter()
+            .map(|name| fs::read(dir.join(name)))
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self {
+            training: parse_batches(&training.concat())?,
+            testing: parse_batches(&fs::read(dir.join(TESTING))?)?,
+        })
+    }
+}
+
+fn parse_batches(bytes: &[u8]) -> io::Result<InMemoryDataset<f32, Ix4, Ix2>> {
+    if !bytes.len().is_multiple_of(RECORD) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "CIFAR-10 batch contains a partial record",
+        ));
+    }
+
+    let samples = bytes.len() / RECORD;
+    let mut labels = Vec::with_capacity(samples);
+    let mut pixels = Vec::with_capacity(samples * (RECORD - 1));
+    for record in bytes.chunks_exact(RECORD) {
+        labels.push(record[0]);
+        pixels.extend(record[1..].iter().map(|&b| f32::from(b) / 255.0));
+    }
+
+    let images = Array4::from_shape_vec((samples, CHANNELS, SIZE, SIZE), pixels).unwrap();
+    Ok(InMemoryDataset::new(images, one_hot(&labels, 10)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_batches() {
+        let mut bytes = vec![0; RECORD * 2];
+        bytes[0] = 3;
+        bytes[1] = 255;
+        bytes[RECORD] = 7;
+
+        let data = parse_batches(&bytes).unwrap();
+        assert_eq!(data.inputs.dim(), (2, 3, 32, 32));
+        assert!((data.inputs[(0, 0, 0, 0)] - 1.0).abs() < f32::EPSILON);
+        assert!((data.targets[(0, 3)] - 1.0).abs() < f32::EPSILON);
+        assert!((data.targets[(1, 7)] - 1.0).abs() < f32::EPSILON);
+
+        assert!(parse_batches(&bytes[1..]).is_err());
+    }
+}
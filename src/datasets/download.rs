@@ -0,0 +1,67 @@
+#[cfg(feature = "download")]
+use std::fs;
+use std::{io, path::Path};
+
+#[cfg(feature = "download")]
+use flate2::read::GzDecoder;
+
+/// Downloads a gzipped file, decompressing it into the given path
+#[cfg(feature = "download")]
+pub fn download_gz(url: &str, path: &Path) -> io::Result<()> {
+    let response = ureq::get(url).call().map_err(io::Error::other)?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // decompress into a temporary file first, so a failed download doesn't leave a truncated file
+    let tmp = path.with_extension("part");
+    io::copy(
+        &mut GzDecoder::new(response.into_reader()),
+        &mut fs::File::create(&tmp)?,
+    )?;
+    fs::rename(tmp, path)
+}
+
+#[cfg(not(feature = "download"))]
+pub fn download_gz(url: &str, path: &Path) -> io::Result<()> {
+    Err(missing(url, path))
+}
+
+/// Downloads a gzipped tarball, extracting every file in it directly into the given directory
+#[cfg(feature = "download")]
+pub fn download_tar_gz(url: &str, dir: &Path) -> io::Result<()> {
+    let response = ureq::get(url).call().map_err(io::Error::other)?;
+    fs::create_dir_all(dir)?;
+
+    let mut archive = tar::Archive::new(GzDecoder::new(response.into_reader()));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        // flatten any directories inside the archive
+        let path = entry.path()?;
+        if let Some(name) = path.file_name() {
+            let path = dir.join(name);
+            entry.unpack(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "download"))]
+pub fn download_tar_gz(url: &str, dir: &Path) -> io::Result<()> {
+    Err(missing(url, dir))
+}
+
+#[cfg(not(feature = "download"))]
+fn missing(url: &str, path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "{} does not exist. Enable the `download` feature to download it from {url}",
+            path.display()
+        ),
+    )
+}
@@ -0,0 +1,111 @@
+use std::io;
+
+use ndarray::{Array2, ArrayD, Axis, Ix2, IxDyn};
+
+use crate::data::InMemoryDataset;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parses an IDX file containing unsigned bytes, as used by the MNIST family of data sets.
+/// See <http://yann.lecun.com/exdb/mnist/> for a description of the format
+pub fn read_idx(bytes: &[u8]) -> io::Result<ArrayD<u8>> {
+    let (magic, mut rest) = bytes
+        .split_at_checked(4)
+        .ok_or_else(|| invalid("IDX file is too short"))?;
+    if magic[0..3] != [0, 0, 0x08] {
+        return Err(invalid("IDX file does not contain unsigned bytes"));
+    }
+
+    let mut shape = vec![];
+    for _ in 0..magic[3] {
+        let (dim, tail) = rest
+            .split_first_chunk::<4>()
+            .ok_or_else(|| invalid("IDX file is missing dimensions"))?;
+        shape.push(u32::from_be_bytes(*dim) as usize);
+        rest = tail;
+    }
+
+    let len = shape
+        .iter()
+        .try_fold(1_usize, |len, &dim| len.checked_mul(dim))
+        .ok_or_else(|| invalid("IDX file is too large"))?;
+    let data = rest
+        .get(..len)
+        .ok_or_else(|| invalid("IDX file is missing data"))?;
+
+    ArrayD::from_shape_vec(IxDyn(&shape), data.to_vec()).map_err(|_| invalid("IDX shape mismatch"))
+}
+
+/// Converts class labels into one-hot encoded rows.
+/// Returns an error if any label isn't less than `classes`
+pub fn one_hot(labels: &[u8], classes: usize) -> io::Result<Array2<f32>> {
+    let mut encoded = Array2::zeros((labels.len(), classes));
+    for (i, &label) in labels.iter().enumerate() {
+        let class = usize::from(label);
+        if class >= classes {
+            return Err(invalid("label is out of range for the number of classes"));
+        }
+        encoded[(i, class)] = 1.0;
+    }
+    Ok(encoded)
+}
+
+/// Flattens each image into a single row, scaling each byte into the range `0..=1`
+fn normalise_images(images: &ArrayD<u8>) -> Array2<f32> {
+    let samples = images.len_of(Axis(0));
+    images
+        .mapv(|b| f32::from(b) / 255.0)
+        .into_shape((samples, images.len() / samples.max(1)))
+        .unwrap()
+}
+
+/// Builds a data set from raw image and label IDX files
+pub(super) fn idx_dataset(
+    images: &[u8],
+    labels: &[u8],
+    classes: usize,
+) -> io::Result<InMemoryDataset<f32, Ix2, Ix2>> {
+    let images = read_idx(images)?;
+    let labels = read_idx(labels)?;
+    if images.ndim() == 0 || labels.ndim() != 1 {
+        return Err(invalid("IDX files have the wrong number of dimensions"));
+    }
+    if images.len_of(Axis(0)) != labels.len() {
+        return Err(invalid("number of images and labels differ"));
+    }
+    let labels: Vec<u8> = labels.iter().copied().collect();
+    Ok(InMemoryDataset::new(
+        normalise_images(&images),
+        one_hot(&labels, classes)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_idx() {
+        let bytes = [0, 0, 8, 2, 0, 0, 0, 2, 0, 0, 0, 3, 1, 2, 3, 4, 5, 6];
+        let a = read_idx(&bytes).unwrap();
+        assert_eq!(a.shape(), &[2, 3]);
+        assert_eq!(a.as_slice().unwrap(), &[1, 2, 3, 4, 5, 6]);
+
+        assert!(read_idx(&bytes[..15]).is_err());
+        assert!(read_idx(&[0, 0, 9, 1, 0, 0, 0, 0]).is_err());
+
+        // a scalar has no samples, and a label of 3 is out of range for 3 classes
+        let scalar = [0, 0, 8, 0, 1];
+        let labels = [0, 0, 8, 1, 0, 0, 0, 2, 0, 3];
+        assert!(idx_dataset(&scalar, &labels, 3).is_err());
+        assert!(idx_dataset(&bytes, &scalar, 3).is_err());
+        let err = idx_dataset(&bytes, &labels, 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            one_hot(&[0, 2], 3).unwrap(),
+            ndarray::array![[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]
+        );
+    }
+}
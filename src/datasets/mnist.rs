@@ -0,0 +1,57 @@
+use std::{fs, io, path::Path};
+
+use ndarray::Ix2;
+
+use super::{download::download_gz, idx::idx_dataset};
+use crate::data::InMemoryDataset;
+
+const TRAIN_IMAGES: &str = "train-images-idx3-ubyte";
+const TRAIN_LABELS: &str = "train-labels-idx1-ubyte";
+const TEST_IMAGES: &str = "t10k-images-idx3-ubyte";
+const TEST_LABELS: &str = "t10k-labels-idx1-ubyte";
+
+const MIRROR: &str = "https://ossci-datasets.s3.amazonaws.com/mnist/";
+const FASHION_MIRROR: &str =
+    "https://raw.githubusercontent.com/zalandoresearch/fashion-mnist/master/data/fashion/";
+
+/// The MNIST handwritten digit data set.
+///
+/// Inputs are the `28*28` pixel images flattened into rows and scaled into `0..=1`.
+/// Targets are the one-hot encoded digits
+#[derive(Debug, Clone)]
+pub struct Mnist {
+    pub training: InMemoryDataset<f32, Ix2, Ix2>,
+    pub testing: InMemoryDataset<f32, Ix2, Ix2>,
+}
+
+impl Mnist {
+    /// Opens the data set from the four (uncompressed) IDX files in the given directory.
+    ///
+    /// With the `download` feature enabled, any missing files are downloaded into the directory first
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load_from(dir.as_ref(), MIRROR)
+    }
+
+    /// Opens the Fashion-MNIST data set from the given directory. It has the same format as MNIST,
+    /// but the images are of 10 classes of clothing instead of digits.
+    ///
+    /// With the `download` feature enabled, any missing files are downloaded into the directory first
+    pub fn load_fashion(dir: impl AsRef<Path>) -> io::Result<Self> {
+        Self::load_from(dir.as_ref(), FASHION_MIRROR)
+    }
+
+    fn load_from(dir: &Path, mirror: &str) -> io::Result<Self> {
+        let read = |name: &str| {
+            let path = dir.join(name);
+            if !path.exists() {
+                download_gz(&format!("{mirror}{name}.gz"), &path)?;
+            }
+            fs::read(path)
+        };
+
+        Ok(Self {
+            training: idx_dataset(&read(TRAIN_IMAGES)?, &read(TRAIN_LABELS)?, 10)?,
+            testing: idx_dataset(&read(TEST_IMAGES)?, &read(TEST_LABELS)?, 10)?,
+        })
+    }
+}
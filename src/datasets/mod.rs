@@ -0,0 +1,18 @@
+//! Loaders for common benchmark data sets, generators for synthetic ones, and text corpora.
+//! The loaders require the `datasets` feature
+
+#[cfg(feature = "datasets")]
+pub mod cifar;
+#[cfg(feature = "datasets")]
+mod download;
+#[cfg(feature = "datasets")]
+mod idx;
+#[cfg(feature = "datasets")]
+pub mod mnist;
+#[cfg(feature = "datasets")]
+pub mod npy;
+pub mod synthetic;
+pub mod text;
+
+#[cfg(feature = "datasets")]
+pub use idx::{one_hot, read_idx};
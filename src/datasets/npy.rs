@@ -0,0 +1,298 @@
+use std::{
+    collections::BTreeMap,
+    convert::{TryFrom, TryInto},
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use ndarray::{ArrayD, ArrayViewD, IxDyn, ShapeBuilder};
+use num_traits::{Float, FromPrimitive};
+
+use crate::{binary::Element, tensors::Tensors};
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The element types that can be read from `.npy` files
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Dtype {
+    F32,
+    F64,
+    U8,
+    I32,
+    I64,
+}
+
+impl Dtype {
+    const fn size(self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::F32 | Self::I32 => 4,
+            Self::F64 | Self::I64 => 8,
+        }
+    }
+
+    fn read<F: FromPrimitive>(self, bytes: &[u8], little_endian: bool) -> Option<F> {
+        macro_rules! from_bytes {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().ok()?;
+                if little_endian {
+                    <$t>::from_le_bytes(bytes)
+                } else {
+                    <$t>::from_be_bytes(bytes)
+                }
+            }};
+        }
+        match self {
+            Self::F32 => F::from_f32(from_bytes!(f32)),
+            Self::F64 => F::from_f64(from_bytes!(f64)),
+            Self::U8 => F::from_u8(bytes[0]),
+            Self::I32 => F::from_i32(from_bytes!(i32)),
+            Self::I64 => F::from_i64(from_bytes!(i64)),
+        }
+    }
+}
+
+/// The fields of the python dict literal at the start of every `.npy` file,
+/// eg `{'descr': '<f4', 'fortran_order': False, 'shape': (3, 4), }`
+struct Header {
+    dtype: Dtype,
+    little_endian: bool,
+    fortran_order: bool,
+    shape: Vec<usize>,
+}
+
+impl Header {
+    fn parse(header: &str) -> io::Result<Self> {
+        let value = |key: &str| -> io::Result<&str> {
+            let start = header
+                .find(&format!("'{key}'"))
+                .ok_or_else(|| invalid("npy header is missing a field"))?;
+            let rest = header[start + key.len() + 2..].trim_start();
+            Ok(rest.trim_start_matches(':').trim_start())
+        };
+
+        let descr = value("descr")?;
+        let descr = descr
+            .get(1..4)
+            .ok_or_else(|| invalid("npy header has an invalid descr"))?;
+        let (little_endian, dtype) = descr.split_at(1);
+        let dtype = match dtype {
+            "f4" => Dtype::F32,
+            "f8" => Dtype::F64,
+            "u1" | "b1" => Dtype::U8,
+            "i4" => Dtype::I32,
+            "i8" => Dtype::I64,
+            _ => return Err(invalid("npy file has an unsupported element type")),
+        };
+
+        let fortran_order = value("fortran_order")?.starts_with("True");
+
+        let shape = value("shape")?;
+        let shape = shape
+            .strip_prefix('(')
+            .and_then(|s| s.split(')').next())
+            .ok_or_else(|| invalid("npy header has an invalid shape"))?;
+        let shape = shape
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                s.parse()
+                    .map_err(|_| invalid("npy header has an invalid shape"))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            dtype,
+            little_endian: little_endian != ">",
+            fortran_order,
+            shape,
+        })
+    }
+}
+
+/// Parses the contents of a numpy `.npy` file, converting the elements into `F`.
+///
+/// Supports float, unsigned byte, bool and integer arrays. Use
+/// [`into_dimensionality`](ndarray::ArrayBase::into_dimensionality) to get
+/// a fixed dimension array, such as an `Array2`
+pub fn read_npy<F>(bytes: &[u8]) -> io::Result<ArrayD<F>>
+where
+    F: Float + FromPrimitive,
+{
+    let rest = bytes
+        .strip_prefix(b"\x93NUMPY")
+        .ok_or_else(|| invalid("not an npy file"))?;
+    let (header_len, rest) = match rest {
+        [1, _, a, b, rest @ ..] => (u16::from_le_bytes([*a, *b]) as usize, rest),
+        [2 | 3, _, a, b, c, d, rest @ ..] => (u32::from_le_bytes([*a, *b, *c, *d]) as usize, rest),
+        _ => return Err(invalid("npy file has an unsupported version")),
+    };
+
+    let header = rest
+        .get(..header_len)
+        .ok_or_else(|| invalid("npy header is truncated"))?;
+    let header = std::str::from_utf8(header).map_err(|_| invalid("npy header is not utf8"))?;
+    let header = Header::parse(header)?;
+
+    let len = header.shape.iter().product::<usize>();
+    let data = rest[header_len..]
+        .get(..len * header.dtype.size())
+        .ok_or_else(|| invalid("npy file is missing data"))?;
+    let elements = data
+        .chunks_exact(header.dtype.size())
+        .map(|b| header.dtype.read(b, header.little_endian))
+        .collect::<Option<Vec<F>>>()
+        .ok_or_else(|| invalid("npy element cannot be represented"))?;
+
+    let shape = IxDyn(&header.shape).set_f(header.fortran_order);
+    ArrayD::from_shape_vec(shape, elements).map_err(|_| invalid("npy shape mismatch"))
+}
+
+/// Reads a numpy `.npy` file
+pub fn load_npy<F>(path: impl AsRef<Path>) -> io::Result<ArrayD<F>>
+where
+    F: Float + FromPrimitive,
+{
+    read_npy(&fs::read(path)?)
+}
+
+/// Reads every array in a numpy `.npz` archive (compressed or not), keyed by name
+pub fn load_npz<F>(path: impl AsRef<Path>) -> io::Result<BTreeMap<String, ArrayD<F>>>
+where
+    F: Float + FromPrimitive,
+{
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+
+    let mut arrays = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().trim_end_matches(".npy").to_owned();
+
+        let mut bytes = vec![];
+        file.read_to_end(&mut bytes)?;
+        arrays.insert(name, read_npy(&bytes)?);
+    }
+    Ok(arrays)
+}
+
+/// Encodes an array as the contents of a numpy `.npy` file
+pub fn write_npy<F: Element>(array: &ArrayViewD<F>) -> io::Result<Vec<u8>> {
+    let descr = match F::TAG {
+        4 => "<f4",
+        8 => "<f8",
+        _ => unreachable!("unknown element type"),
+    };
+    let shape = match array.shape() {
+        [dim] => format!("({dim},)"),
+        shape => {
+            let dims: Vec<_> = shape.iter().map(ToString::to_string).collect();
+            format!("({})", dims.join(", "))
+        }
+    };
+    let mut header = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+    // the data should start on a 64 byte boundary, after the 10 byte preamble
+    let padding = 63 - (10 + header.len()) % 64;
+    header.push_str(&" ".repeat(padding));
+    header.push('\n');
+
+    let len = u16::try_from(header.len()).map_err(|_| invalid("npy header is too long"))?;
+    let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+    bytes.extend_from_slice(&len.to_le_bytes());
+    bytes.extend_from_slice(header.as_bytes());
+    array.iter().for_each(|&x| x.to_le(&mut bytes));
+    Ok(bytes)
+}
+
+/// Writes every parameter tensor of the state as a named array in a compressed `.npz`
+/// archive, which can be opened in python with `numpy.load`
+pub fn save_npz<F, T>(state: &T, path: impl AsRef<Path>) -> io::Result<()>
+where
+    F: Element,
+    T: Tensors<F>,
+{
+    let mut arrays = vec![];
+    let mut result = Ok(());
+    state.tensors("", &mut |name, tensor| match write_npy(&tensor) {
+        Ok(bytes) => arrays.push((name, bytes)),
+        Err(err) => result = Err(err),
+    });
+    result?;
+
+    let mut archive = zip::ZipWriter::new(fs::File::create(path)?);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for (name, bytes) in arrays {
+        archive.start_file(format!("{name}.npy"), options)?;
+        archive.write_all(&bytes)?;
+    }
+    archive.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+
+    fn npy(header: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"\x93NUMPY\x01\x00".to_vec();
+        let len = u16::try_from(header.len()).unwrap();
+        bytes.extend_from_slice(&len.to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_read_npy() {
+        let data: Vec<u8> = [1.0_f32, 2.0, 3.0, 4.0, 5.0, 6.0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+
+        let c = npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }",
+            &data,
+        );
+        let c = read_npy::<f64>(&c).unwrap();
+        assert_eq!(
+            c,
+            ndarray::array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]].into_dyn()
+        );
+
+        let f = npy(
+            "{'descr': '<f4', 'fortran_order': True, 'shape': (3, 2), }",
+            &data,
+        );
+        let f = read_npy::<f64>(&f).unwrap();
+        assert_eq!(f, c.t());
+
+        let bytes = npy(
+            "{'descr': '|u1', 'fortran_order': False, 'shape': (3,), }",
+            &[7, 8, 9],
+        );
+        let bytes = read_npy::<f32>(&bytes).unwrap();
+        assert_eq!(bytes, ndarray::array![7.0, 8.0, 9.0].into_dyn());
+
+        assert!(read_npy::<f32>(b"not numpy").is_err());
+    }
+
+    #[test]
+    fn test_save_npz() {
+        let state = crate::dense::DenseState {
+            w: ndarray::array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]],
+            b: ndarray::array![7.0, 8.0, 9.0],
+        };
+        let path = crate::temp_path("params.npz");
+        save_npz(&state, &path).unwrap();
+        let arrays = load_npz::<f64>(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(arrays["weight"], state.w.t().into_dyn());
+        assert_eq!(arrays["bias"], state.b.into_dyn());
+    }
+}
@@ -0,0 +1,159 @@
+//! Small generated data sets, useful for tests and examples.
+//!
+//! Every generator takes the number of samples and the standard deviation of the
+//! gaussian noise added to each input. Classification targets are one-hot encoded
+use std::f64::consts::PI;
+
+use ndarray::{Array2, ArrayView2, Ix2};
+use num_traits::{Float, FromPrimitive};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+use crate::data::InMemoryDataset;
+
+type Synthetic<F> = InMemoryDataset<F, Ix2, Ix2>;
+
+/// Builds a data set from a generator of `(input, class)` samples
+fn classification<F, const N: usize>(
+    samples: usize,
+    classes: usize,
+    mut sample: impl FnMut(usize) -> ([f64; N], usize),
+) -> Synthetic<F>
+where
+    F: Float + FromPrimitive,
+{
+    let mut inputs = Array2::zeros((samples, N));
+    let mut targets = Array2::zeros((samples, classes));
+    for i in 0..samples {
+        let (input, class) = sample(i);
+        for (j, x) in input.iter().enumerate() {
+            inputs[(i, j)] = F::from_f64(*x).unwrap();
+        }
+        targets[(i, class)] = F::one();
+    }
+    InMemoryDataset::new(inputs, targets)
+}
+
+fn normal(std_dev: f64) -> Normal<f64> {
+    Normal::new(0.0, std_dev).expect("noise should be a valid standard deviation")
+}
+
+/// Points around the corners of the unit square, where the class is
+/// the exclusive or of the two coordinates
+pub fn xor<F>(samples: usize, noise: f64, rng: &mut impl Rng) -> Synthetic<F>
+where
+    F: Float + FromPrimitive,
+{
+    let noise = normal(noise);
+    classification(samples, 2, |i| {
+        let (a, b) = (i & 1 == 1, i & 2 == 2);
+        let x = f64::from(u8::from(a)) + noise.sample(rng);
+        let y = f64::from(u8::from(b)) + noise.sample(rng);
+        ([x, y], usize::from(a ^ b))
+    })
+}
+
+/// Two interleaving half circles
+pub fn moons<F>(samples: usize, noise: f64, rng: &mut impl Rng) -> Synthetic<F>
+where
+    F: Float + FromPrimitive,
+{
+    let noise = normal(noise);
+    classification(samples, 2, |i| {
+        let class = i % 2;
+        let t = rng.gen_range(0.0..PI);
+        let (x, y) = if class == 0 {
+            (t.cos(), t.sin())
+        } else {
+            (1.0 - t.cos(), 0.5 - t.sin())
+        };
+        ([x + noise.sample(rng), y + noise.sample(rng)], class)
+    })
+}
+
+/// Interleaved spiral arms, one per class, each making a single full turn
+#[allow(clippy::cast_precision_loss)]
+pub fn spirals<F>(samples: usize, classes: usize, noise: f64, rng: &mut impl Rng) -> Synthetic<F>
+where
+    F: Float + FromPrimitive,
+{
+    let noise = normal(noise);
+    classification(samples, classes, |i| {
+        let class = i % classes;
+        let r: f64 = rng.gen();
+        let theta = 2.0 * PI * (r + class as f64 / classes as f64);
+        let x = r.mul_add(theta.cos(), noise.sample(rng));
+        let y = r.mul_add(theta.sin(), noise.sample(rng));
+        ([x, y], class)
+    })
+}
+
+/// Gaussian clusters around each of the given centres (one per row), one class per centre
+pub fn blobs<F>(
+    samples: usize,
+    centres: &ArrayView2<f64>,
+    noise: f64,
+    rng: &mut impl Rng,
+) -> Synthetic<F>
+where
+    F: Float + FromPrimitive,
+{
+    let noise = normal(noise);
+    let (classes, dims) = centres.dim();
+    let mut inputs = Array2::zeros((samples, dims));
+    let mut targets = Array2::zeros((samples, classes));
+    for i in 0..samples {
+        let class = i % classes;
+        for j in 0..dims {
+            inputs[(i, j)] = F::from_f64(centres[(class, j)] + noise.sample(rng)).unwrap();
+        }
+        targets[(i, class)] = F::one();
+    }
+    InMemoryDataset::new(inputs, targets)
+}
+
+/// Inputs uniformly sampled from `-1..1` with targets `sin(pi * x)`.
+/// The noise is added to the targets rather than the inputs
+pub fn regression<F>(samples: usize, noise: f64, rng: &mut impl Rng) -> Synthetic<F>
+where
+    F: Float + FromPrimitive,
+{
+    let noise = normal(noise);
+    let mut inputs = Array2::zeros((samples, 1));
+    let mut targets = Array2::zeros((samples, 1));
+    for i in 0..samples {
+        let x = rng.gen_range(-1.0..1.0);
+        inputs[(i, 0)] = F::from_f64(x).unwrap();
+        targets[(i, 0)] = F::from_f64((PI * x).sin() + noise.sample(rng)).unwrap();
+    }
+    InMemoryDataset::new(inputs, targets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Dataset;
+    use ndarray::{array, Axis};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_generators() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let data = xor::<f64>(8, 0.0, &mut rng);
+        assert_eq!(data.targets.sum_axis(Axis(0)), array![4.0, 4.0]);
+        assert_eq!(data.inputs.row(3), array![1.0, 1.0]);
+        assert_eq!(data.targets.row(3), array![1.0, 0.0]);
+
+        let data = spirals::<f32>(30, 3, 0.1, &mut rng);
+        assert_eq!(data.len(), 30);
+        assert_eq!(data.targets.sum_axis(Axis(0)), array![10.0, 10.0, 10.0]);
+
+        let centres = array![[0.0, 0.0, 0.0], [5.0, 5.0, 5.0]];
+        let data = blobs::<f64>(10, &centres.view(), 0.5, &mut rng);
+        assert_eq!(data.inputs.dim(), (10, 3));
+
+        assert_eq!(moons::<f64>(10, 0.1, &mut rng).targets.dim(), (10, 2));
+        assert_eq!(regression::<f64>(10, 0.1, &mut rng).targets.dim(), (10, 1));
+    }
+}
@@ -0,0 +1,174 @@
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use ndarray::Array3;
+use num_traits::{One, Zero};
+
+use crate::data::Dataset;
+
+/// A mapping between characters and indices, ordered by character
+#[derive(Debug, Clone)]
+pub struct Vocabulary {
+    chars: Vec<char>,
+    indices: BTreeMap<char, usize>,
+}
+
+impl Vocabulary {
+    /// Builds a vocabulary of every distinct character in the text
+    #[must_use]
+    pub fn from_text(text: &str) -> Self {
+        let mut chars: Vec<_> = text.chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        let indices = chars.iter().enumerate().map(|(i, &c)| (c, i)).collect();
+        Self { chars, indices }
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    #[must_use]
+    pub fn index_of(&self, c: char) -> Option<usize> {
+        self.indices.get(&c).copied()
+    }
+
+    #[must_use]
+    pub fn char_at(&self, index: usize) -> Option<char> {
+        self.chars.get(index).copied()
+    }
+
+    /// Converts the text into indices, skipping any characters not in the vocabulary
+    #[must_use]
+    pub fn encode(&self, text: &str) -> Vec<usize> {
+        text.chars().filter_map(|c| self.index_of(c)).collect()
+    }
+
+    /// Converts the indices back into text, skipping any out of range indices
+    #[must_use]
+    pub fn decode(&self, indices: &[usize]) -> String {
+        indices.iter().filter_map(|&i| self.char_at(i)).collect()
+    }
+}
+
+/// A corpus of text for next-character language modelling.
+///
+/// Sample `i` is the window of `seq_len` characters starting at character `i`,
+/// and its target is the same window shifted along by one character.
+/// Batches are one-hot encoded with shape `(batch, time, vocabulary)`
+///
+/// ```
+/// use linear_networks::{data::Dataset, datasets::text::CharDataset};
+///
+/// let data = CharDataset::<f32>::new("hello", 3);
+/// assert_eq!(data.len(), 2);
+///
+/// let (input, target) = data.get(1);
+/// assert_eq!(input.shape(), &[1, 3, 4]);
+/// assert_eq!(data.vocabulary.decode(&data.encoded[1..4]), "ell");
+/// assert_eq!(target[(0, 2, data.vocabulary.index_of('o').unwrap())], 1.0);
+/// ```
+#[derive(Debug, Clone)]
+pub struct CharDataset<F> {
+    pub vocabulary: Vocabulary,
+    pub encoded: Vec<usize>,
+    pub seq_len: usize,
+    _float: PhantomData<F>,
+}
+
+impl<F> CharDataset<F> {
+    /// Builds the vocabulary from the text and encodes it
+    #[must_use]
+    pub fn new(text: &str, seq_len: usize) -> Self {
+        let vocabulary = Vocabulary::from_text(text);
+        Self::with_vocabulary(vocabulary, text, seq_len)
+    }
+
+    /// Encodes the text using an existing vocabulary, eg one shared with a validation corpus
+    #[must_use]
+    pub fn with_vocabulary(vocabulary: Vocabulary, text: &str, seq_len: usize) -> Self {
+        assert!(seq_len > 0, "sequence length must be non-zero");
+        let encoded = vocabulary.encode(text);
+        Self {
+            vocabulary,
+            encoded,
+            seq_len,
+            _float: PhantomData,
+        }
+    }
+
+    /// One-hot encodes the window of characters starting at each offset
+    fn windows(&self, starts: impl ExactSizeIterator<Item = usize>) -> Array3<F>
+    where
+        F: Clone + Zero + One,
+    {
+        let mut encoded = Array3::zeros((starts.len(), self.seq_len, self.vocabulary.len()));
+        for (i, start) in starts.enumerate() {
+            for (t, &c) in self.encoded[start..start + self.seq_len].iter().enumerate() {
+                encoded[(i, t, c)] = F::one();
+            }
+        }
+        encoded
+    }
+}
+
+impl<F: Clone + Zero + One> Dataset for CharDataset<F> {
+    type Input = Array3<F>;
+    type Target = Array3<F>;
+
+    fn len(&self) -> usize {
+        self.encoded.len().saturating_sub(self.seq_len)
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target) {
+        (
+            self.windows(indices.iter().copied()),
+            self.windows(indices.iter().map(|i| i + 1)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{s, Array2, Axis};
+
+    use super::{CharDataset, Vocabulary};
+    use crate::data::Dataset;
+
+    #[test]
+    fn test_vocabulary_round_trip() {
+        let vocabulary = Vocabulary::from_text("banana");
+        assert_eq!(vocabulary.len(), 3);
+        assert_eq!(vocabulary.index_of('a'), Some(0));
+        assert_eq!(vocabulary.index_of('n'), Some(2));
+
+        // characters outside the vocabulary are skipped
+        let encoded = vocabulary.encode("bandana");
+        assert_eq!(encoded, [1, 0, 2, 0, 2, 0]);
+        assert_eq!(vocabulary.decode(&encoded), "banana");
+        assert_eq!(vocabulary.decode(&[1, 3, 0]), "ba");
+    }
+
+    #[test]
+    fn test_targets_are_shifted_inputs() {
+        let data = CharDataset::<f64>::new("hello world", 4);
+        assert_eq!(data.len(), 7);
+
+        let (input, target) = data.batch(&[0, 3, 6]);
+        assert_eq!(input.shape(), &[3, 4, data.vocabulary.len()]);
+        assert_eq!(input.slice(s![.., 1.., ..]), target.slice(s![.., ..3, ..]));
+        // every step is one-hot
+        assert_eq!(input.sum_axis(Axis(2)), Array2::<f64>::ones((3, 4)));
+    }
+
+    #[test]
+    fn test_short_text_is_empty() {
+        let data = CharDataset::<f32>::new("abc", 3);
+        assert!(data.is_empty());
+    }
+}
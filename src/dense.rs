@@ -1,24 +1,36 @@
-use std::marker::PhantomData;
+use std::{
+    io::{self, Read, Write},
+    marker::PhantomData,
+    sync::Arc,
+};
 
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
 use crate::{
     activation::{Activation, Linear},
-    array::{compact_front, dot_front, dot_inner},
+    array::{check_input_shape, compact_front, dot_front, dot_inner, input_shape},
+    binary::{invalid, read_array, write_array, Element},
+    dot::{Dot, DotWriter},
+    error::Result,
     initialisers::Initialiser,
-    train::GraphExecTrain,
-    Graph, GraphExec, Mappable, Shaped, HDF5,
+    named::Named,
+    train::{GraphExecTrain, Modal},
+    Graph, GraphExec, Mappable, Persist, Shaped,
 };
+#[cfg(feature = "hdf5")]
 use hdf5::H5Type;
 use ndarray::{
-    Array, Array1, Array2, ArrayBase, Axis, Data, Dim, DimMax, Dimension, Ix1, LinalgScalar,
-    RemoveAxis, ScalarOperand,
+    linalg::general_mat_mul, Array, Array1, Array2, ArrayBase, Axis, Data, Dim, DimMax, Dimension,
+    Ix1, LinalgScalar, RemoveAxis, ScalarOperand, Zip,
 };
-use num_traits::{FromPrimitive, One, Zero};
+use num_traits::{Float, FromPrimitive, One, Zero};
 use rand::{distributions::Distribution, Rng};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Dense<I> {
     output_size: usize,
     initialiser: I,
+    random_bias: bool,
 }
 
 pub struct DenseSize<I> {
@@ -35,9 +47,20 @@ impl<I> Dense<I> {
         }
     }
 
-    pub fn with_activation<A: Activation>(self, a: A) -> Linear<Self, A> {
+    pub const fn with_activation<A: Activation>(self, a: A) -> Linear<Self, A> {
         Linear::new(self, a)
     }
+
+    pub fn named(self, name: impl Into<Arc<str>>) -> Named<Self> {
+        Named::new(name, self)
+    }
+
+    /// Draws the biases from the initialiser, like the weights, instead of starting them at zero
+    #[must_use]
+    pub const fn with_random_bias(mut self) -> Self {
+        self.random_bias = true;
+        self
+    }
 }
 
 impl<I> DenseSize<I> {
@@ -45,13 +68,17 @@ impl<I> DenseSize<I> {
         Dense {
             output_size: self.output_size,
             initialiser,
+            random_bias: false,
         }
     }
 }
 
+/// The weights are drawn from the initialiser, and the biases start at zero
+/// unless [`with_random_bias`](Dense::with_random_bias) is used
 impl<I, F> Graph<F, usize> for Dense<I>
 where
     I: Initialiser<F, (usize, usize)>,
+    F: Zero + Clone,
 {
     type State = DenseState<F>;
     type OutputShape = usize;
@@ -66,13 +93,22 @@ where
             .into_distribution((input_size, self.output_size));
 
         let w = Array2::from_shape_simple_fn((input_size, self.output_size), || d.sample(rng));
-        let b = Array1::from_shape_simple_fn(self.output_size, || d.sample(rng));
+        let b = if self.random_bias {
+            Array1::from_shape_simple_fn(self.output_size, || d.sample(rng))
+        } else {
+            Array1::zeros(self.output_size)
+        };
 
         DenseState { w, b }
     }
+
+    fn num_params(&self, input_size: usize) -> usize {
+        (input_size + 1) * self.output_size
+    }
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DenseState<F> {
     pub w: Array2<F>,
     pub b: Array1<F>,
@@ -80,41 +116,88 @@ pub struct DenseState<F> {
 
 impl<F, S, D> GraphExec<ArrayBase<S, D>> for DenseState<F>
 where
-    F: LinalgScalar,
+    F: LinalgScalar + Float,
     D: Dimension + DimMax<Ix1, Output = D>,
     S: Data<Elem = F>,
 {
     type Output = Array<F, D>;
 
     fn exec(&self, input: ArrayBase<S, D>) -> Self::Output {
-        dot_inner(input, &self.w.view()) + self.b.view()
+        check_input_shape("Dense layer", input.shape(), self.w.nrows());
+        dot_inner(&input, &self.w.view()) + self.b.view()
+    }
+
+    fn try_exec(&self, input: ArrayBase<S, D>) -> Result<Self::Output> {
+        input_shape("Dense layer", input.shape(), self.w.nrows())?;
+        Ok(self.exec(input))
+    }
+
+    /// Adds the bias and applies the activation in a single pass over the output
+    fn exec_activated<A>(&self, input: ArrayBase<S, D>, activation: &A) -> Self::Output
+    where
+        A: Activation + GraphExec<Self::Output, Output = Self::Output>,
+    {
+        check_input_shape("Dense layer", input.shape(), self.w.nrows());
+        let mut output = dot_inner(&input, &self.w.view());
+        Zip::from(&mut output)
+            .and_broadcast(&self.b)
+            .for_each(|o, &b| *o = activation.apply(*o + b));
+        output
     }
 }
 
-impl<F, D> GraphExecTrain<Array<F, D>> for DenseState<F>
+/// Trains on owned inputs, or borrowed inputs as a [`CowArray`](ndarray::CowArray).
+/// The input is kept for the weight gradients, so borrowed inputs are never copied
+impl<F, S, D> GraphExecTrain<ArrayBase<S, D>> for DenseState<F>
 where
-    F: LinalgScalar + FromPrimitive + ScalarOperand,
+    F: LinalgScalar + Float + FromPrimitive + ScalarOperand,
+    S: Data<Elem = F>,
+    ArrayBase<S, D>: From<Array<F, D>>,
     D: Dimension + DimMax<Ix1, Output = D> + RemoveAxis,
 {
-    type State = Self::Output;
-    fn forward(&self, input: Array<F, D>) -> (Self::State, Self::Output) {
-        (input.clone(), self.exec(input))
+    type State = ArrayBase<S, D>;
+    fn forward(&self, input: ArrayBase<S, D>) -> (Self::State, Self::Output) {
+        // the input is kept for the weight gradients, so execute on a view of it
+        let output = self.exec(input.view());
+        (input, output)
+    }
+
+    fn back(&self, input: Self::State, d_output: Self::Output) -> (ArrayBase<S, D>, Self) {
+        let di = dot_inner(&d_output, &self.w.t());
+        let db = compact_front(&d_output).sum_axis(Axis(0));
+        let dw = dot_front(&input, &d_output);
+        (di.into(), Self { w: dw, b: db })
     }
 
-    fn back(&self, input: Self::State, d_output: Self::Output) -> (Array<F, D>, Self) {
-        let di = dot_inner(d_output.clone(), &self.w.t());
-        let db = compact_front(d_output.clone()).mean_axis(Axis(0)).unwrap();
-        let dw = dot_front(input, d_output);
-        (di, Self { w: dw, b: db })
+    fn back_into(
+        &self,
+        input: Self::State,
+        d_output: Self::Output,
+        grads: &mut Self,
+    ) -> ArrayBase<S, D> {
+        let di = dot_inner(&d_output, &self.w.t());
+        let d_output = compact_front(&d_output);
+        let input = compact_front(&input);
+
+        general_mat_mul(F::one(), &input.t(), &d_output, F::zero(), &mut grads.w);
+        grads.b.fill(F::zero());
+        for row in d_output.rows() {
+            Zip::from(&mut grads.b)
+                .and(&row)
+                .for_each(|b, &d| *b = *b + d);
+        }
+        di.into()
     }
 }
 
+impl<F> Modal for DenseState<F> {}
+
 impl<T> Mappable<T> for DenseState<T> {
     // not redundant. just forces a capture without needing to clone
     #![allow(clippy::redundant_closure)]
 
     fn map<F: FnMut(&T) -> T>(&self, mut f: F) -> Self {
-        let DenseState { w, b } = self;
+        let Self { w, b } = self;
         let w = w.map(|a| f(a));
         let b = b.map(f);
         Self { w, b }
@@ -127,6 +210,16 @@ impl<T> Mappable<T> for DenseState<T> {
         self.w.zip_mut_with(&rhs.w, |a, b| f(a, b));
         self.b.zip_mut_with(&rhs.b, f);
     }
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.w.zip_mut_with(&rhs.w, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, mut f: F)
+    where
+        T: Clone,
+    {
+        self.w.for_each(|a| f(a));
+        self.b.for_each(f);
+    }
 }
 
 impl<T> Shaped<T> for DenseState<T>
@@ -157,7 +250,59 @@ where
     }
 }
 
-impl<F: H5Type, I> HDF5<F, usize> for Dense<I>
+impl<I> Dot for Dense<I> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        vec![dot.layer(&format!("Dense({})", self.output_size), inputs)]
+    }
+}
+
+impl<F> Dot for DenseState<F> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let (i, o) = self.w.dim();
+        vec![dot.layer(&format!("Dense({i} → {o})"), inputs)]
+    }
+}
+
+impl<I> Dense<I> {
+    /// Checks that loaded weights and bias fit together and have this layer's output size
+    fn check_state<F>(&self, w: &Array2<F>, b: &Array1<F>) -> Result<(), String> {
+        if w.ncols() != self.output_size {
+            return Err(format!(
+                "dense layer has {} outputs, but the saved weights have shape {:?}",
+                self.output_size,
+                w.shape()
+            ));
+        }
+        if b.len() != w.ncols() {
+            return Err(format!(
+                "dense layer weights have shape {:?}, but the saved bias has shape {:?}",
+                w.shape(),
+                b.shape()
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<F: Element + Zero, I> Persist<F, usize> for Dense<I>
+where
+    I: Initialiser<F, (usize, usize)>,
+{
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        write_array(w, &state.w)?;
+        write_array(w, &state.b)
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        let w = read_array(r)?;
+        let b = read_array(r)?;
+        self.check_state(&w, &b).map_err(|msg| invalid(&msg))?;
+        Ok(DenseState { w, b })
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<F: H5Type + Zero + Clone, I> HDF5<F, usize> for Dense<I>
 where
     I: Initialiser<F, (usize, usize)>,
 {
@@ -176,7 +321,69 @@ where
     fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State> {
         let w = group.dataset("weights")?.read()?;
         let b = group.dataset("bias")?.read()?;
+        self.check_state(&w, &b)?;
 
         Ok(DenseState { w, b })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use ndarray::{Array1, Array2};
+
+    use super::{Dense, DenseState};
+    use crate::{binary::write_array, initialisers::Xavier, Graph, GraphExec, Persist};
+
+    #[test]
+    #[should_panic(
+        expected = "Dense layer expects inputs of shape [.., 4], but got an input of shape [3, 5]"
+    )]
+    fn test_input_shape_mismatch() {
+        let layer = Dense::output_size(2)
+            .with_initialiser(Xavier)
+            .input_shape(4);
+        layer.exec(Array2::<f64>::zeros((3, 5)));
+    }
+
+    #[test]
+    fn test_bias_init() {
+        let layer = Graph::<f64, _>::input_shape(Dense::output_size(8).with_initialiser(Xavier), 4);
+        assert!(layer.b.iter().all(|&b| b == 0.0));
+        assert!(layer.w.iter().any(|&w| w != 0.0));
+
+        let layer = Graph::<f64, _>::input_shape(
+            Dense::output_size(8)
+                .with_initialiser(Xavier)
+                .with_random_bias(),
+            4,
+        );
+        assert!(layer.b.iter().any(|&b| b != 0.0));
+    }
+
+    #[test]
+    fn test_read_mismatched_state() {
+        let graph = Dense::output_size(3).with_initialiser(Xavier);
+        let state: DenseState<f64> = graph.input_shape(4);
+
+        let mut bytes = vec![];
+        graph.write_state(&state, &mut bytes).unwrap();
+        let other = Dense::output_size(2).with_initialiser(Xavier);
+        let err = Persist::<f64, _>::read_state(&other, &mut bytes.as_slice()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.to_string(),
+            "dense layer has 2 outputs, but the saved weights have shape [4, 3]"
+        );
+
+        let mut bytes = vec![];
+        write_array(&mut bytes, &state.w).unwrap();
+        write_array(&mut bytes, &Array1::<f64>::zeros(2)).unwrap();
+        let err = Persist::<f64, _>::read_state(&graph, &mut bytes.as_slice()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "dense layer weights have shape [4, 3], but the saved bias has shape [2]"
+        );
+    }
+}
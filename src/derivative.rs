@@ -1,34 +1,197 @@
-// use std::ops::{Sub, Div, Add};
-
-// use crate::{GraphExec, Mappable, cost::Cost};
-
-// pub trait DerivativeTesting<F> {
-//     /// Number of adjustable parameters in the graph
-//     fn len(&self) -> usize;
-//     /// Adjust a specific parameter by the amount f
-//     fn get(&self, i: usize) -> F;
-//     fn set(&mut self, i: usize, f: F);
-// }
-
-// pub fn get_grads<G, C, I, O, F>(graph: &mut G, cost: C, f: F, input: I, expected: &O) -> G
-// where
-//     I: Clone,
-//     G: DerivativeTesting<F> + GraphExec<I, Output = O> + Clone + Mappable<F>,
-//     C: Cost<O, Inner = F>,
-//     F: Clone + Add<F, Output = F> + Div<F, Output = F> + Sub<F, Output = F>,
-// {
-//     let base_cost = cost.cost(&graph.exec(input.clone()), expected);
-
-//     let mut grads = graph.clone();
-
-//     for i in 0..graph.len() {
-//         let old = graph.get(i);
-//         graph.set(i, old.clone() + f.clone());
-//         let new_cost = cost.cost(&graph.exec(input.clone()), expected);
-//         graph.set(i, old);
-
-//         grads.set(i, (new_cost - base_cost.clone()) / f.clone());
-//     }
-
-//     grads
-// }
+use num_traits::Float;
+
+use crate::{activation::Linear, cost::Cost, dense::DenseState, train::GraphExecTrain, GraphExec};
+
+/// Flat, indexed access to every adjustable parameter in a graph.
+/// Used to estimate gradients numerically
+pub trait DerivativeTesting<F> {
+    /// Number of adjustable parameters in the graph
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Gets the value of a specific parameter
+    fn get(&self, i: usize) -> F;
+    /// Sets the value of a specific parameter
+    fn set(&mut self, i: usize, f: F);
+}
+
+impl<F: Copy> DerivativeTesting<F> for DenseState<F> {
+    fn len(&self) -> usize {
+        self.w.len() + self.b.len()
+    }
+    fn get(&self, i: usize) -> F {
+        let cols = self.w.ncols();
+        if i < self.w.len() {
+            self.w[(i / cols, i % cols)]
+        } else {
+            self.b[i - self.w.len()]
+        }
+    }
+    fn set(&mut self, i: usize, f: F) {
+        let cols = self.w.ncols();
+        if i < self.w.len() {
+            self.w[(i / cols, i % cols)] = f;
+        } else {
+            self.b[i - self.w.len()] = f;
+        }
+    }
+}
+
+impl<F, G: DerivativeTesting<F>, L> DerivativeTesting<F> for Linear<G, L> {
+    fn len(&self) -> usize {
+        self.graph.len()
+    }
+    fn get(&self, i: usize) -> F {
+        self.graph.get(i)
+    }
+    fn set(&mut self, i: usize, f: F) {
+        self.graph.set(i, f);
+    }
+}
+
+impl<F, T, U> DerivativeTesting<F> for (T, U)
+where
+    T: DerivativeTesting<F>,
+    U: DerivativeTesting<F>,
+{
+    fn len(&self) -> usize {
+        self.0.len() + self.1.len()
+    }
+    fn get(&self, i: usize) -> F {
+        let n = self.0.len();
+        if i < n {
+            self.0.get(i)
+        } else {
+            self.1.get(i - n)
+        }
+    }
+    fn set(&mut self, i: usize, f: F) {
+        let n = self.0.len();
+        if i < n {
+            self.0.set(i, f);
+        } else {
+            self.1.set(i - n, f);
+        }
+    }
+}
+
+/// Estimates the gradient of the cost with respect to every parameter in the graph
+/// using central differences of size `eps`
+pub fn numerical_grads<G, C, I, O, F>(graph: &mut G, cost: &C, eps: F, input: &I, expected: &O) -> G
+where
+    I: Clone,
+    G: DerivativeTesting<F> + GraphExec<I, Output = O> + Clone,
+    C: Cost<O, Inner = F>,
+    F: Float,
+{
+    let mut grads = graph.clone();
+    let two = F::one() + F::one();
+
+    for i in 0..graph.len() {
+        let old = graph.get(i);
+
+        graph.set(i, old + eps);
+        let upper = cost.cost(&graph.exec(input.clone()), expected);
+        graph.set(i, old - eps);
+        let lower = cost.cost(&graph.exec(input.clone()), expected);
+        graph.set(i, old);
+
+        grads.set(i, (upper - lower) / (two * eps));
+    }
+
+    grads
+}
+
+/// Compares the gradients calculated by the graph's backward pass against numerically
+/// estimated gradients. Returns the max relative error over every parameter.
+///
+/// Any layer whose backward pass is implemented correctly should produce
+/// a very small error (less than `1e-5` or so, depending on the precision of `F`)
+pub fn check_grads<G, C, I, O, F>(graph: &mut G, cost: &C, eps: F, input: &I, expected: &O) -> F
+where
+    I: Clone,
+    O: Clone,
+    G: DerivativeTesting<F> + GraphExecTrain<I, Output = O> + Clone,
+    C: Cost<O, Inner = F>,
+    F: Float,
+{
+    let grads = graph.get_grads(input.clone(), expected.clone(), cost).0;
+    let expected_grads = numerical_grads(graph, cost, eps, input, expected);
+
+    (0..grads.len()).fold(F::zero(), |max, i| {
+        let a = grads.get(i);
+        let n = expected_grads.get(i);
+        let scale = a.abs().max(n.abs());
+        let error = if scale > F::zero() {
+            (a - n).abs() / scale
+        } else {
+            F::zero()
+        };
+        max.max(error)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array2};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::check_grads;
+    use crate::{
+        activation::{relu::Relu, sigmoid::Sigmoid},
+        cost::mse::MSE,
+        dense::Dense,
+        initialisers::Xavier,
+        net,
+        train::GraphExecTrain,
+        Graph, Mappable,
+    };
+
+    #[test]
+    fn test_grads() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut network = net![
+            Dense::output_size(6)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid),
+            Dense::output_size(4)
+                .with_initialiser(Xavier)
+                .with_activation(Relu)
+        ]
+        .init_with_random(&mut rng, 8);
+
+        let input = Array1::<f64>::from_shape_simple_fn(8, || rng.gen());
+        let expected = Array1::<f64>::from_shape_simple_fn(4, || rng.gen());
+
+        let error = check_grads(&mut network, &MSE, 1e-6, &input, &expected);
+        assert!(error < 1e-5, "max relative error {}", error);
+    }
+
+    #[test]
+    fn test_grads_into_matches_grads() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = net![
+            Dense::output_size(6)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid),
+            Dense::output_size(4)
+                .with_initialiser(Xavier)
+                .with_activation(Relu)
+        ]
+        .init_with_random(&mut rng, 8);
+
+        let input = Array2::<f64>::from_shape_simple_fn((5, 8), || rng.gen());
+        let expected = Array2::<f64>::from_shape_simple_fn((5, 4), || rng.gen());
+
+        let (grads, cost) = network.get_grads(input.clone(), expected.clone(), &MSE);
+        // the buffer starts with unrelated values, which should all be overwritten
+        let mut buffer = network.clone();
+        let cost_into = network.get_grads_into(input, expected, &MSE, &mut buffer);
+
+        assert!((cost - cost_into).abs() < f64::EPSILON);
+        let mut diff = 0.0_f64;
+        buffer.map_mut_with(&grads, |b, &g| diff = diff.max((*b - g).abs()));
+        assert!(diff < 1e-12, "max difference {}", diff);
+    }
+}
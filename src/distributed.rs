@@ -0,0 +1,469 @@
+//! Parameter averaging between training processes over TCP, for training on several machines.
+//!
+//! One process runs a [`Coordinator`], and every training process connects to it as a
+//! [`Worker`]. Each worker trains its own copy of the graph on its own shard of the data.
+//! Every few batches, the workers send their parameters to the coordinator, which sends
+//! back their average, and every worker continues from it. This is the same scheme as
+//! [`perform_epoch_averaged`](crate::train::Train::perform_epoch_averaged), with processes
+//! instead of threads.
+//!
+//! The protocol is deliberately simple. After a handshake, where the coordinator tells each
+//! worker its rank and the number of workers, each round is one message from every worker
+//! and one reply. A message is the number of batches the worker trained since the last
+//! round, which weights its parameters in the average, then the parameters in the
+//! [binary encoding](crate::binary)'s element format. The reply is just the parameters.
+//!
+//! Every worker must use the same graph and parameter type, and should initialise it
+//! with the same seed so that they start from the same parameters. The coordinator takes
+//! the number of parameters from the first message it receives, and rejects any message
+//! with a different number before reading it. Reads and writes on every connection time
+//! out after [`DEFAULT_TIMEOUT`] unless changed, so a silent peer can't stall training
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    marker::PhantomData,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use num_traits::{Float, FromPrimitive};
+use rand::{seq::SliceRandom, thread_rng};
+use rand_distr::uniform::{SampleBorrow, SampleUniform};
+
+use crate::{
+    binary::{self, invalid, Element},
+    callback::{Timer, TrainEvent},
+    cost::Cost,
+    data::{Dataset, Samples},
+    optimise::Optimiser,
+    train::{GraphExecTrain, Modal, Train},
+    Mappable, Shaped,
+};
+
+const MAGIC: &[u8; 4] = b"LNDP";
+const VERSION: u8 = 1;
+
+/// How long a connection waits to read or write a message by default.
+/// Workers train between rounds, so this should be longer than the slowest round
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_mins(10);
+
+/// Averages the parameters sent by every worker, and sends the average back
+///
+/// ```no_run
+/// use linear_networks::distributed::Coordinator;
+///
+/// let coordinator = Coordinator::<f32>::bind("0.0.0.0:7878").unwrap();
+/// let rounds = coordinator.run(4).unwrap();
+/// println!("averaged {rounds} rounds");
+/// ```
+#[derive(Debug)]
+pub struct Coordinator<F> {
+    listener: TcpListener,
+    timeout: Option<Duration>,
+    float: PhantomData<F>,
+}
+
+impl<F: Element + Float + FromPrimitive> Coordinator<F> {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            timeout: Some(DEFAULT_TIMEOUT),
+            float: PhantomData,
+        })
+    }
+
+    /// How long to wait for each message from a worker, or `None` to wait forever
+    #[must_use]
+    pub const fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The address workers should connect to. Useful when binding to port 0
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Waits for `workers` workers to connect, then averages their parameters every
+    /// round until they have all disconnected. Returns the number of rounds
+    pub fn run(&self, workers: usize) -> io::Result<usize> {
+        let total = u32::try_from(workers).map_err(|_| invalid("too many workers"))?;
+        let mut streams = Vec::with_capacity(workers);
+        for rank in 0..total {
+            let (mut stream, _) = self.listener.accept()?;
+            stream.set_nodelay(true)?;
+            stream.set_read_timeout(self.timeout)?;
+            stream.set_write_timeout(self.timeout)?;
+            binary::read_header(&mut stream, MAGIC, VERSION)?;
+            let mut handshake = [0; 8];
+            handshake[..4].copy_from_slice(&rank.to_le_bytes());
+            handshake[4..].copy_from_slice(&total.to_le_bytes());
+            stream.write_all(&handshake)?;
+            streams.push(stream);
+        }
+
+        let mut rounds = 0;
+        let mut len = None;
+        while round::<F>(&mut streams, &mut len)? {
+            rounds += 1;
+        }
+        Ok(rounds)
+    }
+}
+
+/// Averages one message from every worker. Returns false if they have all disconnected.
+/// `len` is the number of parameters, which is set by the first message if not known yet
+fn round<F: Element + Float + FromPrimitive>(
+    streams: &mut [TcpStream],
+    len: &mut Option<usize>,
+) -> io::Result<bool> {
+    let mut messages = Vec::with_capacity(streams.len());
+    for stream in streams.iter_mut() {
+        match read_u64(stream) {
+            Ok(batches) => {
+                let params = read_params::<F>(stream, *len)?;
+                *len = Some(params.len());
+                messages.push((batches, params));
+            }
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(err) => return Err(err),
+        }
+    }
+    if messages.is_empty() {
+        return Ok(false);
+    }
+    if messages.len() < streams.len() {
+        return Err(invalid("a worker disconnected before the others"));
+    }
+    let len = messages[0].1.len();
+
+    // workers that trained nothing since the last round have nothing to contribute,
+    // unless none of them did
+    let trained: u64 = messages.iter().map(|&(batches, _)| batches).sum();
+    let weight = |batches| F::from_u64(if trained == 0 { 1 } else { batches }).unwrap();
+    let mut average = vec![F::zero(); len];
+    let mut total = F::zero();
+    for (batches, params) in &messages {
+        let weight = weight(*batches);
+        total = total + weight;
+        for (a, &p) in average.iter_mut().zip(params) {
+            *a = *a + p * weight;
+        }
+    }
+
+    let mut reply = vec![];
+    write_params(&mut reply, average.into_iter().map(|a| a / total));
+    for stream in streams {
+        stream.write_all(&reply)?;
+    }
+    Ok(true)
+}
+
+/// A connection to a [`Coordinator`], from one training process
+#[derive(Debug)]
+pub struct Worker<F> {
+    stream: TcpStream,
+    rank: usize,
+    workers: usize,
+    float: PhantomData<F>,
+}
+
+impl<F: Element> Worker<F> {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(DEFAULT_TIMEOUT))?;
+        stream.set_write_timeout(Some(DEFAULT_TIMEOUT))?;
+        binary::write_header(&mut stream, MAGIC, VERSION)?;
+        let mut handshake = [0; 8];
+        stream.read_exact(&mut handshake)?;
+        let [rank, workers] = [&handshake[..4], &handshake[4..]].map(|bytes| {
+            let mut le = [0; 4];
+            le.copy_from_slice(bytes);
+            u32::from_le_bytes(le) as usize
+        });
+        Ok(Self {
+            stream,
+            rank,
+            workers,
+            float: PhantomData,
+        })
+    }
+
+    /// How long to wait for the coordinator, or `None` to wait forever.
+    /// The default is [`DEFAULT_TIMEOUT`]
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)?;
+        self.stream.set_write_timeout(timeout)
+    }
+
+    /// The order this worker connected to the coordinator in, starting from zero
+    #[must_use]
+    pub const fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// The number of workers training together
+    #[must_use]
+    pub const fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// This worker's share of the indices of a data set with `len` samples
+    #[must_use]
+    pub fn shard(&self, len: usize) -> Vec<usize> {
+        (self.rank..len).step_by(self.workers).collect()
+    }
+
+    /// Sends the graph's parameters to the coordinator, weighted by the number of batches
+    /// trained since the last sync, then replaces them with the average of every worker's.
+    /// Blocks until every worker has synced
+    pub fn sync<G: Mappable<F>>(&mut self, graph: &mut G, batches: usize) -> io::Result<()> {
+        let batches = u64::try_from(batches).unwrap_or(u64::MAX);
+        let mut message = batches.to_le_bytes().to_vec();
+        let mut params = vec![];
+        graph.for_each(|&p| params.push(p));
+        write_params(&mut message, params.iter().copied());
+        self.stream.write_all(&message)?;
+
+        let average = read_params::<F>(&mut self.stream, Some(params.len()))?;
+        let mut average = average.into_iter();
+        graph.map_mut(|p| *p = average.next().unwrap());
+        Ok(())
+    }
+}
+
+impl<F, C, O, G> Train<F, C, O, G> {
+    /// Trains over this worker's shard of the data set once, in a random order. The graph
+    /// is synced with every other worker after every `sync_every` batches, and at the end
+    /// of the epoch.
+    ///
+    /// Every worker must use the same data set size, `batch_size` and `sync_every`,
+    /// so that they all take part in the same rounds. Each round is reported as a batch.
+    /// Returns the average cost of each batch this worker trained
+    pub fn perform_epoch_distributed<DS>(
+        &mut self,
+        data: &DS,
+        batch_size: usize,
+        worker: &mut Worker<F>,
+        sync_every: usize,
+    ) -> io::Result<F>
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Element + Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset,
+        DS::Input: Mappable<F> + Samples + Clone,
+        DS::Target: Mappable<F> + Samples,
+    {
+        assert!(batch_size > 0, "batch size must be non-zero");
+        assert!(sync_every > 0, "workers must sync at least once an epoch");
+        let epoch = self.start_epoch();
+        span!(INFO, "epoch", epoch);
+        let mut shard = worker.shard(data.len());
+        shard.shuffle(&mut thread_rng());
+        let batches: Vec<&[usize]> = shard.chunks(batch_size).collect();
+        // the first worker's shard is the largest, which every worker can work out
+        let rounds = data
+            .len()
+            .div_ceil(worker.workers())
+            .div_ceil(batch_size)
+            .div_ceil(sync_every)
+            .max(1);
+
+        let mut cost = F::zero();
+        let start = Timer::start();
+        for round in 0..rounds {
+            let step = Timer::start();
+            let mut round_cost = F::zero();
+            let (mut trained, mut samples) = (0, 0);
+            for batch in batches.iter().skip(round * sync_every).take(sync_every) {
+                round_cost = round_cost + self.train_batch(data, batch);
+                trained += 1;
+                samples += batch.len();
+            }
+            worker.sync(&mut self.graph, trained)?;
+
+            cost = cost + round_cost;
+            self.emit(&TrainEvent::BatchEnd {
+                epoch,
+                batch: round,
+                batches: rounds,
+                cost: round_cost / F::from_usize(trained.max(1)).unwrap(),
+                samples,
+                duration: step.elapsed(),
+                waited: Duration::ZERO,
+            });
+        }
+
+        let cost = cost / F::from_usize(batches.len().max(1)).unwrap();
+        self.emit(&TrainEvent::EpochEnd {
+            epoch,
+            cost,
+            samples: shard.len(),
+            learning_rate: self.current_learning_rate(),
+            duration: start.elapsed(),
+        });
+        self.epoch += 1;
+        Ok(cost)
+    }
+}
+
+fn read_u64(r: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn write_params<F: Element>(bytes: &mut Vec<u8>, params: impl Iterator<Item = F>) {
+    bytes.push(F::TAG);
+    let start = bytes.len();
+    bytes.extend_from_slice(&[0; 8]);
+    let mut count = 0_u64;
+    for p in params {
+        p.to_le(bytes);
+        count += 1;
+    }
+    bytes[start..start + 8].copy_from_slice(&count.to_le_bytes());
+}
+
+/// Reads a message of parameters, which must have `expected` parameters if given.
+/// The count comes from the peer, so the parameters are read as they arrive rather
+/// than allocated up front
+fn read_params<F: Element>(r: &mut impl Read, expected: Option<usize>) -> io::Result<Vec<F>> {
+    let mut tag = [0];
+    r.read_exact(&mut tag)?;
+    if tag[0] != F::TAG {
+        return Err(invalid("parameters have a different element type"));
+    }
+    let count = read_u64(r)?;
+    if expected.is_some_and(|expected| u64::try_from(expected) != Ok(count)) {
+        return Err(invalid("message has a different number of parameters"));
+    }
+    let len = count
+        .checked_mul(F::SIZE as u64)
+        .ok_or_else(|| invalid("too many parameters"))?;
+    let mut bytes = vec![];
+    r.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes.chunks_exact(F::SIZE).map(F::from_le).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use ndarray::array;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{read_params, write_params, Coordinator, Worker};
+    use crate::{
+        binary::Element,
+        data::{self, InMemoryDataset},
+        dense::Dense,
+        dense::DenseState,
+        initialisers::Xavier,
+        optimise::SGD,
+        train::Train,
+        Graph,
+    };
+
+    #[test]
+    fn test_sync() {
+        let coordinator = Coordinator::<f64>::bind("127.0.0.1:0").unwrap();
+        let addr = coordinator.local_addr().unwrap();
+        let workers: Vec<_> = vec![(1.0_f64, 1), (3.0, 3)]
+            .into_iter()
+            .map(|(x, batches)| {
+                thread::spawn(move || {
+                    let mut worker = Worker::connect(addr).unwrap();
+                    let mut graph = DenseState {
+                        w: array![[x]],
+                        b: array![x],
+                    };
+                    worker.sync(&mut graph, batches).unwrap();
+                    graph
+                })
+            })
+            .collect();
+        assert_eq!(coordinator.run(2).unwrap(), 1);
+        for worker in workers {
+            let graph = worker.join().unwrap();
+            // the average is weighted by the batches each worker trained
+            assert_eq!(graph.w, array![[2.5]]);
+            assert_eq!(graph.b, array![2.5]);
+        }
+    }
+
+    #[test]
+    fn test_read_params() {
+        let mut message = vec![];
+        write_params(&mut message, vec![1.0_f64, 2.0].into_iter());
+        assert_eq!(
+            read_params::<f64>(&mut &*message, None).unwrap(),
+            [1.0, 2.0]
+        );
+        assert!(read_params::<f64>(&mut &*message, Some(3)).is_err());
+        assert!(read_params::<f32>(&mut &*message, None).is_err());
+
+        // a huge count is rejected before anything is allocated for it
+        let mut message = vec![f64::TAG];
+        message.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(read_params::<f64>(&mut &*message, Some(2)).is_err());
+        assert!(read_params::<f64>(&mut &*message, None).is_err());
+        message[1..].copy_from_slice(&(1_u64 << 40).to_le_bytes());
+        let err = read_params::<f64>(&mut &*message, None).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_distributed() {
+        let coordinator = Coordinator::<f64>::bind("127.0.0.1:0").unwrap();
+        let addr = coordinator.local_addr().unwrap();
+        let init = || {
+            Graph::<f64, _>::init_with_random(
+                Dense::output_size(1).with_initialiser(Xavier),
+                &mut StdRng::seed_from_u64(0),
+                2,
+            )
+        };
+        let (inputs, targets) = data::linear_samples(&mut StdRng::seed_from_u64(1), 30);
+        let data = InMemoryDataset::new(inputs.clone(), targets.clone());
+
+        // the workers have to be spawned before the coordinator runs
+        #[allow(clippy::needless_collect)]
+        let workers: Vec<_> = (0..3)
+            .map(|_| {
+                let data = data.clone();
+                thread::spawn(move || {
+                    let mut worker = Worker::connect(addr).unwrap();
+                    // every worker starts from the same parameters
+                    let mut trainer = Train::builder(init()).optimiser(SGD::new(0.03)).build();
+                    for _ in 0..20 {
+                        trainer
+                            .perform_epoch_distributed(&data, 10, &mut worker, 1)
+                            .unwrap();
+                    }
+                    trainer.graph
+                })
+            })
+            .collect();
+        // each worker's shard is one batch of 10 samples, so 1 round every epoch
+        assert_eq!(coordinator.run(3).unwrap(), 20);
+
+        // gradients are summed over a batch, so averaging a step on each third of the
+        // data is a whole batch step at a third of the rate
+        let mut serial = Train::builder(init()).optimiser(SGD::new(0.01)).build();
+        for _ in 0..20 {
+            serial.train(inputs.clone(), targets.clone());
+        }
+        for worker in workers {
+            let graph = worker.join().unwrap();
+            let diff = (&graph.w - &serial.graph.w).mapv(f64::abs);
+            assert!(diff.iter().all(|&d| d < 1e-12), "{}", diff);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Exports the layer topology of a graph in the Graphviz DOT format.
+//!
+//! Both graph builders and their states can be exported. States know their input sizes
+//! so include them in the labels. Render the output with `dot -Tsvg network.dot`
+use std::fmt::Write;
+
+use crate::activation::{Activation, Linear};
+
+/// Builds up the nodes and edges of a DOT graph
+#[derive(Debug, Default)]
+pub struct DotWriter {
+    out: String,
+    nodes: usize,
+}
+
+impl DotWriter {
+    /// Adds a node with an edge from each of the `inputs`, returning the new node's id
+    pub fn node(&mut self, label: &str, shape: &str, inputs: &[usize]) -> usize {
+        let id = self.nodes;
+        self.nodes += 1;
+        let label = escape(label);
+        writeln!(self.out, "  n{id} [label=\"{label}\", shape={shape}];").unwrap();
+        for input in inputs {
+            writeln!(self.out, "  n{input} -> n{id};").unwrap();
+        }
+        id
+    }
+
+    /// Adds a layer, drawn as a box
+    pub fn layer(&mut self, label: &str, inputs: &[usize]) -> usize {
+        self.node(label, "box", inputs)
+    }
+
+    /// Groups together all the nodes added by `f` inside a labelled box
+    pub fn cluster<R>(&mut self, label: &str, f: impl FnOnce(&mut Self) -> R) -> R {
+        writeln!(self.out, "  subgraph cluster_{} {{", self.nodes).unwrap();
+        writeln!(self.out, "  label=\"{}\";", escape(label)).unwrap();
+        let r = f(self);
+        self.out.push_str("  }\n");
+        r
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Graphs that can describe their topology in the DOT format
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, dense::Dense, dot::Dot, initialisers::Xavier, net,
+/// };
+///
+/// let network = net![
+///     Dense::output_size(16)
+///         .with_initialiser(Xavier)
+///         .with_activation(Relu),
+///     Dense::output_size(2).with_initialiser(Xavier)
+/// ];
+/// let dot = network.to_dot();
+/// assert!(dot.starts_with("digraph {"));
+/// assert!(dot.contains("Dense(16)"));
+/// ```
+pub trait Dot {
+    /// Adds the nodes of this graph, connected to the `inputs` nodes.
+    /// Returns the nodes that produce the graph's outputs
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize>;
+
+    /// Describes the whole graph, from its input to its outputs
+    fn to_dot(&self) -> String {
+        let mut dot = DotWriter::default();
+        let input = dot.node("input", "plaintext", &[]);
+        let outputs = self.write_dot(&mut dot, &[input]);
+        dot.node("output", "plaintext", &outputs);
+        format!("digraph {{\n{}}}\n", dot.out)
+    }
+}
+
+impl<G: Dot, L: Activation> Dot for Linear<G, L> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let outputs = self.graph.write_dot(dot, inputs);
+        vec![dot.node(L::NAME, "ellipse", &outputs)]
+    }
+}
+
+impl<T: Dot, U: Dot> Dot for (T, U) {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let outputs = self.0.write_dot(dot, inputs);
+        self.1.write_dot(dot, &outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dot;
+    use crate::{
+        activation::relu::Relu, branch::Branch, dense::Dense, initialisers::Xavier, net, Graph,
+    };
+
+    #[test]
+    fn test_to_dot() {
+        let network = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu)
+                .named("hidden \"1\""),
+            Branch(
+                Dense::output_size(2).with_initialiser(Xavier),
+                Dense::output_size(1).with_initialiser(Xavier),
+            )
+        ];
+        assert!(network
+            .to_dot()
+            .contains("n3 [label=\"Dense(2)\", shape=box];"));
+        let state = Graph::<f32, _>::input_shape(network, 4);
+
+        let expected = r#"digraph {
+  n0 [label="input", shape=plaintext];
+  subgraph cluster_1 {
+  label="hidden \"1\"";
+  n1 [label="Dense(4 → 3)", shape=box];
+  n0 -> n1;
+  n2 [label="relu", shape=ellipse];
+  n1 -> n2;
+  }
+  n3 [label="Dense(3 → 2)", shape=box];
+  n2 -> n3;
+  n4 [label="Dense(3 → 1)", shape=box];
+  n2 -> n4;
+  n5 [label="output", shape=plaintext];
+  n3 -> n5;
+  n4 -> n5;
+}
+"#;
+        assert_eq!(state.to_dot(), expected);
+    }
+}
@@ -0,0 +1,206 @@
+//! Exports dense models as C headers, for running inference on microcontrollers.
+//!
+//! The header contains every weight and bias as a `static const float` array,
+//! a table describing each layer, and a small forward pass that runs over that table
+use std::io::{self, Write};
+
+use ndarray::{ArrayView1, ArrayView2};
+use num_traits::{Float, ToPrimitive};
+
+use crate::{
+    activation::{Activation, Linear},
+    dense::DenseState,
+};
+
+/// A dense layer and the name of the activation applied to its output
+pub struct DenseLayer<'a, F> {
+    pub w: ArrayView2<'a, F>,
+    pub b: ArrayView1<'a, F>,
+    pub activation: Option<&'static str>,
+}
+
+/// Graph states made up of a chain of dense layers
+pub trait DenseLayers<F> {
+    /// Pushes each of the dense layers, in order
+    fn push_layers<'a>(&'a self, layers: &mut Vec<DenseLayer<'a, F>>);
+}
+
+impl<F> DenseLayers<F> for DenseState<F> {
+    fn push_layers<'a>(&'a self, layers: &mut Vec<DenseLayer<'a, F>>) {
+        layers.push(DenseLayer {
+            w: self.w.view(),
+            b: self.b.view(),
+            activation: None,
+        });
+    }
+}
+
+impl<F, L: Activation> DenseLayers<F> for Linear<DenseState<F>, L> {
+    fn push_layers<'a>(&'a self, layers: &mut Vec<DenseLayer<'a, F>>) {
+        layers.push(DenseLayer {
+            w: self.graph.w.view(),
+            b: self.graph.b.view(),
+            activation: Some(L::NAME),
+        });
+    }
+}
+
+impl<F, T, U> DenseLayers<F> for (T, U)
+where
+    T: DenseLayers<F>,
+    U: DenseLayers<F>,
+{
+    fn push_layers<'a>(&'a self, layers: &mut Vec<DenseLayer<'a, F>>) {
+        self.0.push_layers(layers);
+        self.1.push_layers(layers);
+    }
+}
+
+/// The layer table types, with `$name` and `$NAME` replaced by the model name
+const TYPES: &str = r"enum $name_activation {
+    $NAME_LINEAR,
+    $NAME_RELU,
+    $NAME_SIGMOID,
+};
+
+struct $name_layer {
+    int inputs;
+    int outputs;
+    const float *weight;
+    const float *bias;
+    enum $name_activation activation;
+};
+";
+
+/// The forward pass over the layer table
+const PREDICT: &str = r"/* Runs the model on `input`, writing $NAME_OUTPUTS values to `output` */
+static inline void $name_predict(const float *input, float *output) {
+    float buffers[2][$NAME_MAX_WIDTH];
+    const float *x = input;
+    for (int l = 0; l < $NAME_LAYERS; l++) {
+        const struct $name_layer *layer = &$name_layers[l];
+        float *y = l == $NAME_LAYERS - 1 ? output : buffers[l % 2];
+        for (int o = 0; o < layer->outputs; o++) {
+            float sum = layer->bias[o];
+            for (int i = 0; i < layer->inputs; i++) {
+                sum += layer->weight[o * layer->inputs + i] * x[i];
+            }
+            switch (layer->activation) {
+            case $NAME_RELU: sum = sum > 0.0f ? sum : 0.0f; break;
+            case $NAME_SIGMOID: sum = 1.0f / (1.0f + expf(-sum)); break;
+            default: break;
+            }
+            y[o] = sum;
+        }
+        x = y;
+    }
+}
+";
+
+fn c_floats<'a, F: ToPrimitive + 'a>(xs: impl Iterator<Item = &'a F>) -> io::Result<String> {
+    let floats = xs
+        .map(|x| match x.to_f32() {
+            Some(x) if x.is_finite() => Ok(format!("{x:?}f")),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "weights must be finite to export",
+            )),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(floats.join(", "))
+}
+
+/// Writes the state as a C header. Every identifier is prefixed with `name`, which
+/// should be a valid C identifier. Weights are stored as `[outputs][inputs]`
+pub fn write_c_header<F, T>(state: &T, name: &str, w: &mut impl Write) -> io::Result<()>
+where
+    F: Float,
+    T: DenseLayers<F>,
+{
+    let mut layers = vec![];
+    state.push_layers(&mut layers);
+    let upper = name.to_uppercase();
+
+    writeln!(w, "/* Generated by linear-networks. Do not edit */")?;
+    writeln!(w, "#ifndef {upper}_H\n#define {upper}_H\n")?;
+    writeln!(w, "#include <math.h>\n")?;
+    writeln!(w, "#define {upper}_LAYERS {}", layers.len())?;
+    if let (Some(first), Some(last)) = (layers.first(), layers.last()) {
+        writeln!(w, "#define {upper}_INPUTS {}", first.w.nrows())?;
+        writeln!(w, "#define {upper}_OUTPUTS {}", last.w.ncols())?;
+    }
+    let max_width = layers.iter().map(|l| l.w.ncols()).max().unwrap_or(0);
+    writeln!(w, "#define {upper}_MAX_WIDTH {max_width}\n")?;
+
+    let template = |t: &str| t.replace("$name", name).replace("$NAME", &upper);
+    writeln!(w, "{}", template(TYPES))?;
+
+    let mut table = vec![];
+    for (i, layer) in layers.iter().enumerate() {
+        let (inputs, outputs) = layer.w.dim();
+        let weight = format!("{name}_layer_{i}_weight");
+        let bias = format!("{name}_layer_{i}_bias");
+
+        writeln!(w, "static const float {weight}[{outputs}][{inputs}] = {{")?;
+        for column in layer.w.columns() {
+            writeln!(w, "    {{{}}},", c_floats(column.iter())?)?;
+        }
+        writeln!(w, "}};")?;
+        writeln!(
+            w,
+            "static const float {bias}[{outputs}] = {{{}}};\n",
+            c_floats(layer.b.iter())?
+        )?;
+
+        let activation = layer.activation.unwrap_or("linear").to_uppercase();
+        table.push(format!(
+            "    {{{inputs}, {outputs}, &{weight}[0][0], {bias}, {upper}_{activation}}},"
+        ));
+    }
+
+    writeln!(
+        w,
+        "static const struct {name}_layer {name}_layers[{upper}_LAYERS] = {{"
+    )?;
+    writeln!(w, "{}\n}};\n", table.join("\n"))?;
+    writeln!(w, "{}", template(PREDICT))?;
+    writeln!(w, "#endif /* {upper}_H */")
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::write_c_header;
+    use crate::{
+        activation::{relu::Relu, Linear},
+        dense::DenseState,
+    };
+
+    #[test]
+    fn test_write_c_header() {
+        let hidden = Linear::new(
+            DenseState {
+                w: array![[1.0, 2.0], [3.0, 4.0], [5.0, 6.0]],
+                b: array![0.5, -0.5],
+            },
+            Relu,
+        );
+        let output = DenseState {
+            w: array![[1.0], [-1.0]],
+            b: array![0.0],
+        };
+
+        let mut header = vec![];
+        write_c_header(&(hidden, output), "xor", &mut header).unwrap();
+        let header = String::from_utf8(header).unwrap();
+
+        assert!(header.contains("#define XOR_LAYERS 2"));
+        assert!(header.contains("#define XOR_INPUTS 3"));
+        assert!(header.contains(
+            "static const float xor_layer_0_weight[2][3] = {\n    {1.0f, 3.0f, 5.0f},\n    {2.0f, 4.0f, 6.0f},\n};"
+        ));
+        assert!(header.contains("static const float xor_layer_0_bias[2] = {0.5f, -0.5f};"));
+        assert!(header.contains("{2, 1, &xor_layer_1_weight[0][0], xor_layer_1_bias, XOR_LINEAR}"));
+    }
+}
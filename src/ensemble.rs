@@ -0,0 +1,241 @@
+//! Combining the predictions of several independently trained graphs.
+//!
+//! Ensembles are usually more accurate than any of their members, since the members'
+//! errors partly cancel out. Training each member on a bootstrap resample of the data
+//! (bagging) makes their errors less correlated
+use ndarray::{Array, Axis, Dimension};
+use num_traits::{Float, FromPrimitive};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{array::argmax, data::Dataset, error::Result, GraphExec};
+
+/// How the outputs of the members of an [`Ensemble`] are combined
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Combine {
+    /// The average of the members' outputs
+    Mean,
+    /// The fraction of members that predicted each class, taken as the largest value
+    /// along the last axis of each member's output
+    Vote,
+}
+
+/// A set of graphs whose outputs are combined in `exec`
+///
+/// ```
+/// use linear_networks::{
+///     dense::Dense, ensemble::Ensemble, initialisers::Xavier, Graph, GraphExec,
+/// };
+/// use ndarray::Array2;
+///
+/// let members = (0..3)
+///     .map(|_| Graph::<f64, _>::input_shape(Dense::output_size(2).with_initialiser(Xavier), 4))
+///     .collect();
+/// let ensemble = Ensemble::new(members);
+///
+/// let output = ensemble.exec(Array2::<f64>::ones((5, 4)).view());
+/// assert_eq!(output.dim(), (5, 2));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Ensemble<G> {
+    pub members: Vec<G>,
+    pub combine: Combine,
+}
+
+impl<G> Ensemble<G> {
+    /// An ensemble that averages the outputs of its members
+    ///
+    /// # Panics
+    /// If there are no members
+    #[must_use]
+    pub fn new(members: Vec<G>) -> Self {
+        assert!(!members.is_empty(), "an ensemble needs at least one member");
+        Self {
+            members,
+            combine: Combine::Mean,
+        }
+    }
+
+    /// An ensemble where each member votes for a class
+    ///
+    /// # Panics
+    /// If there are no members
+    #[must_use]
+    pub fn voting(members: Vec<G>) -> Self {
+        Self {
+            combine: Combine::Vote,
+            ..Self::new(members)
+        }
+    }
+
+    /// Trains `k` members, each on a bootstrap resample of `data`: the same number
+    /// of samples, drawn with replacement. `train` is called with each resample,
+    /// and should return the trained graph
+    pub fn bootstrap<DS: Dataset>(
+        data: &DS,
+        k: usize,
+        seed: u64,
+        mut train: impl FnMut(&Bootstrap<DS>) -> G,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let members = (0..k)
+            .map(|_| train(&Bootstrap::new(data, &mut rng)))
+            .collect();
+        Self::new(members)
+    }
+
+    fn combine<F, D>(&self, outputs: impl Iterator<Item = Array<F, D>>) -> Array<F, D>
+    where
+        F: Float + FromPrimitive,
+        D: Dimension,
+    {
+        let n = F::from_usize(self.members.len()).unwrap();
+        let mut outputs = outputs;
+        let first = outputs
+            .next()
+            .expect("an ensemble needs at least one member");
+        let combined = match self.combine {
+            Combine::Mean => outputs.fold(first, |acc, output| acc + output),
+            Combine::Vote => {
+                let last = Axis(first.ndim() - 1);
+                let mut votes = Array::zeros(first.raw_dim());
+                for output in std::iter::once(first).chain(outputs) {
+                    let lanes = votes.lanes_mut(last).into_iter().zip(output.lanes(last));
+                    for (mut votes, output) in lanes {
+                        let class = argmax(&output);
+                        votes[class] = votes[class] + F::one();
+                    }
+                }
+                votes
+            }
+        };
+        combined.mapv_into(|x| x / n)
+    }
+}
+
+impl<G, F, D, Input> GraphExec<Input> for Ensemble<G>
+where
+    G: GraphExec<Input, Output = Array<F, D>>,
+    Input: Clone,
+    F: Float + FromPrimitive,
+    D: Dimension,
+{
+    type Output = Array<F, D>;
+    fn exec(&self, input: Input) -> Self::Output {
+        self.combine(self.members.iter().map(|g| g.exec(input.clone())))
+    }
+    fn try_exec(&self, input: Input) -> Result<Self::Output> {
+        let outputs = self
+            .members
+            .iter()
+            .map(|g| g.try_exec(input.clone()))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self.combine(outputs.into_iter()))
+    }
+}
+
+/// A resample of a data set, drawn with replacement
+#[derive(Debug)]
+pub struct Bootstrap<'a, DS> {
+    data: &'a DS,
+    indices: Vec<usize>,
+}
+
+impl<'a, DS: Dataset> Bootstrap<'a, DS> {
+    pub fn new(data: &'a DS, rng: &mut impl Rng) -> Self {
+        let len = data.len();
+        let indices = (0..len).map(|_| rng.gen_range(0..len)).collect();
+        Self { data, indices }
+    }
+}
+
+impl<DS: Dataset> Dataset for Bootstrap<'_, DS> {
+    type Input = DS::Input;
+    type Target = DS::Target;
+
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target) {
+        let indices: Vec<_> = indices.iter().map(|&i| self.indices[i]).collect();
+        self.data.batch(&indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{Combine, Ensemble};
+    use crate::{
+        data::{self, Dataset, InMemoryDataset},
+        dense::Dense,
+        initialisers::Xavier,
+        optimise::SGD,
+        train::Train,
+        Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_bootstrap_ensemble() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (inputs, targets) = data::linear_samples(&mut rng, 64);
+        let data = InMemoryDataset::new(inputs.clone(), targets.clone());
+
+        let mut resamples = vec![];
+        let ensemble = Ensemble::bootstrap(&data, 3, 0, |resample| {
+            assert_eq!(resample.len(), 64);
+            resamples.push(resample.indices.clone());
+            let graph = Graph::<f64, _>::init_with_random(
+                Dense::output_size(1).with_initialiser(Xavier),
+                &mut rng,
+                2,
+            );
+            let mut trainer = Train::builder(graph).optimiser(SGD::new(0.05)).build();
+            trainer.fit(resample, 8, 30);
+            trainer.graph
+        });
+        assert_eq!(ensemble.members.len(), 3);
+
+        // every member is trained on a different resample, drawn with replacement
+        assert_ne!(resamples[0], resamples[1]);
+        assert_ne!(resamples[1], resamples[2]);
+        for resample in &mut resamples {
+            resample.sort_unstable();
+            resample.dedup();
+            assert!(resample.len() < 64);
+        }
+
+        let outputs: Vec<_> = ensemble
+            .members
+            .iter()
+            .map(|g| g.exec(inputs.view()))
+            .collect();
+        let mean = (&outputs[0] + &outputs[1] + &outputs[2]) / 3.0;
+        let output = ensemble.exec(inputs.view());
+        assert!((&output - &mean).iter().all(|x| x.abs() < 1e-12));
+        assert!((output - targets).iter().all(|x| x.abs() < 0.1));
+    }
+
+    #[test]
+    fn test_voting() {
+        use crate::dense::DenseState;
+
+        let member = |w| DenseState {
+            w,
+            b: array![0.0, 0.0, 0.0],
+        };
+        let ensemble = Ensemble::voting(vec![
+            member(array![[1.0, 0.0, 0.0]]),
+            member(array![[0.0, 1.0, 0.0]]),
+            member(array![[1.0, 0.0, 0.0]]),
+            member(array![[0.0, 0.0, 1.0]]),
+        ]);
+        assert_eq!(ensemble.combine, Combine::Vote);
+        assert_eq!(
+            ensemble.exec(array![[1.0], [-1.0]]),
+            array![[0.5, 0.25, 0.25], [0.5, 0.5, 0.0]]
+        );
+    }
+}
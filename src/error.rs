@@ -0,0 +1,151 @@
+use std::fmt;
+
+/// The errors returned by the fallible APIs in this crate
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The last axis of an input didn't match the size that a layer was initialised with
+    InputShape {
+        layer: &'static str,
+        input_size: usize,
+        shape: Vec<usize>,
+    },
+    /// Two arrays that should line up, such as the outputs of a network and the
+    /// expected outputs, have different shapes
+    ShapeMismatch {
+        what: &'static str,
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+    /// Arrays that should have one entry per input sample, such as the targets,
+    /// have a different number of samples to the inputs
+    SampleCount {
+        what: &'static str,
+        inputs: usize,
+        found: usize,
+    },
+    /// There were no samples to work with, or they were split into batches of size zero
+    EmptyBatch,
+    /// Every trial of a hyperparameter search diverged, or there were no trials
+    NoTrials,
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InputShape {
+                layer,
+                input_size,
+                shape,
+            } => write!(
+                f,
+                "{layer} expects inputs of shape [.., {input_size}], but got an input of shape {shape:?}"
+            ),
+            Self::ShapeMismatch {
+                what,
+                expected,
+                found,
+            } => write!(f, "{what} should have shape {expected:?}, but has shape {found:?}"),
+            Self::SampleCount {
+                what,
+                inputs,
+                found,
+            } => write!(
+                f,
+                "{what} have {found} samples, but there are {inputs} inputs"
+            ),
+            Self::EmptyBatch => f.write_str("there are no samples"),
+            Self::NoTrials => f.write_str("no trials completed successfully"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Checks that an array has one sample for every input
+pub(crate) const fn same_samples(what: &'static str, inputs: usize, found: usize) -> Result<()> {
+    if inputs == found {
+        Ok(())
+    } else {
+        Err(Error::SampleCount {
+            what,
+            inputs,
+            found,
+        })
+    }
+}
+
+/// Checks that two arrays have the same shape
+pub(crate) fn same_shape(what: &'static str, expected: &[usize], found: &[usize]) -> Result<()> {
+    if expected == found {
+        Ok(())
+    } else {
+        Err(Error::ShapeMismatch {
+            what,
+            expected: expected.to_vec(),
+            found: found.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{s, Array2, Array3};
+
+    use super::Error;
+    use crate::{
+        activation::relu::Relu, dense::Dense, initialisers::Xavier, net, optimise::sgd::SGD,
+        train::Train, Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_errors() {
+        let graph = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ]
+        .input_shape(4);
+
+        let err = graph.try_exec(Array2::<f64>::zeros((5, 3))).unwrap_err();
+        assert!(matches!(err, Error::InputShape { input_size: 4, .. }));
+        assert_eq!(
+            err.to_string(),
+            "Dense layer expects inputs of shape [.., 4], but got an input of shape [5, 3]"
+        );
+
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.1)).build();
+        let inputs = Array2::<f64>::zeros((5, 4));
+        let err = trainer
+            .evaluate(&inputs.view(), &Array2::zeros((5, 3)).view(), 2, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected outputs should have shape [2, 2], but has shape [2, 3]"
+        );
+        assert!(trainer
+            .evaluate(&inputs.view(), &Array2::zeros((5, 2)).view(), 2, &[])
+            .is_ok());
+
+        let err = trainer
+            .evaluate(&inputs.view(), &Array2::zeros((4, 2)).view(), 2, &[])
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "expected outputs have 4 samples, but there are 5 inputs"
+        );
+        let err = trainer
+            .evaluate(&inputs.view(), &Array2::zeros((5, 2)).view(), 0, &[])
+            .unwrap_err();
+        assert!(matches!(err, Error::EmptyBatch));
+
+        // strided inputs aren't in standard layout, so they're copied before the dot product
+        let strided = Array3::<f64>::ones((2, 6, 4));
+        let strided = strided.slice(s![.., ..;2, ..]);
+        let output = trainer.graph.exec(strided.to_owned());
+        assert_eq!(trainer.graph.try_exec(strided).unwrap(), output);
+    }
+}
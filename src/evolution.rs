@@ -0,0 +1,155 @@
+//! Gradient free training with evolution strategies.
+//!
+//! Instead of backpropagating, the gradient of a fitness function is estimated by
+//! evaluating it on randomly perturbed copies of the graph. Parameters are moved towards
+//! the perturbations that scored well. The fitness can be anything computed from the
+//! graph, even if it isn't differentiable, such as the accuracy of a classifier or the
+//! total reward of an episode
+use num_traits::Float;
+use rand::Rng;
+use rand_distr::{Distribution, StandardNormal};
+
+use crate::{optimise::Optimiser, Mappable, Shaped};
+
+/// Estimates gradients using antithetic sampling. Each noise sample is used to perturb
+/// the parameters in both directions, which cancels out most of the estimate's variance
+///
+/// ```
+/// use linear_networks::{
+///     dense::DenseState, evolution::EvolutionStrategies, optimise::SGD, GraphExec,
+/// };
+/// use ndarray::array;
+/// use rand::{rngs::StdRng, SeedableRng};
+///
+/// let mut graph = DenseState {
+///     w: array![[0.0]],
+///     b: array![0.0],
+/// };
+/// // the closer the output is to 3 for an input of 1, the fitter the graph
+/// let fitness = |graph: &DenseState<f64>| -(graph.exec(array![1.0])[0] - 3.0).powi(2);
+///
+/// let es = EvolutionStrategies::new(20, 0.1);
+/// let mut sgd = SGD::new(0.05);
+/// let mut rng = StdRng::seed_from_u64(0);
+/// for _ in 0..100 {
+///     es.step(&mut graph, &mut sgd, &mut rng, fitness);
+/// }
+/// assert!(fitness(&graph) > -1e-3);
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct EvolutionStrategies<F> {
+    /// The number of noise samples per step. The fitness is evaluated twice for each
+    pub population: usize,
+    /// The standard deviation of the noise added to each parameter
+    pub sigma: F,
+}
+
+impl<F: Float> EvolutionStrategies<F> {
+    /// # Panics
+    /// If `population` is zero
+    #[must_use]
+    pub fn new(population: usize, sigma: F) -> Self {
+        assert!(population > 0, "evolution strategies needs a population");
+        Self { population, sigma }
+    }
+
+    /// Estimates the gradient of `fitness` with respect to every parameter of the graph.
+    /// Also returns the mean fitness of the perturbed graphs
+    pub fn gradient<G>(
+        &self,
+        graph: &G,
+        rng: &mut impl Rng,
+        mut fitness: impl FnMut(&G) -> F,
+    ) -> (G, F)
+    where
+        G: Mappable<F> + Shaped<F> + Clone,
+        StandardNormal: Distribution<F>,
+    {
+        let mut grads = G::zero(graph.shape());
+        let mut perturbed = graph.clone();
+        let mut total = F::zero();
+        let n = F::from(self.population).unwrap();
+        let scale = (n * self.sigma * (F::one() + F::one())).recip();
+
+        for _ in 0..self.population {
+            let noise = G::iter(graph.shape(), (&mut *rng).sample_iter(StandardNormal));
+
+            perturbed.map_mut_with(graph, |p, &x| *p = x);
+            perturbed.map_mut_with(&noise, |p, &e| *p = *p + self.sigma * e);
+            let upper = fitness(&perturbed);
+
+            perturbed.map_mut_with(graph, |p, &x| *p = x);
+            perturbed.map_mut_with(&noise, |p, &e| *p = *p - self.sigma * e);
+            let lower = fitness(&perturbed);
+
+            let weight = (upper - lower) * scale;
+            grads.map_mut_with(&noise, |g, &e| *g = *g + weight * e);
+            total = total + upper + lower;
+        }
+        (grads, total / (n + n))
+    }
+
+    /// Moves the graph towards higher fitness, using the optimiser with the estimated
+    /// gradient. Returns the mean fitness of the perturbed graphs
+    pub fn step<G, O>(
+        &self,
+        graph: &mut G,
+        optimiser: &mut O,
+        rng: &mut impl Rng,
+        fitness: impl FnMut(&G) -> F,
+    ) -> F
+    where
+        G: Mappable<F> + Shaped<F> + Clone,
+        O: Optimiser<G>,
+        StandardNormal: Distribution<F>,
+    {
+        let (mut grads, mean) = self.gradient(graph, rng, fitness);
+        // optimisers descend, but the fitness should go up
+        grads.map_mut(|g| *g = -*g);
+        optimiser.optimise(graph, &mut grads);
+        mean
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array1;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::EvolutionStrategies;
+    use crate::{
+        cost::{mse::MSE, Cost},
+        dense::Dense,
+        initialisers::Xavier,
+        train::GraphExecTrain,
+        Graph, GraphExec, Mappable,
+    };
+
+    #[test]
+    fn test_matches_backprop() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Graph::<f64, _>::init_with_random(
+            Dense::output_size(2).with_initialiser(Xavier),
+            &mut rng,
+            3,
+        );
+        let input = Array1::from_shape_simple_fn(3, || rng.gen_range(-1.0..1.0));
+        let expected = Array1::from_shape_simple_fn(2, || rng.gen_range(-1.0..1.0));
+
+        let (backprop, _) = graph.get_grads(input.clone(), expected.clone(), &MSE);
+        let es = EvolutionStrategies::new(2000, 1e-3);
+        let (estimate, _) = es.gradient(&graph, &mut rng, |graph| {
+            -MSE.cost(&graph.exec(input.view()).to_owned(), &expected)
+        });
+
+        // the estimate is of the fitness, which is the negative of the cost
+        let (mut x, mut y) = (vec![], vec![]);
+        backprop.for_each(|&g| x.push(-g));
+        estimate.for_each(|&g| y.push(g));
+        let dot = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(a, b)| a * b).sum::<f64>();
+        let (a, b) = (dot(&x, &x).sqrt(), dot(&y, &y).sqrt());
+        let cosine = dot(&x, &y) / (a * b);
+        assert!(cosine > 0.95, "cosine similarity {}", cosine);
+        assert!((b / a - 1.0).abs() < 0.2);
+    }
+}
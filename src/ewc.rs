@@ -0,0 +1,226 @@
+//! Elastic weight consolidation, for learning new tasks without forgetting old ones.
+//!
+//! Training on a new task moves every parameter, including the ones an old task relied on.
+//! After training on a task, [`Ewc`] records the parameters, and how much the task's cost
+//! depends on each of them, estimated by the Fisher information. Training on later tasks
+//! with [`Consolidated`] then pulls each parameter back towards its recorded value,
+//! in proportion to how important it was
+use num_traits::Float;
+
+use crate::{
+    cost::Cost,
+    data::Dataset,
+    optimise::{Optimiser, TunableOptimiser},
+    train::GraphExecTrain,
+    Mappable,
+};
+
+/// The parameters a task was learned with, and how important each of them is to it
+#[derive(Debug, Clone)]
+pub struct Ewc<F, G> {
+    /// The parameters at the end of the task
+    pub anchor: G,
+    /// The diagonal of the Fisher information: the mean squared gradient of the task's
+    /// cost with respect to each parameter
+    pub fisher: G,
+    /// How strongly the parameters are pulled back towards the anchor
+    pub lambda: F,
+}
+
+impl<F: Float, G: Mappable<F> + Clone> Ewc<F, G> {
+    /// Estimates the Fisher information of the graph on every sample of the task's data set.
+    /// The gradients are computed one sample at a time, since the square of a batch's
+    /// gradient is not the mean of the squares of each sample's.
+    ///
+    /// This is the empirical Fisher, using the data set's targets. A task the graph fits
+    /// exactly has no gradients, so it's estimated to depend on none of the parameters
+    pub fn new<DS, C>(graph: &G, data: &DS, cost: &C, lambda: F) -> Self
+    where
+        DS: Dataset,
+        G: GraphExecTrain<DS::Input, Output = DS::Target>,
+        C: Cost<G::Output>,
+    {
+        let mut fisher = graph.map(|_| F::zero());
+        for i in 0..data.len() {
+            let (input, target) = data.get(i);
+            let (grads, _) = graph.get_grads(input, target, cost);
+            fisher.map_mut_with(&grads, |f, &g| *f = *f + g * g);
+        }
+        let n = F::from(data.len().max(1)).unwrap();
+        fisher.map_mut(|f| *f = *f / n);
+
+        Self {
+            anchor: graph.clone(),
+            fisher,
+            lambda,
+        }
+    }
+
+    /// The penalty for moving away from the anchor: `lambda` times the sum of
+    /// `fisher * (x - anchor)²` over every parameter, like [`L2`](crate::train::Regularisation::L2)
+    pub fn penalty(&self, graph: &G) -> F {
+        let mut penalty = graph.clone();
+        penalty.map_mut_with(&self.anchor, |x, &a| *x = (*x - a) * (*x - a));
+        penalty.map_mut_with(&self.fisher, |x, &f| *x = *x * f);
+        let mut total = F::zero();
+        penalty.for_each(|&x| total = total + x);
+        total * self.lambda
+    }
+
+    /// Adds the gradient of the [`penalty`](Self::penalty) to `grads`
+    pub fn add_grads(&self, graph: &G, grads: &mut G) {
+        let two = self.lambda + self.lambda;
+        let mut pull = graph.clone();
+        pull.map_mut_with(&self.anchor, |x, &a| *x = *x - a);
+        pull.map_mut_with(&self.fisher, |x, &f| *x = *x * f * two);
+        grads.map_mut_with(&pull, |g, &p| *g = *g + p);
+    }
+
+    /// Wraps an optimiser, so that training on the next task consolidates this one
+    pub const fn consolidated<O>(self, optimiser: O) -> Consolidated<F, O, G> {
+        Consolidated {
+            optimiser,
+            ewc: self,
+        }
+    }
+
+    /// Combines the consolidation of an earlier task with a later one, to keep
+    /// consolidating both. The Fisher information is summed and the anchor moves to the
+    /// parameters of the later task, as in online EWC. This approximates keeping a
+    /// separate penalty for every task, without the memory cost
+    #[must_use]
+    pub fn merge(mut self, later: Self) -> Self {
+        self.fisher.map_mut_with(&later.fisher, |a, &b| *a = *a + b);
+        Self {
+            anchor: later.anchor,
+            fisher: self.fisher,
+            lambda: later.lambda,
+        }
+    }
+}
+
+/// An optimiser that adds the gradient of the [`Ewc`] penalty to every step
+#[derive(Debug, Clone)]
+pub struct Consolidated<F, O, G> {
+    pub optimiser: O,
+    pub ewc: Ewc<F, G>,
+}
+
+impl<F, O, G> Optimiser<G> for Consolidated<F, O, G>
+where
+    O: Optimiser<G>,
+    G: Mappable<F> + Clone,
+    F: Float,
+{
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
+        self.ewc.add_grads(graph, grads);
+        self.optimiser.optimise(graph, grads);
+    }
+    fn loss_scale(&self) -> Option<f64> {
+        self.optimiser.loss_scale()
+    }
+}
+
+impl<F, O: TunableOptimiser<F>, G> TunableOptimiser<F> for Consolidated<F, O, G> {
+    fn learning_rate(&self) -> F {
+        self.optimiser.learning_rate()
+    }
+    fn set_learning_rate(&mut self, learning_rate: F) {
+        self.optimiser.set_learning_rate(learning_rate);
+    }
+    fn momentum(&self) -> Option<F> {
+        self.optimiser.momentum()
+    }
+    fn set_momentum(&mut self, momentum: F) {
+        self.optimiser.set_momentum(momentum);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::Ewc;
+    use crate::{
+        cost::mse::MSE,
+        data::InMemoryDataset,
+        dense::{Dense, DenseState},
+        initialisers::Xavier,
+        optimise::{Optimiser, SGD},
+        train::Train,
+        Graph, GraphExec, Mappable,
+    };
+
+    fn task(f: impl Fn(f64) -> [f64; 3]) -> InMemoryDataset<f64, ndarray::Ix2, ndarray::Ix2> {
+        let samples: Vec<_> = (0..20).map(|i| f(f64::from(i) / 10.0 - 1.0)).collect();
+        // the targets are noisy, otherwise the gradients vanish once a task is learned
+        let noise = |i: usize| [0.2, -0.2][i % 2];
+        InMemoryDataset::new(
+            Array2::from_shape_fn((20, 2), |(i, j)| samples[i][j]),
+            Array2::from_shape_fn((20, 1), |(i, _)| samples[i][2] + noise(i)),
+        )
+    }
+
+    fn cost(
+        graph: &DenseState<f64>,
+        data: &InMemoryDataset<f64, ndarray::Ix2, ndarray::Ix2>,
+    ) -> f64 {
+        let error = graph.exec(data.inputs.view()) - &data.targets;
+        error.mapv(|e| e * e).mean().unwrap()
+    }
+
+    #[test]
+    fn test_ewc() {
+        // the first task only needs the sum of the weights to be two,
+        // the second needs the first weight to be zero
+        let first = task(|t| [t, t, 2.0 * t]);
+        let second = task(|t| [t, 0.0, 0.0]);
+
+        let graph = Graph::<f64, _>::init_with_random(
+            Dense::output_size(1).with_initialiser(Xavier),
+            &mut StdRng::seed_from_u64(0),
+            2,
+        );
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.05)).build();
+        trainer.fit(&first, 4, 100);
+        assert!(cost(&trainer.graph, &first) < 0.05);
+
+        let ewc = Ewc::new(&trainer.graph, &first, &MSE, 100.0);
+        assert!(ewc.penalty(&trainer.graph).abs() < 1e-12);
+        let mut fisher = vec![];
+        ewc.fisher.for_each(|&f| fisher.push(f));
+        // both weights are equally important
+        assert!((fisher[0] - fisher[1]).abs() < 1e-9 && fisher[0] > 0.01);
+
+        // SGD diverges once the learning rate times the curvature reaches two. The penalty
+        // adds 2 * lambda * fisher to the curvature, which is 32 for the bias, and the cost
+        // adds 8 over a batch of 4, so the second task needs a smaller learning rate
+        let mut forgetful = Train::builder(trainer.graph.clone())
+            .optimiser(SGD::new(0.025))
+            .build();
+        forgetful.fit(&second, 4, 100);
+        let mut consolidated = Train::builder(trainer.graph.clone())
+            .optimiser(ewc.clone().consolidated(SGD::new(0.025)))
+            .build();
+        consolidated.fit(&second, 4, 100);
+
+        let forgotten = cost(&forgetful.graph, &first);
+        let remembered = cost(&consolidated.graph, &first);
+        assert!(
+            remembered < forgotten / 2.0,
+            "{} >= {}",
+            remembered,
+            forgotten
+        );
+        assert!(ewc.penalty(&consolidated.graph) < ewc.penalty(&forgetful.graph));
+
+        // the penalty pulls the parameters back to the anchor
+        let mut graph = forgetful.graph.clone();
+        let mut grads = graph.map(|_| 0.0);
+        let mut optimiser = ewc.consolidated(SGD::new(0.01));
+        let before = optimiser.ewc.penalty(&graph);
+        optimiser.optimise(&mut graph, &mut grads);
+        assert!(optimiser.ewc.penalty(&graph) < before);
+    }
+}
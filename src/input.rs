@@ -0,0 +1,301 @@
+//! Declares the input shape of a network as part of the network itself.
+//!
+//! A network starting with [`Input`] takes `()` as its input shape, so it can be
+//! initialised without passing the size of the data separately. The declared shape
+//! is kept in the state, and inputs of any other shape are rejected with an error
+use std::{
+    any::Any,
+    fmt::Debug,
+    io::{self, Read, Write},
+};
+
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    array::{check_input_shape, input_shape},
+    binary::Element,
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    embedded::{DenseLayer, DenseLayers},
+    error::Result,
+    named::GetLayer,
+    precision::Cast,
+    quantise::Quantise,
+    stats::{LayerStats, Stats},
+    tensors::{ParamPath, Tensors},
+    train::{GraphExecTrain, Modal, Mode},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+#[cfg(feature = "hdf5")]
+use hdf5::H5Type;
+use ndarray::{ArrayBase, ArrayViewD, ArrayViewMutD, Data, Dimension};
+use rand::Rng;
+
+/// The shape of the inputs to a network. Used as both the builder and the state.
+/// It has no parameters and passes its input through unchanged
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, dense::Dense, initialisers::Xavier, input::Input, net, Graph,
+///     GraphExec,
+/// };
+/// use ndarray::Array2;
+///
+/// let network = net![
+///     Input(28 * 28),
+///     Dense::output_size(16)
+///         .with_initialiser(Xavier)
+///         .with_activation(Relu),
+///     Dense::output_size(10).with_initialiser(Xavier)
+/// ];
+/// let state = Graph::<f32, ()>::input_shape(network, ());
+///
+/// let output = state.exec(Array2::zeros((2, 28 * 28)));
+/// assert_eq!(output.dim(), (2, 10));
+///
+/// let err = state.try_exec(Array2::zeros((2, 28))).unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     "Input layer expects inputs of shape [.., 784], but got an input of shape [2, 28]"
+/// );
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Input<S>(pub S);
+
+impl<F, S: Clone> Graph<F, ()> for Input<S> {
+    type State = Self;
+    type OutputShape = S;
+
+    fn get_output_shape(&self) -> Self::OutputShape {
+        self.0.clone()
+    }
+
+    fn init_with_random(self, _rng: &mut impl Rng, _input_shape: ()) -> Self::State {
+        self
+    }
+
+    fn num_params(&self, _input_shape: ()) -> usize {
+        0
+    }
+}
+
+impl<S, D> GraphExec<ArrayBase<S, D>> for Input<usize>
+where
+    S: Data,
+    D: Dimension,
+{
+    type Output = ArrayBase<S, D>;
+
+    fn exec(&self, input: ArrayBase<S, D>) -> Self::Output {
+        check_input_shape("Input layer", input.shape(), self.0);
+        input
+    }
+
+    fn try_exec(&self, input: ArrayBase<S, D>) -> Result<Self::Output> {
+        input_shape("Input layer", input.shape(), self.0)?;
+        Ok(input)
+    }
+}
+
+impl<S, D> GraphExecTrain<ArrayBase<S, D>> for Input<usize>
+where
+    S: Data,
+    D: Dimension,
+{
+    type State = ();
+    fn forward(&self, input: ArrayBase<S, D>) -> (Self::State, Self::Output) {
+        ((), self.exec(input))
+    }
+
+    fn back(&self, _state: Self::State, d_output: Self::Output) -> (ArrayBase<S, D>, Self) {
+        (d_output, *self)
+    }
+
+    fn back_into(
+        &self,
+        _state: Self::State,
+        d_output: Self::Output,
+        _grads: &mut Self,
+    ) -> ArrayBase<S, D> {
+        d_output
+    }
+}
+
+impl<S> Modal for Input<S> {
+    fn set_mode(&mut self, _mode: Mode) {}
+}
+
+impl<T, S: Clone> Mappable<T> for Input<S> {
+    fn map<F: FnMut(&T) -> T>(&self, _f: F) -> Self {
+        self.clone()
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, _f: F) {}
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, _rhs: &Self, _f: F) {}
+    fn for_each<F: FnMut(&T)>(&self, _f: F)
+    where
+        T: Clone,
+    {
+    }
+}
+
+impl<F, S: Clone> Shaped<F> for Input<S> {
+    type Shape = Self;
+    fn shape(&self) -> Self::Shape {
+        self.clone()
+    }
+    fn zero(shape: Self::Shape) -> Self {
+        shape
+    }
+    fn one(shape: Self::Shape) -> Self {
+        shape
+    }
+    fn iter(shape: Self::Shape, _i: impl Iterator<Item = F>) -> Self {
+        shape
+    }
+}
+
+impl<S: Debug> Dot for Input<S> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        vec![dot.layer(&format!("Input({:?})", self.0), inputs)]
+    }
+}
+
+impl<S> GetLayer for Input<S> {
+    fn get_layer(&self, _name: &str) -> Option<&dyn Any> {
+        None
+    }
+    fn get_layer_mut(&mut self, _name: &str) -> Option<&mut dyn Any> {
+        None
+    }
+}
+
+impl<F, S> LayerStats<F> for Input<S> {
+    fn push_stats(&self, _stats: &mut Vec<Stats<F>>) {}
+}
+
+impl<F, S> DenseLayers<F> for Input<S> {
+    fn push_layers<'a>(&'a self, _layers: &mut Vec<DenseLayer<'a, F>>) {}
+}
+
+impl<F, S> DerivativeTesting<F> for Input<S> {
+    fn len(&self) -> usize {
+        0
+    }
+    fn get(&self, _i: usize) -> F {
+        panic!("input layers have no parameters")
+    }
+    fn set(&mut self, _i: usize, _f: F) {
+        panic!("input layers have no parameters")
+    }
+}
+
+impl<F, S> Tensors<F> for Input<S> {
+    fn visit<'a>(
+        &'a self,
+        _path: &mut ParamPath,
+        _f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>),
+    ) {
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        _path: &mut ParamPath,
+        _f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+    }
+}
+
+impl<S: Clone> Cast<Self> for Input<S> {
+    fn cast(&self) -> Self {
+        self.clone()
+    }
+    fn cast_into(&self, _output: &mut Self) {}
+}
+
+impl<S: Clone> Quantise for Input<S> {
+    type Quantised = Self;
+    fn quantise(&self) -> Self::Quantised {
+        self.clone()
+    }
+}
+
+impl<F: Element, S: Clone> Persist<F, ()> for Input<S> {
+    fn write_state(&self, _state: &Self::State, _w: &mut impl Write) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn read_state(&self, _r: &mut impl Read) -> io::Result<Self::State> {
+        Ok(self.clone())
+    }
+}
+
+/// Input layers have nothing to save, so they don't take up a `layer_{index}` group
+#[cfg(feature = "hdf5")]
+impl<F: H5Type, S: Clone> HDF5<F, ()> for Input<S> {
+    fn save(&self, _state: &Self::State, _group: &hdf5::Group) -> hdf5::Result<()> {
+        Ok(())
+    }
+
+    fn load(&self, _group: &hdf5::Group) -> hdf5::Result<Self::State> {
+        Ok(self.clone())
+    }
+
+    fn save_layers(
+        &self,
+        _state: &Self::State,
+        _group: &hdf5::Group,
+        _index: &mut usize,
+    ) -> hdf5::Result<()> {
+        Ok(())
+    }
+
+    fn load_layers(&self, _group: &hdf5::Group, _index: &mut usize) -> hdf5::Result<Self::State> {
+        Ok(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array2};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Input;
+    use crate::{
+        activation::sigmoid::Sigmoid, cost::mse::MSE, dense::Dense, derivative::check_grads,
+        initialisers::Xavier, net, Graph, GraphExec, Mappable,
+    };
+
+    #[test]
+    fn test_input() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = net![
+            Input(4),
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ];
+        assert_eq!(Graph::<f64, ()>::num_params(&network, ()), 23);
+
+        let mut state = network.init_with_random(&mut rng, ());
+        assert_eq!(state.num_params(), 23);
+        assert_eq!(state.0 .0, 4);
+
+        let input = Array1::<f64>::from_shape_simple_fn(4, || rng.gen());
+        let expected = Array1::<f64>::from_shape_simple_fn(2, || rng.gen());
+        assert_eq!(state.exec(input.view()), state.1.exec(input.view()));
+
+        let error = check_grads(&mut state, &MSE, 1e-6, &input, &expected);
+        assert!(error < 1e-5, "max relative error {}", error);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Input layer expects inputs of shape [.., 4], but got an input of shape [5, 3]"
+    )]
+    fn test_input_shape_mismatch() {
+        let state = Input(4);
+        state.exec(Array2::<f64>::zeros((5, 3)));
+    }
+}
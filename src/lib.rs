@@ -6,23 +6,114 @@
     clippy::missing_errors_doc
 )]
 
+#[macro_use]
+mod trace;
+
 pub mod activation;
 mod array;
+pub mod autoencoder;
+pub mod beam;
+pub mod binary;
+pub mod branch;
+pub mod callback;
+pub mod checkpoint;
+pub mod classify;
 pub mod cost;
+pub mod data;
+pub mod datasets;
 pub mod dense;
 pub mod derivative;
+#[cfg(feature = "distributed")]
+pub mod distributed;
+pub mod dot;
+pub mod embedded;
+pub mod ensemble;
+pub mod error;
+pub mod evolution;
+pub mod ewc;
 pub mod initialisers;
+pub mod input;
+pub mod metrics;
+pub mod named;
 pub mod network;
+pub mod onnx;
 pub mod optimise;
+pub mod precision;
+pub mod prune;
+pub mod quantise;
+pub mod reinforce;
+pub mod repeat;
+pub mod sampling;
+pub mod saved;
+pub mod schedule;
+pub mod search;
+pub mod sequential;
+pub mod siamese;
+pub mod single;
+pub mod sparse;
+pub mod stats;
+pub mod tensors;
 pub mod train;
+pub mod transfer;
+
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
 
+use activation::Activation;
+use binary::Element;
+#[cfg(feature = "hdf5")]
 use hdf5::H5Type;
 use rand::Rng;
 
 pub trait Mappable<T> {
+    #[must_use]
     fn map<F: FnMut(&T) -> T>(&self, f: F) -> Self;
     fn map_mut<F: FnMut(&mut T)>(&mut self, f: F);
     fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F);
+
+    /// Calls `f` with every parameter, in the same order as [`map`](Self::map).
+    /// The default goes through `map`, which makes a throwaway copy of every parameter,
+    /// so it's worth overriding
+    fn for_each<F: FnMut(&T)>(&self, mut f: F)
+    where
+        T: Clone,
+        Self: Sized,
+    {
+        let _ = self.map(|x| {
+            f(x);
+            x.clone()
+        });
+    }
+
+    /// Like [`map_mut_with`](Self::map_mut_with), but skips biases. Used to regularise
+    /// only the weights. Containers forward this to each graph they contain.
+    /// By default, every parameter counts as a weight
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.map_mut_with(rhs, f);
+    }
+
+    /// The number of parameters
+    fn num_params(&self) -> usize
+    where
+        T: Clone,
+        Self: Sized,
+    {
+        let mut count = 0;
+        self.for_each(|_| count += 1);
+        count
+    }
+
+    /// The memory used by the parameters, in bytes
+    fn memory_bytes(&self) -> usize
+    where
+        T: Clone,
+        Self: Sized,
+    {
+        self.num_params() * std::mem::size_of::<T>()
+    }
 }
 
 pub trait Shaped<F> {
@@ -39,6 +130,21 @@ pub trait GraphExec<Input> {
     /// Executes the computation graph on the given input to create
     /// the output value
     fn exec(&self, input: Input) -> Self::Output;
+
+    /// Like [`exec`](Self::exec), but returns an error if the input has the wrong shape
+    /// rather than panicking. Layers that know their input size override this
+    fn try_exec(&self, input: Input) -> error::Result<Self::Output> {
+        Ok(self.exec(input))
+    }
+
+    /// Executes the graph followed by the activation. Layers can override this to apply
+    /// the activation in the same pass that writes their output
+    fn exec_activated<A>(&self, input: Input, activation: &A) -> Self::Output
+    where
+        A: Activation + GraphExec<Self::Output, Output = Self::Output>,
+    {
+        activation.exec(self.exec(input))
+    }
 }
 
 /// An abstract representation of a Computation Graph.
@@ -59,67 +165,194 @@ pub trait Graph<F, InputShape>: Sized {
 
     /// Use to initialise with a predefined random source
     fn init_with_random(self, rng: &mut impl Rng, input_shape: InputShape) -> Self::State;
+
+    /// The number of parameters the state will have, without initialising it
+    fn num_params(&self, input_shape: InputShape) -> usize;
+
+    /// The memory the state's parameters will use, in bytes, without initialising it.
+    ///
+    /// Training needs several times this: one copy for the gradients, plus the
+    /// optimiser's own state, such as the two moment estimates kept by Adam
+    fn memory_bytes(&self, input_shape: InputShape) -> usize {
+        self.num_params(input_shape) * std::mem::size_of::<F>()
+    }
+}
+
+/// Fluent composition of graphs, as an alternative to the [`net!`] macro.
+/// Implemented for every type, so any graph, including custom layers, can be
+/// given an activation or chained into another graph
+///
+/// ```
+/// use linear_networks::{
+///     activation::{relu::Relu, sigmoid::Sigmoid},
+///     dense::Dense,
+///     initialisers::Xavier,
+///     Graph, GraphExt,
+/// };
+///
+/// let network = Dense::output_size(16)
+///     .with_initialiser(Xavier)
+///     .then_activation(Relu)
+///     .then(Dense::output_size(2).with_initialiser(Xavier))
+///     .then_activation(Sigmoid);
+/// assert_eq!(Graph::<f32, usize>::num_params(&network, 4), 114);
+/// ```
+pub trait GraphExt: Sized {
+    /// Feeds the output of this graph into `next`
+    fn then<G>(self, next: G) -> (Self, G) {
+        (self, next)
+    }
+
+    /// Applies the activation to the output of this graph
+    fn with_activation<A: Activation>(self, activation: A) -> activation::Linear<Self, A> {
+        activation::Linear::new(self, activation)
+    }
+
+    /// The same as [`with_activation`](Self::with_activation), but reads better
+    /// after [`then`](Self::then)
+    fn then_activation<A: Activation>(self, activation: A) -> activation::Linear<Self, A> {
+        self.with_activation(activation)
+    }
 }
 
+impl<G> GraphExt for G {}
+
+/// Saves and loads graph states using HDF5 files. Requires the `hdf5` feature.
+///
+/// Chains of layers, such as those built with [`net!`], save each layer into
+/// its own group named `layer_0`, `layer_1`, ... in order
+#[cfg(feature = "hdf5")]
 pub trait HDF5<F: H5Type, InputShape>: Graph<F, InputShape> {
     fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()>;
     fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State>;
+
+    /// Saves the state into the group `layer_{index}`, then increments the index.
+    /// Chains of layers override this to save each of their layers in turn
+    fn save_layers(
+        &self,
+        state: &Self::State,
+        group: &hdf5::Group,
+        index: &mut usize,
+    ) -> hdf5::Result<()> {
+        let layer = group.create_group(&format!("layer_{index}"))?;
+        *index += 1;
+        self.save(state, &layer)
+    }
+
+    /// Loads the state saved by [`save_layers`](Self::save_layers)
+    fn load_layers(&self, group: &hdf5::Group, index: &mut usize) -> hdf5::Result<Self::State> {
+        let layer = group.group(&format!("layer_{index}"))?;
+        *index += 1;
+        self.load(&layer)
+    }
 }
 
-// #[cfg(test)]
-// mod test {
-//     use activation::{relu::Relu, sigmoid::Sigmoid};
-//     use cost::MSE;
-//     use derivative::get_grads;
-//     use initialisers::Xavier;
-//     use ndarray::Array2;
-//     use optimise::{sgd::SGD, Optimiser};
-//     use rand::thread_rng;
-
-//     use crate::dense::Dense;
-//     use crate::*;
-
-//     #[test]
-//     fn test_grads() {
-//         let mut network= tuple![
-//             Dense::new(6, Xavier).with_activation(Sigmoid),
-//             Dense::new(4, Xavier).with_activation(Relu)
-//         ]
-//         .input_shape(8);
-
-//         let mut rng = thread_rng();
-
-//         let input= Array2::<f64>::from_shape_fn((8, 10), |_| rng.gen());
-//         let expected= Array2::<f64>::from_shape_fn((4, 10), |_| rng.gen());
-
-//         let grads= network.get_grads(&input, &expected, &MSE).0;
-//         let expected_grads= get_grads(&mut network, MSE, 1e-8, &input, &expected);
-
-//         println!("{:?}", expected_grads);
-//         println!("{:?}", grads);
-//         panic!();
-//     }
-
-//     #[test]
-//     fn test_train() {
-//         let mut network= tuple![
-//             Dense::new(6, Xavier).with_activation(Sigmoid),
-//             Dense::new(4, Xavier).with_activation(Sigmoid)
-//         ]
-//         .input_shape(8);
-
-//         let mut rng = thread_rng();
-
-//         let input= Array2::<f64>::from_shape_fn((8, 10), |_| rng.gen());
-//         let expected= Array2::<f64>::from_shape_fn((4, 10), |_| rng.gen());
-
-//         let cost1 = MSE.cost(&network.exec(&input), &expected);
-//         let grads= get_grads(&mut network, MSE, 1e-8, &input, &expected);
-//         let mut optimiser = SGD::new(0.1);
-//         optimiser.optimise(&mut network, grads);
-//         let cost2 = MSE.cost(&network.exec(&input), &expected);
-
-//         println!("cost1 {:?}, cost2: {:?}", cost1, cost2);
-//         panic!();
-//     }
-// }
+const MODEL_MAGIC: &[u8; 4] = b"NRMD";
+const MODEL_VERSION: u8 = 2;
+
+/// Saves and loads graph states using the pure-Rust [`binary`] encoding.
+/// Containers write each of their graphs' states in order
+pub trait Persist<F: Element, InputShape>: Graph<F, InputShape> {
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()>;
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State>;
+
+    /// Saves the state to a model file, starting with a magic header and format version,
+    /// followed by a checksum of the parameters so corrupted files are caught on load.
+    ///
+    /// The file is written to a temporary path first and then renamed, so a checkpoint
+    /// interrupted part way through never replaces a good one
+    fn save_file(&self, state: &Self::State, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        span!(INFO, "save_file", path = %path.display());
+        let tmp = path.with_extension("tmp");
+
+        let mut params = vec![];
+        self.write_state(state, &mut params)?;
+
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        binary::write_header(&mut w, MODEL_MAGIC, MODEL_VERSION)?;
+        w.write_all(&[F::TAG])?;
+        w.write_all(&binary::checksum(&params).to_le_bytes())?;
+        w.write_all(&params)?;
+        w.into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+
+        fs::rename(tmp, path)
+    }
+
+    /// Loads the state from a model file written by [`save_file`](Self::save_file).
+    /// Files written before checksums were added are still read, without the check
+    fn load_file(&self, path: impl AsRef<Path>) -> io::Result<Self::State> {
+        let path = path.as_ref();
+        span!(INFO, "load_file", path = %path.display());
+        let mut r = BufReader::new(File::open(path)?);
+        let version = binary::read_header_versions(&mut r, MODEL_MAGIC, 1..=MODEL_VERSION)?;
+
+        let mut tag = [0];
+        r.read_exact(&mut tag)?;
+        if tag[0] != F::TAG {
+            return Err(binary::invalid("model has a different element type"));
+        }
+        // version 1 files have no checksum. Saving them again upgrades them
+        if version == 1 {
+            return self.read_state(&mut r);
+        }
+
+        let mut expected = [0; 8];
+        r.read_exact(&mut expected)?;
+        let expected = u64::from_le_bytes(expected);
+        let mut params = vec![];
+        r.read_to_end(&mut params)?;
+        let found = binary::checksum(&params);
+        if found != expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "model file is truncated or corrupted: checksum is {found:016x}, expected {expected:016x}"
+                ),
+            ));
+        }
+
+        self.read_state(&mut params.as_slice())
+    }
+}
+
+/// A path in the temporary directory for a test's file. It includes the process id,
+/// so that test runs at the same time don't overwrite each other's files
+#[cfg(test)]
+pub(crate) fn temp_path(name: &str) -> std::path::PathBuf {
+    std::env::temp_dir().join(format!("linear_networks_{}_{name}", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mappable;
+
+    /// A state that only implements the required methods of [`Mappable`]
+    struct Pair(f64, f64);
+
+    impl Mappable<f64> for Pair {
+        fn map<F: FnMut(&f64) -> f64>(&self, mut f: F) -> Self {
+            Self(f(&self.0), f(&self.1))
+        }
+        fn map_mut<F: FnMut(&mut f64)>(&mut self, mut f: F) {
+            f(&mut self.0);
+            f(&mut self.1);
+        }
+        fn map_mut_with<F: FnMut(&mut f64, &f64)>(&mut self, rhs: &Self, mut f: F) {
+            f(&mut self.0, &rhs.0);
+            f(&mut self.1, &rhs.1);
+        }
+    }
+
+    #[test]
+    fn test_default_for_each() {
+        let pair = Pair(1.0, 2.0);
+        let mut params = vec![];
+        pair.for_each(|&x| params.push(x));
+        assert_eq!(params, [1.0, 2.0]);
+        assert_eq!(pair.num_params(), 2);
+        assert_eq!(pair.memory_bytes(), 16);
+    }
+}
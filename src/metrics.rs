@@ -0,0 +1,48 @@
+use ndarray::{Array, Dimension, RemoveAxis};
+use num_traits::{Float, FromPrimitive};
+
+use crate::array::argmax;
+
+/// A measurement of how well a graph's output matches the expected output.
+/// Unlike a [`Cost`](crate::cost::Cost), metrics are only reported and never optimised
+pub trait Metric<T> {
+    type Inner;
+    /// Measures the metric, averaged over the batch
+    fn measure(&self, output: &T, expected: &T) -> Self::Inner;
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Fraction of samples where the largest output matches the largest expected value.
+/// The first axis is treated as the batch axis
+pub struct Accuracy;
+
+impl<F, D> Metric<Array<F, D>> for Accuracy
+where
+    F: Float + FromPrimitive,
+    D: Dimension + RemoveAxis,
+{
+    type Inner = F;
+    fn measure(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Self::Inner {
+        let total = output.outer_iter().len();
+        let correct = output
+            .outer_iter()
+            .zip(expected.outer_iter())
+            .filter(|(o, e)| argmax(o) == argmax(e))
+            .count();
+        F::from_usize(correct).unwrap() / F::from_usize(total).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::{Accuracy, Metric};
+
+    #[test]
+    fn test_accuracy() {
+        let output = array![[0.1, 0.9], [0.8, 0.2], [0.3, 0.7], [0.6, 0.4]];
+        let expected = array![[0.0, 1.0], [1.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+        assert!((Accuracy.measure(&output, &expected) - 0.5_f64).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,465 @@
+//! Layers with names, so they can be found again inside a large network.
+//!
+//! Any graph can be named using [`Named::new`], or using the `named` method on the layer
+//! builders. The name is kept in the graph's state, where [`GetLayer`] can look it up,
+//! and is used when naming saved tensors and HDF5 groups
+use std::{
+    any::Any,
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    activation::Linear,
+    binary::Element,
+    dense::DenseState,
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    embedded::{DenseLayer, DenseLayers},
+    error::Result,
+    precision::Cast,
+    quantise::Quantise,
+    stats::{LayerStats, Stats, UnitActivity},
+    tensors::{ParamPath, Tensors},
+    train::{GraphExecTrain, Modal, Mode},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+#[cfg(feature = "hdf5")]
+use hdf5::H5Type;
+use ndarray::{ArrayViewD, ArrayViewMutD};
+use rand::Rng;
+
+/// A graph with a name. Used as both the builder and the state
+///
+/// ```
+/// use linear_networks::{
+///     activation::{relu::Relu, Linear},
+///     dense::{Dense, DenseState},
+///     initialisers::Xavier,
+///     named::GetLayer,
+///     net, Graph,
+/// };
+///
+/// let network = net![
+///     Dense::output_size(16)
+///         .with_initialiser(Xavier)
+///         .with_activation(Relu)
+///         .named("hidden1"),
+///     Dense::output_size(2).with_initialiser(Xavier).named("output")
+/// ];
+/// let state = Graph::<f32, _>::input_shape(network, 4);
+///
+/// let output: &DenseState<f32> = state.layer("output").unwrap();
+/// assert_eq!(output.w.dim(), (16, 2));
+/// assert!(state.layer::<Linear<DenseState<f32>, Relu>>("hidden1").is_some());
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Named<G> {
+    pub name: Arc<str>,
+    pub graph: G,
+}
+
+impl<G> Named<G> {
+    pub fn new(name: impl Into<Arc<str>>, graph: G) -> Self {
+        Self {
+            name: name.into(),
+            graph,
+        }
+    }
+
+    /// Creates a graph with the same name
+    fn with<T>(&self, graph: T) -> Named<T> {
+        Named {
+            name: Arc::clone(&self.name),
+            graph,
+        }
+    }
+}
+
+/// Looks up layers in a graph's state by their [`Named`] name
+pub trait GetLayer {
+    /// The first layer with the given name, if any. The named graph is returned
+    /// without its [`Named`] wrapper
+    fn get_layer(&self, name: &str) -> Option<&dyn Any>;
+    fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any>;
+
+    /// Like [`get_layer`](Self::get_layer), but downcasts the layer to the expected type
+    fn layer<L: 'static>(&self, name: &str) -> Option<&L>
+    where
+        Self: Sized,
+    {
+        self.get_layer(name)?.downcast_ref()
+    }
+
+    /// Like [`get_layer_mut`](Self::get_layer_mut), but downcasts the layer to the expected type
+    fn layer_mut<L: 'static>(&mut self, name: &str) -> Option<&mut L>
+    where
+        Self: Sized,
+    {
+        self.get_layer_mut(name)?.downcast_mut()
+    }
+}
+
+impl<G: GetLayer + 'static> GetLayer for Named<G> {
+    fn get_layer(&self, name: &str) -> Option<&dyn Any> {
+        if &*self.name == name {
+            Some(&self.graph)
+        } else {
+            self.graph.get_layer(name)
+        }
+    }
+    fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any> {
+        if &*self.name == name {
+            Some(&mut self.graph)
+        } else {
+            self.graph.get_layer_mut(name)
+        }
+    }
+}
+
+impl<F> GetLayer for DenseState<F> {
+    fn get_layer(&self, _name: &str) -> Option<&dyn Any> {
+        None
+    }
+    fn get_layer_mut(&mut self, _name: &str) -> Option<&mut dyn Any> {
+        None
+    }
+}
+
+impl<G: GetLayer, L> GetLayer for Linear<G, L> {
+    fn get_layer(&self, name: &str) -> Option<&dyn Any> {
+        self.graph.get_layer(name)
+    }
+    fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any> {
+        self.graph.get_layer_mut(name)
+    }
+}
+
+impl<T: GetLayer, U: GetLayer> GetLayer for (T, U) {
+    fn get_layer(&self, name: &str) -> Option<&dyn Any> {
+        self.0.get_layer(name).or_else(|| self.1.get_layer(name))
+    }
+    fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any> {
+        match self.0.get_layer_mut(name) {
+            Some(layer) => Some(layer),
+            None => self.1.get_layer_mut(name),
+        }
+    }
+}
+
+/// Named graphs are drawn inside a box labelled with their name
+impl<G: Dot> Dot for Named<G> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        dot.cluster(&self.name, |dot| self.graph.write_dot(dot, inputs))
+    }
+}
+
+impl<I, G, F> Graph<F, I> for Named<G>
+where
+    G: Graph<F, I>,
+{
+    type State = Named<G::State>;
+    type OutputShape = G::OutputShape;
+
+    fn get_output_shape(&self) -> Self::OutputShape {
+        self.graph.get_output_shape()
+    }
+
+    fn init_with_random(self, rng: &mut impl Rng, input_shape: I) -> Self::State {
+        Named {
+            name: self.name,
+            graph: self.graph.init_with_random(rng, input_shape),
+        }
+    }
+
+    fn num_params(&self, input_shape: I) -> usize {
+        self.graph.num_params(input_shape)
+    }
+}
+
+impl<G, Input> GraphExec<Input> for Named<G>
+where
+    G: GraphExec<Input>,
+{
+    type Output = G::Output;
+    fn exec(&self, input: Input) -> Self::Output {
+        self.graph.exec(input)
+    }
+    fn try_exec(&self, input: Input) -> Result<Self::Output> {
+        self.graph.try_exec(input)
+    }
+}
+
+impl<G, Input> GraphExecTrain<Input> for Named<G>
+where
+    G: GraphExecTrain<Input>,
+{
+    type State = G::State;
+    fn forward(&self, input: Input) -> (Self::State, Self::Output) {
+        self.graph.forward(input)
+    }
+
+    fn back(&self, state: Self::State, d_output: Self::Output) -> (Input, Self) {
+        let (d_input, grads) = self.graph.back(state, d_output);
+        (d_input, self.with(grads))
+    }
+
+    fn back_into(&self, state: Self::State, d_output: Self::Output, grads: &mut Self) -> Input {
+        self.graph.back_into(state, d_output, &mut grads.graph)
+    }
+}
+
+impl<G: Modal> Modal for Named<G> {
+    fn set_mode(&mut self, mode: Mode) {
+        self.graph.set_mode(mode);
+    }
+}
+
+impl<T, G: Mappable<T>> Mappable<T> for Named<G> {
+    fn map<F: FnMut(&T) -> T>(&self, f: F) -> Self {
+        self.with(self.graph.map(f))
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, f: F) {
+        self.graph.map_mut(f);
+    }
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.graph.map_mut_with(&rhs.graph, f);
+    }
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.graph.map_weights_mut_with(&rhs.graph, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, f: F)
+    where
+        T: Clone,
+    {
+        self.graph.for_each(f);
+    }
+}
+
+impl<F, G: Shaped<F>> Shaped<F> for Named<G> {
+    type Shape = Named<G::Shape>;
+    fn shape(&self) -> Self::Shape {
+        self.with(self.graph.shape())
+    }
+    fn zero(shape: Self::Shape) -> Self {
+        Self {
+            name: shape.name,
+            graph: G::zero(shape.graph),
+        }
+    }
+    fn one(shape: Self::Shape) -> Self {
+        Self {
+            name: shape.name,
+            graph: G::one(shape.graph),
+        }
+    }
+    fn iter(shape: Self::Shape, i: impl Iterator<Item = F>) -> Self {
+        Self {
+            name: shape.name,
+            graph: G::iter(shape.graph, i),
+        }
+    }
+}
+
+impl<F, G: LayerStats<F>> LayerStats<F> for Named<G> {
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        self.graph.push_stats(stats);
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        self.graph.push_activity(activity);
+    }
+}
+
+impl<F, G: DerivativeTesting<F>> DerivativeTesting<F> for Named<G> {
+    fn len(&self) -> usize {
+        self.graph.len()
+    }
+    fn get(&self, i: usize) -> F {
+        self.graph.get(i)
+    }
+    fn set(&mut self, i: usize, f: F) {
+        self.graph.set(i, f);
+    }
+}
+
+/// The name is added to the tensor paths, eg `1/hidden1/weight`
+impl<F, G: Tensors<F>> Tensors<F> for Named<G> {
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        path.push(&self.name);
+        self.graph.visit(path, f);
+        path.pop();
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        path.push(&self.name);
+        self.graph.visit_mut(path, f);
+        path.pop();
+    }
+}
+
+impl<F, G: DenseLayers<F>> DenseLayers<F> for Named<G> {
+    fn push_layers<'a>(&'a self, layers: &mut Vec<DenseLayer<'a, F>>) {
+        self.graph.push_layers(layers);
+    }
+}
+
+impl<G: Cast<T>, T> Cast<Named<T>> for Named<G> {
+    fn cast(&self) -> Named<T> {
+        self.with(self.graph.cast())
+    }
+    fn cast_into(&self, output: &mut Named<T>) {
+        self.graph.cast_into(&mut output.graph);
+    }
+}
+
+impl<G: Quantise> Quantise for Named<G> {
+    type Quantised = Named<G::Quantised>;
+    fn quantise(&self) -> Self::Quantised {
+        self.with(self.graph.quantise())
+    }
+}
+
+impl<F: Element, I, G: Persist<F, I>> Persist<F, I> for Named<G> {
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        self.graph.write_state(&state.graph, w)
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        Ok(self.with(self.graph.read_state(r)?))
+    }
+}
+
+/// Named layers are saved into a group with their name, rather than `layer_{index}`
+#[cfg(feature = "hdf5")]
+impl<F: H5Type, I, G: HDF5<F, I>> HDF5<F, I> for Named<G> {
+    fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()> {
+        self.graph.save(&state.graph, group)
+    }
+
+    fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State> {
+        Ok(self.with(self.graph.load(group)?))
+    }
+
+    fn save_layers(
+        &self,
+        state: &Self::State,
+        group: &hdf5::Group,
+        index: &mut usize,
+    ) -> hdf5::Result<()> {
+        let name = self.group_name()?;
+        if group.link_exists(name) {
+            return Err(format!("there is already a layer named {name:?}").into());
+        }
+        *index += 1;
+        self.save(state, &group.create_group(name)?)
+    }
+
+    fn load_layers(&self, group: &hdf5::Group, index: &mut usize) -> hdf5::Result<Self::State> {
+        *index += 1;
+        self.load(&group.group(self.group_name()?)?)
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<G> Named<G> {
+    /// The name of the layer's group. HDF5 reads `/` as a path separator, which would
+    /// nest the group somewhere else, so names containing it are rejected
+    fn group_name(&self) -> hdf5::Result<&str> {
+        if self.name.is_empty() || self.name.contains('/') || &*self.name == "." {
+            Err(format!("{:?} can't be used as an HDF5 group name", self.name).into())
+        } else {
+            Ok(&self.name)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::GetLayer;
+    use crate::{
+        activation::relu::Relu,
+        dense::{Dense, DenseState},
+        initialisers::Xavier,
+        net,
+        tensors::Tensors,
+        Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_named_layers() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut network = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(2).with_initialiser(Xavier).named("head")
+        ]
+        .init_with_random(&mut rng, 4);
+
+        let head: &DenseState<f64> = network.layer("head").unwrap();
+        assert_eq!(head.w.dim(), (3, 2));
+        assert!(network.layer::<DenseState<f64>>("hidden").is_none());
+        assert!(network.layer::<DenseState<f32>>("head").is_none());
+
+        // zero the head through the lookup
+        network
+            .layer_mut::<DenseState<f64>>("head")
+            .unwrap()
+            .w
+            .fill(0.0);
+        network
+            .layer_mut::<DenseState<f64>>("head")
+            .unwrap()
+            .b
+            .fill(1.0);
+        let input = Array2::<f64>::from_shape_simple_fn((5, 4), || rng.gen());
+        assert_eq!(network.exec(input), Array2::<f64>::ones((5, 2)));
+
+        let mut names = vec![];
+        network.tensors("", &mut |name, _| names.push(name));
+        assert_eq!(
+            names,
+            ["0.weight", "0.bias", "1.head.weight", "1.head.bias"]
+        );
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_hdf5_names() {
+        use crate::HDF5;
+
+        let network = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .named("layer"),
+            Dense::output_size(2)
+                .with_initialiser(Xavier)
+                .named("layer")
+        ];
+        let state = Graph::<f64, _>::input_shape(network.clone(), 4);
+        let path = crate::temp_path("names.h5");
+
+        let err = network
+            .save(&state, &hdf5::File::create(&path).unwrap())
+            .unwrap_err();
+        assert!(err.to_string().contains("already a layer named"), "{}", err);
+
+        let nested = Dense::output_size(2).with_initialiser(Xavier).named("a/b");
+        let state = Graph::<f64, _>::input_shape(nested.clone(), 4);
+        let err = net![nested.clone(), nested]
+            .save(&(state.clone(), state), &hdf5::File::create(&path).unwrap())
+            .unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.to_string().contains("HDF5 group name"), "{}", err);
+    }
+}
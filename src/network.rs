@@ -1,5 +1,27 @@
-use crate::{train::GraphExecTrain, Graph, GraphExec, Mappable, Shaped, HDF5};
+use std::{
+    any::Any,
+    io::{self, Read, Write},
+};
+
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    binary::Element,
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    embedded::{DenseLayer, DenseLayers},
+    error::Result,
+    named::GetLayer,
+    precision::Cast,
+    quantise::Quantise,
+    stats::{LayerStats, Stats, UnitActivity},
+    tensors::{ParamPath, Tensors},
+    train::{GraphExecTrain, Modal, Mode},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+#[cfg(feature = "hdf5")]
 use hdf5::H5Type;
+use ndarray::{ArrayViewD, ArrayViewMutD};
 use rand::Rng;
 
 impl<I, G0, G1, F> Graph<F, I> for (G0, G1)
@@ -21,6 +43,10 @@ where
             self.1.init_with_random(rng, s0),
         )
     }
+
+    fn num_params(&self, input_shape: I) -> usize {
+        self.0.num_params(input_shape) + self.1.num_params(self.0.get_output_shape())
+    }
 }
 
 impl<G0, G1, Input> GraphExec<Input> for (G0, G1)
@@ -33,6 +59,10 @@ where
         let input = self.0.exec(input);
         self.1.exec(input)
     }
+    fn try_exec(&self, input: Input) -> Result<Self::Output> {
+        let input = self.0.try_exec(input)?;
+        self.1.try_exec(input)
+    }
 }
 
 impl<G0, G1, Input> GraphExecTrain<Input> for (G0, G1)
@@ -53,6 +83,19 @@ where
         let (d_output, d0) = self.0.back(s0, d_output);
         (d_output, (d0, d1))
     }
+
+    fn back_into(&self, state: Self::State, d_output: Self::Output, grads: &mut Self) -> Input {
+        let (s0, s1) = state;
+        let d_output = self.1.back_into(s1, d_output, &mut grads.1);
+        self.0.back_into(s0, d_output, &mut grads.0)
+    }
+}
+
+impl<T: Modal, U: Modal> Modal for (T, U) {
+    fn set_mode(&mut self, mode: Mode) {
+        self.0.set_mode(mode);
+        self.1.set_mode(mode);
+    }
 }
 
 impl<S, T, U> Mappable<S> for (T, U)
@@ -73,6 +116,17 @@ where
         self.0.map_mut_with(&rhs.0, |a, b| f(a, b));
         self.1.map_mut_with(&rhs.1, f);
     }
+    fn map_weights_mut_with<F: FnMut(&mut S, &S)>(&mut self, rhs: &Self, mut f: F) {
+        self.0.map_weights_mut_with(&rhs.0, |a, b| f(a, b));
+        self.1.map_weights_mut_with(&rhs.1, f);
+    }
+    fn for_each<F: FnMut(&S)>(&self, mut f: F)
+    where
+        S: Clone,
+    {
+        self.0.for_each(|a| f(a));
+        self.1.for_each(f);
+    }
 }
 
 impl<F, T, U> Shaped<F> for (T, U)
@@ -95,25 +149,403 @@ where
     }
 }
 
+impl<F: Element, I, T, U> Persist<F, I> for (T, U)
+where
+    T: Persist<F, I> + Graph<F, I>,
+    U: Persist<F, T::OutputShape> + Graph<F, T::OutputShape>,
+{
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        self.0.write_state(&state.0, w)?;
+        self.1.write_state(&state.1, w)
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        Ok((self.0.read_state(r)?, self.1.read_state(r)?))
+    }
+}
+
+#[cfg(feature = "hdf5")]
 impl<F: H5Type, I, T, U> HDF5<F, I> for (T, U)
 where
     T: HDF5<F, I> + Graph<F, I>,
     U: HDF5<F, T::OutputShape> + Graph<F, T::OutputShape>,
 {
     fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()> {
-        self.0.save(&state.0, &group.create_group("0")?)?;
-        self.1.save(&state.1, &group.create_group("1")?)?;
-        Ok(())
+        self.save_layers(state, group, &mut 0)
     }
 
     fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State> {
+        self.load_layers(group, &mut 0)
+    }
+
+    fn save_layers(
+        &self,
+        state: &Self::State,
+        group: &hdf5::Group,
+        index: &mut usize,
+    ) -> hdf5::Result<()> {
+        self.0.save_layers(&state.0, group, index)?;
+        self.1.save_layers(&state.1, group, index)
+    }
+
+    fn load_layers(&self, group: &hdf5::Group, index: &mut usize) -> hdf5::Result<Self::State> {
         Ok((
-            self.0.load(&group.group("0")?)?,
-            self.1.load(&group.group("1")?)?,
+            self.0.load_layers(group, index)?,
+            self.1.load_layers(group, index)?,
         ))
     }
 }
 
+/// Emits the given statements in reverse order
+macro_rules! reversed {
+    () => {};
+    ([$($first:tt)*] $($rest:tt)*) => {
+        reversed!($($rest)*);
+        $($first)*
+    };
+}
+
+/// Implements the graph traits for a flat tuple of graphs, `(G0, G1, G2, ..)`,
+/// which behaves the same as the nested pairs `(G0, (G1, (G2, ..)))`.
+/// Each graph is listed with the type it [`Cast`]s to, its index, a name for its state,
+/// and the graph before it
+macro_rules! flat_tuple {
+    ($G0:ident $C0:ident 0 $s0:ident, $($G:ident $C:ident $i:tt $s:ident $Prev:ident),+; $Last:ident) => {
+        impl<F, I, $G0, $($G),+> Graph<F, I> for ($G0, $($G),+)
+        where
+            $G0: Graph<F, I>,
+            $($G: Graph<F, $Prev::OutputShape>,)+
+        {
+            type State = ($G0::State, $($G::State),+);
+            type OutputShape = $Last::OutputShape;
+
+            fn get_output_shape(&self) -> Self::OutputShape {
+                let (.., last) = self;
+                last.get_output_shape()
+            }
+
+            #[allow(unused_variables)]
+            fn init_with_random(self, rng: &mut impl Rng, input_shape: I) -> Self::State {
+                let shape = self.0.get_output_shape();
+                let $s0 = self.0.init_with_random(rng, input_shape);
+                $(
+                    let next = self.$i.get_output_shape();
+                    let $s = self.$i.init_with_random(rng, shape);
+                    let shape = next;
+                )+
+                ($s0, $($s),+)
+            }
+
+            #[allow(unused_variables)]
+            fn num_params(&self, input_shape: I) -> usize {
+                let mut count = self.0.num_params(input_shape);
+                let shape = self.0.get_output_shape();
+                $(
+                    count += self.$i.num_params(shape);
+                    let shape = self.$i.get_output_shape();
+                )+
+                count
+            }
+        }
+
+        impl<Input, $G0, $($G),+> GraphExec<Input> for ($G0, $($G),+)
+        where
+            $G0: GraphExec<Input>,
+            $($G: GraphExec<$Prev::Output>,)+
+        {
+            type Output = $Last::Output;
+            fn exec(&self, input: Input) -> Self::Output {
+                let x = self.0.exec(input);
+                $(let x = self.$i.exec(x);)+
+                x
+            }
+            fn try_exec(&self, input: Input) -> Result<Self::Output> {
+                let x = self.0.try_exec(input)?;
+                $(let x = self.$i.try_exec(x)?;)+
+                Ok(x)
+            }
+        }
+
+        impl<Input, $G0, $($G),+> GraphExecTrain<Input> for ($G0, $($G),+)
+        where
+            $G0: GraphExecTrain<Input>,
+            $($G: GraphExecTrain<$Prev::Output>,)+
+        {
+            type State = ($G0::State, $($G::State),+);
+            fn forward(&self, input: Input) -> (Self::State, Self::Output) {
+                let ($s0, x) = self.0.forward(input);
+                $(let ($s, x) = self.$i.forward(x);)+
+                (($s0, $($s),+), x)
+            }
+
+            fn back(&self, state: Self::State, d_output: Self::Output) -> (Input, Self) {
+                let ($s0, $($s),+) = state;
+                let d = d_output;
+                reversed!($([let (d, $s) = self.$i.back($s, d);])+);
+                let (d, $s0) = self.0.back($s0, d);
+                (d, ($s0, $($s),+))
+            }
+
+            fn back_into(
+                &self,
+                state: Self::State,
+                d_output: Self::Output,
+                grads: &mut Self,
+            ) -> Input {
+                let ($s0, $($s),+) = state;
+                let d = d_output;
+                reversed!($([let d = self.$i.back_into($s, d, &mut grads.$i);])+);
+                self.0.back_into($s0, d, &mut grads.0)
+            }
+        }
+
+        impl<$G0: Modal, $($G: Modal),+> Modal for ($G0, $($G),+) {
+            fn set_mode(&mut self, mode: Mode) {
+                self.0.set_mode(mode);
+                $(self.$i.set_mode(mode);)+
+            }
+        }
+
+        impl<S, $G0: Mappable<S>, $($G: Mappable<S>),+> Mappable<S> for ($G0, $($G),+) {
+            fn map<F: FnMut(&S) -> S>(&self, mut f: F) -> Self {
+                (self.0.map(|a| f(a)), $(self.$i.map(|a| f(a))),+)
+            }
+            fn map_mut<F: FnMut(&mut S)>(&mut self, mut f: F) {
+                self.0.map_mut(|a| f(a));
+                $(self.$i.map_mut(|a| f(a));)+
+            }
+            fn map_mut_with<F: FnMut(&mut S, &S)>(&mut self, rhs: &Self, mut f: F) {
+                self.0.map_mut_with(&rhs.0, |a, b| f(a, b));
+                $(self.$i.map_mut_with(&rhs.$i, |a, b| f(a, b));)+
+            }
+            fn map_weights_mut_with<F: FnMut(&mut S, &S)>(&mut self, rhs: &Self, mut f: F) {
+                self.0.map_weights_mut_with(&rhs.0, |a, b| f(a, b));
+                $(self.$i.map_weights_mut_with(&rhs.$i, |a, b| f(a, b));)+
+            }
+            fn for_each<F: FnMut(&S)>(&self, mut f: F)
+            where
+                S: Clone,
+            {
+                self.0.for_each(|a| f(a));
+                $(self.$i.for_each(|a| f(a));)+
+            }
+        }
+
+        impl<F, $G0: Shaped<F>, $($G: Shaped<F>),+> Shaped<F> for ($G0, $($G),+) {
+            type Shape = ($G0::Shape, $($G::Shape),+);
+            fn shape(&self) -> Self::Shape {
+                (self.0.shape(), $(self.$i.shape()),+)
+            }
+            fn zero(shape: Self::Shape) -> Self {
+                ($G0::zero(shape.0), $($G::zero(shape.$i)),+)
+            }
+            fn one(shape: Self::Shape) -> Self {
+                ($G0::one(shape.0), $($G::one(shape.$i)),+)
+            }
+            fn iter(shape: Self::Shape, mut i: impl Iterator<Item = F>) -> Self {
+                ($G0::iter(shape.0, &mut i), $($G::iter(shape.$i, &mut i)),+)
+            }
+        }
+
+        impl<F: Element, I, $G0, $($G),+> Persist<F, I> for ($G0, $($G),+)
+        where
+            $G0: Persist<F, I> + Graph<F, I>,
+            $($G: Persist<F, $Prev::OutputShape> + Graph<F, $Prev::OutputShape>,)+
+        {
+            fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+                self.0.write_state(&state.0, w)?;
+                $(self.$i.write_state(&state.$i, w)?;)+
+                Ok(())
+            }
+
+            fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+                Ok((self.0.read_state(r)?, $(self.$i.read_state(r)?),+))
+            }
+        }
+
+        #[cfg(feature = "hdf5")]
+        impl<F: H5Type, I, $G0, $($G),+> HDF5<F, I> for ($G0, $($G),+)
+        where
+            $G0: HDF5<F, I> + Graph<F, I>,
+            $($G: HDF5<F, $Prev::OutputShape> + Graph<F, $Prev::OutputShape>,)+
+        {
+            fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()> {
+                self.save_layers(state, group, &mut 0)
+            }
+
+            fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State> {
+                self.load_layers(group, &mut 0)
+            }
+
+            fn save_layers(
+                &self,
+                state: &Self::State,
+                group: &hdf5::Group,
+                index: &mut usize,
+            ) -> hdf5::Result<()> {
+                self.0.save_layers(&state.0, group, index)?;
+                $(self.$i.save_layers(&state.$i, group, index)?;)+
+                Ok(())
+            }
+
+            fn load_layers(
+                &self,
+                group: &hdf5::Group,
+                index: &mut usize,
+            ) -> hdf5::Result<Self::State> {
+                Ok((
+                    self.0.load_layers(group, index)?,
+                    $(self.$i.load_layers(group, index)?),+
+                ))
+            }
+        }
+
+        impl<F, $G0: Tensors<F>, $($G: Tensors<F>),+> Tensors<F> for ($G0, $($G),+) {
+            fn visit<'a>(
+                &'a self,
+                path: &mut ParamPath,
+                f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>),
+            ) {
+                path.push(0);
+                self.0.visit(path, f);
+                path.pop();
+                $(
+                    path.push($i);
+                    self.$i.visit(path, f);
+                    path.pop();
+                )+
+            }
+
+            fn visit_mut<'a>(
+                &'a mut self,
+                path: &mut ParamPath,
+                f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+            ) {
+                path.push(0);
+                self.0.visit_mut(path, f);
+                path.pop();
+                $(
+                    path.push($i);
+                    self.$i.visit_mut(path, f);
+                    path.pop();
+                )+
+            }
+        }
+
+        impl<$G0: Dot, $($G: Dot),+> Dot for ($G0, $($G),+) {
+            fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+                let outputs = self.0.write_dot(dot, inputs);
+                $(let outputs = self.$i.write_dot(dot, &outputs);)+
+                outputs
+            }
+        }
+
+        impl<$G0: GetLayer, $($G: GetLayer),+> GetLayer for ($G0, $($G),+) {
+            fn get_layer(&self, name: &str) -> Option<&dyn Any> {
+                self.0.get_layer(name)$(.or_else(|| self.$i.get_layer(name)))+
+            }
+            fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any> {
+                let ($s0, $($s),+) = self;
+                $s0.get_layer_mut(name)$(.or_else(move || $s.get_layer_mut(name)))+
+            }
+        }
+
+        impl<F, $G0: LayerStats<F>, $($G: LayerStats<F>),+> LayerStats<F> for ($G0, $($G),+) {
+            fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+                self.0.push_stats(stats);
+                $(self.$i.push_stats(stats);)+
+            }
+            fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+                self.0.push_activity(activity);
+                $(self.$i.push_activity(activity);)+
+            }
+        }
+
+        impl<F, $G0, $($G),+> DerivativeTesting<F> for ($G0, $($G),+)
+        where
+            $G0: DerivativeTesting<F>,
+            $($G: DerivativeTesting<F>,)+
+        {
+            fn len(&self) -> usize {
+                self.0.len() $(+ self.$i.len())+
+            }
+            fn get(&self, i: usize) -> F {
+                let n = self.0.len();
+                if i < n {
+                    return self.0.get(i);
+                }
+                let i = i - n;
+                $(
+                    let n = self.$i.len();
+                    if i < n {
+                        return self.$i.get(i);
+                    }
+                    let i = i - n;
+                )+
+                panic!("parameter {} is out of bounds", i + self.len())
+            }
+            fn set(&mut self, i: usize, f: F) {
+                let n = self.0.len();
+                if i < n {
+                    return self.0.set(i, f);
+                }
+                let i = i - n;
+                $(
+                    let n = self.$i.len();
+                    if i < n {
+                        return self.$i.set(i, f);
+                    }
+                    let i = i - n;
+                )+
+                panic!("parameter {} is out of bounds", i + self.len())
+            }
+        }
+
+        impl<$G0, $($G),+, $C0, $($C),+> Cast<($C0, $($C),+)> for ($G0, $($G),+)
+        where
+            $G0: Cast<$C0>,
+            $($G: Cast<$C>,)+
+        {
+            fn cast(&self) -> ($C0, $($C),+) {
+                (self.0.cast(), $(self.$i.cast()),+)
+            }
+            fn cast_into(&self, output: &mut ($C0, $($C),+)) {
+                self.0.cast_into(&mut output.0);
+                $(self.$i.cast_into(&mut output.$i);)+
+            }
+        }
+
+        impl<$G0: Quantise, $($G: Quantise),+> Quantise for ($G0, $($G),+) {
+            type Quantised = ($G0::Quantised, $($G::Quantised),+);
+            fn quantise(&self) -> Self::Quantised {
+                (self.0.quantise(), $(self.$i.quantise()),+)
+            }
+        }
+
+        impl<F, $G0: DenseLayers<F>, $($G: DenseLayers<F>),+> DenseLayers<F> for ($G0, $($G),+) {
+            fn push_layers<'a>(&'a self, layers: &mut Vec<DenseLayer<'a, F>>) {
+                self.0.push_layers(layers);
+                $(self.$i.push_layers(layers);)+
+            }
+        }
+    };
+}
+
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1; G2);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2; G3);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3; G4);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4; G5);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5; G6);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6; G7);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7; G8);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7, G9 C9 9 s9 G8; G9);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7, G9 C9 9 s9 G8, G10 C10 10 s10 G9; G10);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7, G9 C9 9 s9 G8, G10 C10 10 s10 G9, G11 C11 11 s11 G10; G11);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7, G9 C9 9 s9 G8, G10 C10 10 s10 G9, G11 C11 11 s11 G10, G12 C12 12 s12 G11; G12);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7, G9 C9 9 s9 G8, G10 C10 10 s10 G9, G11 C11 11 s11 G10, G12 C12 12 s12 G11, G13 C13 13 s13 G12; G13);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7, G9 C9 9 s9 G8, G10 C10 10 s10 G9, G11 C11 11 s11 G10, G12 C12 12 s12 G11, G13 C13 13 s13 G12, G14 C14 14 s14 G13; G14);
+flat_tuple!(G0 C0 0 s0, G1 C1 1 s1 G0, G2 C2 2 s2 G1, G3 C3 3 s3 G2, G4 C4 4 s4 G3, G5 C5 5 s5 G4, G6 C6 6 s6 G5, G7 C7 7 s7 G6, G8 C8 8 s8 G7, G9 C9 9 s9 G8, G10 C10 10 s10 G9, G11 C11 11 s11 G10, G12 C12 12 s12 G11, G13 C13 13 s13 G12, G14 C14 14 s14 G13, G15 C15 15 s15 G14; G15);
+
 /// Converts the provided values into a nested chain of tuples.
 /// Works by taking each pair of expressions, converting them into a tuple,
 /// Then pushing all of them into the macro recursively
@@ -183,4 +615,256 @@ mod tests {
         let t = net!(0, 1, 2, 3, 4, 5);
         assert_eq!(t, ((0, 1), ((2, 3), (4, 5))));
     }
+
+    #[test]
+    fn test_flat_tuple() {
+        use ndarray::{array, Array1};
+
+        use crate::{
+            activation::{relu::Relu, Linear},
+            dense::{Dense, DenseState},
+            derivative::DerivativeTesting,
+            dot::Dot,
+            initialisers::Xavier,
+            precision::Cast,
+            stats::LayerStats,
+            tensors::Tensors,
+            train::GraphExecTrain,
+            Graph, GraphExec, Mappable, Persist,
+        };
+
+        type Single = Linear<DenseState<f32>, Relu>;
+
+        let graph = (
+            Dense::output_size(5)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(2).with_initialiser(Xavier),
+        );
+        assert_eq!(Graph::<f64, _>::num_params(&graph, 4), 25 + 18 + 8);
+        let (a, b, c) = graph;
+        let nested_graph = (a, (b, c));
+        assert_eq!(graph.to_dot(), nested_graph.to_dot());
+
+        let flat = Graph::<f64, _>::input_shape(graph, 4);
+        let (a, b, c) = flat.clone();
+        let nested = (a, (b, c));
+
+        let mut bytes = vec![];
+        graph.write_state(&flat, &mut bytes).unwrap();
+        let mut nested_bytes = vec![];
+        nested_graph
+            .write_state(&nested, &mut nested_bytes)
+            .unwrap();
+        assert_eq!(bytes, nested_bytes);
+        let loaded = Persist::<f64, _>::read_state(&graph, &mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.2.w, flat.2.w);
+
+        let mut names = vec![];
+        flat.tensors("", &mut |name, _| names.push(name));
+        assert_eq!(names[..2], ["0.weight", "0.bias"]);
+        assert_eq!(names[4..], ["2.weight", "2.bias"]);
+        assert_eq!(flat.stats().len(), 3);
+
+        assert_eq!(DerivativeTesting::len(&flat), 51);
+        let flat_params: Vec<f64> = (0..51).map(|i| DerivativeTesting::get(&flat, i)).collect();
+        let nested_params: Vec<f64> = (0..51).map(|i| nested.get(i)).collect();
+        assert_eq!(flat_params, nested_params);
+        let mut perturbed = flat.clone();
+        perturbed.set(50, 1.0);
+        assert_eq!(perturbed.2.b, array![flat.2.b[0], 1.0]);
+
+        let single: (Single, Single, DenseState<f32>) = flat.cast();
+        let mut restored = flat.clone();
+        single.cast_into(&mut restored);
+        assert!((restored.2.w - &flat.2.w).iter().all(|d| d.abs() < 1e-6));
+        assert_eq!(restored.1.graph.b, single.1.graph.b.mapv(f64::from));
+
+        let input = Array1::linspace(-0.5, 0.5, 24).into_shape((6, 4)).unwrap();
+        assert_eq!(flat.exec(input.view()), nested.exec(input.view()));
+        assert_eq!(flat.num_params(), nested.num_params());
+
+        let (state, output) = flat.forward(input.clone());
+        let (d_input, grads) = flat.back(state, output.clone());
+        let (nested_state, _) = nested.forward(input);
+        let (nested_d_input, nested_grads) = nested.back(nested_state, output);
+        assert_eq!(d_input, nested_d_input);
+
+        let mut params = vec![];
+        grads.for_each(|p| params.push(*p));
+        let mut nested_params = vec![];
+        nested_grads.for_each(|p| nested_params.push(*p));
+        assert_eq!(params, nested_params);
+    }
+
+    #[test]
+    fn test_num_params() {
+        use crate::{
+            activation::{relu::Relu, Linear},
+            dense::{Dense, DenseState},
+            initialisers::Xavier,
+            Graph, Mappable,
+        };
+
+        let network = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ];
+        // (4 + 1) * 3 + (3 + 1) * 2
+        assert_eq!(Graph::<f32, _>::num_params(&network, 4), 23);
+        assert_eq!(Graph::<f32, _>::memory_bytes(&network, 4), 23 * 4);
+
+        let state: (Linear<DenseState<f64>, Relu>, DenseState<f64>) = network.input_shape(4);
+        assert_eq!(state.num_params(), 23);
+        assert_eq!(state.memory_bytes(), 23 * 8);
+    }
+
+    #[test]
+    fn test_set_mode_reaches_every_layer() {
+        use crate::{
+            activation::{relu::Relu, Linear},
+            train::{Modal, Mode},
+        };
+
+        #[derive(Default)]
+        struct Probe(Option<Mode>);
+        impl Modal for Probe {
+            fn set_mode(&mut self, mode: Mode) {
+                self.0 = Some(mode);
+            }
+        }
+
+        let mut network = net![Probe::default(), Linear::new(Probe::default(), Relu)];
+        network.set_mode(Mode::Eval);
+        assert_eq!(
+            (network.0 .0, network.1.graph.0),
+            (Some(Mode::Eval), Some(Mode::Eval))
+        );
+        network.set_mode(Mode::Train);
+        assert_eq!(network.1.graph.0, Some(Mode::Train));
+    }
+
+    #[test]
+    fn test_persist_round_trip() {
+        use crate::{
+            activation::{relu::Relu, Linear},
+            binary::{self, Element},
+            dense::{Dense, DenseState},
+            initialisers::Xavier,
+            Graph, Persist,
+        };
+
+        let network = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ];
+        let state: (Linear<DenseState<f64>, Relu>, DenseState<f64>) = network.input_shape(4);
+
+        let mut bytes = vec![];
+        network.write_state(&state, &mut bytes).unwrap();
+        let loaded: (Linear<DenseState<f64>, Relu>, DenseState<f64>) =
+            network.read_state(&mut bytes.as_slice()).unwrap();
+        assert_eq!(loaded.0.graph.w, state.0.graph.w);
+        assert_eq!(loaded.0.graph.b, state.0.graph.b);
+        assert_eq!(loaded.1.w, state.1.w);
+        assert_eq!(loaded.1.b, state.1.b);
+
+        let path = crate::temp_path("persist.bin");
+        network.save_file(&state, &path).unwrap();
+        let loaded: (Linear<DenseState<f64>, Relu>, DenseState<f64>) =
+            network.load_file(&path).unwrap();
+        assert_eq!(loaded.1.w, state.1.w);
+
+        // flip a bit in the last weight
+        let mut bytes = std::fs::read(&path).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        std::fs::write(&path, bytes).unwrap();
+        let err = Persist::<f64, _>::load_file(&network, &path).unwrap_err();
+        assert!(err.to_string().contains("corrupted"));
+
+        // version 1 files, from before the checksum, are still read
+        let mut bytes = vec![];
+        binary::write_header(&mut bytes, b"NRMD", 1).unwrap();
+        bytes.push(<f64 as Element>::TAG);
+        network.write_state(&state, &mut bytes).unwrap();
+        std::fs::write(&path, bytes).unwrap();
+        let loaded: (Linear<DenseState<f64>, Relu>, DenseState<f64>) =
+            network.load_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.0.graph.w, state.0.graph.w);
+    }
+
+    #[cfg(feature = "hdf5")]
+    #[test]
+    fn test_hdf5_flat_layers() {
+        use crate::{
+            activation::{relu::Relu, Linear},
+            dense::{Dense, DenseState},
+            initialisers::Xavier,
+            Graph, HDF5,
+        };
+
+        type State = (
+            Linear<DenseState<f64>, Relu>,
+            (DenseState<f64>, DenseState<f64>),
+        );
+
+        let network = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(3).with_initialiser(Xavier),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ];
+        let state: State = network.input_shape(4);
+
+        let path = crate::temp_path("layers.h5");
+        network
+            .save(&state, &hdf5::File::create(&path).unwrap())
+            .unwrap();
+
+        let file = hdf5::File::open(&path).unwrap();
+        let mut groups = file.member_names().unwrap();
+        groups.sort();
+        assert_eq!(groups, ["layer_0", "layer_1", "layer_2"]);
+
+        let loaded: State = network.load(&file).unwrap();
+        file.close().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.0.graph.w, state.0.graph.w);
+        assert_eq!(loaded.1 .0.b, state.1 .0.b);
+        assert_eq!(loaded.1 .1.w, state.1 .1.w);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        use crate::{
+            activation::{relu::Relu, Linear},
+            dense::{Dense, DenseState},
+            initialisers::Xavier,
+            Graph,
+        };
+
+        let network = net![
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ];
+        let state: (Linear<DenseState<f64>, Relu>, DenseState<f64>) = network.input_shape(4);
+
+        let json = serde_json::to_string(&state).unwrap();
+        let loaded: (Linear<DenseState<f64>, Relu>, DenseState<f64>) =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.1.w, state.1.w);
+        assert_eq!(loaded.0.graph.b, state.0.graph.b);
+    }
 }
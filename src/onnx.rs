@@ -0,0 +1,487 @@
+//! Imports multi-layer perceptrons from ONNX files.
+//!
+//! Only chains of `Gemm` nodes, each optionally followed by a `Relu`, `Sigmoid` or
+//! `Softmax`, are supported. This is what `torch.onnx.export` produces for a
+//! `nn.Sequential` of `nn.Linear` layers and those activations
+use std::{collections::HashMap, convert::TryFrom, fs, io, path::Path};
+
+use ndarray::{Array1, Array2, ArrayD, Ix1, Ix2, IxDyn};
+use num_traits::{Float, FromPrimitive};
+
+use crate::{classify::softmax, dense::DenseState, tensors::Tensors, GraphExec};
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// The subset of the protobuf wire format needed to read ONNX models
+mod proto {
+    use std::{
+        convert::{TryFrom, TryInto},
+        io,
+    };
+
+    use super::invalid;
+
+    #[derive(Clone, Copy)]
+    pub enum Value<'a> {
+        Varint(u64),
+        Fixed64([u8; 8]),
+        Bytes(&'a [u8]),
+        Fixed32([u8; 4]),
+    }
+
+    /// Iterates over the fields of a protobuf message
+    pub struct Fields<'a>(pub &'a [u8]);
+
+    impl<'a> Fields<'a> {
+        fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+            if self.0.len() < n {
+                return Err(invalid("protobuf message is truncated"));
+            }
+            let (taken, rest) = self.0.split_at(n);
+            self.0 = rest;
+            Ok(taken)
+        }
+
+        pub fn varint(&mut self) -> io::Result<u64> {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let byte = self.take(1)?[0];
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte & 0x80 == 0 {
+                    return Ok(value);
+                }
+            }
+            Err(invalid("protobuf varint is too long"))
+        }
+
+        fn field(&mut self) -> io::Result<(u64, Value<'a>)> {
+            let key = self.varint()?;
+            let value = match key & 7 {
+                0 => Value::Varint(self.varint()?),
+                1 => Value::Fixed64(self.take(8)?.try_into().unwrap()),
+                2 => {
+                    let len = usize::try_from(self.varint()?)
+                        .map_err(|_| invalid("protobuf field is too long"))?;
+                    Value::Bytes(self.take(len)?)
+                }
+                5 => Value::Fixed32(self.take(4)?.try_into().unwrap()),
+                _ => return Err(invalid("unsupported protobuf wire type")),
+            };
+            Ok((key >> 3, value))
+        }
+    }
+
+    impl<'a> Iterator for Fields<'a> {
+        type Item = io::Result<(u64, Value<'a>)>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.0.is_empty() {
+                None
+            } else {
+                Some(self.field())
+            }
+        }
+    }
+
+    pub fn string(bytes: &[u8]) -> io::Result<String> {
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("protobuf string is not utf8"))
+    }
+}
+
+use proto::{Fields, Value};
+
+/// An activation that follows a dense layer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OnnxActivation {
+    Relu,
+    Sigmoid,
+    Softmax,
+}
+
+/// A dense layer, along with the activation applied to its output
+#[derive(Debug, Clone)]
+pub struct OnnxLayer<F> {
+    pub dense: DenseState<F>,
+    pub activation: Option<OnnxActivation>,
+}
+
+/// The layers of a multi-layer perceptron read from an ONNX file
+#[derive(Debug, Clone)]
+pub struct OnnxMlp<F> {
+    pub layers: Vec<OnnxLayer<F>>,
+}
+
+enum Attribute {
+    Float(f32),
+    Int(u64),
+}
+
+struct Node {
+    op_type: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    attributes: HashMap<String, Attribute>,
+}
+
+impl Node {
+    fn float(&self, name: &str, default: f32) -> f32 {
+        match self.attributes.get(name) {
+            Some(Attribute::Float(f)) => *f,
+            _ => default,
+        }
+    }
+
+    fn int(&self, name: &str, default: u64) -> u64 {
+        match self.attributes.get(name) {
+            Some(Attribute::Int(i)) => *i,
+            _ => default,
+        }
+    }
+}
+
+impl<F> OnnxMlp<F>
+where
+    F: Float + FromPrimitive,
+{
+    /// Reads an ONNX model from a file
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&fs::read(path)?)
+    }
+
+    /// Parses the contents of an ONNX model file
+    pub fn read(bytes: &[u8]) -> io::Result<Self> {
+        let mut graph = None;
+        for field in Fields(bytes) {
+            if let (7, Value::Bytes(bytes)) = field? {
+                graph = Some(bytes);
+            }
+        }
+        let graph = graph.ok_or_else(|| invalid("onnx model has no graph"))?;
+
+        let mut nodes = vec![];
+        let mut initialisers = HashMap::new();
+        for field in Fields(graph) {
+            match field? {
+                (1, Value::Bytes(bytes)) => nodes.push(read_node(bytes)?),
+                (5, Value::Bytes(bytes)) => {
+                    let (name, tensor) = read_tensor(bytes)?;
+                    initialisers.insert(name, tensor);
+                }
+                _ => {}
+            }
+        }
+
+        let mut layers: Vec<OnnxLayer<F>> = vec![];
+        let mut previous: Option<String> = None;
+        for node in nodes {
+            let input = node
+                .inputs
+                .first()
+                .ok_or_else(|| invalid("onnx node has no input"))?;
+            if previous.is_some_and(|previous| &previous != input) {
+                return Err(invalid("onnx graph is not a single chain of nodes"));
+            }
+
+            let activation = match node.op_type.as_str() {
+                "Gemm" => {
+                    let dense = read_gemm(&node, &initialisers)?;
+                    layers.push(OnnxLayer {
+                        dense,
+                        activation: None,
+                    });
+                    None
+                }
+                "Relu" => Some(OnnxActivation::Relu),
+                "Sigmoid" => Some(OnnxActivation::Sigmoid),
+                "Softmax" => Some(OnnxActivation::Softmax),
+                _ => return Err(invalid("onnx graph contains an unsupported operator")),
+            };
+            if let Some(activation) = activation {
+                match layers.last_mut() {
+                    Some(layer) if layer.activation.is_none() => {
+                        layer.activation = Some(activation);
+                    }
+                    _ => return Err(invalid("onnx activation must follow a Gemm node")),
+                }
+            }
+
+            previous = node.outputs.into_iter().next();
+        }
+
+        Ok(Self { layers })
+    }
+
+    /// Copies the weights into an initialised graph state, such as a `net![...]` of
+    /// dense layers. The activations of the state should match [`layers`](Self::layers).
+    ///
+    /// Every shape is checked before any weights are copied, so the state is left
+    /// unchanged if the model doesn't match it
+    pub fn copy_into<T: Tensors<F>>(&self, state: &mut T) -> io::Result<()> {
+        let mut source = vec![];
+        for layer in &self.layers {
+            layer
+                .dense
+                .tensors("", &mut |_, tensor| source.push(tensor));
+        }
+
+        let mut shapes = vec![];
+        state.tensors("", &mut |_, tensor| shapes.push(tensor.shape().to_vec()));
+        if shapes.len() > source.len() {
+            return Err(invalid("onnx layers do not match the graph state"));
+        }
+        if shapes.len() < source.len() {
+            return Err(invalid("onnx model has more layers than the graph state"));
+        }
+        if shapes
+            .iter()
+            .zip(&source)
+            .any(|(shape, value)| shape != value.shape())
+        {
+            return Err(invalid("onnx layers do not match the graph state"));
+        }
+
+        let mut source = source.into_iter();
+        state.tensors_mut("", &mut |_, mut tensor| {
+            tensor.assign(&source.next().expect("tensors were checked"));
+        });
+        Ok(())
+    }
+}
+
+impl<F> GraphExec<Array2<F>> for OnnxMlp<F>
+where
+    F: Float + 'static,
+{
+    type Output = Array2<F>;
+
+    fn exec(&self, input: Array2<F>) -> Self::Output {
+        self.layers.iter().fold(input, |input, layer| {
+            let mut output = layer.dense.exec(input);
+            match layer.activation {
+                Some(OnnxActivation::Relu) => output.mapv_inplace(|x| x.max(F::zero())),
+                Some(OnnxActivation::Sigmoid) => {
+                    output.mapv_inplace(|x| F::one() / (F::one() + (-x).exp()));
+                }
+                Some(OnnxActivation::Softmax) => output = softmax(output),
+                None => {}
+            }
+            output
+        })
+    }
+}
+
+fn read_node(bytes: &[u8]) -> io::Result<Node> {
+    let mut node = Node {
+        op_type: String::new(),
+        inputs: vec![],
+        outputs: vec![],
+        attributes: HashMap::new(),
+    };
+    for field in Fields(bytes) {
+        match field? {
+            (1, Value::Bytes(bytes)) => node.inputs.push(proto::string(bytes)?),
+            (2, Value::Bytes(bytes)) => node.outputs.push(proto::string(bytes)?),
+            (4, Value::Bytes(bytes)) => node.op_type = proto::string(bytes)?,
+            (5, Value::Bytes(bytes)) => {
+                let mut name = String::new();
+                let mut value = None;
+                for field in Fields(bytes) {
+                    match field? {
+                        (1, Value::Bytes(bytes)) => name = proto::string(bytes)?,
+                        (2, Value::Fixed32(f)) => {
+                            value = Some(Attribute::Float(f32::from_le_bytes(f)));
+                        }
+                        (3, Value::Varint(i)) => value = Some(Attribute::Int(i)),
+                        _ => {}
+                    }
+                }
+                if let Some(value) = value {
+                    node.attributes.insert(name, value);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+/// Reads a float tensor from either its raw data or its typed data
+fn read_tensor<F>(bytes: &[u8]) -> io::Result<(String, ArrayD<F>)>
+where
+    F: Float + FromPrimitive,
+{
+    const FLOAT: u64 = 1;
+    const DOUBLE: u64 = 11;
+
+    let mut name = String::new();
+    let mut shape = vec![];
+    let mut data_type = 0;
+    let mut raw = None;
+    let mut floats = vec![];
+    let mut doubles = vec![];
+    for field in Fields(bytes) {
+        match field? {
+            (1, Value::Varint(dim)) => shape.push(dim),
+            (1, Value::Bytes(packed)) => {
+                let mut packed = Fields(packed);
+                while !packed.0.is_empty() {
+                    shape.push(packed.varint()?);
+                }
+            }
+            (2, Value::Varint(t)) => data_type = t,
+            (4, Value::Fixed32(f)) => floats.push(f32::from_le_bytes(f)),
+            (4, Value::Bytes(packed)) => floats.extend(
+                packed
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            ),
+            (8, Value::Bytes(bytes)) => name = proto::string(bytes)?,
+            (9, Value::Bytes(bytes)) => raw = Some(bytes),
+            (10, Value::Fixed64(f)) => doubles.push(f64::from_le_bytes(f)),
+            (10, Value::Bytes(packed)) => doubles.extend(packed.chunks_exact(8).map(|b| {
+                let mut le = [0; 8];
+                le.copy_from_slice(b);
+                f64::from_le_bytes(le)
+            })),
+            _ => {}
+        }
+    }
+
+    let elements: Option<Vec<F>> = match (data_type, raw) {
+        (FLOAT, Some(raw)) => raw
+            .chunks_exact(4)
+            .map(|b| F::from_f32(f32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+            .collect(),
+        (DOUBLE, Some(raw)) => raw
+            .chunks_exact(8)
+            .map(|b| {
+                let mut le = [0; 8];
+                le.copy_from_slice(b);
+                F::from_f64(f64::from_le_bytes(le))
+            })
+            .collect(),
+        (FLOAT, None) => floats.into_iter().map(F::from_f32).collect(),
+        (DOUBLE, None) => doubles.into_iter().map(F::from_f64).collect(),
+        _ => return Err(invalid("onnx tensor has an unsupported element type")),
+    };
+    let elements = elements.ok_or_else(|| invalid("onnx element cannot be represented"))?;
+
+    let shape = shape
+        .into_iter()
+        .map(|dim| usize::try_from(dim).map_err(|_| invalid("onnx tensor is too large")))
+        .collect::<io::Result<Vec<_>>>()?;
+    let tensor = ArrayD::from_shape_vec(IxDyn(&shape), elements)
+        .map_err(|_| invalid("onnx tensor shape mismatch"))?;
+    Ok((name, tensor))
+}
+
+/// Converts `Y = alpha * A * B + beta * C` into a dense layer, where `A` is the input
+fn read_gemm<F>(node: &Node, initialisers: &HashMap<String, ArrayD<F>>) -> io::Result<DenseState<F>>
+where
+    F: Float + FromPrimitive,
+{
+    let initialiser = |i: usize| node.inputs.get(i).and_then(|name| initialisers.get(name));
+
+    if node.int("transA", 0) != 0 {
+        return Err(invalid(
+            "onnx Gemm with a transposed input is not supported",
+        ));
+    }
+    let w = initialiser(1)
+        .ok_or_else(|| invalid("onnx Gemm weights must be an initialiser"))?
+        .clone()
+        .into_dimensionality::<Ix2>()
+        .map_err(|_| invalid("onnx Gemm weights must be a matrix"))?;
+    let w = if node.int("transB", 0) == 0 {
+        w
+    } else {
+        w.reversed_axes()
+    };
+    let alpha = F::from_f32(node.float("alpha", 1.0)).unwrap();
+    let w = w.as_standard_layout().mapv(|x| x * alpha);
+
+    let outputs = w.ncols();
+    let b = match initialiser(2) {
+        Some(c) if c.len() == outputs => c.iter().copied().collect(),
+        Some(_) => return Err(invalid("onnx Gemm bias must have one value per output")),
+        None => Array1::zeros(outputs),
+    };
+    let beta = F::from_f32(node.float("beta", 1.0)).unwrap();
+    let b = b.into_dimensionality::<Ix1>().unwrap().mapv(|x| x * beta);
+
+    Ok(DenseState { w, b })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::activation::{relu::Relu, Linear};
+    use ndarray::array;
+
+    /// Encodes a length delimited protobuf field
+    fn field(number: u8, bytes: &[u8]) -> Vec<u8> {
+        let mut field = vec![number << 3 | 2, u8::try_from(bytes.len()).unwrap()];
+        field.extend_from_slice(bytes);
+        field
+    }
+
+    fn node(op_type: &str, input: &[&str], output: &str, attribute: &[u8]) -> Vec<u8> {
+        let mut node: Vec<u8> = input.iter().flat_map(|i| field(1, i.as_bytes())).collect();
+        node.extend(field(2, output.as_bytes()));
+        node.extend(field(4, op_type.as_bytes()));
+        node.extend_from_slice(attribute);
+        field(1, &node)
+    }
+
+    fn initialiser(name: &str, dims: &[u8], data: &[f32]) -> Vec<u8> {
+        let mut tensor: Vec<u8> = dims.iter().flat_map(|&d| vec![1 << 3, d]).collect();
+        tensor.extend([2 << 3, 1]);
+        tensor.extend(field(8, name.as_bytes()));
+        let data: Vec<u8> = data.iter().flat_map(|x| x.to_le_bytes()).collect();
+        tensor.extend(field(9, &data));
+        field(5, &tensor)
+    }
+
+    #[test]
+    fn test_read_gemm_relu() {
+        // transB = 1, as exported by pytorch
+        let trans_b = field(5, &[&field(1, b"transB")[..], &[3 << 3, 1]].concat());
+
+        let mut graph = node("Gemm", &["x", "w", "b"], "h", &trans_b);
+        graph.extend(node("Relu", &["h"], "y", &[]));
+        graph.extend(initialiser("w", &[3, 2], &[1.0, 0.0, 0.0, 1.0, 1.0, -1.0]));
+        graph.extend(initialiser("b", &[3], &[0.0, 0.0, 1.0]));
+        let model = field(7, &graph);
+
+        let mlp = OnnxMlp::<f64>::read(&model).unwrap();
+        assert_eq!(mlp.layers.len(), 1);
+        assert_eq!(mlp.layers[0].activation, Some(OnnxActivation::Relu));
+        assert_eq!(
+            mlp.layers[0].dense.w,
+            array![[1.0, 0.0, 1.0], [0.0, 1.0, -1.0]]
+        );
+
+        let output = mlp.exec(array![[1.0, 2.0], [3.0, -4.0]]);
+        assert_eq!(output, array![[1.0, 2.0, 0.0], [3.0, 0.0, 8.0]]);
+
+        let mut state = Linear::new(
+            DenseState {
+                w: Array2::zeros((2, 3)),
+                b: Array1::zeros(3),
+            },
+            Relu,
+        );
+        mlp.copy_into(&mut state).unwrap();
+        assert_eq!(state.exec(array![[1.0, 2.0], [3.0, -4.0]]), output);
+
+        // the weights match, but the biases don't, so nothing is copied
+        let mut mismatched = DenseState {
+            w: Array2::zeros((2, 3)),
+            b: Array1::zeros(2),
+        };
+        assert!(mlp.copy_into(&mut mismatched).is_err());
+        assert_eq!(mismatched.w, Array2::<f64>::zeros((2, 3)));
+    }
+}
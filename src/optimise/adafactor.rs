@@ -0,0 +1,224 @@
+use ndarray::{Array1, Array2, ArrayViewD, Axis};
+use num_traits::Float;
+
+use crate::tensors::{ParamPath, Tensors};
+
+use super::{Optimiser, TunableOptimiser};
+
+/// The second moment estimate of one parameter tensor
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+enum SecondMoment<F> {
+    /// The row and column sums of the estimate, for tensors with at least two dimensions.
+    /// Every dimension but the last counts as a row
+    Factored { rows: Array1<F>, cols: Array1<F> },
+    /// The full estimate, for vectors like biases
+    Full(Array1<F>),
+}
+
+impl<F: Float> SecondMoment<F> {
+    fn new(tensor: &ArrayViewD<F>) -> Self {
+        match tensor.shape().split_last() {
+            Some((&cols, rows)) if !rows.is_empty() => Self::Factored {
+                rows: Array1::zeros(rows.iter().product::<usize>()),
+                cols: Array1::zeros(cols),
+            },
+            _ => Self::Full(Array1::zeros(tensor.len())),
+        }
+    }
+
+    /// Updates the estimate with the gradients, and returns the gradients divided by
+    /// the root of the estimate, in the tensor's logical order
+    fn update(&mut self, grads: &ArrayViewD<F>, beta2: F, epsilon: F) -> Array1<F> {
+        let one = F::one();
+        let squared = grads.iter().map(|&g| g * g + epsilon);
+        let grads = grads.iter().copied();
+        match self {
+            Self::Factored { rows, cols } => {
+                let squared = Array2::from_shape_vec((rows.len(), cols.len()), squared.collect())
+                    .expect("gradients should have the same shape as the parameters");
+                rows.zip_mut_with(&squared.sum_axis(Axis(1)), |r, &s| {
+                    *r = *r * beta2 + s * (one - beta2);
+                });
+                cols.zip_mut_with(&squared.sum_axis(Axis(0)), |c, &s| {
+                    *c = *c * beta2 + s * (one - beta2);
+                });
+
+                // the estimate is the outer product of the rows and columns,
+                // normalised by the total
+                let total = rows.sum();
+                let (rows, cols) = (&*rows, &*cols);
+                let v = rows
+                    .iter()
+                    .flat_map(|&r| cols.iter().map(move |&c| r * c / total));
+                grads.zip(v).map(|(g, v)| g / v.sqrt()).collect()
+            }
+            Self::Full(full) => {
+                full.iter_mut().zip(squared).for_each(|(v, s)| {
+                    *v = *v * beta2 + s * (one - beta2);
+                });
+                grads.zip(full.iter()).map(|(g, &v)| g / v.sqrt()).collect()
+            }
+        }
+    }
+}
+
+/// Adafactor, from <https://arxiv.org/abs/1804.04235>.
+///
+/// Like [`Adam`](super::Adam) without momentum, but the second moment estimate of each
+/// matrix is factored into the sums of its rows and columns. A `(n, m)` weight only needs
+/// `n + m` extra values, rather than Adam's `2nm`, which makes a large difference for
+/// big dense or embedding layers. The estimates are allocated on the first step.
+///
+/// Each step is clipped so that its root mean square is at most `clip`, and the decay of
+/// the estimate increases over time, `1 - t^-decay`, so no bias correction is needed
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Adafactor<F> {
+    alpha: F,
+    decay: F,
+    epsilon: F,
+    clip: F,
+    moments: Vec<SecondMoment<F>>,
+    t: i32,
+}
+
+impl<F> Adafactor<F> {
+    pub const fn new(alpha: F, decay: F, epsilon: F, clip: F) -> Self {
+        Self {
+            alpha,
+            decay,
+            epsilon,
+            clip,
+            moments: Vec::new(),
+            t: 0,
+        }
+    }
+}
+
+impl<F: Float> Adafactor<F> {
+    /// Uses the hyperparameters recommended by the paper, with the given learning rate.
+    /// A `decay` of 0.8, `epsilon` of 1e-30 and `clip` of 1
+    pub fn with_learning_rate(alpha: F) -> Self {
+        let f = |x| F::from(x).unwrap();
+        Self::new(alpha, f(0.8), f(1e-30), F::one())
+    }
+}
+
+impl<F, G> Optimiser<G> for Adafactor<F>
+where
+    G: Tensors<F>,
+    F: Float,
+{
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
+        self.t += 1;
+        let beta2 = F::one() - F::from(self.t).unwrap().powf(-self.decay);
+        let Self {
+            alpha,
+            epsilon,
+            clip,
+            moments,
+            ..
+        } = self;
+
+        let grads = grads.params();
+        let mut tensors = grads.iter();
+        let mut index = 0;
+        graph.visit_mut(&mut ParamPath::default(), &mut |_, mut param| {
+            let (_, grads) = tensors
+                .next()
+                .expect("gradients should have the same tensors as the graph");
+            // the estimates are allocated the first time each tensor is seen
+            if moments.len() == index {
+                moments.push(SecondMoment::new(grads));
+            }
+            let update = moments[index].update(grads, beta2, *epsilon);
+            index += 1;
+
+            let n = F::from(update.len().max(1)).unwrap();
+            let rms = (update.fold(F::zero(), |sum, &u| sum + u * u) / n).sqrt();
+            let scale = *alpha / (rms / *clip).max(F::one());
+            param
+                .iter_mut()
+                .zip(&update)
+                .for_each(|(x, &u)| *x = *x - u * scale);
+        });
+    }
+}
+
+impl<F: Copy> TunableOptimiser<F> for Adafactor<F> {
+    fn learning_rate(&self) -> F {
+        self.alpha
+    }
+    fn set_learning_rate(&mut self, learning_rate: F) {
+        self.alpha = learning_rate;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::ArrayD;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{Adafactor, SecondMoment};
+    use crate::{
+        activation::relu::Relu, cost::mse::MSE, data, dense::Dense, initialisers::Xavier, net,
+        tensors::Tensors, train::Train, Graph,
+    };
+
+    #[test]
+    fn test_adafactor() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = net![
+            Dense::output_size(8)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(1).with_initialiser(Xavier)
+        ]
+        .init_with_random(&mut rng, 2);
+        let (inputs, targets) = data::linear_samples(&mut rng, 32);
+
+        let mut trainer = Train::builder(graph)
+            .cost(MSE)
+            .optimiser(Adafactor::with_learning_rate(0.01))
+            .build();
+        let params = |graph: &_| -> Vec<ArrayD<f64>> {
+            Tensors::params(graph)
+                .into_iter()
+                .map(|(_, tensor)| tensor.to_owned())
+                .collect()
+        };
+        for i in 0..100 {
+            let before = params(&trainer.graph);
+            trainer.train(inputs.clone(), targets.clone());
+            let steps: Vec<_> = params(&trainer.graph)
+                .iter()
+                .zip(&before)
+                .map(|(after, before)| after - before)
+                .collect();
+
+            // the first estimate of a bias is its squared gradient, so every value moves by
+            // the whole learning rate
+            if i == 0 {
+                assert!(steps[3].iter().all(|s| (s.abs() - 0.01).abs() < 1e-12));
+            }
+            // and no step's root mean square is more than the learning rate
+            for step in &steps {
+                let rms = step.mapv(|s| s * s).mean().unwrap().sqrt();
+                assert!(rms < 0.01 + 1e-12, "{}", rms);
+            }
+        }
+
+        // weights keep one value per row and column, biases keep one per value
+        let sizes: Vec<_> = trainer
+            .optimiser
+            .moments
+            .iter()
+            .map(|moment| match moment {
+                SecondMoment::Factored { rows, cols } => (rows.len(), cols.len()),
+                SecondMoment::Full(full) => (full.len(), 0),
+            })
+            .collect();
+        assert_eq!(sizes, [(8, 2), (8, 0), (1, 8), (1, 0)]);
+    }
+}
@@ -1,11 +1,21 @@
-use ndarray::LinalgScalar;
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "hdf5")]
+use hdf5::H5Type;
+use ndarray::{array, Array1, LinalgScalar};
 use num_traits::{Float, Zero};
 
-use crate::{Mappable, Shaped};
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    binary::{self, read_array, write_array, Element},
+    Mappable, Persist, Shaped,
+};
 
-use super::Optimiser;
+use super::{Optimiser, TunableOptimiser};
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Adam<F, G> {
     alpha: F,
     beta1: F,
@@ -35,12 +45,102 @@ where
     }
 }
 
+impl<F, G> Adam<F, G>
+where
+    F: Float,
+    G: Mappable<F> + Clone + Shaped<F>,
+{
+    /// Uses the commonly recommended hyperparameters, a learning rate of 0.001,
+    /// `beta1` of 0.9, `beta2` of 0.999 and `epsilon` of 1e-8, for a graph of the same shape
+    pub fn default_for(graph: &G) -> Self {
+        let f = |x| F::from(x).unwrap();
+        Self::new(f(0.001), f(0.9), f(0.999), f(1e-8), graph.shape())
+    }
+}
+
+impl<F: Element, G> Adam<F, G> {
+    /// Writes the hyperparameters, step counter and moment estimates, so training
+    /// can resume after a restart. `network` is the graph that produced the state
+    pub fn write<I, N>(&self, network: &N, w: &mut impl Write) -> io::Result<()>
+    where
+        N: Persist<F, I, State = G>,
+    {
+        write_array(w, &array![self.alpha, self.beta1, self.beta2, self.epsilon])?;
+        w.write_all(&self.t.to_le_bytes())?;
+        network.write_state(&self.m, w)?;
+        network.write_state(&self.v, w)
+    }
+
+    pub fn read<I, N>(network: &N, r: &mut impl Read) -> io::Result<Self>
+    where
+        N: Persist<F, I, State = G>,
+    {
+        let params: Array1<F> = read_array(r)?;
+        let Some(&[alpha, beta1, beta2, epsilon]) = params.as_slice() else {
+            return Err(binary::invalid("adam has the wrong number of parameters"));
+        };
+        let mut t = [0; 4];
+        r.read_exact(&mut t)?;
+        Ok(Self {
+            alpha,
+            beta1,
+            beta2,
+            epsilon,
+            t: i32::from_le_bytes(t),
+            m: network.read_state(r)?,
+            v: network.read_state(r)?,
+        })
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<F: H5Type + Copy, G> Adam<F, G> {
+    pub fn save<I, N>(&self, network: &N, group: &hdf5::Group) -> hdf5::Result<()>
+    where
+        N: HDF5<F, I, State = G>,
+    {
+        let params = array![self.alpha, self.beta1, self.beta2, self.epsilon];
+        group
+            .new_dataset_builder()
+            .with_data(params.view())
+            .create("params")?;
+        group
+            .new_dataset_builder()
+            .with_data(array![self.t].view())
+            .create("t")?;
+        network.save(&self.m, &group.create_group("m")?)?;
+        network.save(&self.v, &group.create_group("v")?)
+    }
+
+    pub fn load<I, N>(network: &N, group: &hdf5::Group) -> hdf5::Result<Self>
+    where
+        N: HDF5<F, I, State = G>,
+    {
+        let params = group.dataset("params")?.read_1d::<F>()?;
+        let Some(&[alpha, beta1, beta2, epsilon]) = params.as_slice() else {
+            return Err("adam has the wrong number of parameters".into());
+        };
+        let t = group.dataset("t")?.read_1d::<i32>()?;
+        Ok(Self {
+            alpha,
+            beta1,
+            beta2,
+            epsilon,
+            t: t.first()
+                .copied()
+                .ok_or("adam is missing its step counter")?,
+            m: network.load(&group.group("m")?)?,
+            v: network.load(&group.group("v")?)?,
+        })
+    }
+}
+
 impl<F, G> Optimiser<G> for Adam<F, G>
 where
     G: Mappable<F>,
     F: LinalgScalar + Float,
 {
-    fn optimise(&mut self, graph: &mut G, grads: G) {
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
         // Algorithm defined on Page 2 of https://arxiv.org/pdf/1412.6980v9.pdf
         // https://mlfromscratch.com/optimizers-explained/#actually-explaining-adam
 
@@ -54,29 +154,72 @@ where
         let one = F::one();
 
         // m_t = b1 * m_t-1 + (1 - b1) * g_t
-        self.m.map_mut_with(&grads, |m, &g| {
+        self.m.map_mut_with(grads, |m, &g| {
             *m = *m * b1 + g * (one - b1);
         });
 
         // v_t = b2 * v_t-1 + (1 - b2) * g_t^2
-        self.v.map_mut_with(&grads, |v, &g| {
+        self.v.map_mut_with(grads, |v, &g| {
             *v = *v * b2 + g.powi(2) * (one - b2);
         });
 
+        // the gradients are no longer needed, so the step is calculated in their place
+
         // m_t' = m_t / (1 - b1^t)
-        let mut mb = self.m.map(|&m| m / (one - b1.powi(self.t)));
+        let mb = one - b1.powi(self.t);
+        grads.map_mut_with(&self.m, |x, &m| *x = m / mb);
 
         // v_t' = v_t / (1 - b2^t)
-        let vb = self.v.map(|&v| v / (one - b2.powi(self.t)));
-
         // x_t = a * m_t' / (sqrt(v_t') + e)
-        mb.map_mut_with(&vb, |m, &v| {
-            *m = *m * a / (v.sqrt() + e);
+        let vb = one - b2.powi(self.t);
+        grads.map_mut_with(&self.v, |x, &v| {
+            *x = *x * a / ((v / vb).sqrt() + e);
         });
 
         // g_t = g_t-1 - x_t
-        graph.map_mut_with(&mb, |g, &m| {
-            *g = *g - m;
+        graph.map_mut_with(grads, |g, &x| {
+            *g = *g - x;
         });
     }
 }
+
+impl<F: Copy, G> TunableOptimiser<F> for Adam<F, G> {
+    fn learning_rate(&self) -> F {
+        self.alpha
+    }
+    fn set_learning_rate(&mut self, learning_rate: F) {
+        self.alpha = learning_rate;
+    }
+    fn momentum(&self) -> Option<F> {
+        Some(self.beta1)
+    }
+    fn set_momentum(&mut self, momentum: F) {
+        self.beta1 = momentum;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::Adam;
+    use crate::{dense::Dense, initialisers::Xavier, optimise::Optimiser, Graph, Shaped};
+
+    #[test]
+    fn test_write_read() {
+        let network = Dense::output_size(2).with_initialiser(Xavier);
+        let mut state = network.input_shape(3);
+        let mut adam: Adam<f64, _> = Adam::new(0.001, 0.9, 0.99, 1e-8, state.shape());
+        let mut grads = state.clone();
+        adam.optimise(&mut state, &mut grads);
+
+        let mut bytes = vec![];
+        adam.write(&network, &mut bytes).unwrap();
+        let read: Adam<f64, _> = Adam::read(&network, &mut bytes.as_slice()).unwrap();
+
+        assert_eq!(read.t, 1);
+        assert_eq!(read.m.w, adam.m.w);
+        assert_eq!(read.v.b, adam.v.b);
+        assert_eq!(array![read.alpha, read.beta2], array![0.001, 0.99]);
+    }
+}
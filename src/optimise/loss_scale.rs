@@ -0,0 +1,311 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use ndarray::{Array, Dimension};
+use num_traits::{Float, FromPrimitive};
+
+use crate::{cost::Cost, precision::Cast, Mappable};
+
+use super::{Optimiser, TunableOptimiser};
+
+/// The current loss scale of a [`LossScaling`] optimiser, shared with the
+/// [`LossScaled`] cost function that applies it
+#[derive(Debug, Clone)]
+pub struct LossScale(Arc<AtomicU64>);
+
+impl LossScale {
+    #[must_use]
+    pub fn new(scale: f64) -> Self {
+        Self(Arc::new(AtomicU64::new(scale.to_bits())))
+    }
+
+    #[must_use]
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, scale: f64) {
+        self.0.store(scale.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// Multiplies the gradient of the wrapped cost function by the loss scale, so that small
+/// gradients don't underflow in low precision. The cost itself is left unscaled.
+///
+/// The trainer divides the gradients by the scale again before applying them
+#[derive(Debug, Clone)]
+pub struct LossScaled<C> {
+    cost: C,
+    scale: LossScale,
+}
+
+impl<C, F, D> Cost<Array<F, D>> for LossScaled<C>
+where
+    C: Cost<Array<F, D>>,
+    F: Float + FromPrimitive,
+    D: Dimension,
+{
+    type Inner = C::Inner;
+    fn cost(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Self::Inner {
+        self.cost.cost(output, expected)
+    }
+    fn diff(&self, output: &Array<F, D>, expected: &Array<F, D>) -> Array<F, D> {
+        let scale = F::from_f64(self.scale.get()).unwrap();
+        let mut diff = self.cost.diff(output, expected);
+        diff.mapv_inplace(|x| x * scale);
+        diff
+    }
+}
+
+/// Dynamic loss scaling for a low precision graph, which keeps a master copy of its
+/// parameters, `M`, in a higher precision.
+///
+/// The wrapped optimiser updates the master copy, which is then rounded back into
+/// the graph, so small updates aren't lost to rounding.
+///
+/// The cost function must be wrapped using [`scaled`](Self::scaled). The trainer
+/// divides the gradients by the loss scale before inspecting, regularising or applying
+/// them. If the scaled gradients overflow, the step is skipped and the loss scale is
+/// reduced. After every `growth_interval` steps without overflowing, the loss scale is
+/// increased again, up to the maximum loss scale.
+///
+/// The master copy is taken from the graph on the first step, so the graph shouldn't be
+/// replaced afterwards
+///
+/// ```
+/// use linear_networks::optimise::{loss_scale::LossScaling, sgd::SGD};
+/// use linear_networks::{cost::mse::MSE, dense::DenseState};
+///
+/// let optimiser: LossScaling<f64, SGD<f64>, DenseState<f64>> =
+///     LossScaling::new(SGD::new(0.01));
+/// let cost = optimiser.scaled(MSE);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LossScaling<F, O, M> {
+    optimiser: O,
+    master: Option<M>,
+    grads: Option<M>,
+    scale: LossScale,
+    growth_factor: F,
+    backoff_factor: F,
+    growth_interval: usize,
+    max_scale: f64,
+    good_steps: usize,
+}
+
+impl<F: Float, O, M> LossScaling<F, O, M> {
+    /// Wraps the optimiser, with an initial loss scale of 2^16 that's halved on overflow,
+    /// and doubled after 2000 steps without overflowing, up to 2^24
+    pub fn new(optimiser: O) -> Self {
+        Self {
+            optimiser,
+            master: None,
+            grads: None,
+            scale: LossScale::new(65536.0),
+            growth_factor: F::one() + F::one(),
+            backoff_factor: (F::one() + F::one()).recip(),
+            growth_interval: 2000,
+            max_scale: 16_777_216.0,
+            good_steps: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn with_loss_scale(self, scale: f64) -> Self {
+        self.scale.set(scale);
+        self
+    }
+
+    /// The largest the loss scale can grow to. Larger scales only help if the gradients
+    /// are small enough to underflow, and make every step more likely to overflow
+    #[must_use]
+    pub const fn with_max_loss_scale(mut self, max_scale: f64) -> Self {
+        self.max_scale = max_scale;
+        self
+    }
+
+    #[must_use]
+    pub const fn with_growth_interval(mut self, growth_interval: usize) -> Self {
+        self.growth_interval = growth_interval;
+        self
+    }
+
+    /// Wraps the cost function so that its gradients are multiplied by this optimiser's loss scale
+    pub fn scaled<C>(&self, cost: C) -> LossScaled<C> {
+        LossScaled {
+            cost,
+            scale: self.scale.clone(),
+        }
+    }
+
+    #[must_use]
+    pub fn loss_scale(&self) -> f64 {
+        self.scale.get()
+    }
+
+    /// The high precision master copy of the graph, once training has started
+    pub const fn master(&self) -> Option<&M> {
+        self.master.as_ref()
+    }
+}
+
+impl<F, O, M, G> Optimiser<G> for LossScaling<F, O, M>
+where
+    G: Cast<M>,
+    M: Cast<G> + Mappable<F>,
+    O: Optimiser<M>,
+    F: Float + FromPrimitive,
+{
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
+        let master = self.master.get_or_insert_with(|| graph.cast());
+        let master_grads = match &mut self.grads {
+            Some(master_grads) => {
+                grads.cast_into(master_grads);
+                master_grads
+            }
+            None => self.grads.insert(grads.cast()),
+        };
+
+        // the trainer has already unscaled the gradients, but overflows stay infinite
+        let scale = F::from_f64(self.scale.get()).unwrap();
+        let mut finite = true;
+        master_grads.for_each(|g| finite &= g.is_finite());
+        if !finite {
+            self.scale
+                .set((scale * self.backoff_factor).to_f64().unwrap());
+            self.good_steps = 0;
+            return;
+        }
+
+        self.optimiser.optimise(master, master_grads);
+        master.cast_into(graph);
+
+        self.good_steps += 1;
+        if self.good_steps == self.growth_interval {
+            let grown = (scale * self.growth_factor).to_f64().unwrap();
+            self.scale.set(grown.min(self.max_scale));
+            self.good_steps = 0;
+        }
+    }
+
+    fn loss_scale(&self) -> Option<f64> {
+        Some(self.scale.get())
+    }
+}
+
+impl<T, F, O: TunableOptimiser<T>, M> TunableOptimiser<T> for LossScaling<F, O, M> {
+    fn learning_rate(&self) -> T {
+        self.optimiser.learning_rate()
+    }
+    fn set_learning_rate(&mut self, learning_rate: T) {
+        self.optimiser.set_learning_rate(learning_rate);
+    }
+    fn momentum(&self) -> Option<T> {
+        self.optimiser.momentum()
+    }
+    fn set_momentum(&mut self, momentum: T) {
+        self.optimiser.set_momentum(momentum);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array2};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::LossScaling;
+    use crate::{
+        cost::{mse::MSE, Cost},
+        data,
+        dense::{Dense, DenseState},
+        initialisers::Xavier,
+        optimise::{sgd::SGD, Optimiser},
+        precision::Cast,
+        train::Train,
+        Graph,
+    };
+
+    #[test]
+    fn test_loss_scaling() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+
+        let optimiser: LossScaling<f64, _, DenseState<f64>> =
+            LossScaling::new(SGD::new(0.01)).with_growth_interval(10);
+        let cost = optimiser.scaled(MSE);
+        let mut trainer = Train::builder(graph)
+            .cost(cost)
+            .optimiser(optimiser)
+            .build();
+
+        let mut full = Train::builder(trainer.graph.cast())
+            .optimiser(SGD::new(0.01))
+            .build();
+
+        let (inputs, targets) = data::linear_samples::<f32>(&mut rng, 64);
+        for _ in 0..501 {
+            trainer.train(inputs.clone(), targets.clone());
+            full.train(inputs.mapv(f64::from), targets.mapv(f64::from));
+        }
+        // the steps go to the master copy, which tracks training in full precision
+        // and is rounded into the graph
+        let master: &DenseState<f64> = trainer.optimiser.master().unwrap();
+        let graph: DenseState<f32> = master.cast();
+        assert_eq!(
+            (graph.w, graph.b),
+            (trainer.graph.w.clone(), trainer.graph.b.clone())
+        );
+        let diff = (&master.w - &full.graph.w).mapv(f64::abs);
+        assert!(diff.iter().all(|&d| d < 1e-4), "{}", diff);
+        // the initial scale of 2^16, doubled every 10 steps up to 2^24
+        assert!((trainer.optimiser.loss_scale() - 2.0_f64.powi(24)).abs() < f64::EPSILON);
+
+        // gradients that overflow skip the step and reduce the scale
+        let mut optimiser: LossScaling<f64, _, DenseState<f64>> = LossScaling::new(SGD::new(0.05));
+        let mut graph = trainer.graph.clone();
+        let mut grads = trainer.graph.clone();
+        grads.w[[0, 0]] = f32::INFINITY;
+        optimiser.optimise(&mut graph, &mut grads);
+        assert_eq!(graph.w, trainer.graph.w);
+        assert!((optimiser.loss_scale() - 32768.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_hook_sees_unscaled_grads() {
+        fn record<C, O>(trainer: &mut Train<f32, C, O, DenseState<f32>>) -> Array2<f32>
+        where
+            C: Cost<Array2<f32>, Inner = f32>,
+            O: Optimiser<DenseState<f32>>,
+        {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            trainer.on_grads = Some(Box::new(move |grads: &DenseState<f32>| {
+                sender.send(grads.w.clone()).unwrap();
+            }));
+            trainer.train(array![[0.5_f32, -1.0]], array![[2.0]]);
+            receiver.recv().unwrap()
+        }
+
+        let graph = DenseState {
+            w: array![[1.0_f32], [-1.0]],
+            b: array![0.5],
+        };
+
+        let mut plain = Train::builder(graph.clone())
+            .cost(MSE)
+            .optimiser(SGD::new(0.1))
+            .build();
+        let optimiser: LossScaling<f64, _, DenseState<f64>> = LossScaling::new(SGD::new(0.1));
+        let mut scaled = Train::builder(graph)
+            .cost(optimiser.scaled(MSE))
+            .optimiser(optimiser)
+            .build();
+
+        // the scale is a power of two, so unscaling is exact
+        assert_eq!(record(&mut scaled), record(&mut plain));
+        assert_eq!(scaled.graph.w, plain.graph.w);
+    }
+}
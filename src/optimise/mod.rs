@@ -1,6 +1,92 @@
+pub mod adafactor;
 pub mod adam;
+pub mod loss_scale;
+pub mod radam;
+pub mod scheduled;
 pub mod sgd;
 
+pub use self::{
+    adafactor::Adafactor,
+    adam::Adam,
+    loss_scale::{LossScale, LossScaled, LossScaling},
+    radam::RAdam,
+    scheduled::Scheduled,
+    sgd::SGD,
+};
+
 pub trait Optimiser<G> {
-    fn optimise(&mut self, graph: &mut G, grads: G);
+    /// Applies the gradients to the graph. The gradients can be overwritten as scratch
+    /// space, so that the trainer can reuse the buffer for the next batch without
+    /// either side allocating
+    fn optimise(&mut self, graph: &mut G, grads: &mut G);
+
+    /// The factor the cost's gradients are multiplied by, if the optimiser uses loss
+    /// scaling. The trainer divides the gradients by it before passing them on.
+    /// Wrappers forward this from the optimiser inside them
+    fn loss_scale(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// Optimisers whose hyperparameters can be read and changed between steps,
+/// so that schedules and callbacks can adjust them without knowing which optimiser is used
+pub trait TunableOptimiser<F> {
+    fn learning_rate(&self) -> F;
+    fn set_learning_rate(&mut self, learning_rate: F);
+
+    /// The decay of the first moment estimate, if the optimiser has momentum
+    fn momentum(&self) -> Option<F> {
+        None
+    }
+
+    /// Changes the decay of the first moment estimate.
+    /// Ignored by optimisers without momentum
+    fn set_momentum(&mut self, _momentum: F) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Adafactor, Adam, Optimiser, RAdam, TunableOptimiser, SGD};
+    use crate::{
+        dense::Dense,
+        initialisers::Xavier,
+        prune::{Mask, Scope},
+        Graph,
+    };
+
+    fn halve<F: num_traits::Float, O: TunableOptimiser<F>>(optimiser: &mut O) {
+        let learning_rate = optimiser.learning_rate();
+        optimiser.set_learning_rate(learning_rate / (F::one() + F::one()));
+    }
+
+    #[test]
+    fn test_tunable() {
+        let graph = Graph::<f64, _>::input_shape(Dense::output_size(2).with_initialiser(Xavier), 3);
+
+        let mut sgd = SGD::new(0.1_f64);
+        halve(&mut sgd);
+        assert!((sgd.learning_rate() - 0.05).abs() < 1e-12);
+        assert_eq!(sgd.momentum(), None);
+
+        let mut adam: Adam<f64, _> = Adam::default_for(&graph);
+        halve(&mut adam);
+        adam.set_momentum(0.5);
+        assert!((adam.learning_rate() - 0.0005).abs() < 1e-12);
+        assert_eq!(adam.momentum(), Some(0.5));
+
+        let mut radam: RAdam<f64, _> = RAdam::default_for(&graph);
+        halve(&mut radam);
+        assert!((radam.learning_rate() - 0.0005).abs() < 1e-12);
+
+        let mut adafactor = Adafactor::with_learning_rate(0.01_f64);
+        halve(&mut adafactor);
+        assert!((adafactor.learning_rate() - 0.005).abs() < 1e-12);
+
+        // wrappers tune the optimiser inside them
+        let mut masked = Mask::magnitude(&graph, 0.5, Scope::Global).masked(SGD::new(0.1_f64));
+        halve(&mut masked);
+        assert!((masked.optimiser.learning_rate() - 0.05).abs() < 1e-12);
+        let (mut graph, mut grads) = (graph.clone(), graph);
+        masked.optimise(&mut graph, &mut grads);
+    }
 }
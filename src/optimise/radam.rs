@@ -0,0 +1,202 @@
+use std::io::{self, Read, Write};
+
+use ndarray::{array, Array1, LinalgScalar};
+use num_traits::{Float, Zero};
+
+use crate::{
+    binary::{self, read_array, write_array, Element},
+    Mappable, Persist, Shaped,
+};
+
+use super::{Optimiser, TunableOptimiser};
+
+/// Rectified Adam, from <https://arxiv.org/abs/1908.03265>.
+///
+/// Early in training, Adam's second moment estimate is made from only a few gradients,
+/// so its adaptive learning rate varies wildly, which is usually tamed with a warmup.
+/// `RAdam` scales the adaptive step by how reliable the estimate is. Until the estimate's
+/// variance is tractable, it takes plain momentum steps instead
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RAdam<F, G> {
+    alpha: F,
+    beta1: F,
+    beta2: F,
+    epsilon: F,
+    m: G,
+    v: G,
+    t: i32,
+}
+
+impl<F, G> RAdam<F, G>
+where
+    F: Zero + Copy,
+    G: Mappable<F> + Clone + Shaped<F>,
+{
+    pub fn new(alpha: F, beta1: F, beta2: F, epsilon: F, shape: G::Shape) -> Self {
+        let zero = G::zero(shape);
+        Self {
+            alpha,
+            beta1,
+            beta2,
+            epsilon,
+            m: zero.clone(),
+            v: zero,
+            t: 0,
+        }
+    }
+}
+
+impl<F, G> RAdam<F, G>
+where
+    F: Float,
+    G: Mappable<F> + Clone + Shaped<F>,
+{
+    /// Uses the same hyperparameters as [`Adam::default_for`](super::Adam::default_for),
+    /// for a graph of the same shape
+    pub fn default_for(graph: &G) -> Self {
+        let f = |x| F::from(x).unwrap();
+        Self::new(f(0.001), f(0.9), f(0.999), f(1e-8), graph.shape())
+    }
+}
+
+/// How much the adaptive step is scaled by at step `t`,
+/// or `None` if the variance of the estimate is intractable and momentum is used instead
+fn rectification<F: Float>(beta2: F, t: i32) -> Option<F> {
+    let (one, two, four) = (F::one(), F::from(2).unwrap(), F::from(4).unwrap());
+    // the length of the simple moving average that the estimate approximates
+    let rho_inf = two / (one - beta2) - one;
+    let b2t = beta2.powi(t);
+    let rho = rho_inf - two * F::from(t).unwrap() * b2t / (one - b2t);
+    (rho > four).then(|| {
+        ((rho - four) * (rho - two) * rho_inf / ((rho_inf - four) * (rho_inf - two) * rho)).sqrt()
+    })
+}
+
+impl<F: Element, G> RAdam<F, G> {
+    /// Writes the hyperparameters, step counter and moment estimates, so training
+    /// can resume after a restart. `network` is the graph that produced the state
+    pub fn write<I, N>(&self, network: &N, w: &mut impl Write) -> io::Result<()>
+    where
+        N: Persist<F, I, State = G>,
+    {
+        write_array(w, &array![self.alpha, self.beta1, self.beta2, self.epsilon])?;
+        w.write_all(&self.t.to_le_bytes())?;
+        network.write_state(&self.m, w)?;
+        network.write_state(&self.v, w)
+    }
+
+    pub fn read<I, N>(network: &N, r: &mut impl Read) -> io::Result<Self>
+    where
+        N: Persist<F, I, State = G>,
+    {
+        let params: Array1<F> = read_array(r)?;
+        let Some(&[alpha, beta1, beta2, epsilon]) = params.as_slice() else {
+            return Err(binary::invalid("radam has the wrong number of parameters"));
+        };
+        let mut t = [0; 4];
+        r.read_exact(&mut t)?;
+        Ok(Self {
+            alpha,
+            beta1,
+            beta2,
+            epsilon,
+            t: i32::from_le_bytes(t),
+            m: network.read_state(r)?,
+            v: network.read_state(r)?,
+        })
+    }
+}
+
+impl<F, G> Optimiser<G> for RAdam<F, G>
+where
+    G: Mappable<F>,
+    F: LinalgScalar + Float,
+{
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
+        // Algorithm 2 of https://arxiv.org/pdf/1908.03265.pdf
+        self.t += 1;
+
+        let b1 = self.beta1;
+        let b2 = self.beta2;
+        let e = self.epsilon;
+        let a = self.alpha;
+
+        let one = F::one();
+
+        // m_t = b1 * m_t-1 + (1 - b1) * g_t
+        self.m.map_mut_with(grads, |m, &g| {
+            *m = *m * b1 + g * (one - b1);
+        });
+
+        // v_t = b2 * v_t-1 + (1 - b2) * g_t^2
+        self.v.map_mut_with(grads, |v, &g| {
+            *v = *v * b2 + g.powi(2) * (one - b2);
+        });
+
+        // m_t' = m_t / (1 - b1^t)
+        let mb = one - b1.powi(self.t);
+        grads.map_mut_with(&self.m, |x, &m| *x = m * a / mb);
+
+        // x_t = a * r_t * m_t' / (sqrt(v_t') + e), or a * m_t' without rectification
+        if let Some(r) = rectification(b2, self.t) {
+            let vb = one - b2.powi(self.t);
+            grads.map_mut_with(&self.v, |x, &v| {
+                *x = *x * r / ((v / vb).sqrt() + e);
+            });
+        }
+
+        // g_t = g_t-1 - x_t
+        graph.map_mut_with(grads, |g, &x| {
+            *g = *g - x;
+        });
+    }
+}
+
+impl<F: Copy, G> TunableOptimiser<F> for RAdam<F, G> {
+    fn learning_rate(&self) -> F {
+        self.alpha
+    }
+    fn set_learning_rate(&mut self, learning_rate: F) {
+        self.alpha = learning_rate;
+    }
+    fn momentum(&self) -> Option<F> {
+        Some(self.beta1)
+    }
+    fn set_momentum(&mut self, momentum: F) {
+        self.beta1 = momentum;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::{rectification, RAdam};
+    use crate::{dense::DenseState, optimise::Optimiser};
+
+    #[test]
+    fn test_rectification() {
+        // with the default beta2, the first four steps aren't rectified
+        let steps: Vec<_> = (1..=6).map(|t| rectification(0.999_f64, t)).collect();
+        assert!(steps[..4].iter().all(Option::is_none), "{:?}", steps);
+        assert!(steps[4..].iter().all(Option::is_some));
+        // the rectification approaches one as the estimate becomes reliable
+        let late = rectification(0.999_f64, 100_000).unwrap();
+        assert!((late - 1.0).abs() < 1e-3);
+
+        // without rectification, the first step is plain momentum
+        let mut graph = DenseState {
+            w: array![[1.0_f64]],
+            b: array![1.0],
+        };
+        let mut radam = RAdam::default_for(&graph);
+        let mut grads = DenseState {
+            w: array![[10.0]],
+            b: array![-2.0],
+        };
+        radam.optimise(&mut graph, &mut grads);
+        assert!((graph.w[(0, 0)] - (1.0 - 0.01)).abs() < 1e-12);
+        assert!((graph.b[0] - (1.0 + 0.002)).abs() < 1e-12);
+    }
+}
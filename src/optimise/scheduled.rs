@@ -0,0 +1,121 @@
+use crate::schedule::Policy;
+
+use super::{Optimiser, TunableOptimiser};
+
+/// An optimiser whose hyperparameters follow a [`Policy`], which is applied before
+/// every step
+///
+/// ```
+/// use linear_networks::{
+///     dense::Dense, initialisers::Xavier, optimise::{Adam, Scheduled},
+///     schedule::OneCycle, train::Train, Graph,
+/// };
+///
+/// let graph = Graph::<f64, _>::input_shape(Dense::output_size(2).with_initialiser(Xavier), 4);
+/// let adam = Adam::default_for(&graph);
+/// // 10 epochs of 100 batches
+/// let trainer: Train<f64, _, _, _> = Train::builder(graph)
+///     .optimiser(Scheduled::new(adam, OneCycle::new(0.01, 1000)))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Scheduled<O, P> {
+    pub optimiser: O,
+    pub policy: P,
+    /// The number of steps taken so far
+    pub step: usize,
+}
+
+impl<O, P> Scheduled<O, P> {
+    pub const fn new(optimiser: O, policy: P) -> Self {
+        Self {
+            optimiser,
+            policy,
+            step: 0,
+        }
+    }
+}
+
+impl<O, P, G> Optimiser<G> for Scheduled<O, P>
+where
+    O: Optimiser<G> + TunableOptimiser<P::Float>,
+    P: Policy,
+{
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
+        self.policy.apply(self.step, &mut self.optimiser);
+        self.optimiser.optimise(graph, grads);
+        self.step += 1;
+    }
+    fn loss_scale(&self) -> Option<f64> {
+        self.optimiser.loss_scale()
+    }
+}
+
+/// Changes are overwritten by the policy at the next step
+impl<F, O: TunableOptimiser<F>, P> TunableOptimiser<F> for Scheduled<O, P> {
+    fn learning_rate(&self) -> F {
+        self.optimiser.learning_rate()
+    }
+    fn set_learning_rate(&mut self, learning_rate: F) {
+        self.optimiser.set_learning_rate(learning_rate);
+    }
+    fn momentum(&self) -> Option<F> {
+        self.optimiser.momentum()
+    }
+    fn set_momentum(&mut self, momentum: F) {
+        self.optimiser.set_momentum(momentum);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::Scheduled;
+    use crate::{
+        dense::DenseState,
+        optimise::{Adam, Optimiser, TunableOptimiser, SGD},
+        schedule::{LearningRate, OneCycle, Polynomial},
+    };
+
+    #[test]
+    fn test_scheduled() {
+        let mut graph = DenseState {
+            w: array![[1.0_f64]],
+            b: array![0.0],
+        };
+        let step = |optimiser: &mut dyn Optimiser<DenseState<f64>>, graph: &mut DenseState<f64>| {
+            let mut grads = graph.clone();
+            optimiser.optimise(graph, &mut grads);
+        };
+
+        let policy = OneCycle::new(1.0, 10);
+        let mut adam = Scheduled::new(Adam::default_for(&graph), policy);
+        let mut seen = vec![];
+        for _ in 0..10 {
+            step(&mut adam, &mut graph);
+            seen.push((adam.learning_rate(), adam.momentum().unwrap()));
+        }
+        assert_eq!(adam.step, 10);
+        for (i, &(learning_rate, momentum)) in seen.iter().enumerate() {
+            assert!((learning_rate - policy.learning_rate(i)).abs() < 1e-12);
+            assert!((momentum - policy.momentum(i)).abs() < 1e-12);
+        }
+        // the peak is at the end of the warmup
+        assert!((seen[3].0 - 1.0).abs() < 1e-12 && (seen[3].1 - 0.85).abs() < 1e-12);
+
+        // any schedule can be used for the learning rate alone
+        let decay = LearningRate::new(Polynomial::new(0.1, 0.0, 4, 1.0));
+        let mut sgd = Scheduled::new(SGD::new(0.0_f64), decay);
+        step(&mut sgd, &mut graph);
+        step(&mut sgd, &mut graph);
+        assert!((sgd.learning_rate() - 0.075).abs() < 1e-12);
+
+        // including closures
+        let halving = LearningRate::new(|step| if step < 1 { 1.0 } else { 0.5_f64 });
+        let mut sgd = Scheduled::new(SGD::new(0.0), halving);
+        step(&mut sgd, &mut graph);
+        step(&mut sgd, &mut graph);
+        assert!((sgd.learning_rate() - 0.5).abs() < 1e-12);
+    }
+}
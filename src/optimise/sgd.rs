@@ -2,9 +2,10 @@ use ndarray::LinalgScalar;
 
 use crate::Mappable;
 
-use super::Optimiser;
+use super::{Optimiser, TunableOptimiser};
 
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SGD<F>(F);
 
 impl<F> SGD<F> {
@@ -18,7 +19,16 @@ where
     G: Mappable<F>,
     F: LinalgScalar,
 {
-    fn optimise(&mut self, graph: &mut G, grads: G) {
-        graph.map_mut_with(&grads, |theta, &g| *theta = *theta - g * self.0);
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
+        graph.map_mut_with(grads, |theta, &g| *theta = *theta - g * self.0);
+    }
+}
+
+impl<F: Copy> TunableOptimiser<F> for SGD<F> {
+    fn learning_rate(&self) -> F {
+        self.0
+    }
+    fn set_learning_rate(&mut self, learning_rate: F) {
+        self.0 = learning_rate;
     }
 }
@@ -0,0 +1,158 @@
+//! Conversions between graph states of different float precisions.
+//!
+//! With the `half` feature, states can be stored as [`f16`] or [`bf16`], which halves their
+//! size compared to `f32`. Half precision states can run inference, but ndarray can't yet
+//! train with them, so [`LossScaling`](crate::optimise::loss_scale::LossScaling) is
+//! written against any pair of precisions, eg an `f32` graph with an `f64` master copy
+use ndarray::{Array, Dimension};
+use num_traits::NumCast;
+
+use crate::{activation::Linear, autoencoder::TiedDenseState, branch::Branch, dense::DenseState};
+
+#[cfg(feature = "half")]
+pub use half::{bf16, f16};
+
+/// Graph states that can be converted into the same state at another precision, `T`
+pub trait Cast<T> {
+    #[must_use]
+    fn cast(&self) -> T;
+
+    /// Like [`cast`](Self::cast), but overwrites an existing state of the same shape
+    fn cast_into(&self, output: &mut T);
+}
+
+fn cast<F: NumCast + Copy, T: NumCast>(x: F) -> T {
+    T::from(x).expect("float could not be converted")
+}
+
+impl<F, T, D> Cast<Array<T, D>> for Array<F, D>
+where
+    F: NumCast + Copy,
+    T: NumCast + Copy,
+    D: Dimension,
+{
+    fn cast(&self) -> Array<T, D> {
+        self.mapv(cast)
+    }
+    fn cast_into(&self, output: &mut Array<T, D>) {
+        output.zip_mut_with(self, |o, &x| *o = cast(x));
+    }
+}
+
+impl<F, T> Cast<DenseState<T>> for DenseState<F>
+where
+    F: NumCast + Copy,
+    T: NumCast + Copy,
+{
+    fn cast(&self) -> DenseState<T> {
+        DenseState {
+            w: self.w.cast(),
+            b: self.b.cast(),
+        }
+    }
+    fn cast_into(&self, output: &mut DenseState<T>) {
+        self.w.cast_into(&mut output.w);
+        self.b.cast_into(&mut output.b);
+    }
+}
+
+impl<F, T, A: Clone> Cast<TiedDenseState<T, A>> for TiedDenseState<F, A>
+where
+    F: NumCast + Copy,
+    T: NumCast + Copy,
+{
+    fn cast(&self) -> TiedDenseState<T, A> {
+        TiedDenseState {
+            w: self.w.cast(),
+            b: self.b.cast(),
+            c: self.c.cast(),
+            activation: self.activation.clone(),
+        }
+    }
+    fn cast_into(&self, output: &mut TiedDenseState<T, A>) {
+        self.w.cast_into(&mut output.w);
+        self.b.cast_into(&mut output.b);
+        self.c.cast_into(&mut output.c);
+    }
+}
+
+impl<G, T, L: Clone> Cast<Linear<T, L>> for Linear<G, L>
+where
+    G: Cast<T>,
+{
+    fn cast(&self) -> Linear<T, L> {
+        Linear {
+            graph: self.graph.cast(),
+            linear: self.linear.clone(),
+        }
+    }
+    fn cast_into(&self, output: &mut Linear<T, L>) {
+        self.graph.cast_into(&mut output.graph);
+    }
+}
+
+impl<T, U, T2, U2> Cast<(T2, U2)> for (T, U)
+where
+    T: Cast<T2>,
+    U: Cast<U2>,
+{
+    fn cast(&self) -> (T2, U2) {
+        (self.0.cast(), self.1.cast())
+    }
+    fn cast_into(&self, output: &mut (T2, U2)) {
+        self.0.cast_into(&mut output.0);
+        self.1.cast_into(&mut output.1);
+    }
+}
+
+impl<T, U, T2, U2> Cast<Branch<T2, U2>> for Branch<T, U>
+where
+    T: Cast<T2>,
+    U: Cast<U2>,
+{
+    fn cast(&self) -> Branch<T2, U2> {
+        Branch(self.0.cast(), self.1.cast())
+    }
+    fn cast_into(&self, output: &mut Branch<T2, U2>) {
+        self.0.cast_into(&mut output.0);
+        self.1.cast_into(&mut output.1);
+    }
+}
+
+#[cfg(all(test, feature = "half"))]
+mod tests {
+    use ndarray::Array2;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{f16, Cast};
+    use crate::{
+        activation::{sigmoid::Sigmoid, Linear},
+        dense::{Dense, DenseState},
+        initialisers::Xavier,
+        net, Graph, GraphExec,
+    };
+
+    type Layer<F> = Linear<DenseState<F>, Sigmoid>;
+
+    #[test]
+    fn test_half_inference() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = net![
+            Dense::output_size(8)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid),
+            Dense::output_size(2)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid)
+        ]
+        .init_with_random(&mut rng, 4);
+        let half: (Layer<f16>, Layer<f16>) = network.cast();
+
+        let input = Array2::<f32>::from_shape_simple_fn((5, 4), || rng.gen());
+        let expected = network.exec(input.view());
+        let output = half.exec(input.mapv(f16::from_f32));
+
+        let error = (output.mapv(f32::from) - expected).mapv(f32::abs);
+        assert!(error.iter().all(|&e| e < 1e-2), "{}", error);
+    }
+}
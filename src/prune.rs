@@ -0,0 +1,265 @@
+//! Magnitude based pruning.
+//!
+//! The weights with the smallest magnitudes contribute the least to a network's output,
+//! so they can be set to zero with little loss in accuracy. Pruned networks compress
+//! better, and can be run as sparse layers. Fine-tuning after pruning recovers most of
+//! the lost accuracy, as long as the pruned weights are kept at zero with [`Masked`]
+use std::marker::PhantomData;
+
+use num_traits::Float;
+
+use crate::{
+    optimise::{Optimiser, TunableOptimiser},
+    tensors::{ParamPath, Tensors},
+    Mappable,
+};
+
+/// Which weights are ranked against each other when pruning
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// The smallest weights of the whole graph are pruned. Layers with
+    /// smaller weights end up sparser than others
+    Global,
+    /// The smallest weights of each parameter tensor are pruned, so every
+    /// tensor ends up with the same sparsity
+    PerTensor,
+}
+
+/// Which parameters are kept. A copy of the graph, with one for each kept
+/// parameter and zero for each pruned one
+#[derive(Debug, Clone)]
+pub struct Mask<G> {
+    pub mask: G,
+}
+
+impl<G> Mask<G> {
+    /// Marks the smallest `fraction` of the parameters by magnitude as pruned,
+    /// without changing the graph
+    pub fn magnitude<F>(graph: &G, fraction: F, scope: Scope) -> Self
+    where
+        G: Mappable<F> + Tensors<F> + Clone,
+        F: Float,
+    {
+        let mut mask = graph.clone();
+        match scope {
+            Scope::Global => {
+                let mut magnitudes = vec![];
+                mask.for_each(|x| magnitudes.push(x.abs()));
+                let mut cutoff = Cutoff::new(magnitudes, fraction);
+                mask.map_mut(|x| *x = cutoff.keep(*x));
+            }
+            Scope::PerTensor => {
+                mask.visit_mut(&mut ParamPath::default(), &mut |_, mut tensor| {
+                    let magnitudes = tensor.iter().map(|x| x.abs()).collect();
+                    let mut cutoff = Cutoff::new(magnitudes, fraction);
+                    tensor.map_inplace(|x| *x = cutoff.keep(*x));
+                });
+            }
+        }
+        Self { mask }
+    }
+
+    /// Zeroes the pruned parameters of the graph
+    pub fn apply<F>(&self, graph: &mut G)
+    where
+        G: Mappable<F>,
+        F: Float,
+    {
+        graph.map_mut_with(&self.mask, |x, &keep| *x = *x * keep);
+    }
+
+    /// The fraction of the parameters that are pruned
+    pub fn sparsity<F>(&self) -> F
+    where
+        G: Mappable<F>,
+        F: Float,
+    {
+        sparsity(&self.mask)
+    }
+
+    /// Wraps an optimiser so that the pruned parameters stay at zero while fine-tuning
+    pub const fn masked<F, O>(self, optimiser: O) -> Masked<F, O, G> {
+        Masked {
+            optimiser,
+            mask: self,
+            float: PhantomData,
+        }
+    }
+}
+
+/// Zeroes the smallest `fraction` of the parameters by magnitude, rounded to the
+/// nearest parameter. Returns the mask, which can keep them at zero during further training
+///
+/// ```
+/// use linear_networks::{
+///     dense::DenseState,
+///     prune::{prune, sparsity, Scope},
+/// };
+/// use ndarray::array;
+///
+/// let mut graph = DenseState {
+///     w: array![[0.5, -0.1], [0.05, 2.0]],
+///     b: array![-1.0, 0.2],
+/// };
+/// let mask = prune(&mut graph, 0.5, Scope::Global);
+/// assert_eq!(graph.w, array![[0.5, 0.0], [0.0, 2.0]]);
+/// assert_eq!(graph.b, array![-1.0, 0.0]);
+/// assert_eq!(sparsity::<f64, _>(&graph), 0.5);
+/// assert_eq!(mask.sparsity::<f64>(), 0.5);
+/// ```
+pub fn prune<F, G>(graph: &mut G, fraction: F, scope: Scope) -> Mask<G>
+where
+    G: Mappable<F> + Tensors<F> + Clone,
+    F: Float,
+{
+    let mask = Mask::magnitude(graph, fraction, scope);
+    mask.apply(graph);
+    mask
+}
+
+/// The fraction of the parameters that are exactly zero
+pub fn sparsity<F, G>(graph: &G) -> F
+where
+    G: Mappable<F>,
+    F: Float,
+{
+    let (mut zeros, mut total) = (0_usize, 0_usize);
+    graph.for_each(|x| {
+        total += 1;
+        if x.is_zero() {
+            zeros += 1;
+        }
+    });
+    F::from(zeros).unwrap() / F::from(total.max(1)).unwrap()
+}
+
+/// An optimiser that zeroes the pruned parameters after every step,
+/// so they stay pruned while the rest of the graph is fine-tuned
+#[derive(Debug, Clone)]
+pub struct Masked<F, O, G> {
+    pub optimiser: O,
+    pub mask: Mask<G>,
+    float: PhantomData<F>,
+}
+
+impl<F, O, G> Optimiser<G> for Masked<F, O, G>
+where
+    O: Optimiser<G>,
+    G: Mappable<F>,
+    F: Float,
+{
+    fn optimise(&mut self, graph: &mut G, grads: &mut G) {
+        self.optimiser.optimise(graph, grads);
+        self.mask.apply(graph);
+    }
+    fn loss_scale(&self) -> Option<f64> {
+        self.optimiser.loss_scale()
+    }
+}
+
+impl<F, O: TunableOptimiser<F>, G> TunableOptimiser<F> for Masked<F, O, G> {
+    fn learning_rate(&self) -> F {
+        self.optimiser.learning_rate()
+    }
+    fn set_learning_rate(&mut self, learning_rate: F) {
+        self.optimiser.set_learning_rate(learning_rate);
+    }
+    fn momentum(&self) -> Option<F> {
+        self.optimiser.momentum()
+    }
+    fn set_momentum(&mut self, momentum: F) {
+        self.optimiser.set_momentum(momentum);
+    }
+}
+
+/// Decides which values are pruned, given the magnitudes of all of them
+struct Cutoff<F> {
+    /// Values below this magnitude are pruned
+    threshold: F,
+    /// How many of the values at exactly the threshold are still to be pruned
+    ties: usize,
+}
+
+impl<F: Float> Cutoff<F> {
+    fn new(mut magnitudes: Vec<F>, fraction: F) -> Self {
+        let len = F::from(magnitudes.len()).unwrap();
+        let count = (fraction * len)
+            .round()
+            .to_usize()
+            .unwrap_or(0)
+            .min(magnitudes.len());
+        if count == 0 {
+            return Self {
+                threshold: F::neg_infinity(),
+                ties: 0,
+            };
+        }
+
+        let (below, &mut threshold, _) = magnitudes.select_nth_unstable_by(count - 1, |a, b| {
+            a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let below = below.iter().filter(|&&x| x < threshold).count();
+        Self {
+            threshold,
+            ties: count - below,
+        }
+    }
+
+    /// One if the value is kept, zero if it's pruned
+    fn keep(&mut self, x: F) -> F {
+        let x = x.abs();
+        if x < self.threshold || (x == self.threshold && self.ties > 0) {
+            if x == self.threshold {
+                self.ties -= 1;
+            }
+            F::zero()
+        } else {
+            F::one()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, s};
+
+    use super::{prune, sparsity, Scope};
+    use crate::{
+        dense::DenseState,
+        optimise::{Optimiser, SGD},
+    };
+
+    #[test]
+    fn test_prune() {
+        let graph = DenseState {
+            w: array![[0.1, -0.4, 0.3], [0.2, 0.2, -0.6]],
+            b: array![5.0, -4.0, 3.0],
+        };
+
+        // globally, all the small weights are pruned before any of the biases
+        let mut global = graph.clone();
+        prune(&mut global, 0.4, Scope::Global);
+        assert_eq!(global.w, array![[0.0, -0.4, 0.0], [0.0, 0.0, -0.6]]);
+        assert_eq!(global.b, graph.b);
+
+        // per tensor, a third of the biases are pruned too. Only one of the tied
+        // weights is pruned, so that exactly a third of the weights are
+        let mut per_tensor = graph;
+        let mask = prune(&mut per_tensor, 1.0 / 3.0, Scope::PerTensor);
+        assert_eq!(per_tensor.w, array![[0.0, -0.4, 0.3], [0.0, 0.2, -0.6]]);
+        assert_eq!(per_tensor.b, array![5.0, -4.0, 0.0]);
+        assert!((mask.sparsity::<f64>() - 1.0 / 3.0).abs() < 1e-12);
+
+        // pruned weights stay at zero while fine-tuning
+        let mut optimiser = mask.masked(SGD::new(0.1));
+        let mut grads = DenseState {
+            w: array![[1.0, 1.0, 1.0], [1.0, 1.0, 1.0]],
+            b: array![1.0, 1.0, 1.0],
+        };
+        optimiser.optimise(&mut per_tensor, &mut grads);
+        assert_eq!(per_tensor.w.column(0), array![0.0, 0.0]);
+        assert_eq!(per_tensor.b.slice(s![2..]), array![0.0]);
+        assert!((per_tensor.w[(0, 1)] + 0.5_f64).abs() < 1e-12);
+        assert!((sparsity::<f64, _>(&per_tensor) - 1.0 / 3.0).abs() < 1e-12);
+    }
+}
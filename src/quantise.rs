@@ -0,0 +1,548 @@
+//! Post-training quantisation of weights to `i8`, for smaller model files.
+//!
+//! Every tensor is scaled symmetrically so that its largest magnitude maps to 127.
+//! The quantised model is dequantised back into a graph state for inference,
+//! or graph states can be converted to use `i8` arithmetic directly with [`Quantise`]
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use ndarray::{Array1, Array2, ArrayBase, ArrayD, Axis, Data, Ix2, IxDyn, Zip};
+use num_traits::{Float, FromPrimitive, ToPrimitive};
+
+use crate::{
+    activation::Linear,
+    array::{check_input_shape, input_shape},
+    binary::{self, invalid},
+    branch::Branch,
+    dense::DenseState,
+    dot::{Dot, DotWriter},
+    error,
+    tensors::Tensors,
+    GraphExec,
+};
+
+const QUANTISED_MAGIC: &[u8; 4] = b"NRQ8";
+const QUANTISED_VERSION: u8 = 1;
+
+/// A tensor stored as `i8` values, where each value represents `value * scale`
+#[derive(Debug, Clone)]
+pub struct QuantisedTensor {
+    pub name: String,
+    pub scale: f32,
+    pub values: ArrayD<i8>,
+}
+
+impl QuantisedTensor {
+    #[must_use]
+    pub fn dequantise<F: Float + FromPrimitive>(&self) -> ArrayD<F> {
+        let scale = F::from_f32(self.scale).unwrap();
+        self.values.mapv(|x| F::from_i8(x).unwrap() * scale)
+    }
+}
+
+/// Every parameter tensor of a graph state, quantised to `i8`
+#[derive(Debug, Clone)]
+pub struct Quantised {
+    pub tensors: Vec<QuantisedTensor>,
+}
+
+impl Quantised {
+    /// Quantises every parameter tensor of the state with its own scale
+    pub fn quantise<F, T>(state: &T) -> Self
+    where
+        F: Float,
+        T: Tensors<F>,
+    {
+        let mut tensors = vec![];
+        state.tensors("", &mut |name, tensor| {
+            let max = tensor.fold(F::zero(), |max, &x| max.max(x.abs()));
+            let scale = max.to_f32().unwrap() / 127.0;
+            let values = tensor.mapv(|x| {
+                let x = x.to_f32().unwrap();
+                if scale > 0.0 {
+                    (x / scale).round().clamp(-127.0, 127.0).to_i8().unwrap()
+                } else {
+                    0
+                }
+            });
+            tensors.push(QuantisedTensor {
+                name,
+                scale,
+                values,
+            });
+        });
+        Self { tensors }
+    }
+
+    /// Overwrites every parameter tensor of an initialised state with the dequantised
+    /// values, matched by name, so the state can be used for inference.
+    ///
+    /// Every tensor is checked before any are overwritten, so the state is left
+    /// unchanged if the model doesn't match it
+    pub fn dequantise_into<F, T>(&self, state: &mut T) -> io::Result<()>
+    where
+        F: Float + FromPrimitive,
+        T: Tensors<F>,
+    {
+        let find = |name: &str| self.tensors.iter().find(|t| t.name == name);
+        let mut matches = true;
+        state.tensors("", &mut |name, tensor| {
+            matches &= find(&name).is_some_and(|t| t.values.shape() == tensor.shape());
+        });
+        if !matches {
+            return Err(invalid("quantised model does not match the graph state"));
+        }
+
+        state.tensors_mut("", &mut |name, mut tensor| {
+            tensor.assign(&find(&name).expect("tensors were checked").dequantise());
+        });
+        Ok(())
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let count = u32::try_from(self.tensors.len()).map_err(|_| invalid("too many tensors"))?;
+        w.write_all(&count.to_le_bytes())?;
+        for tensor in &self.tensors {
+            let name_len =
+                u16::try_from(tensor.name.len()).map_err(|_| invalid("tensor name is too long"))?;
+            w.write_all(&name_len.to_le_bytes())?;
+            w.write_all(tensor.name.as_bytes())?;
+            w.write_all(&tensor.scale.to_le_bytes())?;
+
+            let ndim =
+                u8::try_from(tensor.values.ndim()).map_err(|_| invalid("too many dimensions"))?;
+            w.write_all(&[ndim])?;
+            for &dim in tensor.values.shape() {
+                w.write_all(&(dim as u64).to_le_bytes())?;
+            }
+            let bytes: Vec<u8> = tensor.values.iter().map(|x| x.to_le_bytes()[0]).collect();
+            w.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let mut count = [0; 4];
+        r.read_exact(&mut count)?;
+        let count = u32::from_le_bytes(count);
+
+        let mut tensors = vec![];
+        for _ in 0..count {
+            let mut name_len = [0; 2];
+            r.read_exact(&mut name_len)?;
+            let mut name = vec![0; usize::from(u16::from_le_bytes(name_len))];
+            r.read_exact(&mut name)?;
+            let name = String::from_utf8(name).map_err(|_| invalid("tensor name is not utf8"))?;
+
+            let mut scale = [0; 4];
+            r.read_exact(&mut scale)?;
+            let scale = f32::from_le_bytes(scale);
+
+            let mut ndim = [0];
+            r.read_exact(&mut ndim)?;
+            let mut shape = vec![];
+            for _ in 0..ndim[0] {
+                let mut dim = [0; 8];
+                r.read_exact(&mut dim)?;
+                let dim = usize::try_from(u64::from_le_bytes(dim))
+                    .map_err(|_| invalid("tensor dimension is too large"))?;
+                shape.push(dim);
+            }
+
+            let len = shape
+                .iter()
+                .try_fold(1_usize, |len, &dim| len.checked_mul(dim))
+                .ok_or_else(|| invalid("tensor is too large"))?;
+            let bytes = binary::read_bytes(r, len)?;
+            let values = bytes.into_iter().map(|x| i8::from_le_bytes([x])).collect();
+            let values = ArrayD::from_shape_vec(IxDyn(&shape), values)
+                .map_err(|_| invalid("tensor shape mismatch"))?;
+
+            tensors.push(QuantisedTensor {
+                name,
+                scale,
+                values,
+            });
+        }
+        Ok(Self { tensors })
+    }
+
+    /// Saves the quantised model to a file, starting with a magic header and format version
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        binary::write_header(&mut w, QUANTISED_MAGIC, QUANTISED_VERSION)?;
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    /// Loads a quantised model written by [`save`](Self::save)
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+        binary::read_header(&mut r, QUANTISED_MAGIC, QUANTISED_VERSION)?;
+        Self::read(&mut r)
+    }
+}
+
+/// Scales the values symmetrically so that the largest magnitude maps to 127.
+/// Returns the `i8` values and the scale
+fn quantise_values<'a, F, I>(values: I) -> (Vec<i8>, F)
+where
+    F: Float + 'a,
+    I: Iterator<Item = &'a F> + Clone,
+{
+    let max = values.clone().fold(F::zero(), |max, &x| max.max(x.abs()));
+    let scale = max / F::from(127).unwrap();
+    let inv_scale = if scale > F::zero() {
+        scale.recip()
+    } else {
+        F::zero()
+    };
+    // rounds half away from zero, without a slow call to `round`
+    let half = F::from(0.5).unwrap();
+    let values = values
+        .map(|&x| {
+            let x = x * inv_scale;
+            (x + half.copysign(x)).to_i8().unwrap_or(0)
+        })
+        .collect();
+    (values, scale)
+}
+
+/// A dense layer with `i8` weights, for faster inference on the CPU.
+///
+/// The inputs are quantised to `i8` per batch, multiplied with the weights using
+/// `i32` accumulators, then the result is dequantised and the bias added
+#[derive(Debug, Clone)]
+pub struct QuantisedDense<F> {
+    /// The weights, transposed to `(outputs, inputs)` so that each output is contiguous
+    pub w: Array2<i8>,
+    /// The scale of each output's weights
+    pub scales: Array1<F>,
+    pub b: Array1<F>,
+}
+
+impl<F, S> GraphExec<ArrayBase<S, Ix2>> for QuantisedDense<F>
+where
+    F: Float,
+    S: Data<Elem = F>,
+{
+    type Output = Array2<F>;
+
+    fn exec(&self, input: ArrayBase<S, Ix2>) -> Self::Output {
+        check_input_shape("QuantisedDense layer", input.shape(), self.w.ncols());
+        let (x, x_scale) = quantise_values(input.iter());
+
+        // the weights are a public field, so they might not be contiguous
+        let w = self.w.as_standard_layout();
+        let w = w.as_slice().expect("standard layout arrays are contiguous");
+        let mut acc = Array2::zeros((input.nrows(), self.w.nrows()));
+        gemm::gemm_i8(&x, w, self.w.ncols(), acc.as_slice_mut().unwrap());
+
+        let mut output = Array2::zeros(acc.raw_dim());
+        Zip::from(output.rows_mut())
+            .and(acc.rows())
+            .for_each(|output, acc| {
+                Zip::from(output)
+                    .and(acc)
+                    .and(&self.scales)
+                    .and(&self.b)
+                    .for_each(|o, &acc, &scale, &b| {
+                        *o = F::from(acc).unwrap() * x_scale * scale + b;
+                    });
+            });
+        output
+    }
+
+    fn try_exec(&self, input: ArrayBase<S, Ix2>) -> error::Result<Self::Output> {
+        input_shape("QuantisedDense layer", input.shape(), self.w.ncols())?;
+        Ok(self.exec(input))
+    }
+}
+
+/// Integer matrix multiplication, accumulating in `i32`s
+mod gemm {
+    fn dot(x: &[i8], w: &[i8]) -> i32 {
+        x.iter()
+            .zip(w)
+            .map(|(&x, &w)| i32::from(x) * i32::from(w))
+            .sum()
+    }
+
+    /// Multiplies `x`, `(m, k)`, with the transpose of `w`, `(n, k)`, into `out`, `(m, n)`.
+    /// Every matrix is a row-major slice.
+    ///
+    /// # Panics
+    ///
+    /// If the lengths of the slices don't match those shapes
+    pub fn gemm_i8(x: &[i8], w: &[i8], k: usize, out: &mut [i32]) {
+        if k == 0 {
+            out.fill(0);
+            return;
+        }
+
+        assert_eq!(x.len() % k, 0, "inputs should have rows of length {k}");
+        assert_eq!(w.len() % k, 0, "weights should have rows of length {k}");
+        let (rows, outputs) = (x.len() / k, w.len() / k);
+        assert_eq!(
+            out.len(),
+            rows * outputs,
+            "outputs should have shape ({rows}, {outputs})"
+        );
+
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: avx2 is supported by this cpu, and the loads stay in bounds because
+            // - `x` is made of whole rows of length `k`,
+            // - `w` is made of whole rows of length `k`,
+            // - `out` has exactly one value for each pair of rows of `x` and `w`
+            unsafe { avx2::gemm_i8(x, w, k, out) };
+            return;
+        }
+
+        for (x, out) in x.chunks_exact(k).zip(out.chunks_exact_mut(outputs)) {
+            for (w, out) in w.chunks_exact(k).zip(out) {
+                *out = dot(x, w);
+            }
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    mod avx2 {
+        // the loads are all unaligned
+        #![allow(clippy::cast_ptr_alignment)]
+
+        use std::arch::x86_64::{
+            __m128i, __m256i, _mm256_add_epi32, _mm256_castsi256_si128, _mm256_cvtepi8_epi16,
+            _mm256_extracti128_si256, _mm256_madd_epi16, _mm256_setzero_si256, _mm_add_epi32,
+            _mm_cvtsi128_si32, _mm_loadu_si128, _mm_shuffle_epi32,
+        };
+
+        use super::dot;
+
+        #[target_feature(enable = "avx2")]
+        unsafe fn sum(v: __m256i) -> i32 {
+            let v = _mm_add_epi32(_mm256_castsi256_si128(v), _mm256_extracti128_si256(v, 1));
+            let v = _mm_add_epi32(v, _mm_shuffle_epi32(v, 0b01_00_11_10));
+            let v = _mm_add_epi32(v, _mm_shuffle_epi32(v, 0b10_11_00_01));
+            _mm_cvtsi128_si32(v)
+        }
+
+        /// Loads 16 values starting at `i`, widened to `i16`
+        #[inline]
+        #[target_feature(enable = "avx2")]
+        unsafe fn load(a: &[i8], i: usize) -> __m256i {
+            _mm256_cvtepi8_epi16(_mm_loadu_si128(a.as_ptr().add(i).cast::<__m128i>()))
+        }
+
+        /// The dot products of 2 rows of `x` with 4 rows of `w`. Each pair of values is
+        /// widened to `i16`, multiplied and summed into `i32`s, 16 values at a time
+        #[target_feature(enable = "avx2")]
+        unsafe fn block(x: [&[i8]; 2], w: [&[i8]; 4]) -> [[i32; 4]; 2] {
+            let k = x[0].len() / 16 * 16;
+            let mut acc = [[_mm256_setzero_si256(); 4]; 2];
+            for i in (0..k).step_by(16) {
+                let x = [load(x[0], i), load(x[1], i)];
+                for (j, w) in w.iter().enumerate() {
+                    let w = load(w, i);
+                    for (acc, &x) in acc.iter_mut().zip(&x) {
+                        acc[j] = _mm256_add_epi32(acc[j], _mm256_madd_epi16(x, w));
+                    }
+                }
+            }
+
+            let mut out = [[0; 4]; 2];
+            for ((out, acc), x) in out.iter_mut().zip(&acc).zip(&x) {
+                for ((out, &acc), w) in out.iter_mut().zip(acc).zip(&w) {
+                    *out = sum(acc) + dot(&x[k..], &w[k..]);
+                }
+            }
+            out
+        }
+
+        /// # Safety
+        ///
+        /// The cpu must support avx2, `k` must be non-zero, `x` and `w` must be made of
+        /// whole rows of length `k`, and `out` must have a value for each pair of rows
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn gemm_i8(x: &[i8], w: &[i8], k: usize, out: &mut [i32]) {
+            let weights: Vec<&[i8]> = w.chunks_exact(k).collect();
+            let outputs = weights.len();
+
+            for (x, out) in x.chunks(2 * k).zip(out.chunks_mut(2 * outputs)) {
+                // an odd row out is paired with itself
+                let (x0, x1) = x.split_at(k);
+                let x = [x0, if x1.is_empty() { x0 } else { x1 }];
+
+                let blocks = weights.chunks_exact(4);
+                let rest = blocks.remainder();
+                for (j, w) in blocks.enumerate() {
+                    let block = block(x, [w[0], w[1], w[2], w[3]]);
+                    for (out, block) in out.chunks_mut(outputs).zip(&block) {
+                        out[4 * j..4 * j + 4].copy_from_slice(block);
+                    }
+                }
+                for (out, x) in out.chunks_mut(outputs).zip(&x) {
+                    let out = &mut out[outputs - rest.len()..];
+                    for (out, w) in out.iter_mut().zip(rest) {
+                        *out = dot(x, w);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<F> Dot for QuantisedDense<F> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let (o, i) = self.w.dim();
+        vec![dot.layer(&format!("QuantisedDense({i} → {o})"), inputs)]
+    }
+}
+
+/// Graph states that can be converted to run inference using `i8` arithmetic
+pub trait Quantise {
+    type Quantised;
+    fn quantise(&self) -> Self::Quantised;
+}
+
+impl<F: Float> Quantise for DenseState<F> {
+    type Quantised = QuantisedDense<F>;
+    fn quantise(&self) -> Self::Quantised {
+        let mut w = Vec::with_capacity(self.w.len());
+        let mut scales = Vec::with_capacity(self.w.ncols());
+        for column in self.w.axis_iter(Axis(1)) {
+            let (values, scale) = quantise_values(column.iter());
+            w.extend(values);
+            scales.push(scale);
+        }
+        QuantisedDense {
+            w: Array2::from_shape_vec((self.w.ncols(), self.w.nrows()), w).unwrap(),
+            scales: Array1::from(scales),
+            b: self.b.clone(),
+        }
+    }
+}
+
+impl<G: Quantise, L: Clone> Quantise for Linear<G, L> {
+    type Quantised = Linear<G::Quantised, L>;
+    fn quantise(&self) -> Self::Quantised {
+        Linear {
+            graph: self.graph.quantise(),
+            linear: self.linear.clone(),
+        }
+    }
+}
+
+impl<T: Quantise, U: Quantise> Quantise for (T, U) {
+    type Quantised = (T::Quantised, U::Quantised);
+    fn quantise(&self) -> Self::Quantised {
+        (self.0.quantise(), self.1.quantise())
+    }
+}
+
+impl<T: Quantise, U: Quantise> Quantise for Branch<T, U> {
+    type Quantised = Branch<T::Quantised, U::Quantised>;
+    fn quantise(&self) -> Self::Quantised {
+        Branch(self.0.quantise(), self.1.quantise())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array1, Array2};
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{Quantise, Quantised};
+    use crate::{
+        activation::relu::Relu,
+        dense::{Dense, DenseState},
+        initialisers::Xavier,
+        net, Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_quantise_round_trip() {
+        let state = DenseState {
+            w: array![[1.27, -0.5], [0.0, 0.01]],
+            b: array![0.0, 0.0],
+        };
+        let quantised = Quantised::quantise(&state);
+        assert!((quantised.tensors[0].scale - 0.01).abs() < 1e-6);
+        assert!(quantised.tensors[1].scale.abs() < f32::EPSILON);
+
+        let mut bytes = vec![];
+        quantised.write(&mut bytes).unwrap();
+        let quantised = Quantised::read(&mut bytes.as_slice()).unwrap();
+
+        let mut loaded = DenseState::<f64> {
+            w: Array2::zeros((2, 2)),
+            b: Array1::zeros(2),
+        };
+        quantised.dequantise_into(&mut loaded).unwrap();
+        let error = (&loaded.w - &state.w).mapv(f64::abs);
+        assert!(error.iter().all(|&e| e < 0.005));
+        assert_eq!(loaded.b, state.b);
+
+        // the weights match, but the biases don't, so nothing is overwritten
+        let mut mismatched = DenseState::<f64> {
+            w: Array2::zeros((2, 2)),
+            b: Array1::zeros(3),
+        };
+        assert!(quantised.dequantise_into(&mut mismatched).is_err());
+        assert_eq!(mismatched.w, Array2::<f64>::zeros((2, 2)));
+    }
+
+    #[test]
+    fn test_gemm_i8() {
+        let mut rng = StdRng::seed_from_u64(0);
+        // sizes that aren't multiples of any block size
+        let (rows, outputs, inputs) = (3, 7, 37);
+        let x: Vec<i8> = (0..rows * inputs)
+            .map(|_| rng.gen_range(-127..=127))
+            .collect();
+        let w: Vec<i8> = (0..outputs * inputs)
+            .map(|_| rng.gen_range(-127..=127))
+            .collect();
+
+        let mut out = vec![0; rows * outputs];
+        super::gemm::gemm_i8(&x, &w, inputs, &mut out);
+
+        let x = Array2::from_shape_vec((rows, inputs), x).unwrap();
+        let w = Array2::from_shape_vec((outputs, inputs), w).unwrap();
+        let expected = x.mapv(i32::from).dot(&w.t().mapv(i32::from));
+        assert_eq!(out, expected.into_raw_vec());
+    }
+
+    #[test]
+    #[should_panic = "inputs should have rows of length 16"]
+    fn test_gemm_i8_bounds() {
+        // a row of 16 inputs, but only 8 values
+        super::gemm::gemm_i8(&[1; 8], &[1; 32], 16, &mut [0; 2]);
+    }
+
+    #[test]
+    fn test_int8_inference() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = net![
+            Dense::output_size(32)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(4).with_initialiser(Xavier)
+        ]
+        .init_with_random(&mut rng, 16);
+        let quantised = network.quantise();
+
+        let input = Array2::<f32>::from_shape_simple_fn((8, 16), || rng.gen_range(-1.0..1.0));
+        let expected = network.exec(input.view());
+        let output = quantised.exec(input.view());
+
+        let max = expected.fold(0.0_f32, |max, x| max.max(x.abs()));
+        let error = (output - expected).fold(0.0_f32, |max, x| max.max(x.abs()));
+        assert!(error < max * 0.05, "error {} of {}", error, max);
+    }
+}
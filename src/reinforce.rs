@@ -0,0 +1,320 @@
+//! Policy gradient reinforcement learning, using REINFORCE.
+//!
+//! A policy network maps a state to one logit per action, and actions are sampled from
+//! the softmax of the logits. After each episode, every action taken is made more likely
+//! in proportion to the discounted return that followed it, by training with the
+//! [`PolicyGradient`] cost
+//!
+//! ```
+//! use linear_networks::{
+//!     dense::Dense,
+//!     initialisers::Xavier,
+//!     optimise::SGD,
+//!     reinforce::{act, Episode, PolicyGradient},
+//!     train::Train,
+//!     Graph,
+//! };
+//! use ndarray::array;
+//! use rand::{rngs::StdRng, SeedableRng};
+//!
+//! let mut rng = StdRng::seed_from_u64(0);
+//! let policy = Graph::<f64, _>::init_with_random(
+//!     Dense::output_size(2).with_initialiser(Xavier),
+//!     &mut rng,
+//!     1,
+//! );
+//! let mut trainer = Train::builder(policy)
+//!     .cost(PolicyGradient::new(0.9))
+//!     .optimiser(SGD::new(0.1))
+//!     .build();
+//!
+//! let mut episode = Episode::default();
+//! let state = array![1.0];
+//! let action = act(&trainer.graph, state.view(), &mut rng);
+//! episode.push(state, action, if action == 1 { 1.0 } else { 0.0 });
+//! trainer.train_episode(&episode);
+//! ```
+use ndarray::{s, stack, Array1, Array2, ArrayView1, Axis};
+use num_traits::{Float, FromPrimitive};
+use rand::Rng;
+use rand_distr::uniform::{SampleBorrow, SampleUniform};
+
+use crate::{
+    classify::softmax,
+    cost::Cost,
+    optimise::Optimiser,
+    sampling::Sampler,
+    train::{GraphExecTrain, Modal, Train},
+    GraphExec, Mappable, Shaped,
+};
+
+/// The states visited in one episode, the actions taken in them,
+/// and the rewards received after each action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Episode<F> {
+    pub states: Vec<Array1<F>>,
+    pub actions: Vec<usize>,
+    pub rewards: Vec<F>,
+}
+
+impl<F> Default for Episode<F> {
+    fn default() -> Self {
+        Self {
+            states: vec![],
+            actions: vec![],
+            rewards: vec![],
+        }
+    }
+}
+
+impl<F: Float> Episode<F> {
+    /// Records one step of the episode
+    pub fn push(&mut self, state: Array1<F>, action: usize, reward: F) {
+        self.states.push(state);
+        self.actions.push(action);
+        self.rewards.push(reward);
+    }
+
+    /// The number of steps taken
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// The sum of all the rewards, without discounting
+    #[must_use]
+    pub fn total_reward(&self) -> F {
+        self.rewards.iter().fold(F::zero(), |sum, &r| sum + r)
+    }
+
+    /// See [`discounted_returns`]
+    #[must_use]
+    pub fn returns(&self, gamma: F) -> Vec<F> {
+        discounted_returns(&self.rewards, gamma)
+    }
+}
+
+/// The return of each step: its reward plus the rewards of every later step,
+/// each discounted by `gamma` for every step further away
+///
+/// ```
+/// use linear_networks::reinforce::discounted_returns;
+///
+/// assert_eq!(discounted_returns(&[1.0, 0.0, 2.0], 0.5), [1.5, 1.0, 2.0]);
+/// ```
+#[must_use]
+pub fn discounted_returns<F: Float>(rewards: &[F], gamma: F) -> Vec<F> {
+    let mut returns = vec![F::zero(); rewards.len()];
+    let mut running = F::zero();
+    for (r, &reward) in returns.iter_mut().zip(rewards).rev() {
+        running = reward + gamma * running;
+        *r = running;
+    }
+    returns
+}
+
+/// Samples an action from the policy's softmax distribution for the state
+pub fn act<F, G>(policy: &G, state: ArrayView1<F>, rng: &mut impl Rng) -> usize
+where
+    F: Float,
+    G: GraphExec<Array2<F>, Output = Array2<F>>,
+{
+    let logits = policy.exec(state.insert_axis(Axis(0)).to_owned());
+    let probs = softmax(logits);
+    Sampler::Temperature(F::one()).sample(probs.row(0), rng)
+}
+
+/// The REINFORCE cost, for policies that output one logit per action.
+///
+/// The expected outputs are the actions taken, one-hot encoded. The gradient of each
+/// sample is that of softmax cross entropy, scaled by the return that followed the action.
+/// Actions followed by high returns are made more likely, and those followed by low
+/// (or negative) returns less likely
+#[derive(Debug, Clone)]
+pub struct PolicyGradient<F> {
+    /// The discount factor for future rewards
+    pub gamma: F,
+    /// Whether the returns of each episode are shifted and scaled to have a mean of
+    /// zero and a standard deviation of one. This acts as a baseline, so that below
+    /// average actions are discouraged even if every reward is positive
+    pub normalise: bool,
+    /// The return of each sample in the batch being trained on. Samples without
+    /// a return are weighted by one
+    returns: Array1<F>,
+}
+
+impl<F: Float> PolicyGradient<F> {
+    #[must_use]
+    pub fn new(gamma: F) -> Self {
+        Self {
+            gamma,
+            normalise: false,
+            returns: Array1::zeros(0),
+        }
+    }
+
+    /// Normalises the returns of each episode
+    #[must_use]
+    pub fn normalised(self) -> Self {
+        Self {
+            normalise: true,
+            ..self
+        }
+    }
+
+    /// The returns each step of the episode is weighted by
+    #[must_use]
+    pub fn returns(&self, episode: &Episode<F>) -> Array1<F> {
+        let mut returns = Array1::from(episode.returns(self.gamma));
+        if self.normalise && returns.len() > 1 {
+            let n = F::from(returns.len()).unwrap();
+            let mean = returns.sum() / n;
+            let std = (returns.fold(F::zero(), |sum, &r| sum + (r - mean).powi(2)) / n).sqrt();
+            returns.mapv_inplace(|r| (r - mean) / (std + F::epsilon()));
+        }
+        returns
+    }
+
+    fn weight(&self, i: usize) -> F {
+        self.returns.get(i).copied().unwrap_or_else(F::one)
+    }
+}
+
+impl<F: Float> Cost<Array2<F>> for PolicyGradient<F> {
+    type Inner = F;
+    /// The mean of the negative log probability of each action, weighted by its return
+    fn cost(&self, output: &Array2<F>, expected: &Array2<F>) -> Self::Inner {
+        let total = output
+            .outer_iter()
+            .zip(expected.outer_iter())
+            .enumerate()
+            .fold(F::zero(), |cost, (i, (logits, action))| {
+                let max = logits.fold(F::neg_infinity(), |a, &b| a.max(b));
+                let log_sum = logits.fold(F::zero(), |sum, &x| sum + (x - max).exp()).ln();
+                let log_prob = logits
+                    .iter()
+                    .zip(&action)
+                    .fold(F::zero(), |sum, (&x, &a)| sum + a * (x - max - log_sum));
+                cost - self.weight(i) * log_prob
+            });
+        total / F::from(output.nrows().max(1)).unwrap()
+    }
+
+    fn diff(&self, output: &Array2<F>, expected: &Array2<F>) -> Array2<F> {
+        let mut diff = softmax(output.clone()) - expected;
+        for (i, mut d) in diff.outer_iter_mut().enumerate() {
+            let weight = self.weight(i);
+            d.mapv_inplace(|x| x * weight);
+        }
+        diff
+    }
+}
+
+impl<F, O, G> Train<F, PolicyGradient<F>, O, G>
+where
+    O: Optimiser<G>,
+    G: GraphExecTrain<Array2<F>, Output = Array2<F>> + Mappable<F> + Shaped<F> + Modal + Clone,
+    F: Float + SampleBorrow<F> + SampleUniform + FromPrimitive,
+{
+    /// Trains the policy on every step of the episode as a single batch.
+    /// Returns the cost
+    ///
+    /// # Panics
+    /// If the episode is empty
+    pub fn train_episode(&mut self, episode: &Episode<F>) -> F {
+        assert!(!episode.is_empty(), "cannot train on an empty episode");
+        let states: Vec<_> = episode.states.iter().map(Array1::view).collect();
+        let inputs = stack(Axis(0), &states).expect("every state must have the same size");
+
+        let outputs = self
+            .graph
+            .exec(inputs.slice(s![..1, ..]).to_owned())
+            .ncols();
+        let mut actions = Array2::zeros((episode.len(), outputs));
+        for (mut row, &action) in actions.outer_iter_mut().zip(&episode.actions) {
+            row[action] = F::one();
+        }
+
+        self.cost.returns = self.cost.returns(episode);
+        let cost = self.train(inputs, actions);
+        self.cost.returns = Array1::zeros(0);
+        cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{act, discounted_returns, Episode, PolicyGradient};
+    use crate::{
+        classify::softmax, cost::Cost, dense::Dense, initialisers::Xavier, optimise::SGD,
+        train::Train, Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_returns() {
+        assert_eq!(discounted_returns(&[1.0, 1.0, 1.0], 1.0), [3.0, 2.0, 1.0]);
+        assert!(discounted_returns::<f64>(&[], 0.9).is_empty());
+
+        let mut episode = Episode::<f64>::default();
+        for reward in [2.0, 1.0, 0.0] {
+            episode.push(array![0.0], 0, reward);
+        }
+        assert!((episode.total_reward() - 3.0).abs() < 1e-12);
+        let returns = PolicyGradient::<f64>::new(1.0)
+            .normalised()
+            .returns(&episode);
+        assert!(returns.sum().abs() < 1e-12);
+        assert!(returns[0] > returns[1] && returns[1] > returns[2]);
+
+        // the gradient of each sample is scaled by its return
+        let mut cost = PolicyGradient::new(1.0);
+        cost.returns = array![2.0, -1.0];
+        let output = array![[0.0, 0.0], [0.0, 0.0]];
+        let expected = array![[1.0, 0.0], [0.0, 1.0]];
+        assert_eq!(
+            cost.diff(&output, &expected),
+            array![[-1.0, 1.0], [-0.5, 0.5]]
+        );
+        let log_half = 0.5_f64.ln();
+        assert!((cost.cost(&output, &expected) - log_half / -2.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_reinforce() {
+        // a bandit with three arms, where only the last one pays out. Each pull is
+        // independent of the others, so there's no discounting
+        let mut rng = StdRng::seed_from_u64(0);
+        let policy = Graph::<f64, _>::init_with_random(
+            Dense::output_size(3).with_initialiser(Xavier),
+            &mut rng,
+            2,
+        );
+        let mut trainer = Train::builder(policy)
+            .cost(PolicyGradient::new(0.0).normalised())
+            .optimiser(SGD::new(0.05))
+            .build();
+
+        for _ in 0..200 {
+            let mut episode = Episode::default();
+            for _ in 0..8 {
+                let state = array![1.0, 0.5];
+                let action = act(&trainer.graph, state.view(), &mut rng);
+                episode.push(state, action, if action == 2 { 1.0 } else { 0.0 });
+            }
+            if episode.total_reward() > 0.0 {
+                trainer.train_episode(&episode);
+            }
+        }
+
+        let probs = softmax(trainer.graph.exec(array![[1.0, 0.5]]));
+        assert!(probs[(0, 2)] > 0.9, "{}", probs);
+    }
+}
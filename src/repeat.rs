@@ -0,0 +1,434 @@
+use std::{
+    any::Any,
+    io::{self, Read, Write},
+};
+
+#[cfg(feature = "hdf5")]
+use crate::HDF5;
+use crate::{
+    binary::Element,
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    embedded::{DenseLayer, DenseLayers},
+    error::Result,
+    named::GetLayer,
+    stats::{LayerStats, Stats, UnitActivity},
+    tensors::{ParamPath, Tensors},
+    train::{GraphExecTrain, Modal, Mode},
+    Graph, GraphExec, Mappable, Persist, Shaped,
+};
+#[cfg(feature = "hdf5")]
+use hdf5::H5Type;
+use ndarray::{ArrayViewD, ArrayViewMutD};
+use rand::Rng;
+
+/// Stacks `count` copies of the same graph one after another, each initialised independently.
+/// The initialised state is a [`Vec`] holding each copy's state, in order
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, dense::Dense, initialisers::Xavier, net, repeat::Repeat, Graph,
+/// };
+///
+/// let network = net![
+///     Repeat::new(
+///         Dense::output_size(16)
+///             .with_initialiser(Xavier)
+///             .with_activation(Relu),
+///         10,
+///     ),
+///     Dense::output_size(2).with_initialiser(Xavier)
+/// ];
+/// // (4 + 1) * 16 + 9 * (16 + 1) * 16 + (16 + 1) * 2
+/// assert_eq!(Graph::<f32, usize>::num_params(&network, 4), 2562);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Repeat<G> {
+    pub graph: G,
+    pub count: usize,
+}
+
+impl<G> Repeat<G> {
+    /// # Panics
+    /// If `count` is zero
+    pub fn new(graph: G, count: usize) -> Self {
+        assert!(count > 0, "a repeated graph needs at least one copy");
+        Self { graph, count }
+    }
+}
+
+impl<F, I, G> Graph<F, I> for Repeat<G>
+where
+    G: Graph<F, I, OutputShape = I> + Clone,
+{
+    type State = Vec<G::State>;
+    type OutputShape = G::OutputShape;
+
+    fn get_output_shape(&self) -> Self::OutputShape {
+        self.graph.get_output_shape()
+    }
+
+    fn init_with_random(self, rng: &mut impl Rng, input_shape: I) -> Self::State {
+        let mut shape = input_shape;
+        let mut states = Vec::with_capacity(self.count);
+        for _ in 0..self.count {
+            states.push(self.graph.clone().init_with_random(rng, shape));
+            shape = self.graph.get_output_shape();
+        }
+        states
+    }
+
+    fn num_params(&self, input_shape: I) -> usize {
+        let first = self.graph.num_params(input_shape);
+        let rest = self.graph.num_params(self.graph.get_output_shape());
+        first + (self.count - 1) * rest
+    }
+}
+
+/// The output of the first copy in a stack, which every other copy takes as its input
+type Out<G, Input> = <G as GraphExec<Input>>::Output;
+
+/// The first copy in a stack is fed the stack's input, and the rest are fed
+/// the output of the copy before them
+const fn split<G>(stack: &[G]) -> (&G, &[G]) {
+    stack
+        .split_first()
+        .expect("a repeated graph needs at least one copy")
+}
+
+impl<G, Input> GraphExec<Input> for Vec<G>
+where
+    G: GraphExec<Input> + GraphExec<Out<G, Input>, Output = Out<G, Input>>,
+{
+    type Output = Out<G, Input>;
+    fn exec(&self, input: Input) -> Self::Output {
+        let (first, rest) = split(self);
+        let output = GraphExec::<Input>::exec(first, input);
+        rest.iter()
+            .fold(output, |x, g| GraphExec::<Self::Output>::exec(g, x))
+    }
+    fn try_exec(&self, input: Input) -> Result<Self::Output> {
+        let (first, rest) = split(self);
+        let output = GraphExec::<Input>::try_exec(first, input)?;
+        rest.iter()
+            .try_fold(output, |x, g| GraphExec::<Self::Output>::try_exec(g, x))
+    }
+}
+
+impl<G, Input> GraphExecTrain<Input> for Vec<G>
+where
+    G: GraphExecTrain<Input> + GraphExecTrain<Out<G, Input>, Output = Out<G, Input>>,
+{
+    type State = (
+        <G as GraphExecTrain<Input>>::State,
+        Vec<<G as GraphExecTrain<Out<G, Input>>>::State>,
+    );
+    fn forward(&self, input: Input) -> (Self::State, Self::Output) {
+        let (first, rest) = split(self);
+        let (s0, mut output) = GraphExecTrain::<Input>::forward(first, input);
+        let mut states = Vec::with_capacity(rest.len());
+        for g in rest {
+            let (state, x) = GraphExecTrain::<Self::Output>::forward(g, output);
+            states.push(state);
+            output = x;
+        }
+        ((s0, states), output)
+    }
+
+    fn back(&self, state: Self::State, d_output: Self::Output) -> (Input, Self) {
+        let (first, rest) = split(self);
+        let (s0, states) = state;
+        let mut d_output = d_output;
+        let mut grads = Self::with_capacity(self.len());
+        for (g, state) in rest.iter().zip(states).rev() {
+            let (d, grad) = GraphExecTrain::<Self::Output>::back(g, state, d_output);
+            grads.push(grad);
+            d_output = d;
+        }
+        let (d_input, grad) = GraphExecTrain::<Input>::back(first, s0, d_output);
+        grads.push(grad);
+        grads.reverse();
+        (d_input, grads)
+    }
+
+    fn back_into(&self, state: Self::State, d_output: Self::Output, grads: &mut Self) -> Input {
+        let (first, rest) = split(self);
+        let (g0, grads) = grads
+            .split_first_mut()
+            .expect("a repeated graph needs at least one copy");
+        let (s0, states) = state;
+        let mut d_output = d_output;
+        for ((g, grad), state) in rest.iter().zip(grads).zip(states).rev() {
+            d_output = GraphExecTrain::<Self::Output>::back_into(g, state, d_output, grad);
+        }
+        GraphExecTrain::<Input>::back_into(first, s0, d_output, g0)
+    }
+}
+
+impl<G: Modal> Modal for Vec<G> {
+    fn set_mode(&mut self, mode: Mode) {
+        for g in self {
+            g.set_mode(mode);
+        }
+    }
+}
+
+/// Zipping stacks of different lengths would silently skip the extra copies
+fn same_len<T>(a: &[T], b: &[T]) {
+    assert_eq!(
+        a.len(),
+        b.len(),
+        "repeated graphs should have the same number of copies"
+    );
+}
+
+impl<S, T: Mappable<S>> Mappable<S> for Vec<T> {
+    fn map<F: FnMut(&S) -> S>(&self, mut f: F) -> Self {
+        self.iter().map(|t| t.map(|a| f(a))).collect()
+    }
+    fn map_mut<F: FnMut(&mut S)>(&mut self, mut f: F) {
+        for t in self {
+            t.map_mut(|a| f(a));
+        }
+    }
+    fn map_mut_with<F: FnMut(&mut S, &S)>(&mut self, rhs: &Self, mut f: F) {
+        same_len(self, rhs);
+        for (t, rhs) in self.iter_mut().zip(rhs) {
+            t.map_mut_with(rhs, |a, b| f(a, b));
+        }
+    }
+    fn map_weights_mut_with<F: FnMut(&mut S, &S)>(&mut self, rhs: &Self, mut f: F) {
+        same_len(self, rhs);
+        for (t, rhs) in self.iter_mut().zip(rhs) {
+            t.map_weights_mut_with(rhs, |a, b| f(a, b));
+        }
+    }
+    fn for_each<F: FnMut(&S)>(&self, mut f: F)
+    where
+        S: Clone,
+    {
+        for t in self {
+            t.for_each(|a| f(a));
+        }
+    }
+}
+
+impl<F, T: Shaped<F>> Shaped<F> for Vec<T> {
+    type Shape = Vec<T::Shape>;
+    fn shape(&self) -> Self::Shape {
+        self.iter().map(Shaped::shape).collect()
+    }
+    fn zero(shape: Self::Shape) -> Self {
+        shape.into_iter().map(T::zero).collect()
+    }
+    fn one(shape: Self::Shape) -> Self {
+        shape.into_iter().map(T::one).collect()
+    }
+    fn iter(shape: Self::Shape, mut i: impl Iterator<Item = F>) -> Self {
+        shape.into_iter().map(|s| T::iter(s, &mut i)).collect()
+    }
+}
+
+impl<G: Dot> Dot for Repeat<G> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let label = format!("{} ×", self.count);
+        dot.cluster(&label, |dot| self.graph.write_dot(dot, inputs))
+    }
+}
+
+impl<G: Dot> Dot for Vec<G> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let mut outputs = inputs.to_vec();
+        for g in self {
+            outputs = g.write_dot(dot, &outputs);
+        }
+        outputs
+    }
+}
+
+impl<G: GetLayer> GetLayer for Vec<G> {
+    fn get_layer(&self, name: &str) -> Option<&dyn Any> {
+        self.iter().find_map(|g| g.get_layer(name))
+    }
+    fn get_layer_mut(&mut self, name: &str) -> Option<&mut dyn Any> {
+        self.iter_mut().find_map(|g| g.get_layer_mut(name))
+    }
+}
+
+impl<F, G: LayerStats<F>> LayerStats<F> for Vec<G> {
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        for g in self {
+            g.push_stats(stats);
+        }
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        for g in self {
+            g.push_activity(activity);
+        }
+    }
+}
+
+impl<F, G: DenseLayers<F>> DenseLayers<F> for Vec<G> {
+    fn push_layers<'a>(&'a self, layers: &mut Vec<DenseLayer<'a, F>>) {
+        for g in self {
+            g.push_layers(layers);
+        }
+    }
+}
+
+impl<F, G: DerivativeTesting<F>> DerivativeTesting<F> for Vec<G> {
+    fn len(&self) -> usize {
+        self.iter().map(DerivativeTesting::len).sum()
+    }
+    fn get(&self, mut i: usize) -> F {
+        for g in self {
+            if i < g.len() {
+                return g.get(i);
+            }
+            i -= g.len();
+        }
+        panic!("parameter index out of bounds")
+    }
+    fn set(&mut self, mut i: usize, f: F) {
+        for g in self {
+            if i < g.len() {
+                return g.set(i, f);
+            }
+            i -= g.len();
+        }
+        panic!("parameter index out of bounds")
+    }
+}
+
+impl<F, G: Tensors<F>> Tensors<F> for Vec<G> {
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        for (i, g) in self.iter().enumerate() {
+            path.push(i);
+            g.visit(path, f);
+            path.pop();
+        }
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        for (i, g) in self.iter_mut().enumerate() {
+            path.push(i);
+            g.visit_mut(path, f);
+            path.pop();
+        }
+    }
+}
+
+impl<F: Element, I, G> Persist<F, I> for Repeat<G>
+where
+    G: Persist<F, I, OutputShape = I> + Clone,
+{
+    fn write_state(&self, state: &Self::State, w: &mut impl Write) -> io::Result<()> {
+        for s in state {
+            self.graph.write_state(s, w)?;
+        }
+        Ok(())
+    }
+
+    fn read_state(&self, r: &mut impl Read) -> io::Result<Self::State> {
+        (0..self.count).map(|_| self.graph.read_state(r)).collect()
+    }
+}
+
+#[cfg(feature = "hdf5")]
+impl<F: H5Type, I, G> HDF5<F, I> for Repeat<G>
+where
+    G: HDF5<F, I, OutputShape = I> + Clone,
+{
+    fn save(&self, state: &Self::State, group: &hdf5::Group) -> hdf5::Result<()> {
+        self.save_layers(state, group, &mut 0)
+    }
+
+    fn load(&self, group: &hdf5::Group) -> hdf5::Result<Self::State> {
+        self.load_layers(group, &mut 0)
+    }
+
+    fn save_layers(
+        &self,
+        state: &Self::State,
+        group: &hdf5::Group,
+        index: &mut usize,
+    ) -> hdf5::Result<()> {
+        for s in state {
+            self.graph.save_layers(s, group, index)?;
+        }
+        Ok(())
+    }
+
+    fn load_layers(&self, group: &hdf5::Group, index: &mut usize) -> hdf5::Result<Self::State> {
+        (0..self.count)
+            .map(|_| self.graph.load_layers(group, index))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array2};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::Repeat;
+    use crate::{
+        activation::sigmoid::Sigmoid,
+        cost::mse::MSE,
+        dense::{Dense, DenseState},
+        derivative::check_grads,
+        initialisers::Xavier,
+        net, Graph, GraphExec, Mappable,
+    };
+
+    #[test]
+    fn test_repeat_grads() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut network = net![
+            Repeat::new(
+                Dense::output_size(3)
+                    .with_initialiser(Xavier)
+                    .with_activation(Sigmoid),
+                3,
+            ),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ]
+        .init_with_random(&mut rng, 4);
+
+        // each copy is initialised independently
+        assert_eq!(network.0.len(), 3);
+        assert_eq!(network.0[0].graph.w.dim(), (4, 3));
+        assert_eq!(network.0[1].graph.w.dim(), (3, 3));
+        assert_ne!(network.0[1].graph.w, network.0[2].graph.w);
+
+        let input = Array1::<f64>::from_shape_simple_fn(4, || rng.gen());
+        let expected = Array1::<f64>::from_shape_simple_fn(2, || rng.gen());
+        assert_eq!(network.exec(input.view()).dim(), 2);
+
+        let error = check_grads(&mut network, &MSE, 1e-6, &input, &expected);
+        assert!(error < 1e-5, "max relative error {}", error);
+    }
+
+    #[test]
+    #[should_panic(expected = "a repeated graph needs at least one copy")]
+    fn test_empty_stack() {
+        let stack: Vec<DenseState<f64>> = vec![];
+        stack.exec(Array2::zeros((1, 2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "repeated graphs should have the same number of copies")]
+    fn test_stacks_of_different_lengths() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut stack: Vec<DenseState<f64>> =
+            Repeat::new(Dense::output_size(2).with_initialiser(Xavier), 3)
+                .init_with_random(&mut rng, 2);
+        let shorter = stack[..2].to_vec();
+        stack.map_mut_with(&shorter, |a, b| *a += b);
+    }
+}
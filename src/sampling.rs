@@ -0,0 +1,203 @@
+//! Picking the next token from the output of a sequence model.
+//!
+//! Each helper takes the probabilities a model gave to every token in its vocabulary,
+//! such as the softmax output of one step of a recurrent model, and returns the index
+//! of the chosen token. [`generate`] runs a model step by step, feeding each chosen
+//! token back in
+use ndarray::{Array1, ArrayView1};
+use num_traits::Float;
+use rand::Rng;
+
+use crate::array::argmax;
+
+/// How the next token is chosen from a model's probabilities
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sampler<F> {
+    /// See [`greedy`]
+    Greedy,
+    /// See [`temperature`]
+    Temperature(F),
+    /// See [`top_k`]
+    TopK(usize),
+    /// See [`nucleus`]
+    Nucleus(F),
+}
+
+impl<F: Float> Sampler<F> {
+    /// Chooses a token using this strategy
+    pub fn sample(&self, probs: ArrayView1<F>, rng: &mut impl Rng) -> usize {
+        match *self {
+            Self::Greedy => greedy(probs),
+            Self::Temperature(t) => temperature(probs, t, rng),
+            Self::TopK(k) => top_k(probs, k, rng),
+            Self::Nucleus(p) => nucleus(probs, p, rng),
+        }
+    }
+}
+
+/// The most likely token
+#[must_use]
+pub fn greedy<F: Float>(probs: ArrayView1<F>) -> usize {
+    argmax(&probs)
+}
+
+/// Samples with each probability raised to the power of `1 / t` and renormalised
+///
+/// This is the same as dividing the logits by `t`. Temperatures below one make the
+/// likely tokens more likely, and temperatures above one flatten the distribution
+///
+/// # Panics
+/// If `t` is not positive
+pub fn temperature<F: Float>(probs: ArrayView1<F>, t: F, rng: &mut impl Rng) -> usize {
+    assert!(t > F::zero(), "temperature must be positive");
+    // the largest probability is scaled to one first, so that small temperatures don't underflow
+    let max = probs.fold(F::zero(), |a, &b| a.max(b));
+    let weights = probs.mapv(|p| (p / max).powf(t.recip()));
+    sample(weights.iter().copied().enumerate(), rng)
+}
+
+/// Samples from the `k` most likely tokens, renormalised
+///
+/// # Panics
+/// If `k` is zero
+pub fn top_k<F: Float>(probs: ArrayView1<F>, k: usize, rng: &mut impl Rng) -> usize {
+    assert!(k > 0, "top k sampling needs at least one token");
+    let order = by_probability(probs);
+    sample(order.into_iter().take(k).map(|i| (i, probs[i])), rng)
+}
+
+/// Samples from the smallest set of most likely tokens whose probabilities add up
+/// to at least `p`, renormalised. Also called top p sampling
+pub fn nucleus<F: Float>(probs: ArrayView1<F>, p: F, rng: &mut impl Rng) -> usize {
+    let order = by_probability(probs);
+    let mut total = F::zero();
+    let len = order
+        .iter()
+        .position(|&i| {
+            total = total + probs[i];
+            total >= p
+        })
+        .map_or(order.len(), |i| i + 1);
+    sample(order.into_iter().take(len).map(|i| (i, probs[i])), rng)
+}
+
+/// Runs a sequence model one token at a time
+///
+/// `step` is given each token in turn, and returns the probabilities of the token that comes next. It keeps any state,
+/// such as the hidden state of a recurrent model, between calls.
+///
+/// The `seed` tokens are fed in first, then `len` tokens are sampled and fed back in.
+/// Returns the sampled tokens
+///
+/// # Panics
+/// If `seed` is empty
+pub fn generate<F: Float>(
+    seed: &[usize],
+    len: usize,
+    sampler: Sampler<F>,
+    rng: &mut impl Rng,
+    mut step: impl FnMut(usize) -> Array1<F>,
+) -> Vec<usize> {
+    let (last, seed) = seed
+        .split_last()
+        .expect("generating needs at least one seed token");
+    for &token in seed {
+        step(token);
+    }
+
+    let mut tokens = Vec::with_capacity(len);
+    let mut probs = step(*last);
+    for i in 0..len {
+        let token = sampler.sample(probs.view(), rng);
+        tokens.push(token);
+        if i + 1 < len {
+            probs = step(token);
+        }
+    }
+    tokens
+}
+
+/// Token indices, most likely first
+fn by_probability<F: Float>(probs: ArrayView1<F>) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..probs.len()).collect();
+    order.sort_by(|&a, &b| {
+        probs[b]
+            .partial_cmp(&probs[a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    order
+}
+
+/// Picks one of the tokens, in proportion to their weights
+fn sample<F: Float>(
+    weights: impl Iterator<Item = (usize, F)> + Clone,
+    rng: &mut impl Rng,
+) -> usize {
+    let total = weights.clone().fold(F::zero(), |sum, (_, w)| sum + w);
+    let mut target = total * F::from(rng.gen::<f64>()).unwrap();
+    let mut chosen = None;
+    for (i, w) in weights {
+        if w > F::zero() {
+            chosen = Some(i);
+            if target < w {
+                break;
+            }
+            target = target - w;
+        }
+    }
+    chosen.expect("there are no tokens with a positive probability")
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array1};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{generate, greedy, nucleus, temperature, top_k, Sampler};
+
+    #[test]
+    fn test_samplers() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let probs = array![0.1, 0.5, 0.15, 0.25];
+        assert_eq!(greedy(probs.view()), 1);
+
+        let mut counts = [0_i32; 4];
+        for _ in 0..10_000 {
+            counts[temperature(probs.view(), 1.0, &mut rng)] += 1;
+        }
+        let expected = [1000, 5000, 1500, 2500];
+        assert!(counts
+            .iter()
+            .zip(&expected)
+            .all(|(&c, &e)| (c - e).abs() < 200));
+
+        for _ in 0..100 {
+            assert_eq!(temperature(probs.view(), 0.01, &mut rng), 1);
+            assert_eq!(top_k(probs.view(), 1, &mut rng), 1);
+            assert!([1, 3].contains(&top_k(probs.view(), 2, &mut rng)));
+            assert_eq!(nucleus(probs.view(), 0.5, &mut rng), 1);
+            assert!([1, 3].contains(&nucleus(probs.view(), 0.7, &mut rng)));
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        // each token is most likely followed by the next one
+        let step = |token: usize| {
+            let mut probs = Array1::from_elem(4, 0.1);
+            probs[(token + 1) % 4] = 0.7;
+            probs
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let tokens = generate(&[3, 1], 6, Sampler::Greedy, &mut rng, step);
+        assert_eq!(tokens, [2, 3, 0, 1, 2, 3]);
+
+        let mut seen = vec![];
+        generate(&[0, 2], 3, Sampler::TopK(2), &mut rng, |token| {
+            seen.push(token);
+            step(token)
+        });
+        assert_eq!(seen.len(), 4);
+        assert_eq!(seen[..2], [0, 2]);
+    }
+}
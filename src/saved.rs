@@ -0,0 +1,340 @@
+//! Dense models saved along with their architecture.
+//!
+//! [`Persist`](crate::Persist) only saves the parameters, so loading them needs the same
+//! graph type that was trained. A [`SavedModel`] also records each layer's size and
+//! activation, so services can load and run any user's model without knowing its type.
+//!
+//! Only chains of dense layers are supported, each followed by a relu, a sigmoid, or no
+//! activation. Graphs with other layers don't implement [`DenseLayers`], and
+//! [`SavedModel::new`] returns an error for any other activation
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use ndarray::{Array2, ArrayView2};
+use num_traits::{Float, NumCast, ToPrimitive};
+
+use crate::{
+    activation::{relu::Relu, sigmoid::Sigmoid, Activation},
+    array::input_shape,
+    binary::{self, invalid},
+    dense::DenseState,
+    embedded::DenseLayers,
+    error::Result,
+    GraphExec,
+};
+
+const SAVED_MAGIC: &[u8; 4] = b"NRSV";
+const SAVED_VERSION: u8 = 1;
+
+/// The activations a saved model can apply after each dense layer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SavedActivation {
+    Relu,
+    Sigmoid,
+}
+
+impl SavedActivation {
+    pub(crate) fn from_name(name: &str) -> io::Result<Self> {
+        match name {
+            Relu::NAME => Ok(Self::Relu),
+            Sigmoid::NAME => Ok(Self::Sigmoid),
+            _ => Err(invalid(
+                "saved models only support relu and sigmoid activations",
+            )),
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Relu => Relu::NAME,
+            Self::Sigmoid => Sigmoid::NAME,
+        }
+    }
+
+    pub(crate) fn apply<F: Float>(self, x: F) -> F {
+        match self {
+            Self::Relu => Relu.apply(x),
+            Self::Sigmoid => Sigmoid.apply(x),
+        }
+    }
+}
+
+/// A dense layer, along with the activation applied to its output
+#[derive(Debug, Clone)]
+pub struct SavedLayer {
+    pub dense: DenseState<f32>,
+    pub activation: Option<SavedActivation>,
+}
+
+/// A chain of dense layers stored as `f32`, that can run inference
+/// without the concrete type of the graph it was created from
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, dense::Dense, initialisers::Xavier, net, saved::SavedModel,
+///     Graph, GraphExec,
+/// };
+/// use ndarray::Array2;
+///
+/// let network = net![
+///     Dense::output_size(16)
+///         .with_initialiser(Xavier)
+///         .with_activation(Relu),
+///     Dense::output_size(2).with_initialiser(Xavier)
+/// ];
+/// let state = Graph::<f32, _>::input_shape(network, 4);
+///
+/// let mut bytes = vec![];
+/// SavedModel::new(&state).unwrap().write(&mut bytes).unwrap();
+///
+/// // no need to know the type of `state` to load it back
+/// let model = SavedModel::read(&mut bytes.as_slice()).unwrap();
+/// let input = Array2::zeros((3, 4));
+/// assert_eq!(model.predict(input.view()), state.exec(input.view()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SavedModel {
+    /// Never empty, so the model always has an input and output size
+    layers: Vec<SavedLayer>,
+}
+
+impl SavedModel {
+    /// Copies the layers of a state, converting the parameters to `f32`
+    pub fn new<F, T>(state: &T) -> io::Result<Self>
+    where
+        F: ToPrimitive + Copy,
+        T: DenseLayers<F>,
+    {
+        let mut layers = vec![];
+        state.push_layers(&mut layers);
+
+        let cast = |x: F| -> f32 { NumCast::from(x).expect("float could not be converted") };
+        let layers = layers
+            .into_iter()
+            .map(|layer| {
+                Ok(SavedLayer {
+                    dense: DenseState {
+                        w: layer.w.mapv(cast),
+                        b: layer.b.mapv(cast),
+                    },
+                    activation: layer
+                        .activation
+                        .map(SavedActivation::from_name)
+                        .transpose()?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Self::from_layers(layers)
+    }
+
+    /// Creates a model from its layers, which must be non-empty and have matching sizes
+    pub fn from_layers(layers: Vec<SavedLayer>) -> io::Result<Self> {
+        if layers.is_empty() {
+            return Err(invalid("saved models need at least one layer"));
+        }
+        for layer in &layers {
+            if layer.dense.w.ncols() != layer.dense.b.len() {
+                return Err(invalid("saved layer has mismatched weights and biases"));
+            }
+        }
+        if layers
+            .windows(2)
+            .any(|pair| pair[0].dense.w.ncols() != pair[1].dense.w.nrows())
+        {
+            return Err(invalid("saved layers have mismatched sizes"));
+        }
+        Ok(Self { layers })
+    }
+
+    #[must_use]
+    pub fn layers(&self) -> &[SavedLayer] {
+        &self.layers
+    }
+
+    /// The number of features the model expects in each input row
+    #[must_use]
+    pub fn input_size(&self) -> usize {
+        self.layers[0].dense.w.nrows()
+    }
+
+    /// The number of values the model outputs for each input row
+    #[must_use]
+    pub fn output_size(&self) -> usize {
+        self.layers[self.layers.len() - 1].dense.w.ncols()
+    }
+
+    /// Runs the model on a batch of inputs, one per row
+    #[must_use]
+    pub fn predict(&self, input: ArrayView2<f32>) -> Array2<f32> {
+        self.exec(input)
+    }
+
+    /// Writes the layers, followed by a checksum so corrupted files are caught on load
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        let mut body = vec![];
+        let count = u32::try_from(self.layers.len()).map_err(|_| invalid("too many layers"))?;
+        body.write_all(&count.to_le_bytes())?;
+        for layer in &self.layers {
+            let name = layer.activation.map_or("", SavedActivation::name);
+            let len =
+                u8::try_from(name.len()).map_err(|_| invalid("activation name is too long"))?;
+            body.write_all(&[len])?;
+            body.write_all(name.as_bytes())?;
+            binary::write_array(&mut body, &layer.dense.w)?;
+            binary::write_array(&mut body, &layer.dense.b)?;
+        }
+
+        binary::write_header(w, SAVED_MAGIC, SAVED_VERSION)?;
+        w.write_all(&binary::checksum(&body).to_le_bytes())?;
+        w.write_all(&body)
+    }
+
+    /// Reads a model written by [`write`](Self::write)
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        binary::read_header(r, SAVED_MAGIC, SAVED_VERSION)?;
+        let mut expected = [0; 8];
+        r.read_exact(&mut expected)?;
+        let mut body = vec![];
+        r.read_to_end(&mut body)?;
+        if binary::checksum(&body) != u64::from_le_bytes(expected) {
+            return Err(invalid("saved model is truncated or corrupted"));
+        }
+
+        let r = &mut body.as_slice();
+        let mut count = [0; 4];
+        r.read_exact(&mut count)?;
+        let count = u32::from_le_bytes(count);
+
+        // the count isn't trusted to allocate with, since every layer might be missing
+        let mut layers = vec![];
+        for _ in 0..count {
+            let mut len = [0; 1];
+            r.read_exact(&mut len)?;
+            let mut name = vec![0; len[0] as usize];
+            r.read_exact(&mut name)?;
+            let activation = match name.as_slice() {
+                [] => None,
+                name => Some(SavedActivation::from_name(
+                    std::str::from_utf8(name).map_err(|_| invalid("invalid activation name"))?,
+                )?),
+            };
+
+            let dense = DenseState {
+                w: binary::read_array(r)?,
+                b: binary::read_array(r)?,
+            };
+            layers.push(SavedLayer { dense, activation });
+        }
+        Self::from_layers(layers)
+    }
+
+    /// Saves the model to a file. Like [`Persist::save_file`](crate::Persist::save_file),
+    /// the file is written to a temporary path first and then renamed
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        span!(INFO, "save_model", path = %path.display());
+        let tmp = path.with_extension("tmp");
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        self.write(&mut w)?;
+        w.into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .sync_all()?;
+        fs::rename(tmp, path)
+    }
+
+    /// Loads a model saved by [`save`](Self::save)
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        span!(INFO, "load_model", path = %path.display());
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+impl GraphExec<ArrayView2<'_, f32>> for SavedModel {
+    type Output = Array2<f32>;
+
+    fn exec(&self, input: ArrayView2<f32>) -> Self::Output {
+        let (first, rest) = self
+            .layers
+            .split_first()
+            .expect("saved models always have a layer");
+        let output = apply(first, first.dense.exec(input));
+        rest.iter()
+            .fold(output, |x, layer| apply(layer, layer.dense.exec(x)))
+    }
+
+    fn try_exec(&self, input: ArrayView2<f32>) -> Result<Self::Output> {
+        input_shape("Saved model", input.shape(), self.input_size())?;
+        Ok(self.exec(input))
+    }
+}
+
+fn apply(layer: &SavedLayer, mut output: Array2<f32>) -> Array2<f32> {
+    if let Some(activation) = layer.activation {
+        output.mapv_inplace(|x| activation.apply(x));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::SavedModel;
+    use crate::{
+        activation::{relu::Relu, sigmoid::Sigmoid},
+        dense::Dense,
+        initialisers::Xavier,
+        net,
+        precision::Cast,
+        Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_saved_model_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = net![
+            Dense::output_size(5)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(3)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid),
+            Dense::output_size(2).with_initialiser(Xavier)
+        ];
+        let state = Graph::<f64, _>::init_with_random(network, &mut rng, 4);
+
+        let path = crate::temp_path("saved.model");
+        SavedModel::new(&state).unwrap().save(&path).unwrap();
+        let model = SavedModel::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((model.input_size(), model.output_size()), (4, 2));
+
+        let input = Array2::<f64>::from_shape_simple_fn((6, 4), || rng.gen());
+        let expected: Array2<f32> = state.exec(input.view()).cast();
+        let input: Array2<f32> = input.cast();
+        let output = model.predict(input.view());
+        assert!(output
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| (a - b).abs() < 1e-5));
+
+        assert!(model.try_exec(Array2::zeros((6, 3)).view()).is_err());
+
+        let mut bytes = vec![];
+        model.write(&mut bytes).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(SavedModel::read(&mut bytes.as_slice()).is_err());
+
+        assert!(SavedModel::from_layers(vec![]).is_err());
+        let mut layers = model.layers().to_vec();
+        layers.swap(0, 1);
+        assert!(SavedModel::from_layers(layers).is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Hyperparameters that change over the course of training.
+//!
+//! A [`Schedule`] gives the value of a hyperparameter for each epoch. The trainer looks
+//! it up at the start of every epoch, eg with [`TrainBuilder::dropout_schedule`](crate::train::TrainBuilder::dropout_schedule).
+//! Any `Fn(usize) -> F` is a schedule, and [`Linear`] covers the common ramps.
+//!
+//! An optimiser's hyperparameters are set before every step by wrapping it in
+//! [`Scheduled`](crate::optimise::Scheduled) with a [`Policy`], counting steps rather
+//! than epochs. Any schedule can drive the learning rate using [`LearningRate`], and
+//! [`OneCycle`] changes the learning rate and momentum together
+use std::marker::PhantomData;
+
+use num_traits::Float;
+
+use crate::optimise::TunableOptimiser;
+
+/// The value of a hyperparameter at each epoch (or step), counting from zero
+pub trait Schedule<F> {
+    fn value(&self, epoch: usize) -> F;
+}
+
+impl<F, S: Fn(usize) -> F> Schedule<F> for S {
+    fn value(&self, epoch: usize) -> F {
+        self(epoch)
+    }
+}
+
+/// Holds `start` until the epoch `from`, then moves linearly to reach `end` at the
+/// epoch `to`, and holds `end` after that.
+///
+/// ```
+/// use linear_networks::schedule::{Linear, Schedule};
+///
+/// // ramp dropout up over the first 10 epochs
+/// let ramp = Linear::new(0.0, 0.5).between(0, 10);
+/// assert_eq!(ramp.value(0), 0.0);
+/// assert_eq!(ramp.value(5), 0.25);
+/// assert_eq!(ramp.value(20), 0.5);
+///
+/// // and turn it off again late in training
+/// let decay = Linear::new(0.5, 0.0).between(80, 100);
+/// assert_eq!(decay.value(50), 0.5);
+/// assert_eq!(decay.value(100), 0.0);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Linear<F> {
+    pub start: F,
+    pub end: F,
+    pub from: usize,
+    pub to: usize,
+}
+
+impl<F> Linear<F> {
+    /// Jumps straight from `start` to `end` after the first epoch,
+    /// until given a range with [`between`](Self::between)
+    pub const fn new(start: F, end: F) -> Self {
+        Self {
+            start,
+            end,
+            from: 0,
+            to: 1,
+        }
+    }
+
+    /// The epochs to move between `start` and `end` over
+    #[must_use]
+    pub const fn between(mut self, from: usize, to: usize) -> Self {
+        self.from = from;
+        self.to = to;
+        self
+    }
+}
+
+impl<F: Float> Schedule<F> for Linear<F> {
+    fn value(&self, epoch: usize) -> F {
+        if epoch <= self.from {
+            self.start
+        } else if epoch >= self.to {
+            self.end
+        } else {
+            let t = F::from(epoch - self.from).unwrap() / F::from(self.to - self.from).unwrap();
+            self.start + (self.end - self.start) * t
+        }
+    }
+}
+
+/// Decays from `start` to `end` over `steps`, following `(1 - t / steps)^power`,
+/// and holds `end` after that. A `power` of one is a linear decay
+///
+/// ```
+/// use linear_networks::schedule::{Polynomial, Schedule};
+///
+/// let decay = Polynomial::new(1.0_f64, 0.0, 10, 2.0);
+/// assert_eq!(decay.value(0), 1.0);
+/// assert!((decay.value(5) - 0.25).abs() < 1e-12);
+/// assert_eq!(decay.value(20), 0.0);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Polynomial<F> {
+    pub start: F,
+    pub end: F,
+    pub steps: usize,
+    pub power: F,
+}
+
+impl<F> Polynomial<F> {
+    pub const fn new(start: F, end: F, steps: usize, power: F) -> Self {
+        Self {
+            start,
+            end,
+            steps,
+            power,
+        }
+    }
+}
+
+impl<F: Float> Schedule<F> for Polynomial<F> {
+    fn value(&self, step: usize) -> F {
+        let done = F::from(step.min(self.steps)).unwrap() / F::from(self.steps.max(1)).unwrap();
+        self.end + (self.start - self.end) * (F::one() - done).powf(self.power)
+    }
+}
+
+/// The 1cycle policy, from <https://arxiv.org/abs/1803.09820>.
+///
+/// Over the first `warmup` steps, the learning rate rises from `max_learning_rate /
+/// initial_div` to `max_learning_rate`, while the momentum falls from its high to its
+/// low value. Over the rest of the `steps`, the learning rate falls to
+/// `max_learning_rate / (initial_div * final_div)` and the momentum rises back again.
+/// Both change linearly
+///
+/// ```
+/// use linear_networks::schedule::OneCycle;
+///
+/// let policy = OneCycle::new(0.1_f64, 100);
+/// assert!((policy.learning_rate(30) - 0.1).abs() < 1e-12);
+/// assert!((policy.momentum(30) - 0.85).abs() < 1e-12);
+/// assert!(policy.learning_rate(100) < 1e-6);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OneCycle<F> {
+    pub max_learning_rate: F,
+    pub initial_div: F,
+    pub final_div: F,
+    /// The high and low momentum
+    pub momentum: (F, F),
+    pub warmup: usize,
+    pub steps: usize,
+}
+
+impl<F: Float> OneCycle<F> {
+    /// Uses the same defaults as `PyTorch`: 30% of the steps warming up, an `initial_div`
+    /// of 25, a `final_div` of 10⁴ and a momentum between 0.95 and 0.85
+    pub fn new(max_learning_rate: F, steps: usize) -> Self {
+        let f = |x| F::from(x).unwrap();
+        Self {
+            max_learning_rate,
+            initial_div: f(25.0),
+            final_div: f(1e4),
+            momentum: (f(0.95), f(0.85)),
+            warmup: steps * 3 / 10,
+            steps,
+        }
+    }
+
+    pub fn learning_rate(&self, step: usize) -> F {
+        let max = self.max_learning_rate;
+        let initial = max / self.initial_div;
+        if step < self.warmup {
+            Linear::new(initial, max)
+                .between(0, self.warmup)
+                .value(step)
+        } else {
+            let last = initial / self.final_div;
+            Linear::new(max, last)
+                .between(self.warmup, self.steps)
+                .value(step)
+        }
+    }
+
+    pub fn momentum(&self, step: usize) -> F {
+        let (high, low) = self.momentum;
+        if step < self.warmup {
+            Linear::new(high, low).between(0, self.warmup).value(step)
+        } else {
+            Linear::new(low, high)
+                .between(self.warmup, self.steps)
+                .value(step)
+        }
+    }
+}
+
+/// Sets an optimiser's hyperparameters for each step, counting from zero.
+/// Values are scheduled with a [`Schedule`], and a policy decides which hyperparameters
+/// they are applied to
+pub trait Policy {
+    /// The type of the hyperparameters
+    type Float;
+    fn apply<O: TunableOptimiser<Self::Float> + ?Sized>(&self, step: usize, optimiser: &mut O);
+}
+
+/// A policy that sets only the learning rate, following any [`Schedule`]
+///
+/// ```
+/// use linear_networks::{
+///     optimise::{Scheduled, TunableOptimiser, SGD},
+///     schedule::LearningRate,
+/// };
+///
+/// // halve the learning rate every 100 steps
+/// let schedule = |step: usize| 0.1 * 0.5_f64.powi((step / 100) as i32);
+/// let sgd = Scheduled::new(SGD::new(0.1), LearningRate::new(schedule));
+/// assert_eq!(sgd.learning_rate(), 0.1);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LearningRate<S, F> {
+    pub schedule: S,
+    float: PhantomData<fn() -> F>,
+}
+
+impl<S: Schedule<F>, F> LearningRate<S, F> {
+    pub const fn new(schedule: S) -> Self {
+        Self {
+            schedule,
+            float: PhantomData,
+        }
+    }
+}
+
+impl<S: Schedule<F>, F> Policy for LearningRate<S, F> {
+    type Float = F;
+    fn apply<O: TunableOptimiser<F> + ?Sized>(&self, step: usize, optimiser: &mut O) {
+        optimiser.set_learning_rate(self.schedule.value(step));
+    }
+}
+
+/// Optimisers without momentum only have their learning rate scheduled
+impl<F: Float> Policy for OneCycle<F> {
+    type Float = F;
+    fn apply<O: TunableOptimiser<F> + ?Sized>(&self, step: usize, optimiser: &mut O) {
+        optimiser.set_learning_rate(self.learning_rate(step));
+        optimiser.set_momentum(self.momentum(step));
+    }
+}
@@ -0,0 +1,294 @@
+use ndarray::{Array, ArrayView, Dimension, RemoveAxis};
+use num_traits::{Float, FromPrimitive};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rand_distr::uniform::{SampleBorrow, SampleUniform};
+
+use crate::{
+    cost::Cost,
+    data::{batch::Batches, Dataset},
+    error::{Error, Result},
+    optimise::Optimiser,
+    train::{GraphExecTrain, Modal, Regularisation, Train},
+    Mappable, Shaped,
+};
+
+/// A single configuration to train with
+#[derive(Debug, Clone, Copy)]
+pub struct Hyperparameters<F> {
+    pub learning_rate: F,
+    pub batch_size: usize,
+    pub dropout: F,
+    pub regularisation: Option<Regularisation<F>>,
+}
+
+/// Searches every combination of the given values
+#[derive(Debug, Clone)]
+pub struct Grid<F> {
+    pub learning_rates: Vec<F>,
+    pub batch_sizes: Vec<usize>,
+    pub dropouts: Vec<F>,
+    pub regularisations: Vec<Option<Regularisation<F>>>,
+}
+
+impl<F: Copy> Grid<F> {
+    #[must_use]
+    pub fn trials(&self) -> Vec<Hyperparameters<F>> {
+        let mut trials = vec![];
+        for &learning_rate in &self.learning_rates {
+            for &batch_size in &self.batch_sizes {
+                for &dropout in &self.dropouts {
+                    for &regularisation in &self.regularisations {
+                        trials.push(Hyperparameters {
+                            learning_rate,
+                            batch_size,
+                            dropout,
+                            regularisation,
+                        });
+                    }
+                }
+            }
+        }
+        trials
+    }
+}
+
+/// Searches randomly sampled configurations.
+/// The learning rate is sampled log-uniformly and the dropout uniformly within their ranges
+#[derive(Debug, Clone)]
+pub struct RandomSearch<F> {
+    pub learning_rate: (F, F),
+    pub batch_sizes: Vec<usize>,
+    pub dropout: (F, F),
+    pub regularisations: Vec<Option<Regularisation<F>>>,
+    /// How many configurations to sample
+    pub trials: usize,
+}
+
+impl<F> RandomSearch<F>
+where
+    F: Float + SampleUniform,
+{
+    pub fn trials(&self, rng: &mut impl Rng) -> Vec<Hyperparameters<F>> {
+        let (lr_low, lr_high) = self.learning_rate;
+        let (dropout_low, dropout_high) = self.dropout;
+        (0..self.trials)
+            .map(|_| Hyperparameters {
+                learning_rate: rng.gen_range(lr_low.ln()..=lr_high.ln()).exp(),
+                batch_size: *self.batch_sizes.choose(rng).unwrap(),
+                dropout: rng.gen_range(dropout_low..=dropout_high),
+                regularisation: *self.regularisations.choose(rng).unwrap(),
+            })
+            .collect()
+    }
+}
+
+/// The outcome of training with a single configuration
+#[derive(Debug, Clone)]
+pub struct Trial<F> {
+    pub hyperparameters: Hyperparameters<F>,
+    /// The average training cost of each epoch
+    pub history: Vec<F>,
+    /// The cost per sample of the validation set after training,
+    /// or of the training set if there was no validation set
+    pub score: F,
+}
+
+/// A set of inputs along with their expected outputs
+pub type Split<'a, F, D1, D2> = (ArrayView<'a, F, D1>, ArrayView<'a, F, D2>);
+
+#[derive(Debug, Clone)]
+pub struct SearchResult<F> {
+    /// The trial with the lowest score
+    pub best: Trial<F>,
+    /// Every trial, in the order they were run
+    pub trials: Vec<Trial<F>>,
+}
+
+/// Trains a fresh network for each configuration and returns the one with the lowest score.
+///
+/// `build` is given each configuration along with a random number generator
+/// seeded with `seed`, and should initialise the graph and optimiser (using the learning rate).
+/// The dropout and regularisation are applied to the trainer automatically.
+/// The same seed is also used to shuffle the batches, so each trial is reproducible.
+///
+/// Every trial is scored by its cost per sample, so trials with different batch sizes
+/// can be compared.
+///
+/// Returns an error if the validation set doesn't match the networks,
+/// or if every trial diverged
+pub fn search<F, C, O, G, DS, D1, D2>(
+    trials: impl IntoIterator<Item = Hyperparameters<F>>,
+    epochs: usize,
+    seed: u64,
+    training: &DS,
+    validation: Option<&Split<F, D1, D2>>,
+    mut build: impl FnMut(&Hyperparameters<F>, &mut StdRng) -> Train<F, C, O, G>,
+) -> Result<SearchResult<F>>
+where
+    C: Cost<Array<F, D2>, Inner = F>,
+    O: Optimiser<G>,
+    G: GraphExecTrain<Array<F, D1>, Output = Array<F, D2>>
+        + Mappable<F>
+        + Shaped<F>
+        + Modal
+        + Clone,
+    F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+    DS: Dataset<Input = Array<F, D1>, Target = Array<F, D2>>,
+    D1: Dimension + RemoveAxis,
+    D2: Dimension + RemoveAxis,
+{
+    let trials = trials
+        .into_iter()
+        .map(|hyperparameters| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut trainer = build(&hyperparameters, &mut rng);
+            trainer.dropout = hyperparameters.dropout;
+            trainer.regularisation = hyperparameters.regularisation;
+
+            let history: Vec<_> = (0..epochs)
+                .map(|_| {
+                    let batches =
+                        Batches::shuffled(training.len(), hyperparameters.batch_size, &mut rng);
+                    trainer.perform_epoch_with(training, batches)
+                })
+                .collect();
+
+            let batch_size = hyperparameters.batch_size;
+            let score = match validation {
+                Some((inputs, expected)) => {
+                    trainer.evaluate(inputs, expected, batch_size, &[])?.cost
+                }
+                None => trainer.validate(training, batch_size),
+            };
+
+            Ok(Trial {
+                hyperparameters,
+                history,
+                score,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let best = trials
+        .iter()
+        .filter(|trial| !trial.score.is_nan())
+        .min_by(|a, b| a.score.partial_cmp(&b.score).unwrap())
+        .ok_or(Error::NoTrials)?
+        .clone();
+
+    Ok(SearchResult { best, trials })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Ix2;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{search, Grid, Hyperparameters, RandomSearch};
+    use crate::{
+        cost::mse::MSE,
+        data::{self, InMemoryDataset},
+        dense::{Dense, DenseState},
+        error::Error,
+        initialisers::Xavier,
+        optimise::sgd::SGD,
+        train::{Regularisation, Train},
+        Graph,
+    };
+
+    #[test]
+    fn test_grid_trials() {
+        let grid = Grid {
+            learning_rates: vec![0.1, 0.01],
+            batch_sizes: vec![16, 32, 64],
+            dropouts: vec![0.0],
+            regularisations: vec![None, Some(Regularisation::L2(0.01))],
+        };
+        let trials = grid.trials();
+        assert_eq!(trials.len(), 12);
+        assert!((trials[0].learning_rate - 0.1_f64).abs() < f64::EPSILON);
+        assert_eq!(trials[11].batch_size, 64);
+    }
+
+    fn build(
+        h: &Hyperparameters<f64>,
+        rng: &mut StdRng,
+    ) -> Train<f64, MSE, SGD<f64>, DenseState<f64>> {
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(rng, 2);
+        Train::builder(graph)
+            .optimiser(SGD::new(h.learning_rate))
+            .build()
+    }
+
+    #[test]
+    fn test_search() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (inputs, targets) = data::linear_samples(&mut rng, 64);
+        let training = InMemoryDataset::new(inputs, targets);
+        let (inputs, targets) = data::linear_samples(&mut rng, 32);
+        let validation = (inputs.view(), targets.view());
+
+        // a learning rate of zero never trains
+        let grid = Grid {
+            learning_rates: vec![0.0, 0.005],
+            batch_sizes: vec![4, 64],
+            dropouts: vec![0.0],
+            regularisations: vec![None],
+        };
+        let result = search(grid.trials(), 10, 0, &training, Some(&validation), build).unwrap();
+        assert_eq!(result.trials.len(), 4);
+        assert!((result.best.hyperparameters.learning_rate - 0.005).abs() < f64::EPSILON);
+        assert_eq!(result.best.hyperparameters.batch_size, 64);
+        assert_eq!(result.best.history.len(), 10);
+        assert_eq!(result.best.history, result.trials[3].history);
+
+        // every trial starts from the same parameters, and the score is per sample,
+        // so untrained networks score the same whatever their batch size
+        let (small, large) = (&result.trials[0], &result.trials[1]);
+        assert!((small.score - large.score).abs() < 1e-12);
+
+        // trials are reproducible
+        let again = search(grid.trials(), 10, 0, &training, Some(&validation), build).unwrap();
+        assert_eq!(again.best.history, result.best.history);
+    }
+
+    #[test]
+    fn test_random_search() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (inputs, targets) = data::linear_samples(&mut rng, 64);
+        let training = InMemoryDataset::new(inputs, targets);
+
+        let random = RandomSearch {
+            learning_rate: (0.0001, 0.01),
+            batch_sizes: vec![8, 16],
+            dropout: (0.0, 0.0),
+            regularisations: vec![None],
+            trials: 4,
+        };
+        let trials = random.trials(&mut StdRng::seed_from_u64(1));
+        assert!(trials
+            .iter()
+            .all(|t| (0.0001..=0.01).contains(&t.learning_rate)));
+        let result =
+            search::<_, _, _, _, _, Ix2, Ix2>(trials, 5, 0, &training, None, build).unwrap();
+        let best = result
+            .trials
+            .iter()
+            .map(|t| t.score)
+            .fold(f64::INFINITY, f64::min);
+        assert!((result.best.score - best).abs() < f64::EPSILON);
+
+        // a trial that diverges is skipped, and an error is returned if none are left
+        let grid = Grid {
+            learning_rates: vec![f64::NAN],
+            batch_sizes: vec![8],
+            dropouts: vec![0.0],
+            regularisations: vec![None],
+        };
+        let err = search::<_, _, _, _, _, Ix2, Ix2>(grid.trials(), 2, 0, &training, None, build)
+            .unwrap_err();
+        assert!(matches!(err, Error::NoTrials));
+    }
+}
@@ -0,0 +1,503 @@
+//! A chain of layers that's built at runtime, rather than being encoded in the type.
+//!
+//! Networks built with [`net!`](crate::net) are nested tuples, so their type grows with every
+//! layer and has to be known at compile time. A [`Sequential`] network holds boxed [`Layer`]
+//! objects instead, so layers can be pushed, inserted and removed at runtime,
+//! for instance when the architecture is read from a config file
+use std::any::Any;
+
+use ndarray::{Array, ArrayD, Dimension, IxDyn};
+use num_traits::{One, Zero};
+
+use crate::{
+    error::{Error, Result},
+    train::{GraphExecTrain, Modal, Mode},
+    GraphExec, Mappable, Shaped,
+};
+
+/// An object safe version of the graph traits, over batches of `ArrayD<F>`.
+///
+/// This is implemented for every trainable graph state, so it shouldn't need implementing
+/// directly. Plugins can hand out their layers as `Box<dyn Layer<F>>`.
+/// The intermediate training state is type erased, and the graphs passed back
+/// in (eg the gradients) must be the same type of layer
+pub trait Layer<F>: Send + Sync {
+    fn exec(&self, input: ArrayD<F>) -> ArrayD<F>;
+    fn try_exec(&self, input: ArrayD<F>) -> Result<ArrayD<F>>;
+    fn forward(&self, input: ArrayD<F>) -> (Box<dyn Any>, ArrayD<F>);
+    fn back(&self, state: Box<dyn Any>, d_output: ArrayD<F>) -> (ArrayD<F>, Box<dyn Layer<F>>);
+    fn back_into(
+        &self,
+        state: Box<dyn Any>,
+        d_output: ArrayD<F>,
+        grads: &mut dyn Layer<F>,
+    ) -> ArrayD<F>;
+    fn set_mode(&mut self, mode: Mode);
+
+    fn map(&self, f: &mut dyn FnMut(&F) -> F) -> Box<dyn Layer<F>>;
+    fn map_mut(&mut self, f: &mut dyn FnMut(&mut F));
+    fn map_mut_with(&mut self, rhs: &dyn Layer<F>, f: &mut dyn FnMut(&mut F, &F));
+    fn map_weights_mut_with(&mut self, rhs: &dyn Layer<F>, f: &mut dyn FnMut(&mut F, &F));
+    fn for_each(&self, f: &mut dyn FnMut(&F));
+
+    fn box_clone(&self) -> Box<dyn Layer<F>>;
+    /// Used to downcast the layer back into its concrete type
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+fn downcast<L: 'static>(state: Box<dyn Any>) -> L {
+    *state
+        .downcast()
+        .expect("training state should come from the same layer")
+}
+
+fn same_layer<F: 'static, L: 'static>(layer: &dyn Layer<F>) -> &L {
+    layer
+        .as_any()
+        .downcast_ref()
+        .expect("layers should have the same type")
+}
+
+impl<F, L> Layer<F> for L
+where
+    L: GraphExecTrain<ArrayD<F>, Output = ArrayD<F>>
+        + Mappable<F>
+        + Modal
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+    L::State: 'static,
+    F: Clone + 'static,
+{
+    fn exec(&self, input: ArrayD<F>) -> ArrayD<F> {
+        GraphExec::exec(self, input)
+    }
+    fn try_exec(&self, input: ArrayD<F>) -> Result<ArrayD<F>> {
+        GraphExec::try_exec(self, input)
+    }
+    fn forward(&self, input: ArrayD<F>) -> (Box<dyn Any>, ArrayD<F>) {
+        let (state, output) = GraphExecTrain::forward(self, input);
+        (Box::new(state), output)
+    }
+    fn back(&self, state: Box<dyn Any>, d_output: ArrayD<F>) -> (ArrayD<F>, Box<dyn Layer<F>>) {
+        let (d_input, grads) = GraphExecTrain::back(self, downcast(state), d_output);
+        (d_input, Box::new(grads))
+    }
+    fn back_into(
+        &self,
+        state: Box<dyn Any>,
+        d_output: ArrayD<F>,
+        grads: &mut dyn Layer<F>,
+    ) -> ArrayD<F> {
+        let grads = grads
+            .as_any_mut()
+            .downcast_mut()
+            .expect("layers should have the same type");
+        GraphExecTrain::back_into(self, downcast(state), d_output, grads)
+    }
+    fn set_mode(&mut self, mode: Mode) {
+        Modal::set_mode(self, mode);
+    }
+
+    fn map(&self, f: &mut dyn FnMut(&F) -> F) -> Box<dyn Layer<F>> {
+        Box::new(Mappable::map(self, f))
+    }
+    fn map_mut(&mut self, f: &mut dyn FnMut(&mut F)) {
+        Mappable::map_mut(self, f);
+    }
+    fn map_mut_with(&mut self, rhs: &dyn Layer<F>, f: &mut dyn FnMut(&mut F, &F)) {
+        Mappable::map_mut_with(self, same_layer(rhs), f);
+    }
+    fn map_weights_mut_with(&mut self, rhs: &dyn Layer<F>, f: &mut dyn FnMut(&mut F, &F)) {
+        Mappable::map_weights_mut_with(self, same_layer(rhs), f);
+    }
+    fn for_each(&self, f: &mut dyn FnMut(&F)) {
+        Mappable::for_each(self, f);
+    }
+
+    fn box_clone(&self) -> Box<dyn Layer<F>> {
+        Box::new(self.clone())
+    }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A chain of boxed layers, each feeding its output into the next.
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, dense::Dense, initialisers::Xavier, sequential::Sequential, Graph,
+///     GraphExec,
+/// };
+/// use ndarray::Array2;
+///
+/// let mut network = Sequential::new();
+/// network.push(Dense::output_size(8).with_initialiser(Xavier).with_activation(Relu).input_shape(4));
+/// network.push(Dense::output_size(2).with_initialiser(Xavier).input_shape(8));
+///
+/// let output = network.exec(Array2::<f64>::zeros((3, 4)));
+/// assert_eq!(output.dim(), (3, 2));
+/// ```
+pub struct Sequential<F> {
+    layers: Vec<Box<dyn Layer<F>>>,
+}
+
+impl<F> Sequential<F> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { layers: Vec::new() }
+    }
+
+    /// Adds a layer to the end of the network
+    ///
+    /// # Panics
+    /// If the layer's input size doesn't match the output size of the last layer.
+    /// Only layers that check the size of their inputs, such as dense layers, are compared
+    pub fn push(&mut self, layer: impl Layer<F> + 'static)
+    where
+        F: Clone + Zero,
+    {
+        self.insert(self.len(), layer);
+    }
+
+    /// Inserts a layer at position `index`, shifting all the layers after it along
+    ///
+    /// # Panics
+    /// If `index > len`, or if the layer's sizes don't match the layers either side of it,
+    /// as in [`push`](Self::push)
+    pub fn insert(&mut self, index: usize, layer: impl Layer<F> + 'static)
+    where
+        F: Clone + Zero,
+    {
+        let layer_sizes = sizes(&layer);
+        let before = index.checked_sub(1).and_then(|i| self.get(i));
+        if let (Some((inputs, _)), Some((_, outputs))) = (layer_sizes, before.and_then(sizes)) {
+            assert_eq!(
+                inputs, outputs,
+                "layer {index} takes inputs of size {inputs}, but the layer before it outputs {outputs}"
+            );
+        }
+        let after = self.get(index);
+        if let (Some((_, outputs)), Some((inputs, _))) = (layer_sizes, after.and_then(sizes)) {
+            assert_eq!(
+                outputs, inputs,
+                "layer {index} outputs {outputs}, but the layer after it takes inputs of size {inputs}"
+            );
+        }
+        self.layers.insert(index, Box::new(layer));
+    }
+
+    /// Removes the layer at position `index`
+    pub fn remove(&mut self, index: usize) -> Box<dyn Layer<F>> {
+        self.layers.remove(index)
+    }
+
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&dyn Layer<F>> {
+        self.layers.get(index).map(AsRef::as_ref)
+    }
+
+    #[must_use]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut (dyn Layer<F> + 'static)> {
+        self.layers.get_mut(index).map(AsMut::as_mut)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+}
+
+impl<F> Default for Sequential<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> Clone for Sequential<F> {
+    fn clone(&self) -> Self {
+        Self {
+            layers: self.layers.iter().map(|layer| layer.box_clone()).collect(),
+        }
+    }
+}
+
+impl<F> std::fmt::Debug for Sequential<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sequential")
+            .field("layers", &self.layers.len())
+            .finish()
+    }
+}
+
+/// The input and output sizes of a layer, found by running an empty batch through it.
+/// Only layers that check the size of their inputs report the size they expect
+fn sizes<F: Clone + Zero>(layer: &dyn Layer<F>) -> Option<(usize, usize)> {
+    let Err(Error::InputShape {
+        input_size: inputs, ..
+    }) = layer.try_exec(ArrayD::zeros(IxDyn(&[0, 0])))
+    else {
+        return None;
+    };
+    let output = layer.try_exec(ArrayD::zeros(IxDyn(&[0, inputs]))).ok()?;
+    Some((inputs, *output.shape().last()?))
+}
+
+/// Converts the output of the last layer back into the dimension of the input
+fn into_dim<F, D: Dimension>(output: ArrayD<F>) -> Array<F, D> {
+    output
+        .into_dimensionality()
+        .expect("layers should keep the number of dimensions of their input")
+}
+
+/// Runs on arrays of any dimension. Each layer is given the input as an [`ArrayD`],
+/// and the output is converted back into the input's dimension
+impl<F, D: Dimension> GraphExec<Array<F, D>> for Sequential<F> {
+    type Output = Array<F, D>;
+    fn exec(&self, input: Array<F, D>) -> Self::Output {
+        let output = self
+            .layers
+            .iter()
+            .fold(input.into_dyn(), |input, layer| layer.exec(input));
+        into_dim(output)
+    }
+    fn try_exec(&self, input: Array<F, D>) -> Result<Self::Output> {
+        let output = self
+            .layers
+            .iter()
+            .try_fold(input.into_dyn(), |input, layer| layer.try_exec(input))?;
+        Ok(into_dim(output))
+    }
+}
+
+impl<F, D: Dimension> GraphExecTrain<Array<F, D>> for Sequential<F> {
+    type State = Vec<Box<dyn Any>>;
+    fn forward(&self, input: Array<F, D>) -> (Self::State, Self::Output) {
+        let mut states = Vec::with_capacity(self.layers.len());
+        let mut input = input.into_dyn();
+        for layer in &self.layers {
+            let (state, output) = layer.forward(input);
+            states.push(state);
+            input = output;
+        }
+        (states, into_dim(input))
+    }
+
+    fn back(&self, states: Self::State, d_output: Self::Output) -> (Array<F, D>, Self) {
+        let mut layers = Vec::with_capacity(self.layers.len());
+        let mut d_output = d_output.into_dyn();
+        for (layer, state) in self.layers.iter().zip(states).rev() {
+            let (d_input, grads) = layer.back(state, d_output);
+            layers.push(grads);
+            d_output = d_input;
+        }
+        layers.reverse();
+        (into_dim(d_output), Self { layers })
+    }
+
+    fn back_into(
+        &self,
+        states: Self::State,
+        d_output: Self::Output,
+        grads: &mut Self,
+    ) -> Array<F, D> {
+        let mut d_output = d_output.into_dyn();
+        let layers = self.layers.iter().zip(&mut grads.layers);
+        for ((layer, grads), state) in layers.zip(states).rev() {
+            d_output = layer.back_into(state, d_output, grads.as_mut());
+        }
+        into_dim(d_output)
+    }
+}
+
+impl<F> Modal for Sequential<F> {
+    fn set_mode(&mut self, mode: Mode) {
+        for layer in &mut self.layers {
+            layer.set_mode(mode);
+        }
+    }
+}
+
+impl<T> Mappable<T> for Sequential<T> {
+    fn map<F: FnMut(&T) -> T>(&self, mut f: F) -> Self {
+        Self {
+            layers: self.layers.iter().map(|layer| layer.map(&mut f)).collect(),
+        }
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, mut f: F) {
+        for layer in &mut self.layers {
+            layer.map_mut(&mut f);
+        }
+    }
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, mut f: F) {
+        assert_eq!(
+            self.len(),
+            rhs.len(),
+            "networks should have the same layers"
+        );
+        for (layer, rhs) in self.layers.iter_mut().zip(&rhs.layers) {
+            layer.map_mut_with(rhs.as_ref(), &mut f);
+        }
+    }
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, mut f: F) {
+        assert_eq!(
+            self.len(),
+            rhs.len(),
+            "networks should have the same layers"
+        );
+        for (layer, rhs) in self.layers.iter_mut().zip(&rhs.layers) {
+            layer.map_weights_mut_with(rhs.as_ref(), &mut f);
+        }
+    }
+    fn for_each<F: FnMut(&T)>(&self, mut f: F)
+    where
+        T: Clone,
+    {
+        for layer in &self.layers {
+            layer.for_each(&mut f);
+        }
+    }
+}
+
+/// The shape of a network is a copy of it, since the layers can't be known ahead of time
+impl<F> Shaped<F> for Sequential<F>
+where
+    F: Clone + Zero + One,
+{
+    type Shape = Self;
+    fn shape(&self) -> Self::Shape {
+        self.clone()
+    }
+    fn zero(shape: Self::Shape) -> Self {
+        Mappable::map(&shape, |_| F::zero())
+    }
+    fn one(shape: Self::Shape) -> Self {
+        Mappable::map(&shape, |_| F::one())
+    }
+    fn iter(shape: Self::Shape, mut i: impl Iterator<Item = F>) -> Self {
+        Mappable::map(&shape, |_| i.next().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array3, ArrayD};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::Sequential;
+    use crate::{
+        activation::{relu::Relu, Linear},
+        data,
+        dense::{Dense, DenseState},
+        initialisers::Xavier,
+        net,
+        optimise::sgd::SGD,
+        train::Train,
+        Graph, GraphExec, Mappable,
+    };
+
+    #[test]
+    fn test_sequential() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (hidden, output) = net![
+            Dense::output_size(8)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(1).with_initialiser(Xavier)
+        ]
+        .init_with_random(&mut rng, 2);
+
+        let mut network = Sequential::new();
+        network.push(output.clone());
+        network.insert(0, hidden.clone());
+        let extra = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 1);
+        network.push(extra);
+        assert!(network.remove(2).as_any().is::<DenseState<f64>>());
+        assert_eq!(network.len(), 2);
+        assert_eq!(network.num_params(), 3 * 8 + 9);
+
+        let (inputs, targets) = data::linear_samples(&mut rng, 64);
+        let expected = (hidden.clone(), output.clone()).exec(inputs.clone());
+        assert_eq!(network.exec(inputs.clone()), expected);
+
+        // layers are boxed over arrays of any dimension
+        let batches = inputs.clone().into_shape((8, 8, 2)).unwrap();
+        let outputs: Array3<f64> = network.exec(batches);
+        assert_eq!(outputs.into_shape((64, 1)).unwrap(), expected);
+        let layer: Box<dyn super::Layer<f64>> = network.get(1).unwrap().box_clone();
+        assert_eq!(layer.exec(ArrayD::zeros(vec![3, 8])).shape(), [3, 1]);
+
+        // the boxed layers train just like the tuple of the same layers
+        let mut trainer = Train::builder(network).optimiser(SGD::new(0.005)).build();
+        let mut tuple = Train::builder((hidden.clone(), output))
+            .optimiser(SGD::new(0.005))
+            .build();
+        for _ in 0..10 {
+            let cost = trainer.train(inputs.clone(), targets.clone());
+            let expected = tuple.train(inputs.clone(), targets.clone());
+            assert!((cost - expected).abs() < 1e-12, "{} {}", cost, expected);
+        }
+
+        let layer: &Linear<DenseState<f64>, Relu> = trainer
+            .graph
+            .get(0)
+            .unwrap()
+            .as_any()
+            .downcast_ref()
+            .unwrap();
+        assert_ne!(layer.graph.w, hidden.graph.w);
+        assert_eq!(layer.graph.w, tuple.graph.0.graph.w);
+    }
+
+    #[test]
+    #[should_panic(expected = "layer 1 takes inputs of size 3, but the layer before it outputs 8")]
+    fn test_push_checks_sizes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut network = Sequential::new();
+        network.push(Graph::<f64, _>::init_with_random(
+            Dense::output_size(8).with_initialiser(Xavier),
+            &mut rng,
+            2,
+        ));
+        network.push(Graph::<f64, _>::init_with_random(
+            Dense::output_size(1).with_initialiser(Xavier),
+            &mut rng,
+            3,
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "layer 0 outputs 4, but the layer after it takes inputs of size 2")]
+    fn test_insert_checks_sizes() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut network = Sequential::new();
+        network.push(Graph::<f64, _>::init_with_random(
+            Dense::output_size(1).with_initialiser(Xavier),
+            &mut rng,
+            2,
+        ));
+        network.insert(
+            0,
+            Graph::<f64, _>::init_with_random(
+                Dense::output_size(4)
+                    .with_initialiser(Xavier)
+                    .with_activation(Relu),
+                &mut rng,
+                3,
+            ),
+        );
+    }
+}
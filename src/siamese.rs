@@ -0,0 +1,243 @@
+//! Metric learning, training one network to embed inputs so that similar inputs end up
+//! close together.
+//!
+//! Each training step runs the same graph on two or three batches of inputs, one for
+//! each side of the pairs or triplets. The losses compare the embeddings, and the
+//! gradients from every batch are summed, since they all share the same weights.
+//!
+//! Like [`Cost::diff`](crate::cost::Cost::diff), the gradients the losses return are
+//! per sample, and aren't divided by the batch size
+use ndarray::{Array1, Array2, ArrayBase, Data, Ix1, Zip};
+use num_traits::Float;
+
+use crate::{
+    optimise::Optimiser,
+    train::{GraphExecTrain, Modal, Mode, Train},
+    Mappable,
+};
+
+/// Pulls similar pairs together, and pushes dissimilar pairs at least `margin` apart
+///
+/// For embeddings `a` and `b` at a distance `d`, the loss is `d²` for similar pairs
+/// and `max(0, margin - d)²` for dissimilar ones
+#[derive(Debug, Copy, Clone)]
+pub struct Contrastive<F> {
+    pub margin: F,
+}
+
+impl<F: Float> Contrastive<F> {
+    pub const fn new(margin: F) -> Self {
+        Self { margin }
+    }
+
+    /// The mean loss over every pair, and its gradients with respect to both embeddings.
+    /// `similar` is one for pairs that should be close together and zero otherwise
+    #[must_use]
+    pub fn loss(
+        &self,
+        a: &Array2<F>,
+        b: &Array2<F>,
+        similar: &Array1<F>,
+    ) -> (F, Array2<F>, Array2<F>) {
+        let two = F::one() + F::one();
+        let mut da = a - b;
+        let mut total = F::zero();
+        for (mut diff, &y) in da.outer_iter_mut().zip(similar) {
+            let d = squared_norm(&diff).sqrt();
+            let gap = (self.margin - d).max(F::zero());
+            total = total + y * d * d + (F::one() - y) * gap * gap;
+
+            // the gradient of `d` is `diff / d`, which points nowhere if the embeddings are equal
+            let push = if d > F::zero() { gap / d } else { F::zero() };
+            let scale = two * (y - (F::one() - y) * push);
+            diff.mapv_inplace(|x| x * scale);
+        }
+        let db = da.mapv(|x| -x);
+        (total / batch_size(a), da, db)
+    }
+}
+
+/// Pulls each anchor closer to its positive than to its negative, by at least `margin`
+///
+/// For embeddings `a`, `p` and `n`, the loss is `max(0, |a - p|² - |a - n|² + margin)`
+#[derive(Debug, Copy, Clone)]
+pub struct Triplet<F> {
+    pub margin: F,
+}
+
+impl<F: Float> Triplet<F> {
+    pub const fn new(margin: F) -> Self {
+        Self { margin }
+    }
+
+    /// The mean loss over every triplet, and its gradients with respect to the anchor,
+    /// positive and negative embeddings
+    #[must_use]
+    pub fn loss(
+        &self,
+        anchor: &Array2<F>,
+        positive: &Array2<F>,
+        negative: &Array2<F>,
+    ) -> (F, [Array2<F>; 3]) {
+        let two = F::one() + F::one();
+        let mut grads = [
+            Array2::zeros(anchor.raw_dim()),
+            Array2::zeros(anchor.raw_dim()),
+            Array2::zeros(anchor.raw_dim()),
+        ];
+        let [da, dp, dn] = &mut grads;
+        let mut total = F::zero();
+        let rows = Zip::from(anchor.rows())
+            .and(positive.rows())
+            .and(negative.rows())
+            .and(da.rows_mut())
+            .and(dp.rows_mut())
+            .and(dn.rows_mut());
+        rows.for_each(|a, p, n, mut da, mut dp, mut dn| {
+            let to_positive = &a - &p;
+            let to_negative = &a - &n;
+            let loss = squared_norm(&to_positive) - squared_norm(&to_negative) + self.margin;
+            if loss > F::zero() {
+                total = total + loss;
+                da.assign(&(&n - &p).mapv(|x| x * two));
+                dp.assign(&to_positive.mapv(|x| -x * two));
+                dn.assign(&to_negative.mapv(|x| x * two));
+            }
+        });
+        (total / batch_size(anchor), grads)
+    }
+}
+
+fn squared_norm<F: Float, S: Data<Elem = F>>(x: &ArrayBase<S, Ix1>) -> F {
+    x.fold(F::zero(), |sum, &x| sum + x * x)
+}
+
+fn batch_size<F: Float>(a: &Array2<F>) -> F {
+    F::from(a.nrows().max(1)).unwrap()
+}
+
+impl<F, C, O, G> Train<F, C, O, G>
+where
+    O: Optimiser<G>,
+    G: GraphExecTrain<Array2<F>, Output = Array2<F>> + Mappable<F> + Modal,
+    F: Float,
+{
+    /// Trains the graph to embed the pairs `(a[i], b[i])` close together when
+    /// `similar[i]` is one, and far apart when it's zero. Returns the loss
+    pub fn train_pairs(
+        &mut self,
+        a: Array2<F>,
+        b: Array2<F>,
+        similar: &Array1<F>,
+        loss: &Contrastive<F>,
+    ) -> F {
+        self.train_shared(vec![a, b], |outputs| {
+            let (cost, da, db) = loss.loss(&outputs[0], &outputs[1], similar);
+            (cost, vec![da, db])
+        })
+    }
+
+    /// Trains the graph to embed each anchor closer to its positive than its negative.
+    /// Returns the loss
+    pub fn train_triplets(
+        &mut self,
+        anchor: Array2<F>,
+        positive: Array2<F>,
+        negative: Array2<F>,
+        loss: &Triplet<F>,
+    ) -> F {
+        self.train_shared(vec![anchor, positive, negative], |outputs| {
+            let (cost, grads) = loss.loss(&outputs[0], &outputs[1], &outputs[2]);
+            (cost, Vec::from(grads))
+        })
+    }
+
+    /// Runs the graph on each batch of inputs, then backpropagates the loss's gradients
+    /// through each run and applies their sum
+    fn train_shared(
+        &mut self,
+        inputs: Vec<Array2<F>>,
+        loss: impl FnOnce(&[Array2<F>]) -> (F, Vec<Array2<F>>),
+    ) -> F {
+        span!(TRACE, "train_step");
+        self.graph.set_mode(Mode::Train);
+        let graph = &self.graph;
+        let (states, outputs): (Vec<_>, Vec<_>) =
+            inputs.into_iter().map(|input| graph.forward(input)).unzip();
+        let (cost, d_outputs) = loss(&outputs);
+
+        let mut grads: Option<G> = None;
+        for (state, d_output) in states.into_iter().zip(d_outputs) {
+            let (_, g) = self.graph.back(state, d_output);
+            match &mut grads {
+                Some(grads) => grads.map_mut_with(&g, |a, &b| *a = *a + b),
+                None => grads = Some(g),
+            }
+        }
+        grads.map_or(cost, |mut grads| self.apply_grads(&mut grads, cost))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{array, Array2, Axis};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{Contrastive, Triplet};
+    use crate::{
+        dense::Dense, initialisers::Xavier, optimise::SGD, train::Train, Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_losses() {
+        let a = array![[0.0_f64, 0.0], [0.0, 0.0]];
+        let b = array![[3.0, 4.0], [0.3, 0.4]];
+        let (loss, da, db) = Contrastive::new(1.0).loss(&a, &b, &array![1.0, 0.0]);
+        // 5² for the similar pair, and (1 - 0.5)² for the dissimilar one
+        assert!((loss - 25.25 / 2.0).abs() < 1e-12);
+        assert!((&da - &array![[-6.0, -8.0], [0.6, 0.8]])
+            .iter()
+            .all(|x| x.abs() < 1e-12));
+        assert_eq!(db, -da);
+
+        let anchor = array![[0.0_f64, 0.0], [0.0, 0.0]];
+        let positive = array![[1.0, 0.0], [1.0, 0.0]];
+        let negative = array![[0.0, 1.5], [0.0, 0.5]];
+        let (loss, [da, dp, dn]) = Triplet::new(1.0).loss(&anchor, &positive, &negative);
+        // only the second triplet is within the margin
+        assert!((loss - 1.75 / 2.0).abs() < 1e-12);
+        assert_eq!(da, array![[0.0, 0.0], [-2.0, 1.0]]);
+        assert_eq!(dp, array![[0.0, 0.0], [2.0, 0.0]]);
+        assert_eq!(dn, array![[0.0, 0.0], [0.0, -1.0]]);
+    }
+
+    #[test]
+    fn test_train_triplets() {
+        // points in two nearby clusters, one around (0.2, 1) and the other around (-0.2, 1)
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut cluster = |x: f64, n: usize| {
+            Array2::from_shape_fn((n, 2), |(_, j)| [x, 1.0][j] + rng.gen_range(-0.1..0.1))
+        };
+        let (anchor, positive, negative) = (cluster(0.2, 16), cluster(0.2, 16), cluster(-0.2, 16));
+
+        let graph = Graph::<f64, _>::init_with_random(
+            Dense::output_size(2).with_initialiser(Xavier),
+            &mut StdRng::seed_from_u64(1),
+            2,
+        );
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.01)).build();
+        let loss = Triplet::new(1.0);
+        for _ in 0..50 {
+            trainer.train_triplets(anchor.clone(), positive.clone(), negative.clone(), &loss);
+        }
+
+        // every anchor ends up closer to its positive than to its negative
+        let embed = |x: &Array2<f64>| trainer.graph.exec(x.view());
+        let (a, p, n) = (embed(&anchor), embed(&positive), embed(&negative));
+        let squared = |x: Array2<f64>| x.mapv(|d| d * d).sum_axis(Axis(1));
+        let (to_positive, to_negative) = (squared(&a - &p), squared(&a - &n));
+        for (&p, &n) in to_positive.iter().zip(&to_negative) {
+            assert!(p < n, "{} {}", to_positive, to_negative);
+        }
+    }
+}
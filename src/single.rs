@@ -0,0 +1,210 @@
+//! Low latency inference on one sample at a time.
+//!
+//! Batched [`exec`](crate::GraphExec::exec) is built for throughput. Every call allocates
+//! its outputs and goes through `ndarray`'s matrix products, which costs more than the
+//! arithmetic itself for a single small input. [`SingleSample`] copies the dense layers
+//! into flat buffers, and runs each layer and its activation in one loop, keeping the
+//! intermediate values in buffers on the stack
+use std::io;
+
+use ndarray::{Array1, ArrayView1};
+use num_traits::Float;
+
+use crate::{
+    array::input_shape, binary::invalid, embedded::DenseLayers, error::Result,
+    saved::SavedActivation, GraphExec,
+};
+
+/// A dense layer, with the weights stored as `[outputs][inputs]` so that each output
+/// is computed from one contiguous slice
+#[derive(Debug, Clone)]
+struct Layer<F> {
+    inputs: usize,
+    w: Vec<F>,
+    b: Vec<F>,
+    activation: Option<SavedActivation>,
+}
+
+impl<F: Float> Layer<F> {
+    fn exec(&self, input: &[F], output: &mut [F]) {
+        let rows = self.w.chunks_exact(self.inputs);
+        for ((o, w), &b) in output.iter_mut().zip(rows).zip(&self.b) {
+            let sum = w.iter().zip(input).fold(b, |sum, (&w, &x)| sum + w * x);
+            *o = self.activation.map_or(sum, |a| a.apply(sum));
+        }
+    }
+}
+
+/// A chain of dense layers, specialised for running on a single sample.
+/// The hidden layers can be at most `W` wide
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, dense::Dense, initialisers::Xavier, net, single::SingleSample,
+///     Graph, GraphExec,
+/// };
+/// use ndarray::Array1;
+///
+/// let network = net![
+///     Dense::output_size(16)
+///         .with_initialiser(Xavier)
+///         .with_activation(Relu),
+///     Dense::output_size(2).with_initialiser(Xavier)
+/// ];
+/// let state = Graph::<f32, _>::input_shape(network, 4);
+/// let model = SingleSample::<f32, 16>::new(&state).unwrap();
+///
+/// let input = [0.5, -1.0, 0.25, 2.0];
+/// let mut output = [0.0; 2];
+/// model.predict_into(&input, &mut output);
+///
+/// let expected = state.exec(Array1::from(input.to_vec()));
+/// assert!(output.iter().zip(&expected).all(|(a, b)| (a - b).abs() < 1e-6));
+/// ```
+#[derive(Debug, Clone)]
+pub struct SingleSample<F, const W: usize> {
+    layers: Vec<Layer<F>>,
+}
+
+impl<F: Float, const W: usize> SingleSample<F, W> {
+    /// Copies the layers of a state. Fails if a hidden layer is wider than `W`,
+    /// or uses an activation other than relu or sigmoid
+    pub fn new<T: DenseLayers<F>>(state: &T) -> io::Result<Self> {
+        let mut dense = vec![];
+        state.push_layers(&mut dense);
+        if dense.is_empty() {
+            return Err(invalid("single sample models need at least one layer"));
+        }
+        if dense.iter().any(|layer| layer.w.nrows() == 0) {
+            return Err(invalid("single sample models need at least one input"));
+        }
+        let hidden = &dense[..dense.len() - 1];
+        if hidden.iter().any(|layer| layer.w.ncols() > W) {
+            return Err(invalid("hidden layer is wider than the stack buffers"));
+        }
+
+        let layers = dense
+            .into_iter()
+            .map(|layer| {
+                Ok(Layer {
+                    inputs: layer.w.nrows(),
+                    w: layer.w.t().iter().copied().collect(),
+                    b: layer.b.to_vec(),
+                    activation: layer
+                        .activation
+                        .map(SavedActivation::from_name)
+                        .transpose()?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { layers })
+    }
+
+    /// The number of features the model expects in each input
+    #[must_use]
+    pub fn input_size(&self) -> usize {
+        self.layers[0].inputs
+    }
+
+    /// The number of values the model outputs
+    #[must_use]
+    pub fn output_size(&self) -> usize {
+        self.layers[self.layers.len() - 1].b.len()
+    }
+
+    /// Runs the model on `input`, writing the result to `output`. Doesn't allocate
+    ///
+    /// # Panics
+    /// If `input` or `output` are the wrong size
+    pub fn predict_into(&self, input: &[F], output: &mut [F]) {
+        assert_eq!(input.len(), self.input_size(), "wrong input size");
+        assert_eq!(output.len(), self.output_size(), "wrong output size");
+
+        let (last, hidden) = self.layers.split_last().unwrap();
+        let Some((first, hidden)) = hidden.split_first() else {
+            return last.exec(input, output);
+        };
+
+        let [x, y] = &mut [[F::zero(); W]; 2];
+        let (mut x, mut y) = (x, y);
+        first.exec(input, x);
+        for layer in hidden {
+            layer.exec(&x[..layer.inputs], y);
+            std::mem::swap(&mut x, &mut y);
+        }
+        last.exec(&x[..last.inputs], output);
+    }
+}
+
+impl<F: Float, const W: usize> GraphExec<ArrayView1<'_, F>> for SingleSample<F, W> {
+    type Output = Array1<F>;
+
+    fn exec(&self, input: ArrayView1<F>) -> Self::Output {
+        let mut output = Array1::zeros(self.output_size());
+        let output_slice = output.as_slice_mut().unwrap();
+        match input.as_slice() {
+            Some(input) => self.predict_into(input, output_slice),
+            None => self.predict_into(&input.to_vec(), output_slice),
+        }
+        output
+    }
+
+    fn try_exec(&self, input: ArrayView1<F>) -> Result<Self::Output> {
+        input_shape("Single sample model", input.shape(), self.input_size())?;
+        Ok(self.exec(input))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array1;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::SingleSample;
+    use crate::{
+        activation::{relu::Relu, sigmoid::Sigmoid},
+        dense::Dense,
+        initialisers::Xavier,
+        net, Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_single_sample() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let network = net![
+            Dense::output_size(8)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(5)
+                .with_initialiser(Xavier)
+                .with_activation(Sigmoid),
+            Dense::output_size(6)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(3).with_initialiser(Xavier)
+        ];
+        let state = Graph::<f64, _>::init_with_random(network, &mut rng, 4);
+        let model = SingleSample::<f64, 8>::new(&state).unwrap();
+        assert_eq!((model.input_size(), model.output_size()), (4, 3));
+
+        for _ in 0..5 {
+            let input = Array1::<f64>::from_shape_simple_fn(4, || rng.gen_range(-1.0..1.0));
+            let expected = state.exec(input.view());
+            let output = model.exec(input.view());
+            assert!(output
+                .iter()
+                .zip(&expected)
+                .all(|(a, b)| (a - b).abs() < 1e-12));
+        }
+        assert!(model.try_exec(Array1::zeros(3).view()).is_err());
+
+        assert!(SingleSample::<f64, 7>::new(&state).is_err());
+        let output_layer = &state.1 .1;
+        assert_eq!(
+            SingleSample::<f64, 0>::new(output_layer)
+                .unwrap()
+                .input_size(),
+            6
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Dense layers with sparse weights, for very wide inputs such as bag-of-words
+//! or one-hot encoded categorical features.
+//!
+//! Each input is only connected to some of the outputs. The connections are stored in
+//! compressed sparse row (CSR) format, and inputs that are zero are skipped entirely
+use std::{any::Any, marker::PhantomData, sync::Arc};
+
+use ndarray::{Array, Array1, Array2, ArrayBase, Axis, Data, Dimension, LinalgScalar};
+use num_traits::{Float, FromPrimitive, One, Zero};
+use rand::{distributions::Distribution, seq::index, Rng};
+
+use crate::{
+    activation::{Activation, Linear},
+    array::{check_input_shape, compact_front, input_shape},
+    derivative::DerivativeTesting,
+    dot::{Dot, DotWriter},
+    error::Result,
+    initialisers::Initialiser,
+    named::{GetLayer, Named},
+    stats::{LayerStats, Stats, UnitActivity},
+    train::{GraphExecTrain, Modal},
+    Graph, GraphExec, Mappable, Shaped,
+};
+
+/// Which outputs each input is connected to, in compressed sparse row format.
+///
+/// The connections of input `i` are `indices[indptr[i]..indptr[i + 1]]`,
+/// and their weights are stored in the same positions of [`SparseDenseState::w`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Csr {
+    pub outputs: usize,
+    pub indptr: Vec<usize>,
+    pub indices: Vec<usize>,
+}
+
+impl Csr {
+    /// The number of inputs. An empty `indptr` has none
+    #[must_use]
+    pub const fn inputs(&self) -> usize {
+        self.indptr.len().saturating_sub(1)
+    }
+
+    /// The number of connections
+    #[must_use]
+    pub const fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// The range of weights and output indices belonging to input `i`
+    fn row(&self, i: usize) -> std::ops::Range<usize> {
+        self.indptr[i]..self.indptr[i + 1]
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SparseDense<I> {
+    output_size: usize,
+    connections: usize,
+    initialiser: I,
+}
+
+pub struct SparseDenseSize<I> {
+    output_size: usize,
+    connections: usize,
+    initialiser: PhantomData<I>,
+}
+
+impl<I> SparseDense<I> {
+    /// Every input is connected to every output, unless
+    /// [`with_connections`](SparseDenseSize::with_connections) is used
+    #[must_use]
+    pub const fn output_size(output_size: usize) -> SparseDenseSize<I> {
+        SparseDenseSize {
+            output_size,
+            connections: output_size,
+            initialiser: PhantomData,
+        }
+    }
+
+    pub const fn with_activation<A: Activation>(self, a: A) -> Linear<Self, A> {
+        Linear::new(self, a)
+    }
+
+    pub fn named(self, name: impl Into<Arc<str>>) -> Named<Self> {
+        Named::new(name, self)
+    }
+}
+
+impl<I> SparseDenseSize<I> {
+    /// Connects each input to this many randomly chosen outputs
+    #[must_use]
+    pub const fn with_connections(mut self, connections: usize) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    pub const fn with_initialiser(self, initialiser: I) -> SparseDense<I> {
+        SparseDense {
+            output_size: self.output_size,
+            connections: self.connections,
+            initialiser,
+        }
+    }
+}
+
+/// The weights are drawn from the initialiser, and the biases start at zero
+impl<I, F> Graph<F, usize> for SparseDense<I>
+where
+    I: Initialiser<F, (usize, usize)>,
+    F: Zero + Clone,
+{
+    type State = SparseDenseState<F>;
+    type OutputShape = usize;
+
+    fn get_output_shape(&self) -> usize {
+        self.output_size
+    }
+
+    fn init_with_random(self, rng: &mut impl Rng, input_size: usize) -> Self::State {
+        let d = self
+            .initialiser
+            .into_distribution((input_size, self.output_size));
+
+        let connections = self.connections.min(self.output_size);
+        let mut indptr = vec![0];
+        let mut indices = Vec::with_capacity(input_size * connections);
+        for _ in 0..input_size {
+            let mut row = index::sample(rng, self.output_size, connections).into_vec();
+            row.sort_unstable();
+            indices.extend(row);
+            indptr.push(indices.len());
+        }
+
+        let w = Array1::from_shape_simple_fn(indices.len(), || d.sample(rng));
+        let b = Array1::zeros(self.output_size);
+        let pattern = Arc::new(Csr {
+            outputs: self.output_size,
+            indptr,
+            indices,
+        });
+
+        SparseDenseState { pattern, w, b }
+    }
+
+    fn num_params(&self, input_size: usize) -> usize {
+        (input_size * self.connections.min(self.output_size)) + self.output_size
+    }
+}
+
+/// The state of a [`SparseDense`] layer. Gradients share the same connections,
+/// so only the weights of existing connections are ever updated
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SparseDenseState<F> {
+    pub pattern: Arc<Csr>,
+    pub w: Array1<F>,
+    pub b: Array1<F>,
+}
+
+impl<F> SparseDenseState<F> {
+    /// Converts the weights to a dense `(inputs, outputs)` matrix, with zeros where
+    /// there are no connections
+    #[must_use]
+    pub fn to_dense(&self) -> Array2<F>
+    where
+        F: Clone + Zero,
+    {
+        let mut dense = Array2::zeros((self.pattern.inputs(), self.pattern.outputs));
+        for i in 0..self.pattern.inputs() {
+            for k in self.pattern.row(i) {
+                dense[(i, self.pattern.indices[k])] = self.w[k].clone();
+            }
+        }
+        dense
+    }
+}
+
+impl<F, S, D> GraphExec<ArrayBase<S, D>> for SparseDenseState<F>
+where
+    F: LinalgScalar,
+    S: Data<Elem = F>,
+    D: Dimension,
+{
+    type Output = Array<F, D>;
+
+    fn exec(&self, input: ArrayBase<S, D>) -> Self::Output {
+        check_input_shape("SparseDense layer", input.shape(), self.pattern.inputs());
+        let mut dim = input.raw_dim();
+        dim.set_last_elem(self.pattern.outputs);
+        let input = compact_front(&input);
+
+        let shape = (input.nrows(), self.pattern.outputs);
+        let mut output = self.b.broadcast(shape).unwrap().to_owned();
+        for (x, mut o) in input.rows().into_iter().zip(output.rows_mut()) {
+            for (i, &xi) in x.iter().enumerate() {
+                if xi.is_zero() {
+                    continue;
+                }
+                for k in self.pattern.row(i) {
+                    let j = self.pattern.indices[k];
+                    o[j] = o[j] + xi * self.w[k];
+                }
+            }
+        }
+        output.into_shape(dim).unwrap()
+    }
+
+    fn try_exec(&self, input: ArrayBase<S, D>) -> Result<Self::Output> {
+        input_shape("SparseDense layer", input.shape(), self.pattern.inputs())?;
+        Ok(self.exec(input))
+    }
+}
+
+impl<F, D> GraphExecTrain<Array<F, D>> for SparseDenseState<F>
+where
+    F: LinalgScalar + FromPrimitive,
+    D: Dimension,
+{
+    type State = Array<F, D>;
+    fn forward(&self, input: Array<F, D>) -> (Self::State, Self::Output) {
+        let output = self.exec(input.view());
+        (input, output)
+    }
+
+    fn back(&self, input: Self::State, d_output: Self::Output) -> (Array<F, D>, Self) {
+        let mut grads = Self {
+            pattern: Arc::clone(&self.pattern),
+            w: Array1::zeros(self.w.len()),
+            b: Array1::zeros(self.b.len()),
+        };
+        let di = self.back_into(input, d_output, &mut grads);
+        (di, grads)
+    }
+
+    fn back_into(
+        &self,
+        input: Self::State,
+        d_output: Self::Output,
+        grads: &mut Self,
+    ) -> Array<F, D> {
+        let dim = input.raw_dim();
+        let input = compact_front(&input);
+        let d_output = compact_front(&d_output);
+
+        let mut di = Array2::zeros(input.raw_dim());
+        grads.w.fill(F::zero());
+        let rows = input.rows().into_iter().zip(d_output.rows());
+        for ((x, d), mut dx) in rows.zip(di.rows_mut()) {
+            for (i, (&xi, dxi)) in x.iter().zip(&mut dx).enumerate() {
+                for k in self.pattern.row(i) {
+                    let dj = d[self.pattern.indices[k]];
+                    *dxi = *dxi + self.w[k] * dj;
+                    // zero inputs don't contribute to the weight gradients
+                    if !xi.is_zero() {
+                        grads.w[k] = grads.w[k] + xi * dj;
+                    }
+                }
+            }
+        }
+        grads.b.assign(&d_output.sum_axis(Axis(0)));
+        di.into_shape(dim).unwrap()
+    }
+}
+
+impl<F> Modal for SparseDenseState<F> {}
+
+impl<T> Mappable<T> for SparseDenseState<T> {
+    // not redundant. just forces a capture without needing to clone
+    #![allow(clippy::redundant_closure)]
+
+    fn map<F: FnMut(&T) -> T>(&self, mut f: F) -> Self {
+        let w = self.w.map(|a| f(a));
+        let b = self.b.map(f);
+        Self {
+            pattern: Arc::clone(&self.pattern),
+            w,
+            b,
+        }
+    }
+    fn map_mut<F: FnMut(&mut T)>(&mut self, mut f: F) {
+        self.w.map_mut(|a| f(a));
+        self.b.map_mut(f);
+    }
+    fn map_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, mut f: F) {
+        self.w.zip_mut_with(&rhs.w, |a, b| f(a, b));
+        self.b.zip_mut_with(&rhs.b, f);
+    }
+    fn map_weights_mut_with<F: FnMut(&mut T, &T)>(&mut self, rhs: &Self, f: F) {
+        self.w.zip_mut_with(&rhs.w, f);
+    }
+    fn for_each<F: FnMut(&T)>(&self, mut f: F)
+    where
+        T: Clone,
+    {
+        self.w.for_each(|a| f(a));
+        self.b.for_each(f);
+    }
+}
+
+impl<T> Shaped<T> for SparseDenseState<T>
+where
+    T: Clone + Zero + One,
+{
+    type Shape = Arc<Csr>;
+    fn shape(&self) -> Self::Shape {
+        Arc::clone(&self.pattern)
+    }
+    fn zero(pattern: Self::Shape) -> Self {
+        Self {
+            w: Array1::zeros(pattern.nnz()),
+            b: Array1::zeros(pattern.outputs),
+            pattern,
+        }
+    }
+    fn one(pattern: Self::Shape) -> Self {
+        Self {
+            w: Array1::ones(pattern.nnz()),
+            b: Array1::ones(pattern.outputs),
+            pattern,
+        }
+    }
+    fn iter(pattern: Self::Shape, mut i: impl Iterator<Item = T>) -> Self {
+        Self {
+            w: Array1::from_shape_fn(pattern.nnz(), |_| i.next().unwrap()),
+            b: Array1::from_shape_fn(pattern.outputs, |_| i.next().unwrap()),
+            pattern,
+        }
+    }
+}
+
+impl<F: Copy> DerivativeTesting<F> for SparseDenseState<F> {
+    fn len(&self) -> usize {
+        self.w.len() + self.b.len()
+    }
+    fn get(&self, i: usize) -> F {
+        if i < self.w.len() {
+            self.w[i]
+        } else {
+            self.b[i - self.w.len()]
+        }
+    }
+    fn set(&mut self, i: usize, f: F) {
+        if i < self.w.len() {
+            self.w[i] = f;
+        } else {
+            self.b[i - self.w.len()] = f;
+        }
+    }
+}
+
+impl<I> Dot for SparseDense<I> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let label = format!(
+            "SparseDense({}, {} connections)",
+            self.output_size, self.connections
+        );
+        vec![dot.layer(&label, inputs)]
+    }
+}
+
+impl<F> Dot for SparseDenseState<F> {
+    fn write_dot(&self, dot: &mut DotWriter, inputs: &[usize]) -> Vec<usize> {
+        let label = format!(
+            "SparseDense({} → {}, {} connections)",
+            self.pattern.inputs(),
+            self.pattern.outputs,
+            self.pattern.nnz()
+        );
+        vec![dot.layer(&label, inputs)]
+    }
+}
+
+impl<F> GetLayer for SparseDenseState<F> {
+    fn get_layer(&self, _name: &str) -> Option<&dyn Any> {
+        None
+    }
+    fn get_layer_mut(&mut self, _name: &str) -> Option<&mut dyn Any> {
+        None
+    }
+}
+
+impl<F: Float + FromPrimitive> LayerStats<F> for SparseDenseState<F> {
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        stats.push(Stats::of(self));
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        let mut layer = UnitActivity::new(self.b.len());
+        for (&unit, grad) in self.pattern.indices.iter().zip(&self.w) {
+            layer.record(unit, grad);
+        }
+        for (unit, grad) in self.b.iter().enumerate() {
+            layer.record(unit, grad);
+        }
+        activity.push(layer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::{Array1, Array2};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{Csr, SparseDense};
+    use crate::{
+        activation::sigmoid::Sigmoid, cost::mse::MSE, derivative::check_grads,
+        initialisers::Xavier, Graph, GraphExec,
+    };
+
+    #[test]
+    fn test_empty_csr() {
+        let csr = Csr {
+            outputs: 3,
+            indptr: vec![],
+            indices: vec![],
+        };
+        assert_eq!((csr.inputs(), csr.nnz()), (0, 0));
+    }
+
+    #[test]
+    fn test_sparse_dense() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut layer = SparseDense::output_size(4)
+            .with_connections(2)
+            .with_initialiser(Xavier)
+            .with_activation(Sigmoid)
+            .init_with_random(&mut rng, 10);
+        assert_eq!(layer.graph.pattern.nnz(), 20);
+        assert_eq!(layer.graph.b, Array1::<f64>::zeros(4));
+        // give the bias values so the comparisons below cover it
+        layer.graph.b = Array1::from_shape_simple_fn(4, || rng.gen());
+
+        // mostly zero inputs, like a bag of words
+        let input = Array2::<f64>::from_shape_simple_fn((3, 10), || {
+            if rng.gen_bool(0.3) {
+                rng.gen()
+            } else {
+                0.0
+            }
+        });
+        let dense = input.dot(&layer.graph.to_dense()) + &layer.graph.b;
+        let error = (layer.graph.exec(input.view()) - dense).mapv(f64::abs);
+        assert!(error.iter().all(|&e| e < 1e-12));
+
+        let input = input.row(0).to_owned();
+        let expected = Array1::<f64>::from_shape_simple_fn(4, || rng.gen());
+        let error = check_grads(&mut layer, &MSE, 1e-6, &input, &expected);
+        assert!(error < 1e-5, "max relative error {}", error);
+    }
+}
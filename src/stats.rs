@@ -0,0 +1,565 @@
+use std::{cell::RefCell, fmt, marker::PhantomData, rc::Rc};
+
+use num_traits::{Float, FromPrimitive, Zero};
+
+use crate::{
+    activation::Linear,
+    callback::{Callback, TrainEvent},
+    dense::DenseState,
+    tensors::{ParamPath, Tensors},
+    Mappable,
+};
+
+/// Summary statistics over a set of parameters (or gradients)
+#[derive(Debug, Copy, Clone)]
+pub struct Stats<F> {
+    pub count: usize,
+    pub min: F,
+    pub max: F,
+    pub mean: F,
+    /// The standard deviation of the values
+    pub std: F,
+    /// The L2 norm of all the values
+    pub l2_norm: F,
+}
+
+impl<F: Float + FromPrimitive> Stats<F> {
+    /// Calculates the statistics over every value in the graph
+    pub fn of<G: Mappable<F>>(graph: &G) -> Self {
+        Self::accumulate(|push| graph.for_each(|&x| push(x)))
+    }
+
+    /// Calculates the statistics over the values
+    pub fn of_values(values: impl IntoIterator<Item = F>) -> Self {
+        Self::accumulate(|push| values.into_iter().for_each(push))
+    }
+
+    /// Calculates the statistics over the values that `for_each` pushes
+    fn accumulate(for_each: impl FnOnce(&mut dyn FnMut(F))) -> Self {
+        let mut count = 0;
+        let mut min = F::infinity();
+        let mut max = F::neg_infinity();
+        let mut sum = F::zero();
+        let mut sum_sq = F::zero();
+        for_each(&mut |x| {
+            count += 1;
+            min = min.min(x);
+            max = max.max(x);
+            sum = sum + x;
+            sum_sq = sum_sq + x * x;
+        });
+        let n = F::from_usize(count).unwrap();
+        let mean = sum / n;
+        let variance = sum_sq / n - mean.powi(2);
+        Self {
+            count,
+            min,
+            max,
+            mean,
+            std: variance.max(F::zero()).sqrt(),
+            l2_norm: sum_sq.sqrt(),
+        }
+    }
+}
+
+impl<F: fmt::Display> fmt::Display for Stats<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let p = f.precision().unwrap_or(4);
+        write!(
+            f,
+            "min {:.p$} max {:.p$} mean {:.p$} std {:.p$}",
+            self.min, self.max, self.mean, self.std,
+        )
+    }
+}
+
+/// A table of the statistics of each parameter tensor in a graph, one per line.
+/// Printing this gives a quick health check of a network without printing every weight
+///
+/// ```
+/// use linear_networks::{dense::Dense, initialisers::Xavier, net, stats::Summary, Graph};
+///
+/// let network = net![
+///     Dense::output_size(16).with_initialiser(Xavier),
+///     Dense::output_size(2).with_initialiser(Xavier)
+/// ];
+/// let state = Graph::<f32, _>::input_shape(network, 4);
+/// println!("{}", Summary::new(&state));
+/// ```
+pub struct Summary<'a, F, G> {
+    graph: &'a G,
+    float: PhantomData<F>,
+}
+
+impl<'a, F, G: Tensors<F>> Summary<'a, F, G> {
+    pub const fn new(graph: &'a G) -> Self {
+        Self {
+            graph,
+            float: PhantomData,
+        }
+    }
+}
+
+impl<F, G> fmt::Display for Summary<'_, F, G>
+where
+    F: Float + FromPrimitive + fmt::Display,
+    G: Tensors<F>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self.graph.params();
+        let rows: Vec<_> = params
+            .iter()
+            .map(|(path, tensor)| (path.to_string(), format!("{:?}", tensor.shape())))
+            .collect();
+        let path_width = rows.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+        let shape_width = rows.iter().map(|(_, shape)| shape.len()).max().unwrap_or(0);
+
+        let p = f.precision().unwrap_or(4);
+        for ((path, shape), (_, tensor)) in rows.iter().zip(&params) {
+            let stats = Stats::of_values(tensor.iter().copied());
+            writeln!(f, "{path:path_width$}  {shape:shape_width$}  {stats:.p$}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<F, G> fmt::Debug for Summary<'_, F, G>
+where
+    Self: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+/// How many values fall in each of a number of equal width bins, between the smallest
+/// and the largest value.
+///
+/// Recording these over training shows how the distributions of the weights and
+/// gradients drift. Non-finite values are left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram<F> {
+    pub min: F,
+    pub max: F,
+    pub counts: Vec<usize>,
+}
+
+impl<F: Float + FromPrimitive> Histogram<F> {
+    /// Buckets every value in the graph
+    pub fn of<G: Mappable<F>>(graph: &G, bins: usize) -> Self {
+        let mut values = vec![];
+        graph.for_each(|&x| values.push(x));
+        Self::of_values(values, bins)
+    }
+
+    /// Buckets each parameter tensor of the graph separately. Can be called on a graph's
+    /// state, or on the gradients produced by training it
+    pub fn per_tensor<G: Tensors<F>>(graph: &G, bins: usize) -> Vec<(ParamPath, Self)> {
+        graph
+            .params()
+            .into_iter()
+            .map(|(path, tensor)| (path, Self::of_values(tensor.iter().copied(), bins)))
+            .collect()
+    }
+
+    /// Buckets the values
+    ///
+    /// # Panics
+    /// If `bins` is zero
+    pub fn of_values(values: impl IntoIterator<Item = F>, bins: usize) -> Self {
+        assert!(bins > 0, "a histogram needs at least one bin");
+        let values: Vec<F> = values.into_iter().filter(|x| x.is_finite()).collect();
+        let min = values.iter().copied().fold(F::infinity(), F::min);
+        let max = values.iter().copied().fold(F::neg_infinity(), F::max);
+
+        let mut counts = vec![0; bins];
+        let scale = F::from_usize(bins).unwrap() / (max - min);
+        for x in values {
+            // every value is in the first bin if they're all the same
+            let bin = ((x - min) * scale).to_usize().unwrap_or(0);
+            counts[bin.min(bins - 1)] += 1;
+        }
+        Self { min, max, counts }
+    }
+
+    /// The range of values in the `i`th bin
+    pub fn bin(&self, i: usize) -> (F, F) {
+        let width = (self.max - self.min) / F::from_usize(self.counts.len()).unwrap();
+        let start = self.min + width * F::from_usize(i).unwrap();
+        (start, start + width)
+    }
+}
+
+/// Draws the histogram as a line of bars, followed by the range of values
+impl<F: fmt::Display> fmt::Display for Histogram<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let highest = self.counts.iter().copied().max().unwrap_or(0).max(1);
+        for &count in &self.counts {
+            let bar = if count == 0 {
+                ' '
+            } else {
+                BARS[(count * BARS.len() - 1) / highest]
+            };
+            write!(f, "{bar}")?;
+        }
+        let p = f.precision().unwrap_or(4);
+        write!(f, " [{:.p$}, {:.p$}]", self.min, self.max)
+    }
+}
+
+/// How many of a layer's gradients are zero, and which of its units had any
+/// non-zero gradients at all.
+///
+/// A unit whose gradients stay zero for a whole epoch isn't learning, eg a
+/// [`Relu`](crate::activation::relu::Relu) unit whose input is negative for every sample.
+/// Lots of these suggest a poor initialiser, a learning rate that's too high,
+/// or an activation that saturates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnitActivity {
+    /// The number of gradients that were exactly zero
+    pub zeros: usize,
+    /// The total number of gradients
+    pub count: usize,
+    /// Whether each unit had any non-zero gradients
+    pub active: Vec<bool>,
+}
+
+impl UnitActivity {
+    /// A layer with the given number of units, before any gradients are recorded
+    #[must_use]
+    pub fn new(units: usize) -> Self {
+        Self {
+            zeros: 0,
+            count: 0,
+            active: vec![false; units],
+        }
+    }
+
+    /// Records a gradient of the given unit
+    pub fn record<F: Zero>(&mut self, unit: usize, grad: &F) {
+        self.count += 1;
+        if grad.is_zero() {
+            self.zeros += 1;
+        } else {
+            self.active[unit] = true;
+        }
+    }
+
+    /// Adds the gradients recorded by another step of the same layer
+    pub fn merge(&mut self, other: &Self) {
+        assert_eq!(
+            self.active.len(),
+            other.active.len(),
+            "layers should have the same number of units"
+        );
+        self.zeros += other.zeros;
+        self.count += other.count;
+        for (active, &other) in self.active.iter_mut().zip(&other.active) {
+            *active |= other;
+        }
+    }
+
+    /// The fraction of gradients that were zero
+    #[must_use]
+    pub fn zero_fraction<F: Float + FromPrimitive>(&self) -> F {
+        F::from_usize(self.zeros).unwrap() / F::from_usize(self.count.max(1)).unwrap()
+    }
+
+    /// The number of units that had no non-zero gradients
+    #[must_use]
+    pub fn dead_units(&self) -> usize {
+        self.active.iter().filter(|&&active| !active).count()
+    }
+}
+
+/// Per layer statistics, useful for diagnosing exploding or vanishing gradients.
+/// Can be called on a graph's state, or on the gradients produced by training it
+pub trait LayerStats<F> {
+    /// Pushes the statistics of each layer, in order
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>);
+
+    fn stats(&self) -> Vec<Stats<F>> {
+        let mut stats = vec![];
+        self.push_stats(&mut stats);
+        stats
+    }
+
+    /// Pushes the activity of each layer's gradients, in the same order as
+    /// [`push_stats`](Self::push_stats). Should be called on gradients.
+    /// Layers without units to report on push nothing, which is the default
+    fn push_activity(&self, _activity: &mut Vec<UnitActivity>) {}
+
+    fn activity(&self) -> Vec<UnitActivity> {
+        let mut activity = vec![];
+        self.push_activity(&mut activity);
+        activity
+    }
+}
+
+impl<F: Float + FromPrimitive> LayerStats<F> for DenseState<F> {
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        stats.push(Stats::of(self));
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        // the weights are `(inputs, outputs)`, so each column belongs to a unit
+        let mut layer = UnitActivity::new(self.b.len());
+        for ((_, unit), grad) in self.w.indexed_iter() {
+            layer.record(unit, grad);
+        }
+        for (unit, grad) in self.b.iter().enumerate() {
+            layer.record(unit, grad);
+        }
+        activity.push(layer);
+    }
+}
+
+impl<F, G: LayerStats<F>, L> LayerStats<F> for Linear<G, L> {
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        self.graph.push_stats(stats);
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        self.graph.push_activity(activity);
+    }
+}
+
+impl<F, T, U> LayerStats<F> for (T, U)
+where
+    T: LayerStats<F>,
+    U: LayerStats<F>,
+{
+    fn push_stats(&self, stats: &mut Vec<Stats<F>>) {
+        self.0.push_stats(stats);
+        self.1.push_stats(stats);
+    }
+    fn push_activity(&self, activity: &mut Vec<UnitActivity>) {
+        self.0.push_activity(activity);
+        self.1.push_activity(activity);
+    }
+}
+
+/// The gradient activity of every layer, recorded so far this epoch and
+/// in each finished epoch
+#[derive(Debug, Default)]
+struct ActivityLog {
+    epoch: Vec<UnitActivity>,
+    history: Vec<Vec<UnitActivity>>,
+}
+
+/// Records the [`UnitActivity`] of each layer over every epoch of training, to find
+/// dead units.
+///
+/// The gradients are recorded by the trainer's gradient hook, from [`hook`](Self::hook),
+/// and each epoch is finished off by adding this as a callback. Clones share the same record.
+///
+/// The record can't be shared between threads, so nothing is recorded by
+/// [`perform_epoch_averaged`](crate::train::Train::perform_epoch_averaged), whose worker
+/// threads train without the gradient hook
+///
+/// ```
+/// use linear_networks::{
+///     activation::relu::Relu, dense::Dense, initialisers::Xavier, net, stats::DeadUnits,
+///     train::Train, Graph,
+/// };
+///
+/// let graph = Graph::<f64, _>::input_shape(
+///     net![
+///         Dense::output_size(16)
+///             .with_initialiser(Xavier)
+///             .with_activation(Relu),
+///         Dense::output_size(2).with_initialiser(Xavier)
+///     ],
+///     4,
+/// );
+/// let dead = DeadUnits::new();
+/// let trainer: Train<f64, _, _, _> = Train::builder(graph)
+///     .on_grads(dead.hook())
+///     .callback(dead.clone())
+///     .build();
+/// // after training, `dead.history()` has the activity of each layer for every epoch
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DeadUnits {
+    log: Rc<RefCell<ActivityLog>>,
+}
+
+impl DeadUnits {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the activity of one training step's gradients
+    pub fn record<F, G: LayerStats<F>>(&self, grads: &G) {
+        let activity = grads.activity();
+        let epoch = &mut self.log.borrow_mut().epoch;
+        if epoch.is_empty() {
+            *epoch = activity;
+        } else {
+            for (layer, step) in epoch.iter_mut().zip(&activity) {
+                layer.merge(step);
+            }
+        }
+    }
+
+    /// A gradient hook that records every training step
+    pub fn hook<F, G: LayerStats<F>>(&self) -> impl FnMut(&G) + 'static {
+        let this = self.clone();
+        move |grads| this.record(grads)
+    }
+
+    /// The activity of each layer in every finished epoch
+    #[must_use]
+    pub fn history(&self) -> Vec<Vec<UnitActivity>> {
+        self.log.borrow().history.clone()
+    }
+}
+
+impl<F> Callback<F> for DeadUnits {
+    fn on_event(&mut self, event: &TrainEvent<F>) {
+        if let TrainEvent::EpochEnd { .. } = event {
+            let mut log = self.log.borrow_mut();
+            let epoch = std::mem::take(&mut log.epoch);
+            log.history.push(epoch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::{DeadUnits, Histogram, LayerStats, Stats, Summary, UnitActivity};
+    use crate::{
+        activation::{relu::Relu, Linear},
+        data::InMemoryDataset,
+        dense::DenseState,
+        train::{Regularisation, Train},
+    };
+
+    #[test]
+    fn test_layer_stats() {
+        let layer = DenseState {
+            w: array![[3.0, -4.0]],
+            b: array![0.0, 0.0],
+        };
+        let layers = (layer.clone(), layer);
+
+        let stats = layers.stats();
+        assert_eq!(stats.len(), 2);
+        for Stats {
+            count,
+            min,
+            max,
+            mean,
+            std,
+            l2_norm,
+        } in stats
+        {
+            assert_eq!(count, 4);
+            assert_eq!((min, max, mean, l2_norm), (-4.0, 3.0, -0.25, 5.0));
+            assert!((std - 6.1875_f64.sqrt()).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        let layer = DenseState {
+            w: array![[3.0, -4.0]],
+            b: array![0.0, 0.0],
+        };
+        let layers = (layer.clone(), layer);
+
+        let expected = "\
+0/weight  [2, 1]  min -4.00 max 3.00 mean -0.50 std 3.50
+0/bias    [2]     min 0.00 max 0.00 mean 0.00 std 0.00
+1/weight  [2, 1]  min -4.00 max 3.00 mean -0.50 std 3.50
+1/bias    [2]     min 0.00 max 0.00 mean 0.00 std 0.00
+";
+        assert_eq!(format!("{:.2}", Summary::new(&layers)), expected);
+    }
+
+    #[test]
+    fn test_histogram() {
+        let histogram = Histogram::of_values((0..10).map(f64::from).chain([f64::NAN]), 3);
+        assert_eq!(histogram.counts, [3, 3, 4]);
+        assert_eq!(histogram.bin(1), (3.0, 6.0));
+        assert_eq!(format!("{histogram:.1}"), "▆▆█ [0.0, 9.0]");
+
+        let layer = DenseState {
+            w: array![[3.0, -4.0], [1.0, 2.0]],
+            b: array![0.5, 0.5],
+        };
+        let histograms = Histogram::per_tensor(&layer, 2);
+        assert_eq!(histograms[0].0.to_string(), "weight");
+        assert_eq!(histograms[0].1.counts, [1, 3]);
+        assert_eq!(histograms[1].0.to_string(), "bias");
+        assert_eq!(histograms[1].1.counts, [2, 0]);
+        assert_eq!(Histogram::of(&layer, 4).counts, [1, 0, 3, 2]);
+    }
+
+    #[test]
+    fn test_dead_units() {
+        // the second hidden unit's bias keeps its input negative, so it never activates
+        let hidden = DenseState {
+            w: array![[1.0, 1.0, -1.0], [1.0, 1.0, 1.0]],
+            b: array![0.5, -10.0, 0.5],
+        };
+        let output = DenseState {
+            w: array![[1.0], [1.0], [1.0]],
+            b: array![0.0],
+        };
+        let graph = (Linear::new(hidden, Relu), output);
+
+        let dead = DeadUnits::new();
+        let mut trainer = Train::builder(graph)
+            .on_grads(dead.hook())
+            .callback(dead.clone())
+            .build();
+        let inputs = array![[0.1, 0.2], [0.3, -0.1], [-0.2, 0.4], [0.2, 0.3]];
+        let data = InMemoryDataset::new(inputs, array![[1.0], [0.0], [1.0], [0.0]]);
+        trainer.fit(&data, 2, 3);
+
+        let history = dead.history();
+        assert_eq!(history.len(), 3);
+        for epoch in history {
+            let dead_units: Vec<_> = epoch.iter().map(UnitActivity::dead_units).collect();
+            assert_eq!(dead_units, [1, 0]);
+            // the dead unit's two weights and bias, over both batches
+            assert_eq!((epoch[0].zeros, epoch[0].count), (6, 18));
+            assert!((epoch[0].zero_fraction::<f64>() - 1.0 / 3.0).abs() < 1e-12);
+            assert_eq!(epoch[0].active, [true, false, true]);
+        }
+    }
+
+    #[test]
+    fn test_dead_units_regularised() {
+        let hidden = DenseState {
+            w: array![[1.0, 1.0, -1.0], [1.0, 1.0, 1.0]],
+            b: array![0.5, -10.0, 0.5],
+        };
+        let output = DenseState {
+            w: array![[1.0], [1.0], [1.0]],
+            b: array![0.0],
+        };
+        let graph = (Linear::new(hidden, Relu), output);
+
+        // regularisation gives every weight a gradient, and dropout zeroes half of them,
+        // but the activity is recorded from the gradients of the cost alone
+        let dead = DeadUnits::new();
+        let mut trainer = Train::builder(graph)
+            .regularisation(Regularisation::L2(0.01))
+            .dropout(0.5)
+            .on_grads(dead.hook())
+            .callback(dead.clone())
+            .build();
+        let inputs = array![[0.1, 0.2], [0.3, -0.1], [-0.2, 0.4], [0.2, 0.3]];
+        let data = InMemoryDataset::new(inputs, array![[1.0], [0.0], [1.0], [0.0]]);
+        trainer.fit(&data, 2, 3);
+
+        for epoch in dead.history() {
+            assert_eq!(epoch[0].dead_units(), 1);
+            assert_eq!((epoch[0].zeros, epoch[0].count), (6, 18));
+        }
+    }
+}
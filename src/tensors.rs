@@ -0,0 +1,365 @@
+use std::fmt;
+
+use ndarray::{ArrayViewD, ArrayViewMutD};
+
+use crate::{activation::Linear, dense::DenseState};
+
+/// The location of a parameter tensor inside a graph, eg `1/0/weight`.
+///
+/// Containers add a segment for each graph inside them: tuples and branches add the
+/// element's index, stacks of repeated layers add the copy's index and named layers
+/// add their name. Layers then add the name of each of their tensors
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ParamPath {
+    segments: Vec<String>,
+}
+
+impl ParamPath {
+    #[must_use]
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// The name of the tensor itself, the last segment of the path
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.segments.last().map_or("", String::as_str)
+    }
+
+    #[must_use]
+    pub fn join(&self, separator: &str) -> String {
+        self.segments.join(separator)
+    }
+
+    /// Adds a segment to the end of the path
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn push(&mut self, segment: impl ToString) {
+        self.segments.push(segment.to_string());
+    }
+
+    pub fn pop(&mut self) -> Option<String> {
+        self.segments.pop()
+    }
+}
+
+impl fmt::Display for ParamPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.join("/"))
+    }
+}
+
+/// Graph states whose parameters can be visited as tensors, along with their [`ParamPath`].
+///
+/// Names are derived from the structure of the graph: each element of a tuple
+/// adds its index as a prefix, eg `1.0.weight`. Dense layers store their weights
+/// as `weight` with shape `(outputs, inputs)` and `bias`, matching `PyTorch`'s `nn.Linear`
+pub trait Tensors<F> {
+    /// Calls `f` with the path and value of every parameter tensor, in a deterministic order.
+    /// `path` is the location of this graph, and is left as it was once this returns
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>));
+
+    /// Calls `f` with the path and a mutable view of every parameter tensor,
+    /// in the same order as [`visit`](Self::visit)
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    );
+
+    /// Calls `f` with the name and value of every parameter tensor, in a deterministic order.
+    /// The name is the path joined with `.`, after the `prefix`
+    fn tensors<'a>(&'a self, prefix: &str, f: &mut dyn FnMut(String, ArrayViewD<'a, F>)) {
+        self.visit(&mut ParamPath::default(), &mut |path, tensor| {
+            f(format!("{prefix}{}", path.join(".")), tensor);
+        });
+    }
+
+    /// Calls `f` with the name and a mutable view of every parameter tensor,
+    /// in the same order as [`tensors`](Self::tensors)
+    fn tensors_mut<'a>(
+        &'a mut self,
+        prefix: &str,
+        f: &mut dyn FnMut(String, ArrayViewMutD<'a, F>),
+    ) {
+        self.visit_mut(&mut ParamPath::default(), &mut |path, tensor| {
+            f(format!("{prefix}{}", path.join(".")), tensor);
+        });
+    }
+
+    /// Every parameter tensor along with its path, in the same order as [`visit`](Self::visit)
+    fn params(&self) -> Vec<(ParamPath, ArrayViewD<'_, F>)> {
+        let mut params = vec![];
+        self.visit(&mut ParamPath::default(), &mut |path, tensor| {
+            params.push((path.clone(), tensor));
+        });
+        params
+    }
+}
+
+impl<F> Tensors<F> for DenseState<F> {
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        path.push("weight");
+        f(path, self.w.t().into_dyn());
+        path.pop();
+        path.push("bias");
+        f(path, self.b.view().into_dyn());
+        path.pop();
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        path.push("weight");
+        f(path, self.w.view_mut().reversed_axes().into_dyn());
+        path.pop();
+        path.push("bias");
+        f(path, self.b.view_mut().into_dyn());
+        path.pop();
+    }
+}
+
+impl<F, G: Tensors<F>, L> Tensors<F> for Linear<G, L> {
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        self.graph.visit(path, f);
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        self.graph.visit_mut(path, f);
+    }
+}
+
+impl<F, T, U> Tensors<F> for (T, U)
+where
+    T: Tensors<F>,
+    U: Tensors<F>,
+{
+    fn visit<'a>(&'a self, path: &mut ParamPath, f: &mut dyn FnMut(&ParamPath, ArrayViewD<'a, F>)) {
+        path.push(0);
+        self.0.visit(path, f);
+        path.pop();
+        path.push(1);
+        self.1.visit(path, f);
+        path.pop();
+    }
+
+    fn visit_mut<'a>(
+        &'a mut self,
+        path: &mut ParamPath,
+        f: &mut dyn FnMut(&ParamPath, ArrayViewMutD<'a, F>),
+    ) {
+        path.push(0);
+        self.0.visit_mut(path, f);
+        path.pop();
+        path.push(1);
+        self.1.visit_mut(path, f);
+        path.pop();
+    }
+}
+
+#[cfg(feature = "safetensors")]
+mod safetensors {
+    use std::{fs::File, io, path::Path};
+
+    use ::safetensors::{tensor::TensorView, Dtype, SafeTensors};
+    use memmap2::Mmap;
+
+    use super::Tensors;
+    use crate::binary::Element;
+
+    fn dtype<F: Element>() -> Dtype {
+        match F::TAG {
+            4 => Dtype::F32,
+            8 => Dtype::F64,
+            _ => unreachable!("unknown element type"),
+        }
+    }
+
+    fn invalid(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+
+    /// Writes every parameter tensor of the state to a safetensors file
+    pub fn save_safetensors<F, T>(state: &T, path: impl AsRef<Path>) -> io::Result<()>
+    where
+        F: Element,
+        T: Tensors<F>,
+    {
+        span!(INFO, "save_safetensors", path = %path.as_ref().display());
+        let mut tensors = vec![];
+        state.tensors("", &mut |name, tensor| {
+            let mut bytes = Vec::with_capacity(tensor.len() * F::SIZE);
+            tensor.iter().for_each(|&x| x.to_le(&mut bytes));
+            tensors.push((name, tensor.shape().to_vec(), bytes));
+        });
+
+        let views = tensors
+            .iter()
+            .map(|(name, shape, bytes)| {
+                let view = TensorView::new(dtype::<F>(), shape.clone(), bytes).map_err(invalid)?;
+                Ok((name, view))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        ::safetensors::serialize_to_file(views, &None, path.as_ref()).map_err(invalid)
+    }
+
+    /// Overwrites every parameter tensor of the state with the tensor of the same name
+    /// in a safetensors file. The file is memory mapped rather than read into memory.
+    ///
+    /// The state must already have the right shape, eg by initialising the graph first.
+    /// Every tensor is checked before any are copied, so the state is left unchanged
+    /// if the file doesn't match it
+    pub fn load_safetensors<F, T>(state: &mut T, path: impl AsRef<Path>) -> io::Result<()>
+    where
+        F: Element,
+        T: Tensors<F>,
+    {
+        span!(INFO, "load_safetensors", path = %path.as_ref().display());
+        let file = File::open(path)?;
+        // Safety: the file must not be modified while it is mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+        let file = SafeTensors::deserialize(&mmap).map_err(invalid)?;
+
+        let mut result = Ok(());
+        state.tensors("", &mut |name, tensor| {
+            if result.is_err() {
+                return;
+            }
+            result = match file.tensor(&name) {
+                Err(err) => Err(invalid(err)),
+                Ok(loaded)
+                    if loaded.dtype() != dtype::<F>() || loaded.shape() != tensor.shape() =>
+                {
+                    let msg = format!("tensor {name} has the wrong type or shape");
+                    Err(io::Error::new(io::ErrorKind::InvalidData, msg))
+                }
+                Ok(_) => Ok(()),
+            };
+        });
+        result?;
+
+        state.tensors_mut("", &mut |name, mut tensor| {
+            let loaded = file.tensor(&name).expect("tensors were checked");
+            let values = loaded.data().chunks_exact(F::SIZE).map(F::from_le);
+            tensor.iter_mut().zip(values).for_each(|(x, v)| *x = v);
+        });
+        Ok(())
+    }
+}
+
+#[cfg(feature = "safetensors")]
+pub use self::safetensors::{load_safetensors, save_safetensors};
+
+#[cfg(test)]
+mod tests {
+    use ndarray::array;
+
+    use super::{ParamPath, Tensors};
+    use crate::{
+        activation::relu::Relu,
+        dense::{Dense, DenseState},
+        initialisers::Xavier,
+        net,
+        repeat::Repeat,
+        Graph,
+    };
+
+    #[test]
+    fn test_tensor_names() {
+        let layer = DenseState {
+            w: array![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]],
+            b: array![0.0, 0.0, 0.0],
+        };
+        let layers = ((layer.clone(), layer.clone()), layer);
+
+        let mut tensors = vec![];
+        layers.tensors("", &mut |name, tensor| {
+            tensors.push((name, tensor.shape().to_vec()));
+        });
+        assert_eq!(
+            tensors,
+            vec![
+                ("0.0.weight".to_owned(), vec![3, 2]),
+                ("0.0.bias".to_owned(), vec![3]),
+                ("0.1.weight".to_owned(), vec![3, 2]),
+                ("0.1.bias".to_owned(), vec![3]),
+                ("1.weight".to_owned(), vec![3, 2]),
+                ("1.bias".to_owned(), vec![3]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_param_paths() {
+        let network = net![
+            Repeat::new(Dense::output_size(3).with_initialiser(Xavier), 2),
+            Dense::output_size(2)
+                .with_initialiser(Xavier)
+                .with_activation(Relu)
+                .named("head")
+        ];
+        let mut network = Graph::<f64, _>::input_shape(network, 4);
+
+        let paths: Vec<_> = network
+            .params()
+            .iter()
+            .map(|(path, tensor)| (path.to_string(), tensor.shape().to_vec()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("0/0/weight".to_owned(), vec![3, 4]),
+                ("0/0/bias".to_owned(), vec![3]),
+                ("0/1/weight".to_owned(), vec![3, 3]),
+                ("0/1/bias".to_owned(), vec![3]),
+                ("1/head/weight".to_owned(), vec![2, 3]),
+                ("1/head/bias".to_owned(), vec![2]),
+            ]
+        );
+
+        // per parameter options, eg only zeroing the biases
+        network.visit_mut(&mut ParamPath::default(), &mut |path, mut tensor| {
+            if path.name() == "bias" {
+                tensor.fill(0.0);
+            }
+        });
+        assert!(network.1.graph.graph.b.iter().all(|&b| b == 0.0));
+        assert!(network.1.graph.graph.w.iter().any(|&w| w != 0.0));
+    }
+
+    #[cfg(feature = "safetensors")]
+    #[test]
+    fn test_safetensors_round_trip() {
+        use super::{load_safetensors, save_safetensors};
+
+        let layer = DenseState {
+            w: array![[1.0_f32, 2.0, 3.0], [4.0, 5.0, 6.0]],
+            b: array![7.0, 8.0, 9.0],
+        };
+        let path = crate::temp_path("layer.safetensors");
+        save_safetensors(&layer, &path).unwrap();
+
+        let mut loaded = DenseState::<f32> {
+            w: ndarray::Array2::zeros((2, 3)),
+            b: ndarray::Array1::zeros(3),
+        };
+        load_safetensors(&mut loaded, &path).unwrap();
+
+        // the weights match, but the biases don't, so nothing is loaded
+        let mut mismatched = DenseState::<f32> {
+            w: ndarray::Array2::zeros((2, 3)),
+            b: ndarray::Array1::zeros(2),
+        };
+        assert!(load_safetensors(&mut mismatched, &path).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.w, layer.w);
+        assert_eq!(loaded.b, layer.b);
+        assert!(mismatched.w.iter().all(|&w| w == 0.0));
+    }
+}
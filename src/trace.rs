@@ -0,0 +1,12 @@
+//! Optional instrumentation with the [`tracing`](https://docs.rs/tracing) crate.
+//!
+//! Without the `tracing` feature this expands to nothing, so instrumented code
+//! doesn't need its own `#[cfg]`s
+
+/// Enters a span until the end of the enclosing block
+macro_rules! span {
+    ($level:ident, $($args:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::span!(tracing::Level::$level, $($args)*).entered();
+    };
+}
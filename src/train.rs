@@ -1,19 +1,51 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{sync::mpsc, thread};
 
-use ndarray::{Array, ArrayView, Axis, Dimension, RemoveAxis};
+use ndarray::{concatenate, Array, ArrayBase, ArrayView, Axis, CowArray, Dimension, RemoveAxis};
 use num_traits::{Float, FromPrimitive};
 use rand::prelude::*;
 use rand_distr::{
     uniform::{SampleBorrow, SampleUniform},
-    Uniform,
+    Beta, Uniform,
 };
 
-use crate::{cost::Cost, optimise::Optimiser, GraphExec, Mappable, Shaped};
+use crate::{
+    callback::{Callback, Timer, TrainEvent},
+    cost::{mse::MSE, Cost},
+    data::{
+        batch::Batches,
+        loader::{DataLoader, Loaded},
+        Dataset, Samples,
+    },
+    error::{same_samples, same_shape, Error, Result},
+    metrics::Metric,
+    optimise::{sgd::SGD, Optimiser, TunableOptimiser},
+    schedule::Schedule,
+    GraphExec, Mappable, Shaped,
+};
 
+/// Graphs that can be trained using backpropagation.
+///
+/// The first axis of each batch is the batch axis. Parameter gradients are summed over
+/// the batch, as if the cost were the sum of each sample's cost. To average them instead,
+/// wrap the cost function using [`Reduced`](crate::cost::Reduced)
 pub trait GraphExecTrain<Input>: GraphExec<Input> + Sized {
     type State;
     fn forward(&self, input: Input) -> (Self::State, Self::Output);
     fn back(&self, state: Self::State, d_output: Self::Output) -> (Input, Self);
+
+    /// Like [`back`](Self::back), but writes the gradients into an existing buffer of the
+    /// same shape. Layers override this to reuse the buffer's allocations between batches
+    fn back_into(&self, state: Self::State, d_output: Self::Output, grads: &mut Self) -> Input {
+        let (d_input, g) = self.back(state, d_output);
+        *grads = g;
+        d_input
+    }
+
     fn get_grads<C>(&self, input: Input, expected: Self::Output, cost: &C) -> (Self, C::Inner)
     where
         C: Cost<Self::Output>,
@@ -23,14 +55,296 @@ pub trait GraphExecTrain<Input>: GraphExec<Input> + Sized {
         let d_output = cost.diff(&output, &expected);
         (self.back(state, d_output).1, cost.cost(&output, &expected))
     }
+
+    /// Like [`get_grads`](Self::get_grads), but writes the gradients into an existing buffer
+    fn get_grads_into<C>(
+        &self,
+        input: Input,
+        expected: Self::Output,
+        cost: &C,
+        grads: &mut Self,
+    ) -> C::Inner
+    where
+        C: Cost<Self::Output>,
+    {
+        let (state, output) = self.forward(input);
+
+        let d_output = cost.diff(&output, &expected);
+        self.back_into(state, d_output, grads);
+        cost.cost(&output, &expected)
+    }
+}
+
+/// Whether a graph is currently being trained or used for inference
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Train,
+    Eval,
 }
 
+/// Graphs that can behave differently during training and inference,
+/// such as dropout or batch normalisation layers.
+/// Containers must forward the mode on to every graph they contain
+pub trait Modal {
+    fn set_mode(&mut self, _mode: Mode) {}
+}
+
+/// A function that inspects the gradients of a graph
+pub type GradHook<G> = Box<dyn FnMut(&G)>;
+
 pub struct Train<F, C, O, G> {
     pub graph: G,
     pub optimiser: O,
     pub cost: C,
     pub regularisation: Option<Regularisation<F>>,
+    /// Whether [`regularisation`](Self::regularisation) also penalises biases.
+    /// Biases don't contribute to overfitting in the way weights do, so this is often turned off
+    pub regularise_biases: bool,
+    /// Scales the coefficients of [`regularisation`](Self::regularisation) in each epoch,
+    /// eg to decay the weight decay late in training. Any [`Schedule`] works, the same as
+    /// for learning rates with [`LearningRate`](crate::schedule::LearningRate)
+    pub regularisation_schedule: Option<Box<dyn Schedule<F> + Send>>,
     pub dropout: F,
+    /// Sets [`dropout`](Self::dropout) at the start of every epoch.
+    /// Training panics if it gives a value outside `0..1`
+    pub dropout_schedule: Option<Box<dyn Schedule<F> + Send>>,
+    /// Called with the gradients of the cost in every training step, before dropout or
+    /// regularisation change them, and after any loss scaling is divided out.
+    /// Can be used to record gradient norms, eg using [`LayerStats`](crate::stats::LayerStats)
+    pub on_grads: Option<GradHook<G>>,
+    /// Receive progress events during training
+    pub callbacks: Vec<Box<dyn Callback<F>>>,
+    /// Reads the optimiser's learning rate for each [`TrainEvent::EpochEnd`].
+    /// Set by [`TrainBuilder::log_learning_rate`]
+    pub learning_rate: Option<fn(&O) -> F>,
+    /// The number of epochs completed so far
+    pub epoch: usize,
+    /// Enables mixup with the given alpha, which should be positive. Each training sample is
+    /// blended with another sample from the same batch, weighted by a coefficient sampled
+    /// from `Beta(alpha, alpha)`
+    pub mixup: Option<F>,
+    /// Enables adversarial training with the given epsilon. Each batch is perturbed
+    /// using the fast gradient sign method before being trained on
+    pub adversarial: Option<F>,
+}
+
+impl<F: Float, G> Train<F, MSE, SGD<F>, G> {
+    /// Starts building a trainer for the graph. By default it uses [`MSE`] as the cost,
+    /// [`SGD`] with a learning rate of 0.01, and has no dropout, regularisation,
+    /// mixup or adversarial training
+    ///
+    /// ```
+    /// use linear_networks::{
+    ///     cost::mse::MSE, dense::Dense, initialisers::Xavier, optimise::adam::Adam,
+    ///     train::{Regularisation, Train},
+    ///     Graph,
+    /// };
+    ///
+    /// let graph = Graph::<f64, _>::input_shape(Dense::output_size(2).with_initialiser(Xavier), 4);
+    /// let adam = Adam::default_for(&graph);
+    /// let trainer = Train::builder(graph)
+    ///     .cost(MSE)
+    ///     .optimiser(adam)
+    ///     .regularisation(Regularisation::L2(0.001))
+    ///     .dropout(0.2_f64)
+    ///     .build();
+    /// ```
+    pub fn builder(graph: G) -> TrainBuilder<F, MSE, SGD<F>, G> {
+        TrainBuilder {
+            train: Self {
+                graph,
+                optimiser: SGD::new(F::from(0.01).unwrap()),
+                cost: MSE,
+                regularisation: None,
+                regularise_biases: true,
+                regularisation_schedule: None,
+                dropout: F::zero(),
+                dropout_schedule: None,
+                on_grads: None,
+                callbacks: vec![],
+                learning_rate: None,
+                epoch: 0,
+                mixup: None,
+                adversarial: None,
+            },
+        }
+    }
+}
+
+/// Builds a [`Train`], created using [`Train::builder`]
+pub struct TrainBuilder<F, C, O, G> {
+    train: Train<F, C, O, G>,
+}
+
+impl<F, C, O, G> TrainBuilder<F, C, O, G> {
+    pub fn cost<C2>(self, cost: C2) -> TrainBuilder<F, C2, O, G> {
+        let Train {
+            graph,
+            optimiser,
+            regularisation,
+            regularise_biases,
+            regularisation_schedule,
+            dropout,
+            dropout_schedule,
+            on_grads,
+            callbacks,
+            learning_rate,
+            epoch,
+            mixup,
+            adversarial,
+            ..
+        } = self.train;
+        TrainBuilder {
+            train: Train {
+                graph,
+                optimiser,
+                cost,
+                regularisation,
+                regularise_biases,
+                regularisation_schedule,
+                dropout,
+                dropout_schedule,
+                on_grads,
+                callbacks,
+                learning_rate,
+                epoch,
+                mixup,
+                adversarial,
+            },
+        }
+    }
+
+    pub fn optimiser<O2>(self, optimiser: O2) -> TrainBuilder<F, C, O2, G> {
+        let Train {
+            graph,
+            cost,
+            regularisation,
+            regularise_biases,
+            regularisation_schedule,
+            dropout,
+            dropout_schedule,
+            on_grads,
+            callbacks,
+            epoch,
+            mixup,
+            adversarial,
+            ..
+        } = self.train;
+        TrainBuilder {
+            train: Train {
+                graph,
+                optimiser,
+                cost,
+                regularisation,
+                regularise_biases,
+                regularisation_schedule,
+                dropout,
+                dropout_schedule,
+                on_grads,
+                callbacks,
+                // the optimiser has changed type, so its learning rate has to be logged again
+                learning_rate: None,
+                epoch,
+                mixup,
+                adversarial,
+            },
+        }
+    }
+
+    #[must_use]
+    pub fn regularisation(mut self, regularisation: Regularisation<F>) -> Self {
+        self.train.regularisation = Some(regularisation);
+        self
+    }
+
+    /// See [`Train::regularise_biases`]. Defaults to true
+    #[must_use]
+    pub const fn regularise_biases(mut self, regularise_biases: bool) -> Self {
+        self.train.regularise_biases = regularise_biases;
+        self
+    }
+
+    /// Scales the regularisation coefficients at each epoch. See [`schedule`](crate::schedule)
+    #[must_use]
+    pub fn regularisation_schedule(mut self, schedule: impl Schedule<F> + Send + 'static) -> Self {
+        self.train.regularisation_schedule = Some(Box::new(schedule));
+        self
+    }
+
+    /// The probability of each parameter's gradient being dropped in a training step
+    ///
+    /// # Panics
+    /// If `dropout` isn't in `0..1`
+    #[must_use]
+    pub fn dropout(mut self, dropout: F) -> Self
+    where
+        F: Float,
+    {
+        assert!(
+            F::zero() <= dropout && dropout < F::one(),
+            "dropout should be in 0..1"
+        );
+        self.train.dropout = dropout;
+        self
+    }
+
+    /// Changes the dropout at the start of every epoch. See [`schedule`](crate::schedule).
+    /// Every value the schedule gives should be in `0..1`
+    #[must_use]
+    pub fn dropout_schedule(mut self, schedule: impl Schedule<F> + Send + 'static) -> Self {
+        self.train.dropout_schedule = Some(Box::new(schedule));
+        self
+    }
+
+    /// See [`Train::mixup`]
+    ///
+    /// # Panics
+    /// If `alpha` isn't positive
+    #[must_use]
+    pub fn mixup(mut self, alpha: F) -> Self
+    where
+        F: Float,
+    {
+        assert!(alpha > F::zero(), "mixup alpha should be positive");
+        self.train.mixup = Some(alpha);
+        self
+    }
+
+    /// See [`Train::adversarial`]
+    #[must_use]
+    pub fn adversarial(mut self, epsilon: F) -> Self {
+        self.train.adversarial = Some(epsilon);
+        self
+    }
+
+    #[must_use]
+    pub fn on_grads(mut self, on_grads: impl FnMut(&G) + 'static) -> Self {
+        self.train.on_grads = Some(Box::new(on_grads));
+        self
+    }
+
+    /// Includes the optimiser's learning rate in every [`TrainEvent::EpochEnd`],
+    /// eg to be written by [`CsvLogger`](crate::callback::CsvLogger).
+    /// Call this after setting the [`optimiser`](Self::optimiser)
+    #[must_use]
+    pub fn log_learning_rate(mut self) -> Self
+    where
+        O: TunableOptimiser<F>,
+    {
+        self.train.learning_rate = Some(O::learning_rate);
+        self
+    }
+
+    /// Adds a callback. Can be called multiple times
+    #[must_use]
+    pub fn callback(mut self, callback: impl Callback<F> + 'static) -> Self {
+        self.train.callbacks.push(Box::new(callback));
+        self
+    }
+
+    pub fn build(self) -> Train<F, C, O, G> {
+        self.train
+    }
 }
 
 impl<F, C, O, G> Deref for Train<F, C, O, G> {
@@ -46,130 +360,894 @@ impl<F, C, O, G> DerefMut for Train<F, C, O, G> {
 }
 
 impl<F, C, O, G> Train<F, C, O, G> {
-    pub fn perform_epoch<D1, D2>(
+    /// Trains over the whole data set once, in a random order.
+    /// Returns the average cost of each batch.
+    ///
+    /// The batches are gathered on a second thread, so the next batch is
+    /// ready by the time the current one has finished training. On wasm32 there are no
+    /// threads, so each batch is gathered when it's needed instead.
+    ///
+    /// Sharing the data set with that thread needs it to be `Sync`. Data sets that
+    /// aren't can be trained on the current thread with
+    /// [`perform_epoch_with`](Self::perform_epoch_with) and [`Batches::shuffled`]
+    pub fn perform_epoch<DS>(&mut self, data: &DS, batch_size: usize) -> C::Inner
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset + Sync,
+        DS::Input: Mappable<F> + Samples + Clone + Send,
+        DS::Target: Mappable<F> + Samples + Send,
+    {
+        let batches = Batches::shuffled(data.len(), batch_size, &mut thread_rng());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let total_batches = batches.len();
+            thread::scope(|scope| {
+                // only one batch is prepared ahead, which keeps the memory overhead small
+                let (sender, receiver) = mpsc::sync_channel(1);
+                scope.spawn(move || {
+                    for indices in batches {
+                        let (input, target) = data.batch(&indices);
+                        // training stopped early, so the remaining batches aren't needed
+                        if sender.send((indices, input, target)).is_err() {
+                            break;
+                        }
+                    }
+                });
+                let loaded = (0..total_batches)
+                    .map(|_| receiver.recv().expect("batch preparation panicked"));
+                self.run_epoch::<DS>(loaded)
+            })
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        self.perform_epoch_with(data, batches)
+    }
+
+    /// Like [`perform_epoch`](Self::perform_epoch), followed by a pass over the held-out
+    /// `validation` set using [`validate`](Self::validate).
+    /// Returns the average training cost of each batch and the validation cost
+    pub fn perform_epoch_validated<DS>(
+        &mut self,
+        data: &DS,
+        validation: &DS,
+        batch_size: usize,
+    ) -> (C::Inner, C::Inner)
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset + Sync,
+        DS::Input: Mappable<F> + Samples + Clone + Send,
+        DS::Target: Mappable<F> + Samples + Send,
+    {
+        let cost = self.perform_epoch(data, batch_size);
+        (cost, self.validate(validation, batch_size))
+    }
+
+    /// Runs the graph over a data set in order, `batch_size` samples at a time, without
+    /// dropout or regularisation. Returns the cost averaged over every sample
+    pub fn validate<DS>(&mut self, data: &DS, batch_size: usize) -> C::Inner
+    where
+        C: Cost<G::Output, Inner = F>,
+        G: GraphExec<DS::Input, Output = DS::Target> + Modal,
+        F: Float + FromPrimitive,
+        DS: Dataset,
+    {
+        span!(INFO, "validate", samples = data.len());
+        self.graph.set_mode(Mode::Eval);
+        let mut totals = Totals::new(0);
+        for indices in Batches::new(data.len(), batch_size) {
+            let (input, expected) = data.batch(&indices);
+            let output = self.graph.exec(input);
+            let cost = self.cost.cost(&output, &expected);
+            totals.add(indices.len(), cost, std::iter::empty());
+        }
+        totals.average().cost
+    }
+
+    /// Trains over every batch of indices produced by `batches`.
+    /// Returns the average cost of each batch
+    pub fn perform_epoch_with<DS>(&mut self, data: &DS, batches: Batches) -> C::Inner
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset,
+        DS::Input: Mappable<F> + Samples + Clone,
+        DS::Target: Mappable<F> + Samples,
+    {
+        let loaded = batches.map(|indices| {
+            let (input, target) = data.batch(&indices);
+            (indices, input, target)
+        });
+        self.run_epoch::<DS>(loaded)
+    }
+
+    /// Trains over every batch gathered by the loader.
+    /// Returns the average cost of each batch
+    pub fn perform_epoch_loaded<DS>(&mut self, loader: DataLoader<DS>) -> C::Inner
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset,
+        DS::Input: Mappable<F> + Samples + Clone,
+        DS::Target: Mappable<F> + Samples,
+    {
+        self.run_epoch::<DS>(loader)
+    }
+
+    fn run_epoch<DS>(&mut self, batches: impl ExactSizeIterator<Item = Loaded<DS>>) -> C::Inner
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset,
+        DS::Input: Mappable<F> + Samples + Clone,
+        DS::Target: Mappable<F> + Samples,
+    {
+        let epoch = self.start_epoch();
+        span!(INFO, "epoch", epoch);
+        let total_batches = batches.len();
+        let mut buffers = Buffers::new();
+        let mut cost = F::zero();
+        let (start, mut waiting) = (Timer::start(), Timer::start());
+        let mut samples = 0;
+        for (i, (indices, inputs, expected)) in batches.enumerate() {
+            let waited = waiting.elapsed();
+            let step = Timer::start();
+            let batch_cost = self.train_with(&mut buffers, inputs, expected);
+            cost = cost + batch_cost;
+            samples += indices.len();
+            self.emit(&TrainEvent::BatchEnd {
+                epoch,
+                batch: i,
+                batches: total_batches,
+                cost: batch_cost,
+                samples: indices.len(),
+                duration: step.elapsed(),
+                waited,
+            });
+            waiting = Timer::start();
+        }
+
+        let cost = cost / F::from_usize(total_batches).unwrap();
+        self.emit(&TrainEvent::EpochEnd {
+            epoch,
+            cost,
+            samples,
+            learning_rate: self.current_learning_rate(),
+            duration: start.elapsed(),
+        });
+        self.epoch += 1;
+        cost
+    }
+
+    /// Trains for the given number of epochs, shuffling the data each epoch.
+    /// Returns the average cost of each epoch.
+    /// The data set needs to be `Sync`, as in [`perform_epoch`](Self::perform_epoch)
+    pub fn fit<DS>(&mut self, data: &DS, batch_size: usize, epochs: usize) -> Vec<C::Inner>
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset + Sync,
+        DS::Input: Mappable<F> + Samples + Clone + Send,
+        DS::Target: Mappable<F> + Samples + Send,
+    {
+        span!(INFO, "fit", epochs, batch_size, samples = data.len());
+        (0..epochs)
+            .map(|_| self.perform_epoch(data, batch_size))
+            .collect()
+    }
+
+    /// Trains over consecutive slices of the inputs and targets, in order, without
+    /// shuffling. Each batch of inputs is borrowed as a [`CowArray`] rather than copied
+    /// out of the data set, so graphs must be trainable on `CowArray` inputs,
+    /// as [`DenseState`](crate::dense::DenseState) is.
+    /// Returns the average cost of each batch
+    pub fn perform_epoch_slices<D1, D2>(
         &mut self,
         inputs: &ArrayView<F, D1>,
-        expected: &ArrayView<F, D2>,
+        targets: &ArrayView<F, D2>,
         batch_size: usize,
     ) -> C::Inner
     where
-        C: Cost<G::Output, Inner = F>,
+        C: Cost<Array<F, D2>, Inner = F>,
         O: Optimiser<G>,
-        G: GraphExecTrain<Array<F, D1>, Output = Array<F, D2>> + Mappable<F> + Shaped<F> + Clone,
+        G: for<'a> GraphExecTrain<CowArray<'a, F, D1>, Output = Array<F, D2>>
+            + Mappable<F>
+            + Shaped<F>
+            + Modal
+            + Clone,
         F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
         D1: Dimension + RemoveAxis,
         D2: Dimension + RemoveAxis,
     {
-        assert_eq!(inputs.raw_dim()[0], expected.raw_dim()[0]);
-        let total_inputs = inputs.raw_dim()[0];
-
-        let mut rng = thread_rng();
-        let mut indicies: Vec<_> = (0..total_inputs).collect();
-        indicies.shuffle(&mut rng);
+        assert_eq!(inputs.raw_dim()[0], targets.raw_dim()[0]);
 
+        let epoch = self.start_epoch();
+        span!(INFO, "epoch", epoch);
+        let total_batches = inputs.len_of(Axis(0)).div_ceil(batch_size);
+        let mut buffers = Buffers::new();
         let mut cost = F::zero();
-        for i in (0..total_inputs).step_by(batch_size) {
-            cost = cost + self.train_batch(inputs, expected, &indicies[i..i + batch_size]);
+        let batches = inputs
+            .axis_chunks_iter(Axis(0), batch_size)
+            .zip(targets.axis_chunks_iter(Axis(0), batch_size));
+        let start = Timer::start();
+        for (i, (input, expected)) in batches.enumerate() {
+            let step = Timer::start();
+            let samples = input.len_of(Axis(0));
+            let batch_cost = self.train_with(&mut buffers, input.into(), expected.to_owned());
+            cost = cost + batch_cost;
+            self.emit(&TrainEvent::BatchEnd {
+                epoch,
+                batch: i,
+                batches: total_batches,
+                cost: batch_cost,
+                samples,
+                duration: step.elapsed(),
+                waited: Duration::ZERO,
+            });
         }
-        if total_inputs % batch_size != 0 {
-            let i = total_inputs - total_inputs % batch_size;
-            cost = cost + self.train_batch(inputs, expected, &indicies[i..total_inputs]);
+
+        let cost = cost / F::from_usize(total_batches).unwrap();
+        self.emit(&TrainEvent::EpochEnd {
+            epoch,
+            cost,
+            samples: inputs.len_of(Axis(0)),
+            learning_rate: self.current_learning_rate(),
+            duration: start.elapsed(),
+        });
+        self.epoch += 1;
+        cost
+    }
+
+    /// Perturbs the input in the direction that increases the cost the most
+    /// (the fast gradient sign method)
+    fn fgsm<I>(&self, mut input: I, expected: &G::Output, epsilon: F) -> I
+    where
+        C: Cost<G::Output, Inner = F>,
+        G: GraphExecTrain<I>,
+        F: Float,
+        I: Mappable<F> + Clone,
+    {
+        let (state, output) = self.graph.forward(input.clone());
+        let d_output = self.cost.diff(&output, expected);
+        let (d_input, _) = self.graph.back(state, d_output);
+
+        input.map_mut_with(&d_input, |x, &d| *x = *x + d.signum() * epsilon);
+        input
+    }
+
+    /// The regularisation for the current epoch, scaled by the
+    /// [`regularisation_schedule`](Self::regularisation_schedule)
+    pub fn current_regularisation(&self) -> Option<Regularisation<F>>
+    where
+        F: Float,
+    {
+        let regularisation = self.regularisation?;
+        Some(
+            self.regularisation_schedule
+                .as_ref()
+                .map_or(regularisation, |schedule| {
+                    regularisation.scaled(schedule.value(self.epoch))
+                }),
+        )
+    }
+
+    /// Updates any scheduled hyperparameters for the epoch about to start,
+    /// and returns the epoch
+    pub(crate) fn start_epoch(&mut self) -> usize
+    where
+        F: Float,
+    {
+        if let Some(schedule) = &self.dropout_schedule {
+            let dropout = schedule.value(self.epoch);
+            assert!(
+                F::zero() <= dropout && dropout < F::one(),
+                "the dropout schedule should stay in 0..1, but gave {:?} for epoch {}",
+                dropout.to_f64(),
+                self.epoch
+            );
+            self.dropout = dropout;
         }
+        self.epoch
+    }
 
-        cost / F::from_usize((total_inputs + batch_size - 1) / batch_size).unwrap()
+    pub(crate) fn current_learning_rate(&self) -> Option<F> {
+        self.learning_rate
+            .map(|learning_rate| learning_rate(&self.optimiser))
     }
 
-    pub fn train_batch<D1, D2>(
+    pub(crate) fn emit(&mut self, event: &TrainEvent<F>)
+    where
+        F: Float,
+    {
+        #[cfg(feature = "tracing")]
+        match *event {
+            TrainEvent::BatchEnd { batch, cost, .. } => {
+                tracing::debug!(batch, cost = cost.to_f64(), "batch finished");
+            }
+            TrainEvent::EpochEnd { epoch, cost, .. } => {
+                let samples_per_second = event.samples_per_second();
+                tracing::info!(
+                    epoch,
+                    cost = cost.to_f64(),
+                    samples_per_second,
+                    "epoch finished"
+                );
+            }
+        }
+        for callback in &mut self.callbacks {
+            callback.on_event(event);
+        }
+    }
+
+    /// Executes the graph over a large set of inputs, `batch_size` samples at a time,
+    /// and stitches the outputs back together along the first axis.
+    /// This avoids having to execute the entire data set in one go.
+    ///
+    /// Returns an error if there are no inputs or `batch_size` is zero
+    pub fn predict<D1, D2>(
+        &mut self,
+        inputs: &ArrayView<F, D1>,
+        batch_size: usize,
+    ) -> Result<Array<F, D2>>
+    where
+        G: GraphExec<Array<F, D1>, Output = Array<F, D2>> + Modal,
+        F: Clone,
+        D1: Dimension + RemoveAxis,
+        D2: Dimension + RemoveAxis,
+    {
+        if inputs.raw_dim()[0] == 0 || batch_size == 0 {
+            return Err(Error::EmptyBatch);
+        }
+        self.graph.set_mode(Mode::Eval);
+        let outputs: Vec<_> = inputs
+            .axis_chunks_iter(Axis(0), batch_size)
+            .map(|input| self.graph.exec(input.to_owned()))
+            .collect();
+        let outputs: Vec<_> = outputs.iter().map(ArrayBase::view).collect();
+        Ok(concatenate(Axis(0), &outputs).expect("every batch has the same output shape"))
+    }
+
+    /// Runs the graph over the given test set in batches, without dropout or regularisation.
+    /// Returns the cost and each of the requested metrics, averaged over every sample.
+    ///
+    /// Returns an error if the test set is empty or `batch_size` is zero, or if the inputs,
+    /// outputs and expected outputs don't line up
+    pub fn evaluate<D1, D2>(
         &mut self,
         inputs: &ArrayView<F, D1>,
         expected: &ArrayView<F, D2>,
-        indicies: &[usize],
-    ) -> C::Inner
+        batch_size: usize,
+        metrics: &[&dyn Metric<Array<F, D2>, Inner = F>],
+    ) -> Result<Evaluation<F>>
     where
         C: Cost<G::Output, Inner = F>,
-        O: Optimiser<G>,
-        G: GraphExecTrain<Array<F, D1>, Output = Array<F, D2>> + Mappable<F> + Shaped<F> + Clone,
-        F: Float + SampleBorrow<F> + SampleUniform + Clone,
+        G: GraphExec<Array<F, D1>, Output = Array<F, D2>> + Modal,
+        F: Float + FromPrimitive,
         D1: Dimension + RemoveAxis,
         D2: Dimension + RemoveAxis,
     {
-        let mut input_dim = inputs.raw_dim();
-        input_dim.as_array_view_mut()[0] = indicies.len();
-
-        let mut expected_dim = expected.raw_dim();
-        expected_dim.as_array_view_mut()[0] = indicies.len();
+        let total_inputs = inputs.raw_dim()[0];
+        if total_inputs == 0 || batch_size == 0 {
+            return Err(Error::EmptyBatch);
+        }
+        same_samples("expected outputs", total_inputs, expected.raw_dim()[0])?;
 
-        let mut shuffled_inputs = Array::uninit(input_dim);
-        let mut shuffled_expected = Array::uninit(expected_dim);
+        self.graph.set_mode(Mode::Eval);
+        let mut totals = Totals::new(metrics.len());
 
-        for (i, &j) in indicies.iter().enumerate() {
-            inputs
-                .index_axis(Axis(0), j)
-                .assign_to(shuffled_inputs.index_axis_mut(Axis(0), i));
+        let batches = inputs
+            .axis_chunks_iter(Axis(0), batch_size)
+            .zip(expected.axis_chunks_iter(Axis(0), batch_size));
+        for (input, expected) in batches {
+            let samples = input.len_of(Axis(0));
+            let output = self.graph.try_exec(input.to_owned())?;
+            let expected = expected.to_owned();
+            same_shape("expected outputs", output.shape(), expected.shape())?;
 
-            expected
-                .index_axis(Axis(0), j)
-                .assign_to(shuffled_expected.index_axis_mut(Axis(0), i));
+            let measured = metrics.iter().map(|m| m.measure(&output, &expected));
+            totals.add(samples, self.cost.cost(&output, &expected), measured);
         }
+        Ok(totals.average())
+    }
 
-        unsafe {
-            self.train(
-                shuffled_inputs.assume_init(),
-                shuffled_expected.assume_init(),
-            )
+    /// Gathers the given samples from the data set and trains on them as a single batch
+    pub fn train_batch<DS>(&mut self, data: &DS, indicies: &[usize]) -> C::Inner
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive,
+        DS: Dataset,
+        DS::Input: Mappable<F> + Samples + Clone,
+        DS::Target: Mappable<F> + Samples,
+    {
+        let (inputs, expected) = data.batch(indicies);
+        self.train_with(&mut Buffers::new(), inputs, expected)
+    }
+
+    /// Blends each sample with another sample of the same batch, for [`mixup`](Self::mixup).
+    /// Each sample is paired with the one a random number of places after it, so no
+    /// sample is paired with itself
+    fn mix<I>(mut input: I, mut expected: G::Output, alpha: F) -> (I, G::Output)
+    where
+        G: GraphExec<I>,
+        G::Output: Mappable<F> + Samples,
+        F: Float,
+        I: Mappable<F> + Samples,
+    {
+        let samples = input.samples();
+        if samples < 2 {
+            return (input, expected);
         }
+        let mut rng = thread_rng();
+        let shift = rng.gen_range(1..samples);
+        let partners: Vec<_> = (0..samples).map(|i| (i + shift) % samples).collect();
+
+        let alpha = alpha.to_f64().unwrap();
+        let beta = Beta::new(alpha, alpha).expect("mixup alpha should be positive");
+        let lambda: f64 = beta.sample(&mut rng);
+        let lambda = F::from(lambda).unwrap();
+        let mix = |x: &mut F, &y: &F| *x = *x * lambda + y * (F::one() - lambda);
+
+        input.map_mut_with(&input.select(&partners), mix);
+        expected.map_mut_with(&expected.select(&partners), mix);
+        (input, expected)
+    }
+
+    /// Performs a single training step on the given input.
+    /// For graphs with multiple outputs, `expected` is a tuple of each expected output
+    pub fn train<I>(&mut self, input: I, expected: G::Output) -> C::Inner
+    where
+        C: Cost<G::Output, Inner = F>,
+        O: Optimiser<G>,
+        G: GraphExecTrain<I> + Mappable<F> + Shaped<F> + Modal + Clone,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone,
+        G::Output: Mappable<F> + Samples,
+        I: Mappable<F> + Samples + Clone,
+    {
+        self.train_with(&mut Buffers::new(), input, expected)
     }
 
-    pub fn train<D1, D2>(&mut self, input: Array<F, D1>, expected: Array<F, D2>) -> C::Inner
+    /// Performs a single training step, reusing the buffers from previous steps
+    pub fn train_with<I>(
+        &mut self,
+        buffers: &mut Buffers<G>,
+        input: I,
+        expected: G::Output,
+    ) -> C::Inner
     where
         C: Cost<G::Output, Inner = F>,
         O: Optimiser<G>,
-        G: GraphExecTrain<Array<F, D1>, Output = Array<F, D2>> + Mappable<F> + Shaped<F> + Clone,
+        G: GraphExecTrain<I> + Mappable<F> + Shaped<F> + Modal + Clone,
         F: Float + SampleBorrow<F> + SampleUniform + Clone,
-        D1: Dimension,
-        D2: Dimension,
+        G::Output: Mappable<F> + Samples,
+        I: Mappable<F> + Samples + Clone,
     {
+        span!(TRACE, "train_step");
+        self.graph.set_mode(Mode::Train);
         let zero = F::zero();
         let one = F::one();
 
-        let (mut grads, mut cost) = if (zero..one).contains(&self.dropout) {
-            let dropouts = G::iter(
-                self.graph.shape(),
-                thread_rng().sample_iter(Uniform::new_inclusive(zero, one)),
-            );
+        let (input, expected) = match self.mixup {
+            Some(alpha) => Self::mix(input, expected, alpha),
+            None => (input, expected),
+        };
+        let input = match self.adversarial {
+            Some(epsilon) => self.fgsm(input, &expected, epsilon),
+            None => input,
+        };
+
+        let graph = &self.graph;
+        let grads = buffers.grads.get_or_insert_with(|| graph.clone());
+        let cost = self
+            .graph
+            .get_grads_into(input, expected, &self.cost, grads);
+        self.unscale(grads);
+        if let Some(on_grads) = &mut self.on_grads {
+            on_grads(grads);
+        }
+
+        if zero < self.dropout && self.dropout < one {
+            let dropouts = buffers.dropouts.get_or_insert_with(|| graph.clone());
+            let uniform = Uniform::new_inclusive(zero, one);
+            let mut rng = thread_rng();
+            dropouts.map_mut(|d| *d = rng.sample(&uniform));
 
             let a = one / (one - self.dropout);
-            let mut graph = self.graph.clone();
-            graph.map_mut_with(&dropouts, |g, &d| {
+            grads.map_mut_with(dropouts, |g, &d| {
                 if d < self.dropout {
                     *g = zero;
                 } else {
                     *g = *g * a;
                 }
             });
+        }
 
-            let (mut grads, cost) = self.graph.get_grads(input, expected, &self.cost);
+        self.step(grads, cost)
+    }
 
-            grads.map_mut_with(&dropouts, |g, &d| {
-                if d < self.dropout {
-                    *g = zero;
-                } else {
-                    *g = *g * a;
+    /// Divides the gradients by the optimiser's loss scale, if it uses one
+    fn unscale(&self, grads: &mut G)
+    where
+        O: Optimiser<G>,
+        G: Mappable<F>,
+        F: Float,
+    {
+        if let Some(scale) = self.optimiser.loss_scale() {
+            let unscale: F = num_traits::cast(scale.recip()).unwrap();
+            grads.map_mut(|g| *g = *g * unscale);
+        }
+    }
+
+    /// Unscales the gradients and passes them to the gradient hook, then regularises
+    /// and applies them
+    pub(crate) fn apply_grads(&mut self, grads: &mut G, cost: F) -> F
+    where
+        O: Optimiser<G>,
+        G: Mappable<F>,
+        F: Float,
+    {
+        self.unscale(grads);
+        if let Some(on_grads) = &mut self.on_grads {
+            on_grads(grads);
+        }
+        self.step(grads, cost)
+    }
+
+    /// Regularises the gradients, then applies them
+    fn step(&mut self, grads: &mut G, mut cost: F) -> F
+    where
+        O: Optimiser<G>,
+        G: Mappable<F>,
+        F: Float,
+    {
+        if let Some(r) = self.current_regularisation() {
+            cost = cost + r.apply(grads, &self.graph, self.regularise_biases);
+        }
+        self.optimiser.optimise(&mut self.graph, grads);
+        cost
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<F, C, O, G> Train<F, C, O, G> {
+    /// Creates a bare trainer with the same settings and graph, for a worker thread.
+    /// Callbacks can't be shared between threads, so the worker doesn't get any.
+    /// Progress is reported by the main thread instead
+    fn worker(&self, optimiser: O) -> impl FnOnce() -> Self + Send
+    where
+        C: Clone + Send,
+        O: Send,
+        G: Clone + Send,
+        F: Float + Send,
+    {
+        let (graph, cost) = (self.graph.clone(), self.cost.clone());
+        let regularisation = self.current_regularisation();
+        let regularise_biases = self.regularise_biases;
+        let (dropout, mixup, adversarial) = (self.dropout, self.mixup, self.adversarial);
+        let epoch = self.epoch;
+        move || Self {
+            graph,
+            optimiser,
+            cost,
+            regularisation,
+            regularise_biases,
+            regularisation_schedule: None,
+            dropout,
+            dropout_schedule: None,
+            on_grads: None,
+            callbacks: vec![],
+            learning_rate: None,
+            epoch,
+            mixup,
+            adversarial,
+        }
+    }
+
+    /// Trains over the whole data set once using parameter averaging. The shuffled data set
+    /// is split into one shard per worker thread, and each worker trains its own copy of
+    /// the graph (and optimiser) on its shard. After every `sync_every` batches, the
+    /// workers' graphs are averaged and every worker continues from the average.
+    ///
+    /// Afterwards the graph is the final average, and the optimiser is the first worker's.
+    /// Returns the average cost of each batch
+    ///
+    /// # Panics
+    /// If `workers` or `sync_every` is zero
+    pub fn perform_epoch_averaged<DS>(
+        &mut self,
+        data: &DS,
+        batch_size: usize,
+        workers: usize,
+        sync_every: usize,
+    ) -> F
+    where
+        C: Cost<G::Output, Inner = F> + Clone + Send,
+        O: Optimiser<G> + Clone + Send,
+        G: GraphExecTrain<DS::Input, Output = DS::Target>
+            + Mappable<F>
+            + Shaped<F>
+            + Modal
+            + Clone
+            + Send,
+        F: Float + SampleBorrow<F> + SampleUniform + Clone + FromPrimitive + Send + Sync,
+        DS: Dataset + Sync,
+        DS::Input: Mappable<F> + Samples + Clone,
+        DS::Target: Mappable<F> + Samples,
+    {
+        assert!(
+            workers > 0,
+            "perform_epoch_averaged needs at least one worker"
+        );
+        assert!(sync_every > 0, "sync_every should be at least one batch");
+        let epoch = self.start_epoch();
+        span!(INFO, "epoch", epoch);
+        let mut indices: Vec<usize> = (0..data.len()).collect();
+        indices.shuffle(&mut thread_rng());
+        let shards: Vec<Vec<&[usize]>> = indices
+            .chunks(data.len().div_ceil(workers).max(1))
+            .map(|shard| shard.chunks(batch_size).collect())
+            .collect();
+        let rounds = shards
+            .iter()
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+            .div_ceil(sync_every);
+
+        let mut optimisers = vec![self.optimiser.clone(); shards.len()];
+        let mut cost = F::zero();
+        let mut batches = 0;
+        let start = Timer::start();
+        for round in 0..rounds {
+            let step = Timer::start();
+            let samples = round_samples(&shards, round, sync_every);
+            let results: Vec<(G, O, F, usize)> = thread::scope(|scope| {
+                let handles: Vec<_> = shards
+                    .iter()
+                    .zip(std::mem::take(&mut optimisers))
+                    .map(|(shard, optimiser)| {
+                        let worker = self.worker(optimiser);
+                        scope.spawn(move || {
+                            let mut worker = worker();
+                            let mut cost = F::zero();
+                            let mut batches = 0;
+                            for batch in shard.iter().skip(round * sync_every).take(sync_every) {
+                                cost = cost + worker.train_batch(data, batch);
+                                batches += 1;
+                            }
+                            (worker.graph, worker.optimiser, cost, batches)
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| handle.join().expect("training worker panicked"))
+                    .collect()
+            });
+
+            // workers whose shard has already run out of batches have nothing to contribute
+            let mut average: Option<G> = None;
+            let mut trained = 0;
+            for (graph, optimiser, worker_cost, worker_batches) in results {
+                optimisers.push(optimiser);
+                if worker_batches == 0 {
+                    continue;
+                }
+                cost = cost + worker_cost;
+                batches += worker_batches;
+                trained += 1;
+                match &mut average {
+                    Some(average) => average.map_mut_with(&graph, |a, &g| *a = *a + g),
+                    None => average = Some(graph),
                 }
+            }
+            if let Some(mut average) = average {
+                let trained = F::from_usize(trained).unwrap();
+                average.map_mut(|a| *a = *a / trained);
+                self.graph = average;
+            }
+
+            self.emit(&TrainEvent::BatchEnd {
+                epoch,
+                batch: round,
+                batches: rounds,
+                cost: cost / F::from_usize(batches.max(1)).unwrap(),
+                samples,
+                duration: step.elapsed(),
+                waited: Duration::ZERO,
             });
+        }
 
-            (grads, cost)
-        } else {
-            self.graph.get_grads(input, expected, &self.cost)
-        };
+        if let Some(optimiser) = optimisers.into_iter().next() {
+            self.optimiser = optimiser;
+        }
+        let cost = cost / F::from_usize(batches.max(1)).unwrap();
+        self.emit(&TrainEvent::EpochEnd {
+            epoch,
+            cost,
+            samples: data.len(),
+            learning_rate: self.current_learning_rate(),
+            duration: start.elapsed(),
+        });
+        self.epoch += 1;
+        cost
+    }
+}
+
+/// The number of samples the workers train on in a round of parameter averaging
+#[cfg(not(target_arch = "wasm32"))]
+fn round_samples(shards: &[Vec<&[usize]>], round: usize, sync_every: usize) -> usize {
+    shards
+        .iter()
+        .flat_map(|shard| shard.iter().skip(round * sync_every).take(sync_every))
+        .map(|batch| batch.len())
+        .sum()
+}
 
-        if let Some(r) = self.regularisation {
-            cost = cost + r.apply(&mut grads, &self.graph);
+#[cfg(feature = "parallel")]
+impl<F, C, O, G> Train<F, C, O, G> {
+    /// Trains over the whole data set once, in a random order, splitting every batch
+    /// across the rayon thread pool with [`train_batch_parallel`](Self::train_batch_parallel).
+    /// Returns the average cost of each batch
+    pub fn perform_epoch_parallel<DS>(&mut self, data: &DS, batch_size: usize) -> F
+    where
+        C: Cost<G::Output, Inner = F> + Sync,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Modal + Send + Sync,
+        F: Float + FromPrimitive + Send + Sync,
+        DS: Dataset + Sync,
+    {
+        let epoch = self.start_epoch();
+        span!(INFO, "epoch", epoch);
+        let batches = Batches::shuffled(data.len(), batch_size, &mut thread_rng());
+        let total_batches = batches.len();
+        let mut cost = F::zero();
+        let start = Timer::start();
+        for (i, indices) in batches.enumerate() {
+            let step = Timer::start();
+            let batch_cost = self.train_batch_parallel(data, &indices);
+            cost = cost + batch_cost;
+            self.emit(&TrainEvent::BatchEnd {
+                epoch,
+                batch: i,
+                batches: total_batches,
+                cost: batch_cost,
+                samples: indices.len(),
+                duration: step.elapsed(),
+                waited: Duration::ZERO,
+            });
         }
 
-        self.optimiser.optimise(&mut self.graph, grads);
+        let cost = cost / F::from_usize(total_batches).unwrap();
+        self.emit(&TrainEvent::EpochEnd {
+            epoch,
+            cost,
+            samples: data.len(),
+            learning_rate: self.current_learning_rate(),
+            duration: start.elapsed(),
+        });
+        self.epoch += 1;
         cost
     }
+
+    /// Splits the batch into one shard per rayon thread, computes the gradients of each
+    /// shard in parallel, then sums them into a single step. Gradients and costs are
+    /// summed over a batch, so this is the same step as [`train_batch`](Self::train_batch).
+    ///
+    /// Dropout, mixup and adversarial training are not applied
+    pub fn train_batch_parallel<DS>(&mut self, data: &DS, indices: &[usize]) -> F
+    where
+        C: Cost<G::Output, Inner = F> + Sync,
+        O: Optimiser<G>,
+        G: GraphExecTrain<DS::Input, Output = DS::Target> + Mappable<F> + Modal + Send + Sync,
+        F: Float + FromPrimitive + Send + Sync,
+        DS: Dataset + Sync,
+    {
+        use rayon::prelude::*;
+
+        span!(TRACE, "train_step", samples = indices.len());
+        self.graph.set_mode(Mode::Train);
+        let shard_size = indices.len().div_ceil(rayon::current_num_threads()).max(1);
+
+        // borrow the fields separately, since the callbacks are not Sync
+        let (graph, cost) = (&self.graph, &self.cost);
+        let (mut grads, cost) = indices
+            .par_chunks(shard_size)
+            .map(|shard| {
+                let (input, expected) = data.batch(shard);
+                graph.get_grads(input, expected, cost)
+            })
+            .reduce_with(|(mut grads, cost), (shard_grads, shard_cost)| {
+                grads.map_mut_with(&shard_grads, |g, &s| *g = *g + s);
+                (grads, cost + shard_cost)
+            })
+            .expect("batch should not be empty");
+
+        self.apply_grads(&mut grads, cost)
+    }
+}
+
+/// Buffers reused between training steps. The shapes of a graph are fixed once it's
+/// initialised, so the gradients only need allocating for the first batch of each epoch
+pub struct Buffers<G> {
+    grads: Option<G>,
+    dropouts: Option<G>,
+}
+
+impl<G> Buffers<G> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            grads: None,
+            dropouts: None,
+        }
+    }
+}
+
+impl<G> Default for Buffers<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The result of [`Train::evaluate`]
+#[derive(Debug, Clone)]
+pub struct Evaluation<F> {
+    pub cost: F,
+    /// The measured metrics, in the order they were requested
+    pub metrics: Vec<F>,
+}
+
+/// The running totals of an evaluation, batch by batch
+struct Totals<F> {
+    samples: usize,
+    cost: F,
+    metrics: Vec<F>,
+}
+
+impl<F: Float + FromPrimitive> Totals<F> {
+    fn new(metrics: usize) -> Self {
+        Self {
+            samples: 0,
+            cost: F::zero(),
+            metrics: vec![F::zero(); metrics],
+        }
+    }
+
+    /// Costs are already summed over the batch, but metrics are means over it,
+    /// so only the metrics are weighted by the number of samples
+    fn add(&mut self, samples: usize, cost: F, metrics: impl Iterator<Item = F>) {
+        let n = F::from_usize(samples).unwrap();
+        self.samples += samples;
+        self.cost = self.cost + cost;
+        for (total, m) in self.metrics.iter_mut().zip(metrics) {
+            *total = *total + m * n;
+        }
+    }
+
+    /// The cost and metrics per sample
+    fn average(self) -> Evaluation<F> {
+        let samples = F::from_usize(self.samples.max(1)).unwrap();
+        Evaluation {
+            cost: self.cost / samples,
+            metrics: self.metrics.into_iter().map(|m| m / samples).collect(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -183,28 +1261,563 @@ impl<F> Regularisation<F>
 where
     F: Float,
 {
-    fn apply<G: Mappable<F>>(self, grads: &mut G, graph: &G) -> F {
-        let mut cost = F::zero();
+    /// Multiplies every coefficient by `factor`
+    #[must_use]
+    pub fn scaled(self, factor: F) -> Self {
         match self {
-            Regularisation::L1(a) => {
-                grads.map_mut_with(graph, move |g, &x| {
-                    cost = cost + x.abs() * a;
-                    *g = *g + x.signum() * a;
-                });
+            Self::L1(a) => Self::L1(a * factor),
+            Self::L2(a) => Self::L2(a * factor),
+            Self::L1_2(a, b) => Self::L1_2(a * factor, b * factor),
+        }
+    }
+
+    /// Adds the penalty's gradient to `grads`, and returns the penalty.
+    /// Biases are skipped unless `biases` is set
+    fn apply<G: Mappable<F>>(self, grads: &mut G, graph: &G, biases: bool) -> F {
+        let mut cost = F::zero();
+        let mut penalise = |g: &mut F, &x: &F| {
+            let (penalty, grad) = match self {
+                Self::L1(a) => (x.abs() * a, x.signum() * a),
+                Self::L2(a) => (x * x * a, (x + x) * a),
+                Self::L1_2(a, b) => (x.abs() * a + x * x * b, x.signum() * a + (x + x) * b),
+            };
+            cost = cost + penalty;
+            *g = *g + grad;
+        };
+        if biases {
+            grads.map_mut_with(graph, &mut penalise);
+        } else {
+            grads.map_weights_mut_with(graph, &mut penalise);
+        }
+        cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        sync::Mutex,
+        thread::{self, ThreadId},
+    };
+
+    use ndarray::{array, Array2};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::{Regularisation, Train};
+    use crate::{
+        activation::relu::Relu,
+        callback::TrainEvent,
+        cost::Cost,
+        data::{self, Dataset, InMemoryDataset},
+        dense::{Dense, DenseState},
+        error::Error,
+        initialisers::Xavier,
+        optimise::sgd::SGD,
+        schedule::Linear,
+        Graph, GraphExec, Mappable,
+    };
+
+    #[test]
+    fn test_predict_matches_exec() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(3)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 4);
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.1)).build();
+
+        let inputs = Array2::<f64>::from_shape_simple_fn((10, 4), || rng.gen());
+        let predicted = trainer.predict(&inputs.view(), 3).unwrap();
+        assert_eq!(predicted, trainer.exec(inputs.clone()));
+
+        assert!(matches!(
+            trainer.predict(&inputs.view(), 0),
+            Err(Error::EmptyBatch)
+        ));
+        let empty = Array2::<f64>::zeros((0, 4));
+        assert!(matches!(
+            trainer.predict(&empty.view(), 3),
+            Err(Error::EmptyBatch)
+        ));
+    }
+
+    #[test]
+    fn test_fit_matches_full_batch_steps() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.005)).build();
+        let mut expected = Train::builder(trainer.graph.clone())
+            .optimiser(SGD::new(0.005))
+            .build();
+
+        // with one batch per epoch, shuffling only reorders the samples within it
+        let (inputs, targets) = data::linear_samples::<f64>(&mut rng, 64);
+        let data = InMemoryDataset::new(inputs.clone(), targets.clone());
+        let history = trainer.fit(&data, 64, 20);
+        let costs: Vec<_> = (0..20)
+            .map(|_| expected.train(inputs.clone(), targets.clone()))
+            .collect();
+
+        assert_eq!(trainer.epoch, 20);
+        assert_eq!(history.len(), 20);
+        for (cost, step) in history.iter().zip(&costs) {
+            assert!(
+                (cost - step).abs() < 1e-9 * step,
+                "{:?} {:?}",
+                history,
+                costs
+            );
+        }
+        let diff = (&trainer.graph.w - &expected.graph.w).mapv(f64::abs);
+        assert!(diff.iter().all(|&d| d < 1e-12), "{}", diff);
+    }
+
+    /// Records which thread gathered each batch
+    struct Recorded<DS> {
+        data: DS,
+        gathered: Mutex<Vec<(ThreadId, Vec<usize>)>>,
+    }
+
+    impl<DS: Dataset> Dataset for Recorded<DS> {
+        type Input = DS::Input;
+        type Target = DS::Target;
+
+        fn len(&self) -> usize {
+            self.data.len()
+        }
+
+        fn batch(&self, indices: &[usize]) -> (Self::Input, Self::Target) {
+            let gathered = (thread::current().id(), indices.to_vec());
+            self.gathered.lock().unwrap().push(gathered);
+            self.data.batch(indices)
+        }
+    }
+
+    #[test]
+    fn test_perform_epoch_gathers_off_thread() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.05)).build();
+
+        let inputs = Array2::<f64>::from_shape_simple_fn((30, 2), || rng.gen());
+        let targets = Array2::<f64>::zeros((30, 1));
+        let data = Recorded {
+            data: InMemoryDataset::new(inputs, targets),
+            gathered: Mutex::default(),
+        };
+        trainer.perform_epoch(&data, 4);
+
+        let gathered = data.gathered.into_inner().unwrap();
+        assert_eq!(gathered.len(), 8);
+        let current = thread::current().id();
+        assert!(gathered.iter().all(|(id, _)| *id != current));
+
+        let mut indices: Vec<usize> = gathered.into_iter().flat_map(|(_, i)| i).collect();
+        indices.sort_unstable();
+        assert_eq!(indices, (0..30).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_fit_events() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let mut trainer = Train::builder(graph)
+            .optimiser(SGD::new(0.05))
+            .log_learning_rate()
+            .callback(sender)
+            .build();
+
+        let inputs = Array2::<f64>::from_shape_simple_fn((20, 2), || rng.gen());
+        let data = InMemoryDataset::new(inputs, Array2::zeros((20, 1)));
+        trainer.fit(&data, 8, 2);
+        drop(trainer);
+
+        let events: Vec<_> = receiver.into_iter().collect();
+        assert_eq!(events.len(), 8);
+        let batch_samples: Vec<_> = events[..3]
+            .iter()
+            .map(|event| match *event {
+                TrainEvent::BatchEnd { samples, .. } => samples,
+                TrainEvent::EpochEnd { .. } => panic!("expected a batch event"),
+            })
+            .collect();
+        assert_eq!(batch_samples, [8, 8, 4]);
+        match events[3] {
+            TrainEvent::EpochEnd {
+                epoch,
+                samples,
+                learning_rate,
+                duration,
+                ..
+            } => {
+                assert_eq!((epoch, samples), (0, 20));
+                assert_eq!(learning_rate, Some(0.05));
+                assert!(duration.as_nanos() > 0);
+                assert!(events[3].samples_per_second() > 0.0);
             }
-            Regularisation::L2(a) => {
-                grads.map_mut_with(graph, move |g, &x| {
-                    cost = cost + x * x * a;
-                    *g = *g + (x + x) * a;
-                });
+            TrainEvent::BatchEnd { .. } => panic!("expected an epoch event"),
+        }
+    }
+
+    #[test]
+    fn test_fit_history_matches_epoch_events() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let costs = Rc::new(RefCell::new(vec![]));
+        let recorded = Rc::clone(&costs);
+        let mut trainer = Train::builder(graph)
+            .callback(move |event: &TrainEvent<f64>| {
+                if let TrainEvent::EpochEnd { epoch, cost, .. } = *event {
+                    recorded.borrow_mut().push((epoch, cost));
+                }
+            })
+            .build();
+
+        let inputs = Array2::<f64>::from_shape_simple_fn((20, 2), || rng.gen());
+        let data = InMemoryDataset::new(inputs, Array2::zeros((20, 1)));
+        let history = trainer.fit(&data, 8, 3);
+
+        let costs = costs.borrow();
+        assert_eq!(
+            costs.iter().map(|&(epoch, _)| epoch).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            costs.iter().map(|&(_, cost)| cost).collect::<Vec<_>>(),
+            history
+        );
+    }
+
+    #[test]
+    fn test_mixup_blends_samples() {
+        // with zero weights, the weight gradients are -x * y summed over the batch. Each
+        // sample is one-hot and only the first has a target, so the second input's weight
+        // only gets a gradient once the samples are blended
+        let weight_grads = |mixup: Option<f64>, epoch: bool| {
+            let graph = DenseState {
+                w: Array2::<f64>::zeros((2, 1)),
+                b: ndarray::Array1::zeros(1),
+            };
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let mut builder = Train::builder(graph)
+                .on_grads(move |grads: &DenseState<f64>| sender.send(grads.w.clone()).unwrap());
+            if let Some(alpha) = mixup {
+                builder = builder.mixup(alpha);
             }
-            Regularisation::L1_2(a, b) => {
-                grads.map_mut_with(graph, move |g, &x| {
-                    cost = cost + x.abs() * a + x * x * b;
-                    *g = *g + x.signum() * a + (x + x) * b;
-                });
+            let (inputs, targets) = (array![[1.0, 0.0], [0.0, 1.0]], array![[1.0], [0.0]]);
+            let mut trainer = builder.build();
+            if epoch {
+                trainer.perform_epoch(&InMemoryDataset::new(inputs, targets), 2);
+            } else {
+                trainer.train(inputs, targets);
             }
+            receiver.recv().unwrap()
+        };
+
+        // single steps are blended as well as epochs
+        for epoch in [true, false] {
+            assert!(weight_grads(None, epoch)[(1, 0)].abs() < 1e-12);
+            assert!(weight_grads(Some(2.0), epoch)[(1, 0)].abs() > 1e-6);
         }
-        cost
+    }
+
+    #[test]
+    #[should_panic(expected = "mixup alpha should be positive")]
+    fn test_mixup_rejects_zero_alpha() {
+        let graph: DenseState<f64> = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut StdRng::seed_from_u64(0), 2);
+        let _ = Train::builder(graph).mixup(0.0);
+    }
+
+    #[test]
+    fn test_fgsm_moves_inputs_up_the_cost() {
+        let graph = DenseState {
+            w: array![[1.0_f64], [-1.0]],
+            b: array![0.0],
+        };
+        let trainer = Train::builder(graph).build();
+
+        // the output is too small, so raising the first input or lowering the second
+        // would help. FGSM steps each input the other way by epsilon
+        let input = array![[0.0, 0.0]];
+        let expected = array![[1.0]];
+        let perturbed = trainer.fgsm(input.clone(), &expected, 0.1);
+        assert_eq!(perturbed, array![[-0.1, 0.1]]);
+
+        let cost = |input: Array2<f64>| trainer.cost.cost(&trainer.exec(input), &expected);
+        assert!(cost(perturbed) > cost(input));
+    }
+
+    #[test]
+    fn test_evaluate_is_per_sample() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let mut trainer = Train::builder(graph).build();
+        let (inputs, targets) = data::linear_samples::<f64>(&mut rng, 64);
+
+        let mut evaluate = |batch_size| {
+            trainer
+                .evaluate(&inputs.view(), &targets.view(), batch_size, &[])
+                .unwrap()
+                .cost
+        };
+        // the last batch of 5 is only partly full
+        let (one, five, all) = (evaluate(1), evaluate(5), evaluate(64));
+        assert!((one - five).abs() < 1e-12, "{} {}", one, five);
+        assert!((one - all).abs() < 1e-12, "{} {}", one, all);
+    }
+
+    #[test]
+    fn test_validated_epoch() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let mut trainer = Train::builder(graph).dropout(0.5).build();
+
+        let mut data = || {
+            let inputs = Array2::<f64>::from_shape_simple_fn((20, 2), || rng.gen());
+            let targets = inputs
+                .sum_axis(ndarray::Axis(1))
+                .insert_axis(ndarray::Axis(1));
+            InMemoryDataset::new(inputs, targets)
+        };
+        let (train, validation) = (data(), data());
+        let (_, validation_cost) = trainer.perform_epoch_validated(&train, &validation, 8);
+        assert_eq!(trainer.epoch, 1);
+
+        // the same as evaluating it, with no dropout
+        let evaluation = trainer
+            .evaluate(
+                &validation.inputs.view(),
+                &validation.targets.view(),
+                8,
+                &[],
+            )
+            .unwrap();
+        assert!((validation_cost - evaluation.cost).abs() < 1e-12);
+
+        // and it doesn't depend on the batch size
+        let cost = trainer.validate(&validation, 3);
+        assert!((validation_cost - cost).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_dropout_schedule() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let mut trainer = Train::builder(graph)
+            .dropout_schedule(Linear::new(0.0, 0.5).between(0, 2))
+            .build();
+
+        let inputs = Array2::<f64>::from_shape_simple_fn((20, 2), || rng.gen());
+        let data = InMemoryDataset::new(inputs, Array2::zeros((20, 1)));
+        let mut dropouts = vec![];
+        for _ in 0..4 {
+            trainer.perform_epoch(&data, 8);
+            dropouts.push(trainer.dropout);
+        }
+        assert_eq!(dropouts, [0.0, 0.25, 0.5, 0.5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "dropout should be in 0..1")]
+    fn test_dropout_rejects_one() {
+        let graph: DenseState<f64> = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut StdRng::seed_from_u64(0), 2);
+        let _ = Train::builder(graph).dropout(1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "the dropout schedule should stay in 0..1")]
+    fn test_dropout_schedule_out_of_range() {
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut StdRng::seed_from_u64(0), 2);
+        let mut trainer = Train::builder(graph)
+            .dropout_schedule(Linear::new(0.5, 1.5).between(0, 1))
+            .build();
+        let data = InMemoryDataset::new(Array2::<f64>::zeros((4, 2)), Array2::zeros((4, 1)));
+        trainer.perform_epoch(&data, 2);
+        trainer.perform_epoch(&data, 2);
+    }
+
+    #[test]
+    fn test_regularisation_schedule() {
+        let graph = Graph::<f64, _>::input_shape(Dense::output_size(1).with_initialiser(Xavier), 2);
+        let mut trainer = Train::builder(graph)
+            .regularisation(Regularisation::L1_2(0.1, 0.2))
+            .regularisation_schedule(Linear::new(1.0, 0.0).between(0, 4))
+            .build();
+
+        let mut coefficients: Vec<(f64, f64)> = vec![];
+        for epoch in [0, 1, 4, 10].iter().copied() {
+            trainer.epoch = epoch;
+            match trainer.current_regularisation() {
+                Some(Regularisation::L1_2(a, b)) => coefficients.push((a, b)),
+                r => panic!("unexpected regularisation {:?}", r),
+            }
+        }
+        let expected = [(0.1, 0.2), (0.075, 0.15), (0.0, 0.0), (0.0, 0.0)];
+        for ((a, b), (x, y)) in coefficients.into_iter().zip(expected.iter()) {
+            assert!((a - x).abs() < 1e-12 && (b - y).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_regularise_biases() {
+        let graph = (
+            DenseState {
+                w: array![[1.0_f64, -2.0]],
+                b: array![3.0],
+            },
+            DenseState {
+                w: array![[0.5]],
+                b: array![-1.0],
+            },
+        );
+        let zero = || graph.map(|_| 0.0);
+
+        let mut grads = zero();
+        let cost = Regularisation::L2(0.5).apply(&mut grads, &graph, true);
+        assert!((cost - 7.625).abs() < 1e-12);
+        assert_eq!(grads.0.b, array![3.0]);
+
+        let mut grads = zero();
+        let cost = Regularisation::L2(0.5).apply(&mut grads, &graph, false);
+        assert!((cost - 2.625).abs() < 1e-12);
+        assert_eq!(grads.0.w, array![[1.0, -2.0]]);
+        assert_eq!(grads.0.b, array![0.0]);
+        assert_eq!(grads.1.w, array![[0.5]]);
+        assert_eq!(grads.1.b, array![0.0]);
+    }
+
+    #[test]
+    fn test_slices_epoch_matches_batch_steps() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = crate::net![
+            Dense::output_size(4)
+                .with_initialiser(Xavier)
+                .with_activation(Relu),
+            Dense::output_size(1).with_initialiser(Xavier)
+        ]
+        .init_with_random(&mut rng, 2);
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.02)).build();
+        let mut copied = Train::builder(trainer.graph.clone())
+            .optimiser(SGD::new(0.02))
+            .build();
+
+        // borrowed slices train just like the same batches copied out, in order
+        let (inputs, targets) = data::linear_samples::<f64>(&mut rng, 60);
+        for _ in 0..5 {
+            let cost = trainer.perform_epoch_slices(&inputs.view(), &targets.view(), 8);
+            let costs: Vec<_> = inputs
+                .axis_chunks_iter(ndarray::Axis(0), 8)
+                .zip(targets.axis_chunks_iter(ndarray::Axis(0), 8))
+                .map(|(input, target)| copied.train(input.to_owned(), target.to_owned()))
+                .collect();
+            // the last batch only has 4 samples
+            assert_eq!(costs.len(), 8);
+            let expected = costs.iter().sum::<f64>() / 8.0;
+            assert!((cost - expected).abs() < 1e-12, "{} {}", cost, expected);
+        }
+        assert_eq!(trainer.epoch, 5);
+        assert_eq!(trainer.graph.1.w, copied.graph.1.w);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn test_averaged_epoch_matches_serial_steps() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut rng, 2);
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.01)).build();
+        let mut serial = Train::builder(trainer.graph.clone())
+            .optimiser(SGD::new(0.005))
+            .build();
+
+        // each worker takes one step on its half of the data. Costs and gradients are
+        // summed over a batch, so their average is a whole batch step at half the rate
+        let (inputs, targets) = data::linear_samples::<f64>(&mut rng, 64);
+        let data = InMemoryDataset::new(inputs.clone(), targets.clone());
+        for _ in 0..10 {
+            let cost = trainer.perform_epoch_averaged(&data, 32, 2, 1);
+            let expected = serial.train(inputs.clone(), targets.clone());
+            assert!(
+                (cost - expected / 2.0).abs() < 1e-9 * cost,
+                "{} {}",
+                cost,
+                expected
+            );
+        }
+        assert_eq!(trainer.epoch, 10);
+        let diff = (&trainer.graph.w - &serial.graph.w).mapv(f64::abs);
+        assert!(diff.iter().all(|&d| d < 1e-12), "{}", diff);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    #[should_panic(expected = "sync_every should be at least one batch")]
+    fn test_averaged_epoch_rejects_zero_sync() {
+        let graph = Dense::output_size(1)
+            .with_initialiser(Xavier)
+            .init_with_random(&mut StdRng::seed_from_u64(0), 2);
+        let mut trainer = Train::builder(graph).optimiser(SGD::new(0.05)).build();
+        let data = InMemoryDataset::new(Array2::<f64>::zeros((4, 2)), Array2::zeros((4, 1)));
+        trainer.perform_epoch_averaged(&data, 2, 2, 0);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_epoch_matches_serial_steps() {
+        // the gradients of every shard add up to those of the whole batch,
+        // however many shards it's split into
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let mut rng = StdRng::seed_from_u64(0);
+            let graph = Dense::output_size(1)
+                .with_initialiser(Xavier)
+                .init_with_random(&mut rng, 2);
+            let mut trainer = Train::builder(graph).optimiser(SGD::new(0.005)).build();
+            let mut serial = Train::builder(trainer.graph.clone())
+                .optimiser(SGD::new(0.005))
+                .build();
+
+            let (inputs, targets) = data::linear_samples::<f64>(&mut rng, 64);
+            let data = InMemoryDataset::new(inputs.clone(), targets.clone());
+            for _ in 0..10 {
+                let cost = trainer.perform_epoch_parallel(&data, 64);
+                let expected = serial.train(inputs.clone(), targets.clone());
+                assert!(
+                    (cost - expected).abs() < 1e-9 * expected,
+                    "{} {}",
+                    cost,
+                    expected
+                );
+            }
+            assert_eq!(trainer.epoch, 10);
+            let diff = (&trainer.graph.w - &serial.graph.w).mapv(f64::abs);
+            assert!(diff.iter().all(|&d| d < 1e-12), "{}", diff);
+        });
     }
 }
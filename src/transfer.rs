@@ -0,0 +1,58 @@
+use rand::Rng;
+
+use crate::Graph;
+
+/// Splits a trained `(body, head)` state and attaches a freshly initialised head in place
+/// of the old one, returning the new state along with the old head.
+///
+/// The body's builder is needed to know the shape of the body's output.
+/// Remember to create a new optimiser for the new state, since its shape will have changed
+///
+/// ```
+/// use linear_networks::{
+///     activation::{relu::Relu, sigmoid::Sigmoid},
+///     dense::Dense,
+///     initialisers::Xavier,
+///     net,
+///     transfer::replace_head,
+///     Graph, GraphExec,
+/// };
+/// use ndarray::Array2;
+///
+/// let body = net![
+///     Dense::output_size(16)
+///         .with_initialiser(Xavier)
+///         .with_activation(Relu),
+///     Dense::output_size(16)
+///         .with_initialiser(Xavier)
+///         .with_activation(Relu)
+/// ];
+/// let head = Dense::output_size(10)
+///     .with_initialiser(Xavier)
+///     .with_activation(Sigmoid);
+///
+/// let trained = (body, head).input_shape(28 * 28);
+///
+/// // replace the 10 class head with a 2 class head
+/// let new_head = Dense::output_size(2)
+///     .with_initialiser(Xavier)
+///     .with_activation(Sigmoid);
+/// let (state, _old_head) = replace_head(&body, trained, new_head, &mut rand::thread_rng());
+///
+/// let output = state.exec(Array2::<f32>::zeros((1, 28 * 28)));
+/// assert_eq!(output.shape(), &[1, 2]);
+/// ```
+pub fn replace_head<F, I, B, H, S>(
+    body: &B,
+    state: (B::State, S),
+    head: H,
+    rng: &mut impl Rng,
+) -> ((B::State, H::State), S)
+where
+    B: Graph<F, I>,
+    H: Graph<F, B::OutputShape>,
+{
+    let (body_state, old_head) = state;
+    let head_state = head.init_with_random(rng, body.get_output_shape());
+    ((body_state, head_state), old_head)
+}